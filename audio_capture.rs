@@ -0,0 +1,816 @@
+// src/audio_capture.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::filters::{AudioMixer, MixerInput};
+use crate::resample::{AudioConverter, AudioFormat};
+
+/// Битрейт звука по умолчанию (кбит/с).
+pub const DEFAULT_AUDIO_BITRATE_KBPS: u32 = 128;
+
+/// Кодеки звука, которые можно выбрать (строки из GUI и CLI); auto — по контейнеру.
+pub const AUDIO_CODECS: &[&str] = &["auto", "AAC", "Opus", "FLAC"];
+
+/// Режимы libopus: audio — музыка и звук рабочего стола, voip — речь,
+/// lowdelay — минимальная задержка ценой качества.
+pub const OPUS_APPLICATIONS: &[&str] = &["audio", "voip", "lowdelay"];
+pub const DEFAULT_OPUS_APPLICATION: &str = "audio";
+
+/// Формат сэмплов, в котором звук буферизуется до кодера: встроенные кодеры
+/// AAC и Opus принимают планарные f32, libopus — только чередующиеся.
+const PLANAR_F32: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar);
+const PACKED_F32: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed);
+/// Целые форматы кодера FLAC: он не принимает f32.
+const PACKED_S32: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::I32(ffmpeg::format::sample::Type::Packed);
+const PACKED_S16: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::I16(ffmpeg::format::sample::Type::Packed);
+
+/// Уровень сжатия FLAC (0–12). Звук сжимается без потерь при любом уровне;
+/// 8 заметно экономит место, а поток 48 кГц стерео всё равно кодируется
+/// с большим запасом по времени.
+const FLAC_COMPRESSION_LEVEL: usize = 8;
+
+/// Примерный битрейт FLAC для оценки размера: около 60% от 16-битного PCM
+/// 48 кГц стерео (1536 кбит/с).
+pub const FLAC_ESTIMATED_KBPS: u32 = 900;
+
+/// Момент, когда появился первый видеокадр: от него отсчитываются PTS звука.
+pub type VideoStart = Arc<Mutex<Option<Instant>>>;
+
+/// Строка устройства, означающая звук рабочего стола (монитор вывода по умолчанию).
+pub const DEFAULT_MONITOR: &str = "default-monitor";
+
+/// Имя источника PulseAudio/PipeWire для FFmpeg-входа "pulse" по строке из GUI:
+/// - "default" — текущий источник (микрофон) по умолчанию;
+/// - `DEFAULT_MONITOR` — монитор устройства вывода по умолчанию;
+/// - имя источника или его описание ("Built-in Audio Analog Stereo");
+/// - число — id узла PipeWire (pipewire-pulse использует его как индекс источника).
+/// Умолчания разворачиваются в настоящие имена, чтобы в логах было видно, что
+/// именно пишется; если pactl недоступен, строка передаётся FFmpeg как есть.
+pub fn resolve_device(device: &str) -> String {
+    match device {
+        "default" => pactl(&["get-default-source"]).unwrap_or_else(|| device.to_string()),
+        DEFAULT_MONITOR => match pactl(&["get-default-sink"]) {
+            Some(sink) => format!("{}.monitor", sink),
+            None => "@DEFAULT_MONITOR@".to_string(),
+        },
+        _ if device.chars().all(|c| c.is_ascii_digit()) => device.to_string(),
+        _ => source_by_description(device).unwrap_or_else(|| device.to_string()),
+    }
+}
+
+/// Вывод pactl без пробелов по краям; None, если pactl нет или он завершился с ошибкой.
+fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Источник звука PulseAudio/PipeWire: имя для FFmpeg и описание для списка.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioSource {
+    pub name: String,
+    pub description: String,
+}
+
+/// Источники записи из `pactl list sources` (pipewire-pulse отдаёт и узлы
+/// PipeWire). Пустой список, если pactl недоступен.
+pub fn list_sources() -> Vec<AudioSource> {
+    let listing = match pactl(&["list", "sources"]) {
+        Some(listing) => listing,
+        None => return Vec::new(),
+    };
+    let mut sources: Vec<AudioSource> = Vec::new();
+    for line in listing.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("Name:") {
+            sources.push(AudioSource {
+                name: value.trim().to_string(),
+                description: String::new(),
+            });
+        } else if let Some(value) = line.strip_prefix("Description:") {
+            if let Some(source) = sources.last_mut() {
+                source.description = value.trim().to_string();
+            }
+        }
+    }
+    sources
+}
+
+/// Ищет источник, у которого совпадает имя или описание (без учёта регистра).
+fn source_by_description(device: &str) -> Option<String> {
+    list_sources()
+        .into_iter()
+        .find(|source| source.name == device || source.description.eq_ignore_ascii_case(device))
+        .map(|source| source.name)
+}
+
+/// Открытый вход звука с декодером.
+pub struct AudioInput {
+    ictx: ffmpeg::format::context::Input,
+    decoder: ffmpeg::decoder::Audio,
+    stream_index: usize,
+    /// База времени PTS пакетов устройства (у pulse — микросекунды).
+    time_base: ffmpeg::Rational,
+    device: String,
+}
+
+impl AudioInput {
+    pub fn open(device: &str) -> Result<Self> {
+        let device = resolve_device(device);
+        let ictx = ffmpeg::format::input_with_format(&device, "pulse")
+            .map_err(|e| anyhow::anyhow!("Failed to open audio device '{}': {:?}", device, e))?;
+        let stream = ictx
+            .streams()
+            .best(ffmpeg::media::Type::Audio)
+            .ok_or_else(|| anyhow::anyhow!("No audio stream found on device '{}'", device))?;
+        let stream_index = stream.index();
+        let time_base = stream.time_base();
+        let decoder = stream
+            .codec()
+            .decoder()
+            .audio()
+            .map_err(|e| anyhow::anyhow!("Failed to open audio decoder: {:?}", e))?;
+        Ok(Self {
+            ictx,
+            decoder,
+            stream_index,
+            time_base,
+            device,
+        })
+    }
+
+    /// Формат декодированных кадров. pulse не всегда сообщает раскладку,
+    /// тогда берётся стандартная для числа каналов.
+    pub fn format(&self) -> AudioFormat {
+        let layout = match self.decoder.channel_layout() {
+            layout if layout.bits() == 0 => {
+                ffmpeg::ChannelLayout::default(self.decoder.channels() as i32)
+            }
+            layout => layout,
+        };
+        AudioFormat {
+            sample: self.decoder.format(),
+            layout,
+            rate: self.decoder.rate(),
+        }
+    }
+
+    /// Читает устройство и отдаёт декодированные кадры в `on_frame`, пока не поднят `stop`.
+    fn read(
+        &mut self,
+        stop: &AtomicBool,
+        mut on_frame: impl FnMut(&ffmpeg::frame::Audio) -> Result<()>,
+    ) -> Result<()> {
+        let mut decoded = ffmpeg::frame::Audio::empty();
+        for (stream, packet) in self.ictx.packets() {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            if stream.index() != self.stream_index {
+                continue;
+            }
+            self.decoder
+                .send_packet(&packet)
+                .map_err(|e| anyhow::anyhow!("Error sending audio packet to decoder: {:?}", e))?;
+            while self.decoder.receive_frame(&mut decoded).is_ok() {
+                on_frame(&decoded)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Источники дорожки: основной и необязательный второй (обычно звук рабочего
+/// стола и микрофон), у каждого своё усиление (1.0 — без изменений).
+pub struct AudioSources {
+    primary: AudioInput,
+    primary_gain: f64,
+    secondary: Option<(AudioInput, f64)>,
+}
+
+impl AudioSources {
+    pub fn new(primary: AudioInput, gain: f64) -> Self {
+        Self {
+            primary,
+            primary_gain: gain,
+            secondary: None,
+        }
+    }
+
+    pub fn with_secondary(mut self, secondary: Option<AudioInput>, gain: f64) -> Self {
+        self.secondary = secondary.map(|input| (input, gain));
+        self
+    }
+
+    /// Один источник без усиления идёт к кодеру напрямую, без графа фильтров.
+    fn needs_mixer(&self) -> bool {
+        self.secondary.is_some() || (self.primary_gain - 1.0).abs() > f64::EPSILON
+    }
+}
+
+/// Кодек звуковой дорожки.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+    /// Без потерь, для архивных записей; пишется только в mkv.
+    Flac,
+}
+
+impl AudioCodec {
+    /// Кодек под контейнер: WebM допускает только Opus и Vorbis, остальным — AAC.
+    pub fn for_container(container: &str) -> Self {
+        match container {
+            "webm" => AudioCodec::Opus,
+            _ => AudioCodec::Aac,
+        }
+    }
+
+    /// Разбирает строку кодека из GUI/CLI; "auto" (или пустая строка пресетов
+    /// старых версий) выбирает кодек по контейнеру.
+    pub fn parse(text: &str, container: &str) -> Result<Self> {
+        match text {
+            "" | "auto" => Ok(Self::for_container(container)),
+            "AAC" => Ok(AudioCodec::Aac),
+            "Opus" => Ok(AudioCodec::Opus),
+            "FLAC" => Ok(AudioCodec::Flac),
+            other => anyhow::bail!(
+                "Unknown audio codec '{}' (expected auto, AAC, Opus or FLAC)",
+                other
+            ),
+        }
+    }
+
+    pub fn id(self) -> ffmpeg::codec::Id {
+        match self {
+            AudioCodec::Aac => ffmpeg::codec::Id::AAC,
+            AudioCodec::Opus => ffmpeg::codec::Id::OPUS,
+            AudioCodec::Flac => ffmpeg::codec::Id::FLAC,
+        }
+    }
+
+    /// Кодеры FFmpeg в порядке предпочтения: libopus звучит лучше встроенного
+    /// экспериментального кодера Opus и умеет режимы audio/voip.
+    fn encoder_names(self) -> &'static [&'static str] {
+        match self {
+            AudioCodec::Aac => &["aac"],
+            AudioCodec::Opus => &["libopus", "opus"],
+            AudioCodec::Flac => &["flac"],
+        }
+    }
+
+    /// Кодер из локальной сборки FFmpeg; ошибка называет недостающие кодеры.
+    pub fn find_encoder(self) -> Result<ffmpeg::Codec> {
+        self.encoder_names()
+            .iter()
+            .find_map(|name| ffmpeg::encoder::find_by_name(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{:?} audio needs the {} encoder, which this FFmpeg build does not include",
+                    self,
+                    self.encoder_names().join(" or ")
+                )
+            })
+    }
+
+    /// Контейнер отдельной звуковой дорожки (режим split_audio): расширение
+    /// объекта и мультиплексор FFmpeg, который его пишет.
+    pub fn split_container(self) -> (&'static str, &'static str) {
+        match self {
+            AudioCodec::Aac => ("m4a", "ipod"),
+            AudioCodec::Opus => ("opus", "opus"),
+            AudioCodec::Flac => ("flac", "flac"),
+        }
+    }
+
+    /// Opus работает только на 48 кГц; AAC и FLAC пишутся с частотой устройства.
+    fn rate(self, input_rate: u32) -> i32 {
+        match self {
+            AudioCodec::Aac | AudioCodec::Flac => input_rate as i32,
+            AudioCodec::Opus => 48000,
+        }
+    }
+}
+
+/// Кодер звука и поток звука в выходном контейнере.
+pub struct AudioOutput {
+    encoder: ffmpeg::encoder::Audio,
+    sample_format: ffmpeg::format::Sample,
+    pub stream_index: usize,
+    /// База времени кодера (1/частота); пакеты пересчитываются из неё в базу потока.
+    pub time_base: ffmpeg::Rational,
+}
+
+impl AudioOutput {
+    /// Открывает кодер `audio_codec` с битрейтом `bitrate_kbps` под формат входа
+    /// `input` (`AudioInput::format`) и добавляет поток в контейнер. Вызывается до `write_header`.
+    /// `opus_application` (audio, voip, lowdelay) понимает только libopus;
+    /// FLAC битрейт не использует, его размер задаёт уровень сжатия.
+    pub fn add_to(
+        octx: &mut ffmpeg::format::context::Output,
+        input: AudioFormat,
+        audio_codec: AudioCodec,
+        bitrate_kbps: u32,
+        opus_application: &str,
+        global_header: bool,
+    ) -> Result<Self> {
+        let codec = audio_codec.find_encoder()?;
+        let sample_format = sample_format_for(&codec, input.sample)?;
+        let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .audio()
+            .map_err(|e| anyhow::anyhow!("Failed to create {:?} encoder: {:?}", audio_codec, e))?;
+        let rate = audio_codec.rate(input.rate);
+        let time_base = ffmpeg::Rational(1, rate);
+        encoder.set_rate(rate);
+        encoder.set_channel_layout(input.layout);
+        encoder.set_format(sample_format);
+        if audio_codec == AudioCodec::Flac {
+            encoder.set_compression(Some(FLAC_COMPRESSION_LEVEL));
+        } else {
+            encoder.set_bit_rate(bitrate_kbps as usize * 1000);
+        }
+        encoder.set_time_base(time_base);
+        if global_header {
+            encoder.set_flags(ffmpeg::codec::flag::Flags::GLOBAL_HEADER);
+        }
+        let mut options = ffmpeg::Dictionary::new();
+        match codec.name() {
+            "libopus" => options.set("application", opus_application),
+            "opus" => {
+                // Встроенный кодер Opus помечен в FFmpeg как экспериментальный.
+                unsafe {
+                    (*encoder.as_mut_ptr()).strict_std_compliance =
+                        ffmpeg::ffi::FF_COMPLIANCE_EXPERIMENTAL;
+                }
+                if opus_application != DEFAULT_OPUS_APPLICATION {
+                    warn!(
+                        "This FFmpeg build has no libopus; Opus application '{}' is ignored",
+                        opus_application
+                    );
+                }
+            }
+            _ => {}
+        }
+        let encoder = encoder
+            .open_as_with(codec, options)
+            .map_err(|e| anyhow::anyhow!("Failed to open {} encoder: {:?}", codec.name(), e))?;
+        info!(
+            "Audio: {} at {} Hz, {:?} samples, {} samples per frame",
+            codec.name(),
+            rate,
+            sample_format,
+            encoder.frame_size()
+        );
+        let mut stream = octx
+            .add_stream(codec)
+            .map_err(|e| anyhow::anyhow!("Failed to add audio stream: {:?}", e))?;
+        stream.set_time_base(time_base);
+        stream.set_parameters(&encoder);
+        Ok(Self {
+            stream_index: stream.index(),
+            encoder,
+            sample_format,
+            time_base,
+        })
+    }
+}
+
+/// Формат сэмплов для кодера. Кодерам с потерями — f32: планарный, если кодер его
+/// умеет, иначе чередующийся. FLAC хранит целые сэмплы: формат захвата (обычно
+/// s16 или s32) идёт к нему как есть, если кодер его принимает, иначе — s32.
+fn sample_format_for(
+    codec: &ffmpeg::Codec,
+    captured: ffmpeg::format::Sample,
+) -> Result<ffmpeg::format::Sample> {
+    let formats: Vec<ffmpeg::format::Sample> = codec
+        .audio()
+        .ok()
+        .and_then(|audio| audio.formats())
+        .map(|formats| formats.collect())
+        .unwrap_or_default();
+    let preferred = if codec.id() == ffmpeg::codec::Id::FLAC {
+        vec![captured, PACKED_S32, PACKED_S16]
+    } else {
+        vec![PLANAR_F32, PACKED_F32]
+    };
+    preferred
+        .iter()
+        .copied()
+        .find(|format| formats.contains(format))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} encoder accepts none of the sample formats {:?}",
+                codec.name(),
+                preferred
+            )
+        })
+}
+
+/// Поток захвата звука: читает устройство, кодирует звук и отдаёт готовые пакеты
+/// (в базе времени кодера) потоку записи, который мультиплексирует их с видео.
+pub struct AudioCapture {
+    handle: JoinHandle<Result<()>>,
+    packets: Receiver<ffmpeg::Packet>,
+    stop: Arc<AtomicBool>,
+}
+
+impl AudioCapture {
+    pub fn start(sources: AudioSources, output: AudioOutput, video_start: VideoStart) -> Self {
+        let (sender, packets) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        let handle = thread::spawn(move || {
+            match sources.secondary.as_ref() {
+                Some((secondary, _)) => info!(
+                    "Capturing audio from '{}' mixed with '{}'",
+                    sources.primary.device, secondary.device
+                ),
+                None => info!("Capturing audio from '{}'", sources.primary.device),
+            }
+            run_capture(
+                sources,
+                output.encoder,
+                output.sample_format,
+                &video_start,
+                &sender,
+                &stop_clone,
+            )
+        });
+        Self {
+            handle,
+            packets,
+            stop,
+        }
+    }
+
+    /// Пакеты, накопившиеся с прошлого вызова.
+    pub fn ready_packets(&self) -> Vec<ffmpeg::Packet> {
+        self.packets.try_iter().collect()
+    }
+
+    /// Останавливает захват, дожидается сброса кодера и возвращает оставшиеся пакеты.
+    pub fn finish(self) -> Result<Vec<ffmpeg::Packet>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle
+            .join()
+            .map_err(|_| anyhow::anyhow!("Audio capture thread panicked"))??;
+        Ok(self.packets.try_iter().collect())
+    }
+}
+
+fn run_capture(
+    sources: AudioSources,
+    mut encoder: ffmpeg::encoder::Audio,
+    sample_format: ffmpeg::format::Sample,
+    video_start: &Mutex<Option<Instant>>,
+    sender: &Sender<ffmpeg::Packet>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let rate = encoder.rate();
+    let layout = encoder.channel_layout();
+    let frame_size = (encoder.frame_size() as usize).max(1);
+    let output_format = AudioFormat {
+        sample: sample_format,
+        layout,
+        rate,
+    };
+    // Кодер берёт кадры ровно по frame_size сэмплов (у Opus — 20 мс на 48 кГц),
+    // поэтому звук приводится к частоте кодера до очереди, а не после.
+    let mut converter = AudioConverter::new(output_format);
+    let mut fifo = SampleFifo::new(sample_format, layout.channels() as usize);
+    // PTS следующего сэмпла, отданного кодеру; None — отсчёт ещё не начат.
+    let mut next_pts: Option<i64> = None;
+
+    let mut mixer = if sources.needs_mixer() {
+        let mut inputs = vec![MixerInput {
+            format: sources.primary.format(),
+            time_base: sources.primary.time_base,
+            gain: sources.primary_gain,
+        }];
+        if let Some((secondary, gain)) = sources.secondary.as_ref() {
+            inputs.push(MixerInput {
+                format: secondary.format(),
+                time_base: secondary.time_base,
+                gain: *gain,
+            });
+        }
+        Some(AudioMixer::new(&inputs, output_format)?)
+    } else {
+        None
+    };
+    let AudioSources {
+        mut primary,
+        secondary,
+        ..
+    } = sources;
+    let secondary = secondary.map(|(input, _)| SecondaryReader::start(input));
+
+    let send_ready = |encoder: &mut ffmpeg::encoder::Audio| -> Result<()> {
+        let mut packet = ffmpeg::Packet::empty();
+        while encoder.receive_packet(&mut packet).is_ok() {
+            // Поток записи уже завершён — пакеты больше некуда отдавать.
+            if sender.send(packet.clone()).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    };
+
+    let mut encode = |frames: Vec<ffmpeg::frame::Audio>,
+                      encoder: &mut ffmpeg::encoder::Audio|
+     -> Result<()> {
+        for frame in frames {
+            // Звук до первого видеокадра не пишется: дорожки начинаются вместе.
+            // Дальше PTS считается по числу сэмплов, без дрожания времени прихода.
+            if next_pts.is_none() {
+                match *video_start.lock().unwrap() {
+                    Some(started) => {
+                        next_pts = Some((started.elapsed().as_secs_f64() * rate as f64) as i64);
+                    }
+                    None => continue,
+                }
+            }
+            fifo.push(&converter.convert(&frame)?);
+            while let Some(mut frame) = fifo.pop(frame_size, layout, rate) {
+                let pts = next_pts.unwrap_or(0);
+                frame.set_pts(Some(pts));
+                next_pts = Some(pts + frame.samples() as i64);
+                encoder
+                    .send_frame(&frame)
+                    .map_err(|e| anyhow::anyhow!("Error sending audio frame to encoder: {:?}", e))?;
+                send_ready(encoder)?;
+            }
+        }
+        Ok(())
+    };
+
+    // Основной источник задаёт темп: второй читается своим потоком, и его
+    // кадры добавляются в микшер всякий раз, когда приходит кадр основного.
+    let read = primary.read(stop, |decoded| {
+        let frames = match mixer.as_mut() {
+            Some(mixer) => {
+                mixer.push(0, decoded)?;
+                if let Some(reader) = secondary.as_ref() {
+                    for frame in reader.frames.try_iter() {
+                        mixer.push(1, &frame)?;
+                    }
+                }
+                mixer.pull()
+            }
+            None => vec![decoded.clone()],
+        };
+        encode(frames, &mut encoder)
+    });
+    if let Some(reader) = secondary {
+        if let Err(e) = reader.finish() {
+            warn!("Secondary audio source failed: {:?}", e);
+        }
+    }
+    read?;
+    if let Some(mixer) = mixer.as_mut() {
+        encode(mixer.flush()?, &mut encoder)?;
+    }
+    // Хвост короче кадра кодеры AAC и Opus принимают только последним.
+    if let Some(mut frame) = fifo.pop_remaining(layout, rate) {
+        frame.set_pts(next_pts);
+        encoder
+            .send_frame(&frame)
+            .map_err(|e| anyhow::anyhow!("Error sending audio frame to encoder: {:?}", e))?;
+    }
+    encoder
+        .send_eof()
+        .map_err(|e| anyhow::anyhow!("Error sending EOF to audio encoder: {:?}", e))?;
+    send_ready(&mut encoder)
+}
+
+/// Второй источник звука: чтение устройства блокируется, поэтому идёт в своём
+/// потоке, а декодированные кадры забирает поток основного источника.
+struct SecondaryReader {
+    frames: Receiver<ffmpeg::frame::Audio>,
+    handle: JoinHandle<Result<()>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl SecondaryReader {
+    fn start(mut input: AudioInput) -> Self {
+        let (sender, frames) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        let handle = thread::spawn(move || {
+            input.read(&stop_clone, |frame| {
+                // Основной поток уже закончил — кадры больше некуда отдавать.
+                let _ = sender.send(frame.clone());
+                Ok(())
+            })
+        });
+        Self {
+            frames,
+            handle,
+            stop,
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle
+            .join()
+            .map_err(|_| anyhow::anyhow!("Secondary audio thread panicked"))?
+    }
+}
+
+/// Буфер f32-сэмплов: кодер принимает кадры строго по `frame_size`
+/// сэмплов, а устройство отдаёт блоки произвольной длины. Планарные сэмплы
+/// лежат по буферу на канал, чередующиеся — в одном буфере.
+struct SampleFifo {
+    format: ffmpeg::format::Sample,
+    /// Сколько байт приходится на один сэмпл в каждом буфере.
+    sample_bytes: usize,
+    planes: Vec<Vec<u8>>,
+}
+
+impl SampleFifo {
+    fn new(format: ffmpeg::format::Sample, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let (planes, sample_bytes) = if format.is_planar() {
+            (channels, format.bytes())
+        } else {
+            (1, format.bytes() * channels)
+        };
+        Self {
+            format,
+            sample_bytes,
+            planes: vec![Vec::new(); planes],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.planes[0].len() / self.sample_bytes
+    }
+
+    fn push(&mut self, frame: &ffmpeg::frame::Audio) {
+        let bytes = frame.samples() * self.sample_bytes;
+        for (plane, buffer) in self.planes.iter_mut().enumerate() {
+            buffer.extend_from_slice(&frame.data(plane)[..bytes]);
+        }
+    }
+
+    fn pop(
+        &mut self,
+        samples: usize,
+        layout: ffmpeg::ChannelLayout,
+        rate: u32,
+    ) -> Option<ffmpeg::frame::Audio> {
+        if self.len() < samples {
+            return None;
+        }
+        Some(self.take(samples, layout, rate))
+    }
+
+    fn pop_remaining(
+        &mut self,
+        layout: ffmpeg::ChannelLayout,
+        rate: u32,
+    ) -> Option<ffmpeg::frame::Audio> {
+        let samples = self.len();
+        if samples == 0 {
+            return None;
+        }
+        Some(self.take(samples, layout, rate))
+    }
+
+    fn take(
+        &mut self,
+        samples: usize,
+        layout: ffmpeg::ChannelLayout,
+        rate: u32,
+    ) -> ffmpeg::frame::Audio {
+        let mut frame = ffmpeg::frame::Audio::new(self.format, samples, layout);
+        frame.set_rate(rate);
+        let bytes = samples * self.sample_bytes;
+        for (plane, buffer) in self.planes.iter_mut().enumerate() {
+            frame.data_mut(plane)[..bytes].copy_from_slice(&buffer[..bytes]);
+            buffer.drain(..bytes);
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Выгребает готовые пакеты кодера в поток `output` контейнера.
+    fn write_packets(output: &mut AudioOutput, octx: &mut ffmpeg::format::context::Output) {
+        let stream_time_base = octx.stream(output.stream_index).unwrap().time_base();
+        let mut packet = ffmpeg::Packet::empty();
+        while output.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(output.stream_index);
+            packet.rescale_ts(output.time_base, stream_time_base);
+            packet.write_interleaved(octx).unwrap();
+        }
+    }
+
+    #[test]
+    fn muxes_flac_into_mkv() {
+        ffmpeg::init().unwrap();
+        let path = std::env::temp_dir().join(format!("rscap-test-{}.mkv", uuid::Uuid::new_v4()));
+        let input = AudioFormat {
+            sample: PACKED_S16,
+            layout: ffmpeg::ChannelLayout::STEREO,
+            rate: 48000,
+        };
+        let mut octx = ffmpeg::format::output(&path).unwrap();
+        let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+        let mut output = AudioOutput::add_to(
+            &mut octx,
+            input,
+            AudioCodec::Flac,
+            DEFAULT_AUDIO_BITRATE_KBPS,
+            DEFAULT_OPUS_APPLICATION,
+            global_header,
+        )
+        .unwrap();
+        // Захваченные s16 идут к FLAC без перевода в другой формат.
+        assert_eq!(output.sample_format, PACKED_S16);
+        octx.write_header().unwrap();
+
+        // Полсекунды пилообразного сигнала кадрами по frame_size сэмплов.
+        let frame_size = (output.encoder.frame_size() as usize).max(1);
+        let mut pts = 0;
+        while pts < 24_000 {
+            let mut frame = ffmpeg::frame::Audio::new(PACKED_S16, frame_size, input.layout);
+            frame.set_rate(input.rate);
+            for (i, sample) in frame.data_mut(0).chunks_exact_mut(2).enumerate() {
+                sample.copy_from_slice(&((i as i16).wrapping_mul(64)).to_le_bytes());
+            }
+            frame.set_pts(Some(pts));
+            output.encoder.send_frame(&frame).unwrap();
+            write_packets(&mut output, &mut octx);
+            pts += frame_size as i64;
+        }
+        output.encoder.send_eof().unwrap();
+        write_packets(&mut output, &mut octx);
+        octx.write_trailer().unwrap();
+        drop(octx);
+
+        let mut ictx = ffmpeg::format::input(&path).unwrap();
+        let stream = ictx.streams().best(ffmpeg::media::Type::Audio).unwrap();
+        let codec_id = stream.parameters().id();
+        let packets = ictx.packets().count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(codec_id, ffmpeg::codec::Id::FLAC);
+        assert!(packets > 0);
+    }
+
+    #[test]
+    fn every_codec_has_a_separate_container() {
+        ffmpeg::init().unwrap();
+        let input = AudioFormat {
+            sample: PACKED_S16,
+            layout: ffmpeg::ChannelLayout::STEREO,
+            rate: 48000,
+        };
+        for codec in [AudioCodec::Aac, AudioCodec::Opus, AudioCodec::Flac] {
+            if codec.find_encoder().is_err() {
+                continue;
+            }
+            let (extension, muxer) = codec.split_container();
+            let path = std::env::temp_dir()
+                .join(format!("rscap-test-{}.{}", uuid::Uuid::new_v4(), extension));
+            let mut octx = ffmpeg::format::output_as(&path, muxer).unwrap();
+            let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+            AudioOutput::add_to(
+                &mut octx,
+                input,
+                codec,
+                DEFAULT_AUDIO_BITRATE_KBPS,
+                DEFAULT_OPUS_APPLICATION,
+                global_header,
+            )
+            .unwrap();
+            let header = octx.write_header();
+            drop(octx);
+            std::fs::remove_file(&path).unwrap();
+            assert!(header.is_ok(), "{:?} does not mux into {}", codec, muxer);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// src/audio_meter.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tracing::warn;
+
+use crate::audio_capture;
+use crate::resample::{AudioConverter, AudioFormat};
+
+/// Нижняя граница шкалы индикатора в dBFS: всё, что тише, считается тишиной.
+pub const METER_FLOOR_DB: f32 = -60.0;
+
+/// Уровни звука для одного блока сэмплов (линейная шкала 0.0..=1.0).
+#[derive(Debug, Clone, Copy)]
+pub struct AudioLevel {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl AudioLevel {
+    /// Переводит линейный уровень в dBFS, ограничивая снизу `METER_FLOOR_DB`.
+    pub fn to_db(value: f32) -> f32 {
+        if value <= 0.0 {
+            METER_FLOOR_DB
+        } else {
+            (20.0 * value.log10()).max(METER_FLOOR_DB)
+        }
+    }
+
+    /// Положение индикатора 0.0..=1.0 для уровня в dBFS.
+    pub fn to_meter(value: f32) -> f64 {
+        ((Self::to_db(value) - METER_FLOOR_DB) / -METER_FLOOR_DB) as f64
+    }
+}
+
+/// Запускает фоновый поток, который читает звук с устройства `device`
+/// и отправляет уровни RMS/peak в `sender` (обычно это glib-канал GUI).
+/// Возвращает флаг, установка которого останавливает поток.
+pub fn spawn_level_monitor(device: String, sender: glib::Sender<AudioLevel>) -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_clone = stop.clone();
+    thread::spawn(move || {
+        if let Err(e) = run_monitor(&device, &sender, &stop_clone) {
+            warn!("Audio level monitor for '{}' stopped: {:?}", device, e);
+        }
+    });
+    stop
+}
+
+fn run_monitor(device: &str, sender: &glib::Sender<AudioLevel>, stop: &AtomicBool) -> Result<()> {
+    ffmpeg::init().map_err(|e| anyhow::anyhow!("FFmpeg init error: {:?}", e))?;
+    let device = audio_capture::resolve_device(device);
+    let mut ictx = ffmpeg::format::input_with_format(&device, "pulse")
+        .map_err(|e| anyhow::anyhow!("Failed to open audio device '{}': {:?}", device, e))?;
+
+    let input_stream = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Audio)
+        .ok_or_else(|| anyhow::anyhow!("No audio stream found on device '{}'", device))?;
+    let input_index = input_stream.index();
+    let mut decoder = input_stream
+        .codec()
+        .decoder()
+        .audio()
+        .map_err(|e| anyhow::anyhow!("Failed to open audio decoder: {:?}", e))?;
+
+    // Приводим сэмплы к f32 (interleaved), чтобы считать уровни одним способом
+    // независимо от формата устройства, в том числе если он сменится на ходу.
+    let mut converter = AudioConverter::new(AudioFormat {
+        sample: ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed),
+        layout: decoder.channel_layout(),
+        rate: decoder.rate(),
+    });
+
+    let mut frame = ffmpeg::frame::Audio::empty();
+    for (stream, packet) in ictx.packets() {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        if stream.index() != input_index {
+            continue;
+        }
+        decoder
+            .send_packet(&packet)
+            .map_err(|e| anyhow::anyhow!("Error sending audio packet to decoder: {:?}", e))?;
+        while decoder.receive_frame(&mut frame).is_ok() {
+            let converted = converter.convert(&frame)?;
+            let level = compute_level(converted.data(0));
+            // GUI закрыто — мониторить больше некому.
+            if sender.send(level).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Считает RMS и пиковый уровень по interleaved f32-сэмплам.
+fn compute_level(data: &[u8]) -> AudioLevel {
+    let mut sum = 0.0f64;
+    let mut peak = 0.0f32;
+    let mut count = 0usize;
+    for chunk in data.chunks_exact(4) {
+        let sample = f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]).abs();
+        sum += (sample as f64) * (sample as f64);
+        peak = peak.max(sample);
+        count += 1;
+    }
+    let rms = if count > 0 { (sum / count as f64).sqrt() as f32 } else { 0.0 };
+    AudioLevel {
+        rms: rms.min(1.0),
+        peak: peak.min(1.0),
+    }
+}
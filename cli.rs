@@ -0,0 +1,222 @@
+// src/cli.rs
+
+use clap::builder::PossibleValuesParser;
+use clap::Parser;
+
+use crate::audio_capture::{self, DEFAULT_AUDIO_BITRATE_KBPS, DEFAULT_OPUS_APPLICATION};
+use crate::crop::CropRect;
+use crate::formats::{self, VideoCodec};
+use crate::gpu;
+use crate::gui::{RecordParams, DEFAULT_KEYFRAME_INTERVAL_SECS};
+use crate::naming::DEFAULT_FILENAME_TEMPLATE;
+use crate::portal;
+use crate::rate_control::DEFAULT_CRF;
+use crate::storage;
+
+/// Запуск без GTK: для серверов без дисплея, CI и скриптов.
+/// Портал ScreenCast и PipeWire по-прежнему нужны, окно — нет.
+#[derive(Debug, Parser)]
+#[command(name = "rscap", about = "Record the screen and upload it to OCI Object Storage")]
+pub struct CliArgs {
+    /// Не открывать окно, даже если других аргументов нет
+    #[arg(long)]
+    pub no_gui: bool,
+    /// Только проверить кодер, хранилище и портал, ничего не записывая
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Уровень журнала (error, warn, info, debug, trace или фильтр tracing);
+    /// по умолчанию RUST_LOG, а без неё — info
+    #[arg(long)]
+    pub log_level: Option<String>,
+    /// Bucket, в который выгружается запись; s3://bucket/prefix или
+    /// oci://bucket/prefix выбирают хранилище сами, prefix добавляется к имени
+    #[arg(long)]
+    pub bucket: String,
+    /// Хранилище: oci (переменные OCI_*), s3 (S3_ENDPOINT, S3_REGION, AWS_*)
+    /// или local (--bucket — путь к папке)
+    #[arg(
+        long,
+        default_value = "oci",
+        value_parser = PossibleValuesParser::new(storage::BACKENDS.iter().copied())
+    )]
+    pub backend: String,
+    /// Имя объекта без расширения; {date}, {time}, {timestamp} и strftime
+    /// (%Y-%m-%d_%H-%M-%S) раскрываются временем начала записи, {uuid} — случайным
+    /// UUID, {n} — следующим свободным номером в bucket
+    #[arg(long, visible_alias = "filename", default_value = DEFAULT_FILENAME_TEMPLATE)]
+    pub filename_template: String,
+    /// Контейнер записи; по умолчанию mp4, для VP9 и AV1 — webm
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(formats::CONTAINERS.iter().copied())
+    )]
+    pub container: Option<String>,
+    /// Видеокодек; по умолчанию H264, для webm — VP9
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(formats::VIDEO_CODECS.iter().copied())
+    )]
+    pub video_codec: Option<String>,
+    /// Аппаратное кодирование; если VAAPI или NVENC не запустится, пишет программный кодер
+    #[arg(
+        long,
+        default_value = "none",
+        value_parser = PossibleValuesParser::new(gpu::HW_ACCELS.iter().copied())
+    )]
+    pub hw_accel: String,
+    /// Пресет libx264
+    #[arg(
+        long,
+        default_value = formats::DEFAULT_X264_PRESET,
+        value_parser = PossibleValuesParser::new(formats::X264_PRESETS.iter().copied())
+    )]
+    pub x264_preset: String,
+    /// Tune libx264 (zerolatency, film...)
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(formats::X264_TUNES.iter().copied())
+    )]
+    pub x264_tune: Option<String>,
+    /// Качество для --encoding-mode CRF (0 — лучшее, 51 — худшее)
+    #[arg(long, default_value_t = DEFAULT_CRF)]
+    pub crf: u32,
+    /// Интервал ключевых кадров в секундах
+    #[arg(long, default_value_t = DEFAULT_KEYFRAME_INTERVAL_SECS)]
+    pub keyframe_interval: u32,
+    /// Точный размер GOP в кадрах вместо --keyframe-interval
+    #[arg(long)]
+    pub gop_size: Option<u32>,
+    /// Битрейт видео в кбит/с
+    #[arg(long, default_value_t = 1000)]
+    pub bitrate: u32,
+    /// Ширина записи; без --out-height высота выводится из пропорций источника
+    #[arg(long)]
+    pub out_width: Option<u32>,
+    /// Высота записи; без --out-width ширина выводится из пропорций источника
+    #[arg(long)]
+    pub out_height: Option<u32>,
+    /// Режим кодирования: CBR, VBR, CRF, Lossless или Near-lossless
+    #[arg(long, default_value = "CBR")]
+    pub encoding_mode: String,
+    /// Какие источники предлагать в диалоге портала
+    #[arg(
+        long,
+        default_value = "any",
+        value_parser = PossibleValuesParser::new(portal::SOURCE_TYPES.iter().copied())
+    )]
+    pub source: String,
+    /// Курсор мыши в записи
+    #[arg(
+        long,
+        default_value = "embedded",
+        value_parser = PossibleValuesParser::new(portal::CURSOR_MODES.iter().copied())
+    )]
+    pub cursor_mode: String,
+    /// Узел PipeWire из ответа портала, если выбрано несколько источников
+    /// (список узлов печатается при старте); по умолчанию — первый
+    #[arg(long)]
+    pub node_id: Option<u32>,
+    /// Остановить запись через столько секунд (0 — без ограничения)
+    #[arg(long)]
+    pub max_duration: Option<u64>,
+    /// Записывать только прямоугольник источника: x,y,width,height в пикселях
+    #[arg(long, value_parser = CropRect::parse)]
+    pub crop: Option<CropRect>,
+    /// Писать в локальный файл и выгружать после завершения записи
+    /// (обычный, не фрагментированный mp4)
+    #[arg(long)]
+    pub upload_after_finish: bool,
+    /// Выгружать видео и звук отдельными объектами с одним базовым именем
+    #[arg(long)]
+    pub split_audio: bool,
+    /// Сколько раз повторять временный сбой выгрузки (сеть, 429, 5xx)
+    #[arg(long, default_value_t = storage::DEFAULT_MAX_RETRIES)]
+    pub upload_retries: u32,
+    /// Устройство захвата звука; пустая строка — без звука
+    #[arg(long, default_value = "default")]
+    pub audio_device: String,
+    /// Второе устройство, сводимое с первым (например, микрофон к монитору
+    /// рабочего стола); пустая строка — один источник
+    #[arg(long, default_value = "")]
+    pub audio_device_secondary: String,
+    /// Громкость первого источника в процентах
+    #[arg(long, default_value_t = 100)]
+    pub audio_gain: u32,
+    /// Громкость второго источника в процентах
+    #[arg(long, default_value_t = 100)]
+    pub audio_secondary_gain: u32,
+    /// Битрейт звука (кбит/с)
+    #[arg(long, default_value_t = DEFAULT_AUDIO_BITRATE_KBPS)]
+    pub audio_bitrate: u32,
+    /// Кодек звука; auto — Opus в webm, иначе AAC; FLAC (без потерь) — только в mkv
+    #[arg(
+        long,
+        default_value = "auto",
+        value_parser = PossibleValuesParser::new(audio_capture::AUDIO_CODECS.iter().copied())
+    )]
+    pub audio_codec: String,
+    /// Режим libopus: audio для звука рабочего стола, voip для речи
+    #[arg(
+        long,
+        default_value = DEFAULT_OPUS_APPLICATION,
+        value_parser = PossibleValuesParser::new(audio_capture::OPUS_APPLICATIONS.iter().copied())
+    )]
+    pub opus_application: String,
+}
+
+impl CliArgs {
+    /// Запускать ли CLI вместо GUI: любой аргумент командной строки
+    /// (GTK своих аргументов от rscap не ждёт).
+    pub fn requested() -> bool {
+        std::env::args_os().len() > 1
+    }
+
+    /// Параметры записи; остальные поля — как в только что открытой форме.
+    pub fn into_params(self) -> RecordParams {
+        let container = self.container.unwrap_or_else(|| {
+            let codec = self.video_codec.as_deref().and_then(|codec| VideoCodec::parse(codec).ok());
+            codec.map_or("mp4", VideoCodec::default_container).to_string()
+        });
+        let video_codec = self.video_codec.unwrap_or_else(|| {
+            VideoCodec::default_for_container(&container)
+                .map_or_else(|| "H264".to_string(), |codec| format!("{:?}", codec))
+        });
+        RecordParams {
+            output_folder: self.bucket,
+            backend: self.backend,
+            filename_template: self.filename_template,
+            container,
+            video_codec,
+            hw_accel: self.hw_accel,
+            bitrate: self.bitrate,
+            keyframe_interval_secs: self.keyframe_interval,
+            gop_size: self.gop_size,
+            x264_preset: self.x264_preset,
+            x264_tune: self.x264_tune.unwrap_or_default(),
+            out_width: self.out_width,
+            out_height: self.out_height,
+            encoding_mode: self.encoding_mode,
+            crf: self.crf,
+            audio_device: self.audio_device,
+            audio_device_secondary: self.audio_device_secondary,
+            audio_gain_percent: self.audio_gain,
+            audio_secondary_gain_percent: self.audio_secondary_gain,
+            audio_bitrate: self.audio_bitrate,
+            audio_codec: self.audio_codec,
+            opus_application: self.opus_application,
+            source_types: self.source,
+            cursor_mode: self.cursor_mode,
+            node_id: self.node_id,
+            // 0 — без ограничения, как в GUI.
+            max_duration_secs: self.max_duration.filter(|secs| *secs > 0),
+            crop_x: self.crop.map_or(0, |rect| rect.x),
+            crop_y: self.crop.map_or(0, |rect| rect.y),
+            crop_w: self.crop.map_or(0, |rect| rect.width),
+            crop_h: self.crop.map_or(0, |rect| rect.height),
+            upload_retries: self.upload_retries,
+            upload_after_finish: self.upload_after_finish,
+            split_audio: self.split_audio,
+            ..RecordParams::default()
+        }
+    }
+}
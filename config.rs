@@ -0,0 +1,96 @@
+// src/config.rs
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::paths;
+use crate::presets::Preset;
+
+/// Файл настроек формы: $XDG_CONFIG_HOME/rscap/settings.toml.
+const SETTINGS_FILE: &str = "settings.toml";
+
+pub fn settings_path() -> Result<PathBuf> {
+    Ok(paths::config_dir()?.join(SETTINGS_FILE))
+}
+
+/// Форма при последнем старте записи. Нет файла — None, и GUI открывается
+/// со значениями по умолчанию; испорченный файл — тоже None, с предупреждением.
+pub fn load_settings() -> Option<Preset> {
+    let result = settings_path().and_then(|path| {
+        if path.exists() {
+            read_settings(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    });
+    result.unwrap_or_else(|e| {
+        warn!("Ignoring saved settings: {:?}", e);
+        None
+    })
+}
+
+/// Сохраняет форму; вызывается при старте записи.
+pub fn save_settings(settings: &Preset) -> Result<()> {
+    paths::write_atomically(&settings_path()?, &to_toml(settings)?)
+}
+
+fn read_settings(path: &Path) -> Result<Preset> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    from_toml(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn to_toml(settings: &Preset) -> Result<String> {
+    toml::to_string_pretty(settings).context("Failed to serialize settings")
+}
+
+fn from_toml(text: &str) -> Result<Preset> {
+    Ok(toml::from_str(text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_control::TargetSize;
+
+    #[test]
+    fn settings_survive_a_round_trip() {
+        let settings = Preset {
+            output_folder: "oci://recordings/demo".to_string(),
+            filename_template: "rec-{date}-{n}".to_string(),
+            bitrate: 6000,
+            target_size: Some(TargetSize {
+                size_mb: 100,
+                duration_secs: 600,
+            }),
+            object_metadata: vec![("project".to_string(), "rscap".to_string())],
+            render_node: Some("/dev/dri/renderD129".to_string()),
+            max_duration_secs: Some(3600),
+            preview: true,
+            ..Preset::default()
+        };
+        let restored = from_toml(&to_toml(&settings).unwrap()).unwrap();
+        assert_eq!(restored.output_folder, settings.output_folder);
+        assert_eq!(restored.filename_template, settings.filename_template);
+        assert_eq!(restored.bitrate, 6000);
+        assert_eq!(restored.target_size, settings.target_size);
+        assert_eq!(restored.object_metadata, settings.object_metadata);
+        assert_eq!(restored.render_node, settings.render_node);
+        assert_eq!(restored.max_duration_secs, Some(3600));
+        assert!(restored.preview);
+    }
+
+    #[test]
+    fn missing_fields_get_defaults() {
+        let restored = from_toml("bitrate = 2500\n").unwrap();
+        assert_eq!(restored.bitrate, 2500);
+        assert_eq!(restored.filename_template, "");
+        assert_eq!(restored.target_size, None);
+    }
+
+    #[test]
+    fn malformed_file_is_an_error() {
+        assert!(from_toml("bitrate = \"fast\"\n").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+// src/control.rs
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::paths;
+
+/// Метка, поставленная внешней командой, с временем от начала записи.
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub time_ms: i64,
+    pub label: String,
+}
+
+/// Управляющий сокет для автоматизации во время записи.
+///
+/// Протокол строковый, одна команда на строку (UTF-8, `\n`):
+/// - `MARK <label>` — поставить метку на текущей позиции; ответ `OK <ms>`;
+/// - `PING` — проверка связи; ответ `PONG`.
+/// На неизвестную команду приходит `ERR <описание>`. Метки записываются
+/// в контейнер главами при завершении записи.
+pub struct ControlServer {
+    path: PathBuf,
+    markers: Arc<Mutex<Vec<Marker>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ControlServer {
+    /// Путь сокета для очередной записи процесса:
+    /// $XDG_RUNTIME_DIR/rscap-control-<pid>-<n>.sock, где n — номер записи с 1.
+    /// У одновременных записей сокеты разные, и ни одна не удаляет чужой.
+    pub fn next_path() -> PathBuf {
+        static RECORDINGS: AtomicU32 = AtomicU32::new(0);
+        let n = RECORDINGS.fetch_add(1, Ordering::Relaxed) + 1;
+        paths::runtime_dir().join(format!("rscap-control-{}-{}.sock", std::process::id(), n))
+    }
+
+    /// Запускает сервер. `position_ms` — текущая позиция записи в миллисекундах,
+    /// её обновляет поток кодирования.
+    pub fn start(path: PathBuf, position_ms: Arc<AtomicI64>) -> Result<Self> {
+        // Сокет от прошлого запуска, завершившегося аварийно, мешает bind;
+        // сокет, на котором кто-то слушает, не трогаем.
+        if UnixStream::connect(&path).is_ok() {
+            anyhow::bail!("Control socket {} is in use by another recording", path.display());
+        }
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        info!("Control socket listening on {}", path.display());
+
+        let markers = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let markers_clone = markers.clone();
+        let stop_clone = stop.clone();
+        thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let markers = markers_clone.clone();
+                        let position_ms = position_ms.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = handle_client(stream, &markers, &position_ms) {
+                                warn!("Control client error: {:?}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        warn!("Control socket accept failed: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            path,
+            markers,
+            stop,
+        })
+    }
+
+    /// Метки, поставленные на данный момент, в порядке поступления.
+    pub fn markers(&self) -> Vec<Marker> {
+        self.markers.lock().unwrap().clone()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn handle_client(
+    stream: UnixStream,
+    markers: &Mutex<Vec<Marker>>,
+    position_ms: &AtomicI64,
+) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let line = line.trim();
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+        match command.to_ascii_uppercase().as_str() {
+            "MARK" => {
+                let time_ms = position_ms.load(Ordering::Relaxed);
+                let label = if argument.is_empty() {
+                    format!("Marker {}", markers.lock().unwrap().len() + 1)
+                } else {
+                    argument.to_string()
+                };
+                info!("Marker '{}' at {} ms", label, time_ms);
+                markers.lock().unwrap().push(Marker { time_ms, label });
+                writeln!(writer, "OK {}", time_ms)?;
+            }
+            "PING" => writeln!(writer, "PONG")?,
+            "" => {}
+            other => writeln!(writer, "ERR unknown command '{}'", other)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_recording_gets_its_own_socket() {
+        assert_ne!(ControlServer::next_path(), ControlServer::next_path());
+    }
+
+    #[test]
+    fn a_live_socket_is_not_taken_over() {
+        let path = std::env::temp_dir().join(format!("rscap-test-{}.sock", uuid::Uuid::new_v4()));
+        let first = ControlServer::start(path.clone(), Arc::new(AtomicI64::new(0))).unwrap();
+        assert!(ControlServer::start(path.clone(), Arc::new(AtomicI64::new(0))).is_err());
+        assert!(path.exists());
+        drop(first);
+        assert!(!path.exists());
+    }
+}
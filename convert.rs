@@ -0,0 +1,132 @@
+// src/convert.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::software::scaling;
+use tracing::info;
+
+/// Формат видеокадра: пиксели и размеры.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoFormat {
+    pub pixel: ffmpeg::format::Pixel,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl VideoFormat {
+    pub fn of_frame(frame: &ffmpeg::frame::Video) -> Self {
+        Self {
+            pixel: frame.format(),
+            width: frame.width(),
+            height: frame.height(),
+        }
+    }
+}
+
+/// Приводит кадры к формату кодера (swscale).
+///
+/// PipeWire отдаёт BGRx, RGBA или NV12 в зависимости от композитора, а кодер
+/// ждёт строго свой формат; какой именно придёт, известно только по первому
+/// кадру. Поэтому масштабировщик создаётся лениво и пересоздаётся, если формат
+/// входа поменялся посреди записи. Кадры уже нужного формата не копируются.
+/// Если отличается и размер, кадр масштабируется тем же проходом (Lanczos).
+pub struct VideoConverter {
+    output: VideoFormat,
+    input: Option<VideoFormat>,
+    context: Option<scaling::Context>,
+}
+
+impl VideoConverter {
+    pub fn new(output: VideoFormat) -> Self {
+        Self {
+            output,
+            input: None,
+            context: None,
+        }
+    }
+
+    /// Переводит кадр в формат кодера, сохраняя PTS и тип кадра.
+    pub fn convert(&mut self, frame: ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
+        let input = VideoFormat::of_frame(&frame);
+        if input == self.output {
+            return Ok(frame);
+        }
+        if self.input != Some(input) || self.context.is_none() {
+            info!("Converting video from {:?} to {:?}", input, self.output);
+            let flags = if (input.width, input.height) == (self.output.width, self.output.height) {
+                scaling::Flags::BILINEAR
+            } else {
+                scaling::Flags::LANCZOS
+            };
+            self.context = Some(
+                scaling::Context::get(
+                    input.pixel,
+                    input.width,
+                    input.height,
+                    self.output.pixel,
+                    self.output.width,
+                    self.output.height,
+                    flags,
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to create video converter: {:?}", e))?,
+            );
+            self.input = Some(input);
+        }
+        let mut converted = ffmpeg::frame::Video::empty();
+        self.context
+            .as_mut()
+            .expect("converter is created above")
+            .run(&frame, &mut converted)
+            .map_err(|e| anyhow::anyhow!("Error converting video frame: {:?}", e))?;
+        converted.set_pts(frame.pts());
+        converted.set_kind(frame.kind());
+        Ok(converted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffmpeg::format::Pixel;
+
+    fn bgrx_frame(width: u32, height: u32) -> ffmpeg::frame::Video {
+        let mut frame = ffmpeg::frame::Video::new(Pixel::BGRZ, width, height);
+        frame.data_mut(0).fill(0x80);
+        frame.set_pts(Some(42));
+        frame
+    }
+
+    fn yuv420p(width: u32, height: u32) -> VideoFormat {
+        VideoFormat {
+            pixel: Pixel::YUV420P,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn converts_bgrx_to_encoder_format() {
+        let mut converter = VideoConverter::new(yuv420p(64, 48));
+        let converted = converter.convert(bgrx_frame(64, 48)).unwrap();
+        assert_eq!(VideoFormat::of_frame(&converted), yuv420p(64, 48));
+        assert_eq!(converted.pts(), Some(42));
+    }
+
+    #[test]
+    fn scales_to_encoder_size() {
+        let mut converter = VideoConverter::new(yuv420p(32, 24));
+        let converted = converter.convert(bgrx_frame(64, 48)).unwrap();
+        assert_eq!(VideoFormat::of_frame(&converted), yuv420p(32, 24));
+    }
+
+    #[test]
+    fn reuses_the_scaler_and_follows_format_changes() {
+        let mut converter = VideoConverter::new(yuv420p(64, 48));
+        converter.convert(bgrx_frame(64, 48)).unwrap();
+        converter.convert(bgrx_frame(64, 48)).unwrap();
+        assert_eq!(converter.input, Some(VideoFormat::of_frame(&bgrx_frame(64, 48))));
+        let converted = converter.convert(bgrx_frame(128, 96)).unwrap();
+        assert_eq!(VideoFormat::of_frame(&converted), yuv420p(64, 48));
+        assert_eq!(converter.input.map(|input| input.width), Some(128));
+    }
+}
@@ -0,0 +1,90 @@
+// src/crop.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+
+use crate::filters::{VideoGraph, VideoInput};
+
+/// Прямоугольник кадрирования в пикселях источника.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    /// Разбирает "x,y,width,height" из командной строки.
+    pub fn parse(text: &str) -> Result<Self> {
+        let parts = text
+            .split(',')
+            .map(|part| part.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Crop '{}' has an invalid number: {:?}", text, e))?;
+        match parts[..] {
+            [x, y, width, height] => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => anyhow::bail!("Crop '{}' must look like x,y,width,height", text),
+        }
+    }
+}
+
+/// Запись части экрана: фильтр crop поверх полного кадра источника.
+pub struct CropFilter {
+    graph: VideoGraph,
+    output: VideoInput,
+}
+
+impl CropFilter {
+    /// Прямоугольник должен целиком лежать в кадре источника: размер кадра
+    /// известен только после открытия потока, поэтому проверка здесь, а не в форме.
+    pub fn new(rect: CropRect, input: VideoInput) -> Result<Self> {
+        if rect.width == 0 || rect.height == 0 {
+            anyhow::bail!(
+                "Crop {}x{}+{}+{} needs a non-zero width and height",
+                rect.width,
+                rect.height,
+                rect.x,
+                rect.y
+            );
+        }
+        if rect.x as u64 + rect.width as u64 > input.width as u64
+            || rect.y as u64 + rect.height as u64 > input.height as u64
+        {
+            anyhow::bail!(
+                "Crop {}x{}+{}+{} does not fit into the {}x{} source; \
+                 reduce the size or move the origin",
+                rect.width,
+                rect.height,
+                rect.x,
+                rect.y,
+                input.width,
+                input.height
+            );
+        }
+        let spec = format!("crop={}:{}:{}:{}:exact=1", rect.width, rect.height, rect.x, rect.y);
+        let graph = VideoGraph::new(input, &spec)?;
+        Ok(Self {
+            graph,
+            output: VideoInput {
+                width: rect.width,
+                height: rect.height,
+                ..input
+            },
+        })
+    }
+
+    /// Параметры кадров после кадрирования — вход следующего этапа.
+    pub fn output(&self) -> VideoInput {
+        self.output
+    }
+
+    pub fn apply(&mut self, frame: &ffmpeg::frame::Video) -> Result<Vec<ffmpeg::frame::Video>> {
+        self.graph.apply(frame)
+    }
+}
@@ -0,0 +1,276 @@
+// src/damage.rs
+
+use anyhow::{Context, Result};
+use pipewire as pw;
+use pw::spa;
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{ChoiceValue, Object, Pod, Property, Value};
+use pw::spa::utils::{Choice, ChoiceEnum, ChoiceFlags, Id};
+use std::io::Cursor;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use tracing::{info, warn};
+
+/// Сколько областей damage композитор может передать в одном буфере.
+/// Если изменений больше, он сводит их в меньшее число областей.
+const MAX_DAMAGE_REGIONS: usize = 16;
+
+/// Счётчик буферов с damage: растёт на единицу с каждым буфером, в котором
+/// композитор сообщил об изменённых областях.
+pub type DamageCount = Arc<AtomicU64>;
+
+/// Читает метаданные SPA_META_VideoDamage потока портала.
+///
+/// FFmpeg-вход pipewire их не передаёт, поэтому монитор подключается к тому же
+/// узлу вторым потребителем: данные кадров он не отображает в память и не
+/// копирует, а только считает буферы, в которых композитор отметил изменения.
+/// Буфер без метаданных damage считается изменённым целиком.
+pub struct DamageMonitor {
+    count: DamageCount,
+    quit: pw::channel::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DamageMonitor {
+    /// Подключается к узлу `node_id` через удалённый PipeWire портала `remote_fd`.
+    /// Дескриптор дублируется: вызывающая сторона остаётся его владельцем.
+    pub fn start(remote_fd: RawFd, node_id: u32) -> Result<Self> {
+        let fd = unsafe { libc::dup(remote_fd) };
+        if fd < 0 {
+            anyhow::bail!("Failed to duplicate the PipeWire remote for damage tracking");
+        }
+        let count = DamageCount::default();
+        let thread_count = count.clone();
+        let (quit, quit_receiver) = pw::channel::channel();
+        let (started, started_receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            // Объекты PipeWire привязаны к потоку своего цикла: создаются здесь же.
+            let result = run_monitor(fd, node_id, thread_count, quit_receiver, &started);
+            if let Err(e) = result {
+                // Если запуск уже подтверждён, получатель ошибку не ждёт.
+                if let Err(mpsc::SendError(Err(e))) = started.send(Err(e)) {
+                    warn!("Damage tracking stopped: {:?}", e);
+                }
+            }
+        });
+        match started_receiver.recv() {
+            Ok(Ok(())) => {
+                info!("Tracking compositor damage on node {}", node_id);
+                Ok(Self {
+                    count,
+                    quit,
+                    handle: Some(handle),
+                })
+            }
+            Ok(Err(e)) => {
+                let _ = handle.join();
+                Err(e)
+            }
+            Err(_) => {
+                let _ = handle.join();
+                Err(anyhow::anyhow!("Damage tracking thread exited during startup"))
+            }
+        }
+    }
+
+    /// Счётчик буферов с damage; читается потоком захвата.
+    pub fn count(&self) -> DamageCount {
+        self.count.clone()
+    }
+}
+
+impl Drop for DamageMonitor {
+    fn drop(&mut self) {
+        let _ = self.quit.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("Damage tracking thread panicked");
+            }
+        }
+    }
+}
+
+fn run_monitor(
+    fd: RawFd,
+    node_id: u32,
+    count: DamageCount,
+    quit: pw::channel::Receiver<()>,
+    started: &mpsc::Sender<Result<()>>,
+) -> Result<()> {
+    let mainloop = pw::MainLoop::new().context("Failed to create PipeWire loop")?;
+    let context = pw::Context::new(&mainloop).context("Failed to create PipeWire context")?;
+    // connect_fd забирает дескриптор себе и закроет его вместе с ядром.
+    let core = context
+        .connect_fd(fd, None)
+        .context("Failed to connect to the portal PipeWire remote")?;
+    let stream = pw::stream::Stream::new(
+        &core,
+        "rscap-damage",
+        pw::properties! {
+            *pw::keys::MEDIA_TYPE => "Video",
+            *pw::keys::MEDIA_CATEGORY => "Capture",
+            *pw::keys::MEDIA_ROLE => "Screen",
+        },
+    )
+    .context("Failed to create the damage stream")?;
+    let _listener = stream
+        .add_local_listener_with_user_data(())
+        .param_changed(|stream, _, id, param| {
+            // После согласования формата просим у узла метаданные damage.
+            if param.is_none() || id != spa::param::ParamType::Format.as_raw() {
+                return;
+            }
+            match damage_meta_param() {
+                Ok(bytes) => {
+                    if let Some(pod) = Pod::from_bytes(&bytes) {
+                        if let Err(e) = stream.update_params(&mut [pod]) {
+                            warn!("Failed to request damage metadata: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to build the damage metadata request: {:?}", e),
+            }
+        })
+        .process(move |stream, _| {
+            if let Some(mut buffer) = stream.dequeue_buffer() {
+                // Тип буфера PipeWire не даёт доступа к метаданным: читаем spa_buffer.
+                let damaged = unsafe { buffer_is_damaged((*buffer.__as_raw_ptr()).buffer) };
+                if damaged {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        })
+        .register()
+        .context("Failed to register the damage stream listener")?;
+
+    // Формат не навязывается: достаточно «видео без сжатия», остальное — как у узла.
+    let format = serialize(Value::Object(Object {
+        type_: spa::sys::SPA_TYPE_OBJECT_Format,
+        id: spa::sys::SPA_PARAM_EnumFormat,
+        properties: vec![
+            Property::new(
+                spa::sys::SPA_FORMAT_mediaType,
+                Value::Id(Id(spa::sys::SPA_MEDIA_TYPE_video)),
+            ),
+            Property::new(
+                spa::sys::SPA_FORMAT_mediaSubtype,
+                Value::Id(Id(spa::sys::SPA_MEDIA_SUBTYPE_raw)),
+            ),
+        ],
+    }))?;
+    let mut params = [Pod::from_bytes(&format).context("Invalid format parameter")?];
+    stream
+        .connect(
+            spa::Direction::Input,
+            Some(node_id),
+            pw::stream::StreamFlags::AUTOCONNECT,
+            &mut params,
+        )
+        .context("Failed to connect the damage stream")?;
+
+    let _quit = quit.attach(&mainloop, {
+        let mainloop = mainloop.clone();
+        move |_| mainloop.quit()
+    });
+    let _ = started.send(Ok(()));
+    mainloop.run();
+    Ok(())
+}
+
+/// Параметр SPA_PARAM_Meta с запросом SPA_META_VideoDamage.
+fn damage_meta_param() -> Result<Vec<u8>> {
+    let region = std::mem::size_of::<spa::sys::spa_meta_region>() as i32;
+    serialize(Value::Object(Object {
+        type_: spa::sys::SPA_TYPE_OBJECT_ParamMeta,
+        id: spa::sys::SPA_PARAM_Meta,
+        properties: vec![
+            Property::new(
+                spa::sys::SPA_PARAM_META_type,
+                Value::Id(Id(spa::sys::SPA_META_VideoDamage)),
+            ),
+            Property::new(
+                spa::sys::SPA_PARAM_META_size,
+                Value::Choice(ChoiceValue::Int(Choice(
+                    ChoiceFlags::empty(),
+                    ChoiceEnum::Range {
+                        default: region * MAX_DAMAGE_REGIONS as i32,
+                        min: region,
+                        max: region * MAX_DAMAGE_REGIONS as i32,
+                    },
+                ))),
+            ),
+        ],
+    }))
+}
+
+fn serialize(value: Value) -> Result<Vec<u8>> {
+    Ok(PodSerializer::serialize(Cursor::new(Vec::new()), &value)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize SPA pod: {:?}", e))?
+        .0
+        .into_inner())
+}
+
+/// Сообщил ли композитор об изменениях в буфере. Без метаданных damage
+/// изменённым считается весь буфер; с ними — если есть хоть одна непустая
+/// область (список областей заканчивается областью нулевого размера).
+///
+/// # Safety
+/// `buffer` — spa_buffer из только что извлечённого из очереди pw_buffer.
+unsafe fn buffer_is_damaged(buffer: *const spa::sys::spa_buffer) -> bool {
+    if buffer.is_null() {
+        return false;
+    }
+    let metas = std::slice::from_raw_parts((*buffer).metas, (*buffer).n_metas as usize);
+    let meta = match metas
+        .iter()
+        .find(|meta| meta.type_ == spa::sys::SPA_META_VideoDamage && !meta.data.is_null())
+    {
+        Some(meta) => meta,
+        None => return true,
+    };
+    let capacity = meta.size as usize / std::mem::size_of::<spa::sys::spa_meta_region>();
+    let regions = std::slice::from_raw_parts(meta.data as *const spa::sys::spa_meta_region, capacity);
+    regions_damaged(regions.iter().map(|region| (region.region.size.width, region.region.size.height)))
+}
+
+/// Есть ли среди областей (ширина, высота) до первой пустой хоть одна.
+fn regions_damaged(sizes: impl IntoIterator<Item = (u32, u32)>) -> bool {
+    sizes
+        .into_iter()
+        .take_while(|&(width, height)| width > 0 && height > 0)
+        .next()
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_before_the_terminator_count_as_damage() {
+        assert!(regions_damaged(vec![(10, 4), (0, 0)]));
+        assert!(!regions_damaged(vec![(0, 0), (10, 4)]));
+        assert!(!regions_damaged(Vec::new()));
+    }
+
+    #[test]
+    fn a_buffer_without_damage_metadata_is_damaged() {
+        let mut buffer: spa::sys::spa_buffer = unsafe { std::mem::zeroed() };
+        let mut regions: [spa::sys::spa_meta_region; 2] = unsafe { std::mem::zeroed() };
+        assert!(unsafe { buffer_is_damaged(&buffer) });
+
+        let mut meta = spa::sys::spa_meta {
+            type_: spa::sys::SPA_META_VideoDamage,
+            size: std::mem::size_of_val(&regions) as u32,
+            data: regions.as_mut_ptr() as *mut _,
+        };
+        buffer.n_metas = 1;
+        buffer.metas = &mut meta;
+        assert!(!unsafe { buffer_is_damaged(&buffer) });
+        regions[0].region.size.width = 64;
+        regions[0].region.size.height = 8;
+        assert!(unsafe { buffer_is_damaged(&buffer) });
+    }
+}
@@ -0,0 +1,175 @@
+// src/file_sink.rs
+
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::storage::{StorageSink, UploadProgress};
+
+/// Проверяет, что в папку можно писать: создаёт её при необходимости
+/// и пробный файл, который сразу удаляется.
+pub fn check_folder(folder: &Path) -> Result<()> {
+    fs::create_dir_all(folder)
+        .with_context(|| format!("Failed to create folder {}", folder.display()))?;
+    let probe = folder.join(format!(".rscap-probe-{}", std::process::id()));
+    File::create(&probe).with_context(|| format!("Folder {} is not writable", folder.display()))?;
+    if let Err(e) = fs::remove_file(&probe) {
+        warn!("Failed to remove {}: {:?}", probe.display(), e);
+    }
+    Ok(())
+}
+
+/// Имена файлов в `folder`, начинающиеся с `prefix`, в виде имён объектов
+/// (путь относительно `folder`). Недописанные `*.part` считаются под своим
+/// итоговым именем: их имя тоже занято.
+pub fn list_files(folder: &Path, prefix: &str) -> Result<Vec<String>> {
+    let (dir, name_prefix) = match prefix.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), prefix),
+    };
+    let path = folder.join(&dir);
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to list {}", path.display())),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let name = name.strip_suffix(".part").unwrap_or(&name);
+        if name.starts_with(name_prefix) {
+            names.push(format!("{}{}", dir, name));
+        }
+    }
+    Ok(names)
+}
+
+/// Запись в локальный файл `{folder}/{object_name}` вместо хранилища.
+///
+/// Пока запись идёт, файл называется `*.part` и переименовывается только в
+/// `finalize`, чтобы прерванная запись не выглядела готовой. Недописанный
+/// `.part` не удаляется: фрагментированный mp4 и mkv из него ещё можно спасти.
+pub struct FileSink {
+    path: PathBuf,
+    part_path: PathBuf,
+    file: BufWriter<File>,
+    /// Сопутствующие файлы (миниатюры и т.п.), записываемые рядом с записью.
+    sidecars: Vec<(String, Vec<u8>)>,
+    folder: PathBuf,
+    progress: Arc<UploadProgress>,
+}
+
+impl FileSink {
+    pub fn create(folder: &Path, object_name: &str) -> Result<Self> {
+        let path = folder.join(object_name);
+        let part_path = path.with_file_name(format!(
+            "{}.part",
+            path.file_name().and_then(|name| name.to_str()).unwrap_or(object_name)
+        ));
+        if let Some(parent) = part_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create folder {}", parent.display()))?;
+        }
+        let file = File::create(&part_path)
+            .with_context(|| format!("Failed to create {}", part_path.display()))?;
+        info!("Recording to {}", part_path.display());
+        Ok(Self {
+            path,
+            part_path,
+            file: BufWriter::new(file),
+            sidecars: Vec::new(),
+            folder: folder.to_path_buf(),
+            progress: Arc::default(),
+        })
+    }
+
+    /// Счётчики хода записи; для диска записанное сразу считается выгруженным.
+    pub fn with_progress(mut self, progress: Arc<UploadProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+impl StorageSink for FileSink {
+    fn add_sidecar(&mut self, object_name: &str, data: Vec<u8>) {
+        self.sidecars.push((object_name.to_string(), data));
+    }
+
+    /// Данные уходят на диск сразу, ждать нечего.
+    fn pending_bytes(&self) -> u64 {
+        0
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_all()?;
+        fs::rename(&self.part_path, &self.path)
+            .with_context(|| format!("Failed to rename {} to {}", self.part_path.display(), self.path.display()))?;
+        for (object_name, data) in std::mem::take(&mut self.sidecars) {
+            let path = self.folder.join(&object_name);
+            fs::write(&path, data).with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        info!("Recording saved to {}", self.path.display());
+        Ok(())
+    }
+}
+
+impl Write for FileSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.progress.wrote(written);
+        self.progress.uploaded(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder() -> PathBuf {
+        std::env::temp_dir().join(format!("rscap-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn records_into_the_folder() {
+        let folder = temp_folder();
+        let mut sink = FileSink::create(&folder, "demo/recording.mkv").unwrap();
+        sink.write_all(&[0x1a, 0x45, 0xdf, 0xa3]).unwrap();
+        sink.write_all(&[0u8; 4096]).unwrap();
+        sink.add_sidecar("demo/recording-thumbs.vtt", b"WEBVTT\n".to_vec());
+        assert!(folder.join("demo/recording.mkv.part").exists());
+        sink.finalize().unwrap();
+
+        let path = folder.join("demo/recording.mkv");
+        let size = fs::metadata(&path).unwrap().len();
+        let part_left = folder.join("demo/recording.mkv.part").exists();
+        let sidecar = fs::read(folder.join("demo/recording-thumbs.vtt")).unwrap();
+        let listed = list_files(&folder, "demo/rec").unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(size, 4100);
+        assert!(!part_left);
+        assert_eq!(sidecar, b"WEBVTT\n");
+        assert_eq!(listed.len(), 2);
+        assert!(listed.contains(&"demo/recording.mkv".to_string()));
+    }
+
+    #[test]
+    fn unfinished_recording_keeps_its_final_name_taken() {
+        let folder = temp_folder();
+        let mut sink = FileSink::create(&folder, "rec-1.mp4").unwrap();
+        sink.write_all(b"partial").unwrap();
+        sink.flush().unwrap();
+        let listed = list_files(&folder, "rec-").unwrap();
+        drop(sink);
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(listed, vec!["rec-1.mp4".to_string()]);
+    }
+}
@@ -0,0 +1,206 @@
+// src/filters.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+
+use crate::resample::AudioFormat;
+
+/// Параметры кадров, поступающих на вход графа.
+#[derive(Debug, Clone, Copy)]
+pub struct VideoInput {
+    pub width: u32,
+    pub height: u32,
+    pub format: ffmpeg::format::Pixel,
+    pub time_base: ffmpeg::Rational,
+}
+
+impl VideoInput {
+    pub fn from_decoder(decoder: &ffmpeg::decoder::Video) -> Self {
+        Self {
+            width: decoder.width(),
+            height: decoder.height(),
+            format: decoder.format(),
+            time_base: decoder.time_base(),
+        }
+    }
+}
+
+/// Видеофильтр-граф buffer -> `spec` -> buffersink.
+pub struct VideoGraph {
+    graph: ffmpeg::filter::Graph,
+}
+
+impl VideoGraph {
+    /// Строит граф по описанию `spec` в синтаксисе -filter_complex:
+    /// вход графа подписан как [in], последняя цепочка уходит в выход.
+    pub fn new(input: VideoInput, spec: &str) -> Result<Self> {
+        let mut graph = ffmpeg::filter::Graph::new();
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect=1/1",
+            input.width,
+            input.height,
+            ffmpeg::ffi::AVPixelFormat::from(input.format) as i32,
+            input.time_base.numerator(),
+            input.time_base.denominator().max(1),
+        );
+        let map_err = |e: ffmpeg::Error| anyhow::anyhow!("Failed to build filter '{}': {:?}", spec, e);
+        graph
+            .add(&ffmpeg::filter::find("buffer").unwrap(), "in", &args)
+            .map_err(map_err)?;
+        graph
+            .add(&ffmpeg::filter::find("buffersink").unwrap(), "out", "")
+            .map_err(map_err)?;
+        graph
+            .output("in", 0)
+            .and_then(|p| p.input("out", 0))
+            .and_then(|p| p.parse(spec))
+            .map_err(map_err)?;
+        graph.validate().map_err(map_err)?;
+        Ok(Self { graph })
+    }
+
+    /// Пропускает кадр через граф и возвращает обработанные кадры.
+    pub fn apply(&mut self, frame: &ffmpeg::frame::Video) -> Result<Vec<ffmpeg::frame::Video>> {
+        self.graph
+            .get("in")
+            .unwrap()
+            .source()
+            .add(frame)
+            .map_err(|e| anyhow::anyhow!("Error feeding filter graph: {:?}", e))?;
+        let mut frames = Vec::new();
+        let mut filtered = ffmpeg::frame::Video::empty();
+        while self.graph.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+            frames.push(filtered.clone());
+        }
+        Ok(frames)
+    }
+}
+
+/// Пропускает пачку кадров через один этап фильтрации.
+pub fn apply_stage<F>(frames: Vec<ffmpeg::frame::Video>, mut stage: F) -> Result<Vec<ffmpeg::frame::Video>>
+where
+    F: FnMut(&ffmpeg::frame::Video) -> Result<Vec<ffmpeg::frame::Video>>,
+{
+    let mut output = Vec::with_capacity(frames.len());
+    for frame in &frames {
+        output.extend(stage(frame)?);
+    }
+    Ok(output)
+}
+
+/// Вход микшера звука: формат кадров, база времени их PTS и усиление.
+#[derive(Debug, Clone, Copy)]
+pub struct MixerInput {
+    pub format: AudioFormat,
+    pub time_base: ffmpeg::Rational,
+    pub gain: f64,
+}
+
+/// Сводит источники звука в одну дорожку (abuffer -> aformat -> volume -> amix -> abuffersink).
+///
+/// Каждый вход до amix приводится к формату выхода: amix складывает только
+/// сэмплы одной частоты и раскладки, а микрофон и монитор вывода часто
+/// работают на разных частотах. amix делит громкость на число входов,
+/// поэтому после него громкость возвращается множителем. Один вход
+/// проходит без amix — только усиление и приведение формата.
+pub struct AudioMixer {
+    graph: ffmpeg::filter::Graph,
+    inputs: usize,
+}
+
+impl AudioMixer {
+    pub fn new(inputs: &[MixerInput], output: AudioFormat) -> Result<Self> {
+        if inputs.is_empty() {
+            anyhow::bail!("Audio mixer needs at least one input");
+        }
+        let aformat = format!(
+            "aformat=sample_fmts={}:sample_rates={}:channel_layouts=0x{:x}",
+            output.sample.name(),
+            output.rate,
+            output.layout.bits()
+        );
+        let spec = if inputs.len() == 1 {
+            format!("[in0]{},volume={:.3}[out]", aformat, inputs[0].gain)
+        } else {
+            let mut chains: Vec<String> = inputs
+                .iter()
+                .enumerate()
+                .map(|(i, input)| format!("[in{}]{},volume={:.3}[a{}]", i, aformat, input.gain, i))
+                .collect();
+            let labels: String = (0..inputs.len()).map(|i| format!("[a{}]", i)).collect();
+            chains.push(format!(
+                "{}amix=inputs={}:duration=longest:dropout_transition=0,volume={},{}[out]",
+                labels,
+                inputs.len(),
+                inputs.len(),
+                aformat
+            ));
+            chains.join(";")
+        };
+
+        let mut graph = ffmpeg::filter::Graph::new();
+        let map_err = |e: ffmpeg::Error| anyhow::anyhow!("Failed to build audio mixer '{}': {:?}", spec, e);
+        for (i, input) in inputs.iter().enumerate() {
+            let args = format!(
+                "time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+                input.time_base.numerator(),
+                input.time_base.denominator().max(1),
+                input.format.rate,
+                input.format.sample.name(),
+                input.format.layout.bits()
+            );
+            graph
+                .add(&ffmpeg::filter::find("abuffer").unwrap(), &format!("in{}", i), &args)
+                .map_err(map_err)?;
+        }
+        graph
+            .add(&ffmpeg::filter::find("abuffersink").unwrap(), "out", "")
+            .map_err(map_err)?;
+        let mut parser = graph.output("in0", 0).map_err(map_err)?;
+        for i in 1..inputs.len() {
+            parser = parser.output(&format!("in{}", i), 0).map_err(map_err)?;
+        }
+        parser
+            .input("out", 0)
+            .and_then(|p| p.parse(&spec))
+            .map_err(map_err)?;
+        graph.validate().map_err(map_err)?;
+        Ok(Self {
+            graph,
+            inputs: inputs.len(),
+        })
+    }
+
+    /// Отдаёт кадр входу `input` (номер в порядке `new`).
+    pub fn push(&mut self, input: usize, frame: &ffmpeg::frame::Audio) -> Result<()> {
+        self.graph
+            .get(&format!("in{}", input))
+            .ok_or_else(|| anyhow::anyhow!("Audio mixer has no input {}", input))?
+            .source()
+            .add(frame)
+            .map_err(|e| anyhow::anyhow!("Error feeding audio mixer: {:?}", e))
+    }
+
+    /// Сведённые кадры, готовые к этому моменту.
+    pub fn pull(&mut self) -> Vec<ffmpeg::frame::Audio> {
+        let mut frames = Vec::new();
+        let mut mixed = ffmpeg::frame::Audio::empty();
+        while self.graph.get("out").unwrap().sink().frame(&mut mixed).is_ok() {
+            frames.push(mixed.clone());
+        }
+        frames
+    }
+
+    /// Закрывает все входы и забирает то, что осталось в графе.
+    pub fn flush(&mut self) -> Result<Vec<ffmpeg::frame::Audio>> {
+        for input in 0..self.inputs {
+            self.graph
+                .get(&format!("in{}", input))
+                .unwrap()
+                .source()
+                .flush()
+                .map_err(|e| anyhow::anyhow!("Error flushing audio mixer: {:?}", e))?;
+        }
+        Ok(self.pull())
+    }
+}
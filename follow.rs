@@ -0,0 +1,164 @@
+// src/follow.rs
+
+use anyhow::{Context, Result};
+use ffmpeg_next as ffmpeg;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+use tracing::warn;
+
+use crate::filters::{VideoGraph, VideoInput};
+
+/// Как часто опрашивается активное окно.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Прямоугольник окна в координатах экрана.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Следит за активным окном и хранит его последний известный прямоугольник.
+///
+/// Ни портал ScreenCast, ни Wayland-композиторы не сообщают клиентам о смене
+/// фокуса, поэтому слежение возможно только через X11 (_NET_ACTIVE_WINDOW);
+/// в чистой Wayland-сессии `start` возвращает ошибку.
+pub struct FocusTracker {
+    rect: Arc<Mutex<Option<WindowRect>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl FocusTracker {
+    pub fn start() -> Result<Self> {
+        if env::var_os("DISPLAY").is_none() {
+            anyhow::bail!("Following the active window requires an X11 (or XWayland) session");
+        }
+        let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to X server")?;
+        let root = conn.setup().roots[screen_num].root;
+        let active_atom = conn
+            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+            .reply()
+            .context("Failed to intern _NET_ACTIVE_WINDOW")?
+            .atom;
+
+        let rect = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let rect_clone = rect.clone();
+        let stop_clone = stop.clone();
+        thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                let current = (|| -> Result<Option<WindowRect>> {
+                    let reply = conn
+                        .get_property(false, root, active_atom, AtomEnum::WINDOW, 0, 1)?
+                        .reply()?;
+                    let window = match reply.value32().and_then(|mut v| v.next()) {
+                        Some(window) if window != 0 => window,
+                        _ => return Ok(None),
+                    };
+                    let geometry = conn.get_geometry(window)?.reply()?;
+                    let origin = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+                    Ok(Some(WindowRect {
+                        x: origin.dst_x as i32,
+                        y: origin.dst_y as i32,
+                        width: geometry.width as u32,
+                        height: geometry.height as u32,
+                    }))
+                })();
+                match current {
+                    // Нет активного окна (рабочий стол) — оставляем прежний прямоугольник.
+                    Ok(Some(found)) => *rect_clone.lock().unwrap() = Some(found),
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to query active window: {:?}", e),
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        Ok(Self { rect, stop })
+    }
+
+    pub fn current(&self) -> Option<WindowRect> {
+        *self.rect.lock().unwrap()
+    }
+}
+
+impl Drop for FocusTracker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Вырезает из кадра активное окно и вписывает его в исходный размер кадра
+/// (с полями), чтобы размер видео для кодера не менялся при смене фокуса.
+/// Граф перестраивается только когда окно сменилось или сдвинулось.
+pub struct FollowFilter {
+    tracker: FocusTracker,
+    input: VideoInput,
+    current: Option<WindowRect>,
+    graph: Option<VideoGraph>,
+}
+
+impl FollowFilter {
+    pub fn new(decoder: &ffmpeg::decoder::Video) -> Result<Self> {
+        Ok(Self {
+            tracker: FocusTracker::start()?,
+            input: VideoInput::from_decoder(decoder),
+            current: None,
+            graph: None,
+        })
+    }
+
+    pub fn apply(&mut self, frame: &ffmpeg::frame::Video) -> Result<Vec<ffmpeg::frame::Video>> {
+        let rect = self.tracker.current().and_then(|rect| self.clamp(rect));
+        if rect != self.current {
+            self.current = rect;
+            self.graph = match rect {
+                Some(rect) => Some(VideoGraph::new(self.input, &self.spec(rect))?),
+                None => None,
+            };
+        }
+        match self.graph.as_mut() {
+            Some(graph) => graph.apply(frame),
+            None => Ok(vec![frame.clone()]),
+        }
+    }
+
+    /// Обрезает прямоугольник окна границами кадра; None, если окно вне кадра.
+    fn clamp(&self, rect: WindowRect) -> Option<WindowRect> {
+        let x0 = rect.x.max(0) as u32;
+        let y0 = rect.y.max(0) as u32;
+        let x1 = ((rect.x + rect.width as i32).max(0) as u32).min(self.input.width);
+        let y1 = ((rect.y + rect.height as i32).max(0) as u32).min(self.input.height);
+        // Чётные размеры нужны для YUV 4:2:0 после обрезки.
+        let width = (x1.saturating_sub(x0)) & !1;
+        let height = (y1.saturating_sub(y0)) & !1;
+        if width < 2 || height < 2 {
+            return None;
+        }
+        Some(WindowRect {
+            x: x0 as i32,
+            y: y0 as i32,
+            width,
+            height,
+        })
+    }
+
+    fn spec(&self, rect: WindowRect) -> String {
+        format!(
+            "crop={w}:{h}:{x}:{y},scale={ow}:{oh}:force_original_aspect_ratio=decrease,\
+             pad={ow}:{oh}:(ow-iw)/2:(oh-ih)/2",
+            w = rect.width,
+            h = rect.height,
+            x = rect.x,
+            y = rect.y,
+            ow = self.input.width,
+            oh = self.input.height
+        )
+    }
+}
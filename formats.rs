@@ -0,0 +1,253 @@
+// src/formats.rs
+
+use ffmpeg_next as ffmpeg;
+
+/// Результат проверки сочетания контейнера и кодека.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compatibility {
+    /// Сочетание поддерживается и воспроизводится обычными плеерами.
+    Supported,
+    /// Файл будет записан, но может не открыться в части плееров.
+    Warning(String),
+    /// Мультиплексор не примет такой поток или файл будет некорректным.
+    Unsupported(String),
+}
+
+/// Контейнеры, которые умеет записывать rscap.
+pub const CONTAINERS: &[&str] = &["mp4", "mkv", "webm"];
+
+/// Видеокодеки, которые можно выбрать для записи (строки из GUI и CLI).
+pub const VIDEO_CODECS: &[&str] = &["H264", "H265", "VP9", "AV1"];
+
+/// Пресеты libx264 от самого быстрого к самому экономному по размеру.
+pub const X264_PRESETS: &[&str] = &[
+    "ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow",
+];
+
+/// Пресет libx264 по умолчанию: запись экрана не должна терять кадры на слабом CPU.
+pub const DEFAULT_X264_PRESET: &str = "ultrafast";
+
+/// Настройки tune libx264; пустая строка — без tune.
+pub const X264_TUNES: &[&str] = &[
+    "", "zerolatency", "film", "animation", "grain", "stillimage", "fastdecode",
+];
+
+/// Видеокодек записи.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoCodec {
+    H264,
+    H265,
+    VP9,
+    AV1,
+}
+
+impl VideoCodec {
+    /// Разбирает строку кодека из GUI/CLI ("H264", "H265", "VP9", "AV1"; "HEVC" — синоним H265).
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        match text {
+            "H264" => Ok(VideoCodec::H264),
+            "H265" | "HEVC" => Ok(VideoCodec::H265),
+            "VP9" => Ok(VideoCodec::VP9),
+            "AV1" => Ok(VideoCodec::AV1),
+            other => anyhow::bail!(
+                "Unknown video codec '{}' (expected H264, H265, VP9 or AV1)",
+                other
+            ),
+        }
+    }
+
+    /// Кодек, который подставляется при выборе контейнера: WebM не принимает H.264/H.265.
+    pub fn default_for_container(container: &str) -> Option<Self> {
+        match container {
+            "webm" => Some(VideoCodec::VP9),
+            _ => None,
+        }
+    }
+
+    /// Контейнер, который подставляется при выборе кодека: VP9 и AV1 пишутся для веба.
+    pub fn default_container(self) -> &'static str {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 => "mp4",
+            VideoCodec::VP9 | VideoCodec::AV1 => "webm",
+        }
+    }
+
+    pub fn id(self) -> ffmpeg::codec::Id {
+        match self {
+            VideoCodec::H264 => ffmpeg::codec::Id::H264,
+            VideoCodec::H265 => ffmpeg::codec::Id::HEVC,
+            VideoCodec::VP9 => ffmpeg::codec::Id::VP9,
+            VideoCodec::AV1 => ffmpeg::codec::Id::AV1,
+        }
+    }
+
+    /// Программные кодеры FFmpeg для этого кодека в порядке предпочтения.
+    /// Для AV1 SVT-AV1 успевает за экраном, libaom — запасной вариант.
+    pub fn encoder_names(self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 => &["libx264"],
+            VideoCodec::H265 => &["libx265"],
+            VideoCodec::VP9 => &["libvpx-vp9"],
+            VideoCodec::AV1 => &["libsvtav1", "libaom-av1"],
+        }
+    }
+
+    /// Кодер из локальной сборки FFmpeg; в ошибке называются недостающие кодеры,
+    /// чтобы было понятно, с какой библиотекой пересобрать FFmpeg.
+    pub fn find_encoder(self) -> anyhow::Result<ffmpeg::Codec> {
+        self.encoder_names()
+            .iter()
+            .find_map(|name| ffmpeg::encoder::find_by_name(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{:?} recording needs the {} encoder, which this FFmpeg build does not include",
+                    self,
+                    self.encoder_names().join(" or ")
+                )
+            })
+    }
+}
+
+/// Матрица совместимости видеокодеков и контейнеров.
+pub fn check_video_codec(container: &str, codec: ffmpeg::codec::Id) -> Compatibility {
+    use ffmpeg::codec::Id;
+    match (container, codec) {
+        (container, _) if !CONTAINERS.contains(&container) => {
+            Compatibility::Unsupported(format!("Unknown container '{}'", container))
+        }
+        ("mp4", Id::H264) | ("mp4", Id::HEVC) | ("mp4", Id::AV1) => Compatibility::Supported,
+        ("mp4", Id::VP9) => Compatibility::Warning(
+            "VP9 in mp4 is valid but many players (QuickTime, older browsers) won't play it; \
+             prefer mkv"
+                .to_string(),
+        ),
+        ("mkv", _) => Compatibility::Supported,
+        ("webm", Id::VP9) | ("webm", Id::AV1) => Compatibility::Supported,
+        (container, codec) => Compatibility::Unsupported(format!(
+            "{:?} video cannot be stored in {}",
+            codec, container
+        )),
+    }
+}
+
+/// Совместимость звукового кодека с контейнером.
+pub fn check_audio_codec(container: &str, codec: ffmpeg::codec::Id) -> Compatibility {
+    use ffmpeg::codec::Id;
+    match (container, codec) {
+        ("mkv", _) | ("mp4", Id::AAC) | ("webm", Id::OPUS) => Compatibility::Supported,
+        // FLAC в mp4 FFmpeg пишет, но плееры его там почти не читают.
+        (_, Id::FLAC) => Compatibility::Unsupported(
+            "FLAC audio is only recorded into mkv".to_string(),
+        ),
+        ("mp4", Id::OPUS) => Compatibility::Warning(
+            "Opus in mp4 is valid but older Safari and QuickTime won't play the audio; \
+             prefer mkv or webm"
+                .to_string(),
+        ),
+        (container, codec) => Compatibility::Unsupported(format!(
+            "{:?} audio cannot be stored in {}",
+            codec, container
+        )),
+    }
+}
+
+/// Отвергает сочетания, которые мультиплексор не примет: иначе ошибка всплывёт
+/// в write_header, уже после диалога портала. Предупреждения не мешают записи.
+pub fn validate_container_codec(container: &str, codec: ffmpeg::codec::Id) -> anyhow::Result<()> {
+    match check_video_codec(container, codec) {
+        Compatibility::Unsupported(reason) => anyhow::bail!(
+            "Cannot record {:?} into {}: {}",
+            codec,
+            container,
+            reason
+        ),
+        Compatibility::Supported | Compatibility::Warning(_) => Ok(()),
+    }
+}
+
+/// Проверяет, что шаблон имени не несёт своё расширение, расходящееся с контейнером:
+/// "talk.mkv" при контейнере mp4 превратится в "talk.mkv.mp4".
+pub fn check_extension(filename_template: &str, container: &str) -> Compatibility {
+    let extension = match filename_template.rsplit_once('.') {
+        Some((_, ext)) => ext.to_ascii_lowercase(),
+        None => return Compatibility::Supported,
+    };
+    const MEDIA_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov", "avi", "ts", "m4v"];
+    if MEDIA_EXTENSIONS.contains(&extension.as_str()) && extension != container {
+        return Compatibility::Warning(format!(
+            "Filename template ends with '.{}' but the container is {}: \
+             the object will be named '{}.{}'",
+            extension, container, filename_template, container
+        ));
+    }
+    Compatibility::Supported
+}
+
+/// Соотношение сторон пикселя/кадра, записываемое в кодер и поток.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AspectRatio {
+    /// Квадратные пиксели (1:1) — по умолчанию.
+    Square,
+    /// Явно заданное соотношение сторон пикселя (SAR).
+    Sample(u32, u32),
+    /// Желаемое соотношение сторон кадра при показе (DAR); SAR выводится из размеров.
+    Display(u32, u32),
+}
+
+impl AspectRatio {
+    /// Разбирает "num:den" как SAR или DAR. Пустая строка — квадратные пиксели.
+    pub fn parse(text: &str, display: bool) -> anyhow::Result<AspectRatio> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(AspectRatio::Square);
+        }
+        let (num, den) = text
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Aspect ratio '{}' must look like num:den", text))?;
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| anyhow::anyhow!("Aspect ratio '{}' has invalid part '{}'", text, s))
+        };
+        let (num, den) = (parse(num)?, parse(den)?);
+        Ok(if display {
+            AspectRatio::Display(num, den)
+        } else {
+            AspectRatio::Sample(num, den)
+        })
+    }
+
+    /// Обратное `parse`: текст "num:den" (пустой для квадратных пикселей)
+    /// и признак DAR.
+    pub fn to_text(&self) -> (String, bool) {
+        match *self {
+            AspectRatio::Square => (String::new(), false),
+            AspectRatio::Sample(num, den) => (format!("{}:{}", num, den), false),
+            AspectRatio::Display(num, den) => (format!("{}:{}", num, den), true),
+        }
+    }
+
+    /// SAR для кадра `width`x`height`: DAR = SAR * width / height.
+    pub fn sample_aspect(&self, width: u32, height: u32) -> ffmpeg::Rational {
+        let (num, den) = match *self {
+            AspectRatio::Square => (1, 1),
+            AspectRatio::Sample(num, den) => (num as u64, den as u64),
+            AspectRatio::Display(num, den) => (
+                num as u64 * height as u64,
+                den as u64 * width.max(1) as u64,
+            ),
+        };
+        let divisor = gcd(num, den).max(1);
+        ffmpeg::Rational::new((num / divisor) as i32, (den / divisor) as i32)
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
@@ -0,0 +1,144 @@
+// src/framerate.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::Rescale;
+
+/// Частота кадров записи по умолчанию.
+pub const DEFAULT_FPS: u32 = 30;
+/// Допустимый диапазон частоты кадров в GUI.
+pub const MIN_FPS: u32 = 15;
+pub const MAX_FPS: u32 = 60;
+
+/// Приводит поток кадров к постоянной частоте `fps`.
+///
+/// PipeWire присылает кадры с произвольной и плавающей частотой. Каждый кадр
+/// попадает в слот 1/fps по своему PTS: кадр в уже занятом слоте отбрасывается,
+/// а пропущенные слоты заполняются повтором предыдущего кадра. Повторы
+/// порождаются только для слотов, которые источник уже прошёл по времени,
+/// поэтому медленный источник не разгоняется быстрее реального времени.
+/// Без `duplicate` (запись по damage) пропуски остаются паузами в PTS.
+pub struct FrameRateFilter {
+    input_time_base: ffmpeg::Rational,
+    output_time_base: ffmpeg::Rational,
+    duplicate: bool,
+    last_slot: Option<i64>,
+    last_frame: Option<ffmpeg::frame::Video>,
+}
+
+impl FrameRateFilter {
+    pub fn new(input_time_base: ffmpeg::Rational, fps: u32, duplicate: bool) -> Self {
+        Self {
+            input_time_base,
+            output_time_base: ffmpeg::Rational(1, fps.max(1) as i32),
+            duplicate,
+            last_slot: None,
+            last_frame: None,
+        }
+    }
+
+    /// База времени выходных кадров (1/fps): PTS на выходе — номера слотов.
+    pub fn time_base(&self) -> ffmpeg::Rational {
+        self.output_time_base
+    }
+
+    pub fn apply(&mut self, frame: &ffmpeg::frame::Video) -> Result<Vec<ffmpeg::frame::Video>> {
+        let slot = match frame.pts() {
+            Some(pts) => pts.rescale(self.input_time_base, self.output_time_base),
+            None => self.last_slot.map_or(0, |slot| slot + 1),
+        };
+        let mut frames = Vec::new();
+        match self.last_slot {
+            // Источник быстрее цели: слот уже занят.
+            Some(last) if slot <= last => return Ok(frames),
+            Some(last) => {
+                if let Some(previous) = self.last_frame.as_ref() {
+                    for gap in last + 1..slot {
+                        let mut repeated = previous.clone();
+                        repeated.set_pts(Some(gap));
+                        frames.push(repeated);
+                    }
+                }
+            }
+            None => {}
+        }
+        let mut frame = frame.clone();
+        frame.set_pts(Some(slot));
+        if self.duplicate {
+            self.last_frame = Some(frame.clone());
+        }
+        self.last_slot = Some(slot);
+        frames.push(frame);
+        Ok(frames)
+    }
+}
+
+/// Строго возрастающие PTS перед кодером.
+///
+/// `FrameRateFilter` уже раскладывает кадры по слотам, но между ним и кодером
+/// кадры проходят через очередь, фильтры и перезапуски сегментов; мультиплексор
+/// на повторе или откате PTS пишет "non-monotonic DTS" и портит файл.
+/// Кадр без PTS или с PTS не больше предыдущего получает следующий номер.
+#[derive(Debug, Default)]
+pub struct MonotonicPts {
+    last: Option<i64>,
+}
+
+impl MonotonicPts {
+    pub fn assign(&mut self, frame: &mut ffmpeg::frame::Video) {
+        let pts = match (frame.pts(), self.last) {
+            (Some(pts), Some(last)) if pts <= last => last + 1,
+            (Some(pts), _) => pts,
+            (None, last) => last.map_or(0, |last| last + 1),
+        };
+        frame.set_pts(Some(pts));
+        self.last = Some(pts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pts: i64) -> ffmpeg::frame::Video {
+        let mut frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::GRAY8, 2, 2);
+        frame.set_pts(Some(pts));
+        frame
+    }
+
+    /// PTS на выходе фильтра при входе с PTS `input` в микросекундах.
+    fn output_pts(input: &[i64], duplicate: bool) -> Vec<i64> {
+        let mut filter = FrameRateFilter::new(ffmpeg::Rational(1, 1_000_000), 30, duplicate);
+        input
+            .iter()
+            .flat_map(|&pts| filter.apply(&frame(pts)).unwrap())
+            .map(|frame| frame.pts().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn irregular_input_gives_evenly_spaced_output() {
+        // Дрожание, кадр в занятом слоте (145 мс) и пропуски в 1 и 2 слота.
+        let input = [0, 40_000, 70_000, 140_000, 145_000, 200_000];
+        assert_eq!(output_pts(&input, true), vec![0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn without_duplication_gaps_stay_pauses() {
+        let input = [0, 40_000, 70_000, 140_000, 145_000, 200_000];
+        assert_eq!(output_pts(&input, false), vec![0, 1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn monotonic_pts_fixes_repeats_and_missing() {
+        let mut monotonic = MonotonicPts::default();
+        let mut assigned = Vec::new();
+        for pts in [Some(0), Some(0), None, Some(2), Some(10)] {
+            let mut frame = frame(0);
+            frame.set_pts(pts);
+            monotonic.assign(&mut frame);
+            assigned.push(frame.pts().unwrap());
+        }
+        assert_eq!(assigned, vec![0, 1, 2, 3, 10]);
+    }
+}
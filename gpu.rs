@@ -0,0 +1,338 @@
+// src/gpu.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crate::formats::VideoCodec;
+use crate::rate_control::EncodingMode;
+
+/// Каталог узлов DRM.
+const DRI_DIR: &str = "/dev/dri";
+
+/// Узел рендеринга DRM (`/dev/dri/renderD*`) с названием драйвера для списка в GUI.
+#[derive(Debug, Clone)]
+pub struct RenderNode {
+    pub path: PathBuf,
+    /// Драйвер ядра (i915, amdgpu, nouveau...), если его удалось определить.
+    pub driver: Option<String>,
+}
+
+impl RenderNode {
+    /// Подпись для списка: "/dev/dri/renderD128 (i915)".
+    pub fn label(&self) -> String {
+        match &self.driver {
+            Some(driver) => format!("{} ({})", self.path.display(), driver),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// Перечисляет узлы рендеринга, отсортированные по имени.
+/// На ноутбуках с двумя GPU обычно renderD128 — встроенная, renderD129 — дискретная.
+pub fn render_nodes() -> Vec<RenderNode> {
+    let mut nodes: Vec<RenderNode> = match fs::read_dir(DRI_DIR) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("renderD"))
+            .map(|entry| RenderNode {
+                driver: driver_name(&entry.file_name().to_string_lossy()),
+                path: entry.path(),
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    nodes.sort_by(|a, b| a.path.cmp(&b.path));
+    nodes
+}
+
+/// Имя драйвера из /sys/class/drm/<node>/device/driver.
+fn driver_name(node: &str) -> Option<String> {
+    let link = fs::read_link(Path::new("/sys/class/drm").join(node).join("device/driver")).ok()?;
+    Some(link.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Аппаратный контекст VAAPI на выбранном узле рендеринга.
+/// Без явного узла FFmpeg берёт первый попавшийся, а на гибридной графике
+/// это часто более слабая встроенная GPU.
+pub struct HwDevice {
+    context: *mut ffmpeg::ffi::AVBufferRef,
+}
+
+// Контекст устройства FFmpeg потокобезопасен по счётчику ссылок.
+unsafe impl Send for HwDevice {}
+
+impl HwDevice {
+    pub fn open_vaapi(node: &Path) -> Result<Self> {
+        let device = CString::new(node.to_string_lossy().as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid render node path: {:?}", e))?;
+        let mut context = ptr::null_mut();
+        let ret = unsafe {
+            ffmpeg::ffi::av_hwdevice_ctx_create(
+                &mut context,
+                ffmpeg::ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+                device.as_ptr(),
+                ptr::null_mut(),
+                0,
+            )
+        };
+        if ret < 0 {
+            anyhow::bail!(
+                "Failed to create VAAPI device on {}: {:?}",
+                node.display(),
+                ffmpeg::Error::from(ret)
+            );
+        }
+        Ok(Self { context })
+    }
+
+    pub fn as_ptr(&self) -> *mut ffmpeg::ffi::AVBufferRef {
+        self.context
+    }
+}
+
+impl Drop for HwDevice {
+    fn drop(&mut self) {
+        unsafe { ffmpeg::ffi::av_buffer_unref(&mut self.context) };
+    }
+}
+
+/// Узел, на котором FFmpeg открывает VAAPI, если GPU не выбрана.
+pub const DEFAULT_RENDER_NODE: &str = "/dev/dri/renderD128";
+
+/// Режимы аппаратного кодирования для выбора в GUI и CLI.
+pub const HW_ACCELS: &[&str] = &["none", "vaapi", "nvenc"];
+
+/// Кодер VAAPI для кодека; None — кодек аппаратно не пишется.
+pub fn vaapi_encoder_name(codec: VideoCodec) -> Option<&'static str> {
+    match codec {
+        VideoCodec::H264 => Some("h264_vaapi"),
+        VideoCodec::H265 => Some("hevc_vaapi"),
+        VideoCodec::AV1 => Some("av1_vaapi"),
+        VideoCodec::VP9 => None,
+    }
+}
+
+/// Кодер NVENC для кодека; None — кодек аппаратно не пишется.
+pub fn nvenc_encoder_name(codec: VideoCodec) -> Option<&'static str> {
+    match codec {
+        VideoCodec::H264 => Some("h264_nvenc"),
+        VideoCodec::H265 => Some("hevc_nvenc"),
+        VideoCodec::AV1 => Some("av1_nvenc"),
+        VideoCodec::VP9 => None,
+    }
+}
+
+/// Пул кадров в памяти GPU: кодер VAAPI принимает только кадры из своего пула.
+pub struct HwFrames {
+    context: *mut ffmpeg::ffi::AVBufferRef,
+}
+
+unsafe impl Send for HwFrames {}
+
+impl HwFrames {
+    /// Пул NV12-поверхностей размера записи на устройстве `device`.
+    pub fn new(device: &HwDevice, width: u32, height: u32) -> Result<Self> {
+        let mut context = unsafe { ffmpeg::ffi::av_hwframe_ctx_alloc(device.as_ptr()) };
+        if context.is_null() {
+            anyhow::bail!("Failed to allocate VAAPI frame pool");
+        }
+        let ret = unsafe {
+            let frames = (*context).data as *mut ffmpeg::ffi::AVHWFramesContext;
+            (*frames).format = ffmpeg::ffi::AVPixelFormat::AV_PIX_FMT_VAAPI;
+            (*frames).sw_format = ffmpeg::ffi::AVPixelFormat::AV_PIX_FMT_NV12;
+            (*frames).width = width as i32;
+            (*frames).height = height as i32;
+            (*frames).initial_pool_size = 20;
+            ffmpeg::ffi::av_hwframe_ctx_init(context)
+        };
+        if ret < 0 {
+            unsafe { ffmpeg::ffi::av_buffer_unref(&mut context) };
+            anyhow::bail!(
+                "Failed to initialize {}x{} VAAPI frame pool: {:?}",
+                width,
+                height,
+                ffmpeg::Error::from(ret)
+            );
+        }
+        Ok(Self { context })
+    }
+
+    pub fn as_ptr(&self) -> *mut ffmpeg::ffi::AVBufferRef {
+        self.context
+    }
+
+    /// Копирует NV12-кадр из памяти в поверхность пула вместе с PTS и типом кадра.
+    pub fn upload(&self, frame: &ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
+        let mut surface = ffmpeg::frame::Video::empty();
+        unsafe {
+            let ret = ffmpeg::ffi::av_hwframe_get_buffer(self.context, surface.as_mut_ptr(), 0);
+            if ret < 0 {
+                anyhow::bail!("Failed to get a VAAPI surface: {:?}", ffmpeg::Error::from(ret));
+            }
+            let ret = ffmpeg::ffi::av_hwframe_transfer_data(surface.as_mut_ptr(), frame.as_ptr(), 0);
+            if ret < 0 {
+                anyhow::bail!("Failed to upload frame to GPU: {:?}", ffmpeg::Error::from(ret));
+            }
+            let ret = ffmpeg::ffi::av_frame_copy_props(surface.as_mut_ptr(), frame.as_ptr());
+            if ret < 0 {
+                anyhow::bail!("Failed to copy frame properties: {:?}", ffmpeg::Error::from(ret));
+            }
+        }
+        Ok(surface)
+    }
+}
+
+impl Drop for HwFrames {
+    fn drop(&mut self) {
+        unsafe { ffmpeg::ffi::av_buffer_unref(&mut self.context) };
+    }
+}
+
+/// Кодирование через VAAPI: устройство, пул кадров и аппаратный кодер.
+pub struct VaapiEncoding {
+    /// Устройство на выбранном узле: на нём живут пул кадров и кодер.
+    device: HwDevice,
+    /// Узел рендеринга устройства — для журнала.
+    pub node: PathBuf,
+    pub frames: HwFrames,
+    pub codec: ffmpeg::Codec,
+}
+
+impl VaapiEncoding {
+    /// Готовит VAAPI на узле `node`. Ошибка означает, что аппаратное кодирование
+    /// здесь недоступно (нет узла, драйвера или кодера в сборке FFmpeg),
+    /// и запись должна идти программным кодером.
+    pub fn open(node: &Path, codec: VideoCodec, width: u32, height: u32) -> Result<Self> {
+        if !node.exists() {
+            anyhow::bail!("Render node {} does not exist", node.display());
+        }
+        let name = vaapi_encoder_name(codec)
+            .ok_or_else(|| anyhow::anyhow!("{:?} has no VAAPI encoder", codec))?;
+        let encoder = ffmpeg::encoder::find_by_name(name).ok_or_else(|| {
+            anyhow::anyhow!("This FFmpeg build does not include the {} encoder", name)
+        })?;
+        let device = HwDevice::open_vaapi(node)?;
+        let frames = HwFrames::new(&device, width, height)?;
+        Ok(Self {
+            device,
+            node: node.to_path_buf(),
+            frames,
+            codec: encoder,
+        })
+    }
+
+    /// Настраивает кодер на кадры из пула: формат VAAPI, hw_frames_ctx и
+    /// hw_device_ctx выбранного узла, чтобы кодер не открыл устройство сам
+    /// на узле по умолчанию.
+    pub fn attach(&self, encoder: &mut ffmpeg::encoder::Video) -> Result<()> {
+        encoder.set_format(ffmpeg::format::Pixel::VAAPI);
+        let device = unsafe { ffmpeg::ffi::av_buffer_ref(self.device.as_ptr()) };
+        if device.is_null() {
+            anyhow::bail!("Failed to reference the VAAPI device on {}", self.node.display());
+        }
+        unsafe { (*encoder.as_mut_ptr()).hw_device_ctx = device };
+        let frames = unsafe { ffmpeg::ffi::av_buffer_ref(self.frames.as_ptr()) };
+        if frames.is_null() {
+            anyhow::bail!("Failed to reference the VAAPI frame pool");
+        }
+        unsafe { (*encoder.as_mut_ptr()).hw_frames_ctx = frames };
+        Ok(())
+    }
+}
+
+/// Аппаратный кодер записи.
+pub enum HwEncoder {
+    /// Кадры выгружаются в пул поверхностей VAAPI.
+    Vaapi(VaapiEncoding),
+    /// NVENC сам копирует кадры из системной памяти на GPU,
+    /// поэтому ни CUDA-контекст, ни пул кадров не нужны.
+    Nvenc(ffmpeg::Codec),
+}
+
+impl HwEncoder {
+    /// Готовит кодер режима `mode` из `HW_ACCELS`; None для "none".
+    /// `node` нужен только VAAPI: NVENC выбирает GPU сам.
+    pub fn open(
+        mode: &str,
+        node: &Path,
+        codec: VideoCodec,
+        width: u32,
+        height: u32,
+    ) -> Result<Option<Self>> {
+        match mode {
+            "none" => Ok(None),
+            "vaapi" => Ok(Some(HwEncoder::Vaapi(VaapiEncoding::open(node, codec, width, height)?))),
+            "nvenc" => {
+                let name = nvenc_encoder_name(codec)
+                    .ok_or_else(|| anyhow::anyhow!("{:?} has no NVENC encoder", codec))?;
+                let encoder = ffmpeg::encoder::find_by_name(name).ok_or_else(|| {
+                    anyhow::anyhow!("This FFmpeg build does not include the {} encoder", name)
+                })?;
+                Ok(Some(HwEncoder::Nvenc(encoder)))
+            }
+            other => anyhow::bail!("Unknown hardware encoding '{}'", other),
+        }
+    }
+
+    pub fn codec(&self) -> ffmpeg::Codec {
+        match self {
+            HwEncoder::Vaapi(vaapi) => vaapi.codec,
+            HwEncoder::Nvenc(codec) => *codec,
+        }
+    }
+
+    /// Формат, в который конвертер приводит кадры перед кодером.
+    pub fn input_format(&self) -> ffmpeg::format::Pixel {
+        ffmpeg::format::Pixel::NV12
+    }
+
+    /// Формат кадров кодера; для VAAPI ещё и пул поверхностей.
+    pub fn attach(&self, encoder: &mut ffmpeg::encoder::Video) -> Result<()> {
+        match self {
+            HwEncoder::Vaapi(vaapi) => vaapi.attach(encoder),
+            HwEncoder::Nvenc(_) => {
+                encoder.set_format(self.input_format());
+                Ok(())
+            }
+        }
+    }
+
+    /// Опции управления битрейтом, которых программные кодеры не знают.
+    /// `crf` — качество для режима CRF: у аппаратных кодеров это cq (NVENC)
+    /// или постоянный qp (VAAPI).
+    pub fn configure_rate_control(
+        &self,
+        options: &mut ffmpeg::Dictionary,
+        mode: EncodingMode,
+        crf: u32,
+    ) {
+        match self {
+            HwEncoder::Nvenc(_) => {
+                // p4 — середина шкалы p1 (быстрее) .. p7 (качественнее).
+                options.set("preset", "p4");
+                options.set("rc", if mode == EncodingMode::Cbr { "cbr" } else { "vbr" });
+                if mode == EncodingMode::Crf {
+                    options.set("cq", &crf.to_string());
+                }
+            }
+            HwEncoder::Vaapi(_) => {
+                if mode == EncodingMode::Crf {
+                    options.set("rc_mode", "CQP");
+                    options.set("qp", &crf.to_string());
+                }
+            }
+        }
+    }
+
+    /// Кадр для кодера: поверхность VAAPI или тот же кадр из памяти для NVENC.
+    pub fn prepare(&self, frame: ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
+        match self {
+            HwEncoder::Vaapi(vaapi) => vaapi.frames.upload(&frame),
+            HwEncoder::Nvenc(_) => Ok(frame),
+        }
+    }
+}
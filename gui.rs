@@ -1,29 +1,786 @@
 // src/gui.rs
 
+use crate::audio_capture::{
+    self, AudioCodec, AUDIO_CODECS, DEFAULT_AUDIO_BITRATE_KBPS, DEFAULT_MONITOR,
+    DEFAULT_OPUS_APPLICATION, FLAC_ESTIMATED_KBPS, OPUS_APPLICATIONS,
+};
+use crate::audio_meter::{self, AudioLevel};
+use crate::config;
+use crate::crop::CropRect;
+use crate::formats::{
+    self, AspectRatio, Compatibility, VideoCodec, DEFAULT_X264_PRESET, VIDEO_CODECS, X264_PRESETS,
+    X264_TUNES,
+};
+use crate::framerate::{DEFAULT_FPS, MAX_FPS, MIN_FPS};
+use crate::gpu;
+use crate::hotkey::{Binding, Hotkey, HotkeyEvent, DEFAULT_HOTKEY};
+use crate::naming::{self, DEFAULT_FILENAME_TEMPLATE};
+use crate::oci_uploader::{self, OciCredentials, PendingUpload};
+use crate::storage::{self, Backend, BACKENDS, DEFAULT_MAX_RETRIES, DEFAULT_PART_SIZE_MB};
+use crate::rate_control::{self, EncodingMode, TargetSize, DEFAULT_CRF, MAX_CRF};
+use crate::pipeline::{PipelineStats, DEFAULT_BUFFER_WINDOW_MS, DEFAULT_QUEUE_DEPTH};
+use crate::portal::{self, StreamChoice};
+use crate::presets::{self, Preset};
+use crate::preview::PreviewImage;
+use crate::redact::RedactRegion;
+use crate::region_picker;
+use crate::status;
+use crate::supersample::MAX_SUPERSAMPLE_FACTOR;
+use crate::thumbnails::ThumbnailSettings;
+use crate::tray::Tray;
+use gdk_pixbuf::{Colorspace, Pixbuf};
 use gtk::prelude::*;
 use gtk::{
-    Application, ApplicationWindow, Box, Button, ComboBoxText, Entry, FileChooserAction,
-    FileChooserDialog, Label, Orientation, ResponseType, RadioButton, SpinButton,
+    Application, ApplicationWindow, Box, Button, ButtonsType, CheckButton, ComboBoxText, Dialog,
+    DialogFlags, Entry, Expander, FileChooserAction, FileChooserDialog, Image, Label, LevelBar,
+    MessageDialog, MessageType, Orientation, ProgressBar, ResponseType, RadioButton, SpinButton,
 };
+use std::cell::{Cell, RefCell};
 use std::env::args;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use tracing::{error, warn};
+
+/// Сколько записей может идти одновременно, если пользователь не задал иное.
+pub const DEFAULT_MAX_RECORDINGS: usize = 2;
+
+/// Интервал ключевых кадров по умолчанию (секунды).
+pub const DEFAULT_KEYFRAME_INTERVAL_SECS: u32 = 2;
 
 #[derive(Debug, Clone)]
 pub struct RecordParams {
-    /// Для OCI здесь используется как имя bucket (или часть логики формирования пути)
+    /// Имя bucket, назначение со схемой (`s3://bucket/prefix`, `oci://bucket/prefix`)
+    /// или, для local, путь к папке
     pub output_folder: String,
+    /// Хранилище: oci, s3 или local (тогда output_folder — путь к папке)
+    pub backend: String,
     /// Шаблон имени объекта (например, "recording_2025_04_09")
     pub filename_template: String,
-    /// Контейнер: mp4 или mkv
+    /// Контейнер: mp4, mkv или webm
     pub container: String,
+    /// Видеокодек: H264, H265 или VP9
+    pub video_codec: String,
     /// Битрейт в килобитах
     pub bitrate: u32,
-    /// Режим кодирования: CBR или VBR
+    /// Частота кадров записи; кадры источника отбрасываются или повторяются под неё
+    pub fps: u32,
+    /// Ширина и высота записи; None — как у источника (после суперсэмплинга).
+    /// Если задано одно измерение, второе выводится с сохранением пропорций
+    pub out_width: Option<u32>,
+    pub out_height: Option<u32>,
+    /// Целевой размер файла и длительность: битрейт вычисляется из них вместо `bitrate`
+    pub target_size: Option<TargetSize>,
+    /// Режим кодирования: CBR, VBR, Lossless или Near-lossless
     pub encoding_mode: String,
     /// Устройство для захвата звука
     pub audio_device: String,
+    /// Второй источник звука, сводимый с первым (amix); пустая строка — без него
+    pub audio_device_secondary: String,
+    /// Усиление основного и второго источника в процентах (100 — без изменений)
+    pub audio_gain_percent: u32,
+    pub audio_secondary_gain_percent: u32,
+    /// Битрейт звука в килобитах
+    pub audio_bitrate: u32,
+    /// Кодек звука: auto (Opus в webm, иначе AAC), AAC или Opus
+    pub audio_codec: String,
+    /// Режим libopus: audio, voip или lowdelay
+    pub opus_application: String,
+    /// Писать во временный файл и выгружать по кнопке "Upload now", а не сразу
+    pub defer_upload: bool,
+    /// Области экрана, которые размываются или закрашиваются в записи
+    pub redact_regions: Vec<RedactRegion>,
+    /// Теги объекта (opc-meta-*): проект, автор, тикет и т.п.
+    pub object_metadata: Vec<(String, String)>,
+    /// Начальная глубина очереди кадров между захватом и кодированием
+    pub queue_depth: usize,
+    /// Допустимое отставание кодера (мс) сверх глубины очереди до выбрасывания кадров
+    pub buffer_window_ms: u64,
+    /// Чувствительность (1–100) принудительных ключевых кадров на смене сцены; None — выключено
+    pub scene_change_sensitivity: Option<u32>,
+    /// Кодировать кадр только при изменении экрана (VFR по damage композитора)
+    pub damage_driven: bool,
+    /// Следовать за активным окном, кадрируя запись по нему (только X11/XWayland)
+    pub follow_active_window: bool,
+    /// Соотношение сторон пикселя/кадра (по умолчанию квадратные пиксели)
+    pub aspect_ratio: AspectRatio,
+    /// Писать время декодирования/фильтрации/кодирования каждого кадра в CSV
+    pub timing_log: bool,
+    /// Начинать с IDR и использовать закрытые GOP, чтобы файлы склеивались без перекодирования
+    pub concat_friendly: bool,
+    /// Принимать метки от внешней автоматизации через управляющий сокет
+    pub control_socket: bool,
+    /// Сколько раз пытаться создать сессию портала, прежде чем сдаться
+    pub portal_attempts: u32,
+    /// Узел DRM для аппаратного кодирования; None — выбор FFmpeg по умолчанию
+    pub render_node: Option<PathBuf>,
+    /// Порт HTTP-статуса на 127.0.0.1 (JSON и остановка записи); None — выключен
+    pub status_port: Option<u16>,
+    /// Спрайт миниатюр с WebVTT-индексом для перемотки в веб-плеерах; None — выключено
+    pub thumbnails: Option<ThumbnailSettings>,
+    /// Предел одновременных записей, чтобы не исчерпать CPU/GPU и канал
+    pub max_concurrent_recordings: usize,
+    /// Вписывать параметры кодирования в поток H.264 как SEI user data
+    pub embed_provenance: bool,
+    /// Во сколько раз уменьшить полное разрешение источника (1 — без суперсэмплинга)
+    pub supersample_factor: u32,
+    /// Сколько раз перезапускать запись новым сегментом после временного сбоя
+    pub max_restarts: u32,
+    /// Мультиплексировать в локальный файл с перемоткой и выгружать после
+    /// write_trailer: обычный (не фрагментированный) mp4 и никакого битого объекта
+    pub upload_after_finish: bool,
+    /// Писать видео и звук в два отдельных объекта с одним базовым именем
+    /// (запись.mp4 и запись.m4a); PTS обоих отсчитываются от первого кадра
+    pub split_audio: bool,
+    /// Сколько раз повторять временный сбой выгрузки (сеть, 429, 5xx)
+    pub upload_retries: u32,
+    /// Качество для режима CRF (0 — лучшее, 51 — худшее)
+    pub crf: u32,
+    /// Пресет libx264 (ultrafast..veryslow) и необязательный tune (пустая строка — без него)
+    pub x264_preset: String,
+    pub x264_tune: String,
+    /// Интервал ключевых кадров в секундах (GOP = интервал * fps); не меньше 1.
+    /// Короткий GOP ускоряет перемотку и дробит фрагменты mp4, но увеличивает файл
+    pub keyframe_interval_secs: u32,
+    /// Явный размер GOP в кадрах; None — из интервала ключевых кадров и fps
+    pub gop_size: Option<u32>,
+    /// Аппаратное кодирование: none, vaapi или nvenc (при сбое — программный кодер)
+    pub hw_accel: String,
+    /// Кадрирование части экрана (в пикселях источника); все нули — весь кадр
+    pub crop_x: u32,
+    pub crop_y: u32,
+    pub crop_w: u32,
+    pub crop_h: u32,
+    /// Запись останавливается сама через столько секунд; None — без ограничения
+    pub max_duration_secs: Option<u64>,
+    /// Какие источники предлагать в диалоге портала: any, monitor, window или virtual
+    pub source_types: String,
+    /// Курсор в записи: hidden, embedded (в кадре) или metadata (пока пишется как embedded)
+    pub cursor_mode: String,
+    /// Узел PipeWire, который записывать, если портал отдал несколько потоков;
+    /// None — спросить пользователя (без GUI — первый поток)
+    pub node_id: Option<u32>,
+    /// Размер части многочастной выгрузки в OCI (МБ): столько записи держится в памяти
+    pub upload_part_size_mb: u32,
+    /// Показывать записываемые кадры в окне (уменьшенные, до 15 кадров/с)
+    pub preview: bool,
+}
+
+/// Значения по умолчанию совпадают с начальным состоянием формы.
+impl Default for RecordParams {
+    fn default() -> Self {
+        Self {
+            output_folder: String::new(),
+            backend: "oci".to_string(),
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            container: "mp4".to_string(),
+            video_codec: "H264".to_string(),
+            bitrate: 1000,
+            fps: DEFAULT_FPS,
+            out_width: None,
+            out_height: None,
+            target_size: None,
+            encoding_mode: "CBR".to_string(),
+            audio_device: "default".to_string(),
+            audio_device_secondary: String::new(),
+            audio_gain_percent: 100,
+            audio_secondary_gain_percent: 100,
+            audio_bitrate: DEFAULT_AUDIO_BITRATE_KBPS,
+            audio_codec: "auto".to_string(),
+            opus_application: DEFAULT_OPUS_APPLICATION.to_string(),
+            defer_upload: false,
+            redact_regions: Vec::new(),
+            object_metadata: Vec::new(),
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            buffer_window_ms: DEFAULT_BUFFER_WINDOW_MS,
+            scene_change_sensitivity: None,
+            damage_driven: false,
+            follow_active_window: false,
+            aspect_ratio: AspectRatio::Square,
+            timing_log: false,
+            concat_friendly: false,
+            control_socket: false,
+            portal_attempts: portal::DEFAULT_CREATE_SESSION_ATTEMPTS,
+            render_node: None,
+            status_port: None,
+            thumbnails: None,
+            max_concurrent_recordings: DEFAULT_MAX_RECORDINGS,
+            embed_provenance: false,
+            supersample_factor: 1,
+            max_restarts: 0,
+            upload_after_finish: false,
+            split_audio: false,
+            upload_retries: DEFAULT_MAX_RETRIES,
+            crf: DEFAULT_CRF,
+            x264_preset: DEFAULT_X264_PRESET.to_string(),
+            x264_tune: String::new(),
+            keyframe_interval_secs: DEFAULT_KEYFRAME_INTERVAL_SECS,
+            gop_size: None,
+            hw_accel: "none".to_string(),
+            crop_x: 0,
+            crop_y: 0,
+            crop_w: 0,
+            crop_h: 0,
+            max_duration_secs: None,
+            source_types: "any".to_string(),
+            cursor_mode: "embedded".to_string(),
+            node_id: None,
+            upload_part_size_mb: DEFAULT_PART_SIZE_MB,
+            preview: false,
+        }
+    }
+}
+
+/// События, которые фоновые потоки отправляют в GUI через glib-канал.
+pub enum GuiEvent {
+    /// Запись началась; счётчики конвейера можно показывать и настраивать.
+    RecordingStarted(Arc<PipelineStats>),
+    /// Запись завершилась (успешно или с ошибкой); счётчики — те же, что в `RecordingStarted`.
+    RecordingFinished(Arc<PipelineStats>),
+    /// Запись завершена, но ждёт выгрузки (режим `defer_upload`).
+    UploadPending(PendingUpload),
+    /// Портал отдал несколько потоков: спросить, какой записывать.
+    /// В `reply` уходит индекс выбранного потока или None при отмене.
+    ChooseStream {
+        streams: Vec<StreamChoice>,
+        reply: std::sync::mpsc::Sender<Option<usize>>,
+    },
+    /// Запись выгружена в хранилище под этим именем объекта.
+    Uploaded(String),
+    /// Запись или выгрузка не удалась; текст — цепочка причин anyhow.
+    Error(String),
+    /// Пользователь отменил выбор источника в портале; можно начать заново.
+    Cancelled(String),
+    /// Этап записи для строки состояния ("Recording…", "Uploading…").
+    Status(String),
+    /// Итог пробного запуска: пройденные проверки или цепочка причин ошибки.
+    SetupChecked(Result<Vec<String>, String>),
+    /// Глобальное сочетание привязано, нажато или не может быть привязано.
+    Hotkey(HotkeyEvent),
+    /// Уменьшенный кадр идущей записи для предпросмотра.
+    Preview(PreviewImage),
+}
+
+impl RecordParams {
+    /// Проверяет параметры до начала записи.
+    /// Пустой bucket и bucket с недопустимым именем — разные ошибки,
+    /// чтобы пользователь сразу понимал, что именно нужно исправить.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let backend = self.storage_backend()?;
+        if let Compatibility::Unsupported(reason) =
+            naming::check_object_name(&self.sample_object_name()?)
+        {
+            anyhow::bail!("{}", reason);
+        }
+        let bucket = self.bucket();
+        if bucket.is_empty() {
+            anyhow::bail!("Output bucket is not set: enter or choose a bucket before recording");
+        }
+        if backend == Backend::Local {
+            if self.defer_upload {
+                anyhow::bail!("Deferred upload makes no sense for local recordings");
+            }
+            return self.validate_encoding();
+        }
+        if bucket.len() > 256
+            || !bucket
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            anyhow::bail!(
+                "Output bucket '{}' is not a valid bucket name \
+                 (allowed: letters, digits, '-', '_', '.'; up to 256 characters)",
+                bucket
+            );
+        }
+        if backend == Backend::S3 && self.defer_upload {
+            anyhow::bail!("Deferred upload is only supported for OCI, not for S3");
+        }
+        self.validate_encoding()
+    }
+
+    /// Имя объекта записи: [prefix/][filename_template].[container],
+    /// где prefix — путь после bucket в назначении `s3://` или `oci://`.
+    pub fn object_name(&self) -> String {
+        format!("{}.{}", self.object_base_name(), self.container)
+    }
+
+    /// Имя объекта без расширения: [prefix/][filename_template]. От него
+    /// строятся имена всех объектов записи — звука в режиме split_audio и сопутствующих.
+    pub fn object_base_name(&self) -> String {
+        match storage::object_destination(&self.output_folder) {
+            Some(destination) if !destination.prefix.is_empty() => {
+                format!("{}/{}", destination.prefix, self.filename_template)
+            }
+            _ => self.filename_template.clone(),
+        }
+    }
+
+    /// Имя объекта звуковой дорожки в режиме split_audio: то же базовое имя
+    /// с расширением контейнера звука (m4a, opus или flac).
+    pub fn audio_object_name(&self) -> anyhow::Result<String> {
+        let (extension, _) = self.audio_codec()?.split_container();
+        Ok(format!("{}.{}", self.object_base_name(), extension))
+    }
+
+    /// Копия параметров с шаблоном имени, раскрытым на текущий момент:
+    /// `%Y-%m-%d_%H-%M-%S` и прочие подстановки strftime. Вызывается при старте
+    /// записи, чтобы все части одной записи получили одно время.
+    pub fn with_expanded_template(&self) -> anyhow::Result<RecordParams> {
+        let mut params = self.clone();
+        params.filename_template =
+            naming::expand_template(&self.filename_template, &chrono::Local::now())?;
+        Ok(params)
+    }
+
+    /// Имя объекта для проверок до записи: время — текущее, вместо `{n}` — 1.
+    fn sample_object_name(&self) -> anyhow::Result<String> {
+        Ok(naming::with_counter(&self.with_expanded_template()?.object_name(), 1))
+    }
+
+    /// Имя bucket без схемы и префикса назначения.
+    pub fn bucket(&self) -> String {
+        match storage::object_destination(&self.output_folder) {
+            Some(destination) => destination.bucket,
+            None => self.output_folder.trim().to_string(),
+        }
+    }
+
+    /// Прямоугольник кадрирования; None, если все четыре поля нулевые.
+    /// Выход за пределы кадра проверяется при открытии потока.
+    pub fn crop(&self) -> Option<CropRect> {
+        if self.crop_x == 0 && self.crop_y == 0 && self.crop_w == 0 && self.crop_h == 0 {
+            return None;
+        }
+        Some(CropRect {
+            x: self.crop_x,
+            y: self.crop_y,
+            width: self.crop_w,
+            height: self.crop_h,
+        })
+    }
+
+    /// Хранилище, в которое выгружается запись. Путь или file:// в поле
+    /// bucket означает запись на диск, какое бы хранилище ни было выбрано.
+    pub fn storage_backend(&self) -> anyhow::Result<Backend> {
+        if storage::local_destination(&self.output_folder).is_some() {
+            return Ok(Backend::Local);
+        }
+        if let Some(destination) = storage::object_destination(&self.output_folder) {
+            return Ok(destination.backend);
+        }
+        Backend::parse(&self.backend)
+    }
+
+    /// Папка для записи на диск (без схемы file://).
+    pub fn local_folder(&self) -> PathBuf {
+        storage::local_destination(&self.output_folder)
+            .unwrap_or_else(|| PathBuf::from(self.output_folder.trim()))
+    }
+
+    /// Проверки, не зависящие от места назначения записи: метаданные, битрейт
+    /// и совместимость кодека с контейнером. Для вывода в память bucket не нужен.
+    pub fn validate_encoding(&self) -> anyhow::Result<()> {
+        oci_uploader::validate_metadata(&self.object_metadata)?;
+        portal::source_types_mask(&self.source_types)?;
+        portal::cursor_mode_value(&self.cursor_mode)?;
+        if !X264_PRESETS.contains(&self.x264_preset.as_str()) {
+            anyhow::bail!("Unknown x264 preset '{}'", self.x264_preset);
+        }
+        if !X264_TUNES.contains(&self.x264_tune.as_str()) {
+            anyhow::bail!("Unknown x264 tune '{}'", self.x264_tune);
+        }
+        if self.crf > MAX_CRF {
+            anyhow::bail!("CRF must be between 0 and {}", MAX_CRF);
+        }
+        if self.keyframe_interval_secs < 1 {
+            anyhow::bail!("Keyframe interval must be at least 1 second");
+        }
+        if !gpu::HW_ACCELS.contains(&self.hw_accel.as_str()) {
+            anyhow::bail!("Unknown hardware encoding '{}' (expected none, vaapi or nvenc)", self.hw_accel);
+        }
+        if let Some(rect) = self.crop() {
+            if rect.width == 0 || rect.height == 0 {
+                anyhow::bail!("Crop width and height must be set (or all crop fields left at 0)");
+            }
+            if self.follow_active_window {
+                anyhow::bail!("Crop cannot be combined with following the active window");
+            }
+        }
+        self.video_bitrate()?;
+        let codec = self.codec()?;
+        formats::validate_container_codec(&self.container, codec.id())?;
+        if self.split_audio {
+            if self.audio_device.is_empty() {
+                anyhow::bail!("A separate audio file needs an audio source");
+            }
+            // Оба объекта пишутся потоком прямо в хранилище.
+            if self.defer_upload || self.upload_after_finish {
+                anyhow::bail!(
+                    "A separate audio file cannot be combined with deferred upload or upload after finish"
+                );
+            }
+        }
+        if !self.audio_device.is_empty() {
+            let audio_codec = self.audio_codec()?;
+            // Отдельный звук пишется в свой контейнер, который принимает любой из кодеков.
+            if !self.split_audio {
+                if let Compatibility::Unsupported(reason) =
+                    formats::check_audio_codec(&self.container, audio_codec.id())
+                {
+                    anyhow::bail!("Cannot record {:?} audio into {}: {}", audio_codec, self.container, reason);
+                }
+            }
+            if !OPUS_APPLICATIONS.contains(&self.opus_application.as_str()) {
+                anyhow::bail!(
+                    "Unknown Opus application '{}' (expected audio, voip or lowdelay)",
+                    self.opus_application
+                );
+            }
+            audio_codec.find_encoder()?;
+            // Одно устройство, открытое дважды, дало бы эхо со сдвигом.
+            if self.audio_device_secondary == self.audio_device {
+                anyhow::bail!("The second audio source is the same device as the first");
+            }
+        }
+        // SVT-AV1 не умеет 4:4:4, а без него настоящего lossless нет.
+        if codec == VideoCodec::AV1 && matches!(self.rate_mode(), Ok(EncodingMode::Lossless)) {
+            anyhow::bail!("Lossless AV1 recording is not supported; use H264, H265 or VP9");
+        }
+        // Отсутствующий в сборке FFmpeg кодер лучше обнаружить до открытия портала.
+        codec.find_encoder()?;
+        Ok(())
+    }
+
+    /// Размер GOP в кадрах: заданный явно или интервал ключевых кадров при заданной частоте.
+    pub fn gop(&self) -> u32 {
+        match self.gop_size {
+            Some(frames) => frames.max(1),
+            None => self.keyframe_interval_secs.max(1) * self.fps.max(1),
+        }
+    }
+
+    /// Битрейт видео (кбит/с): заданный вручную или выведенный из целевого размера.
+    /// Из бюджета размера вычитается дорожка звука, если он записывается.
+    pub fn video_bitrate(&self) -> anyhow::Result<u32> {
+        match self.target_size {
+            Some(target) => {
+                rate_control::bitrate_for_target_size(
+                    target.size_mb,
+                    target.duration_secs,
+                    self.audio_kbps(),
+                )
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Target size of {} MB is too small for {} seconds",
+                            target.size_mb,
+                            target.duration_secs
+                        )
+                    })
+            }
+            None => Ok(self.bitrate),
+        }
+    }
+
+    /// Битрейт звука (кбит/с), учитываемый в бюджете размера; 0 без звука.
+    /// У FLAC битрейта нет — берётся типичный для него.
+    pub fn audio_kbps(&self) -> u32 {
+        if self.audio_device.is_empty() {
+            0
+        } else if self.audio_codec().ok() == Some(AudioCodec::Flac) {
+            FLAC_ESTIMATED_KBPS
+        } else {
+            self.audio_bitrate
+        }
+    }
+
+    /// Режим кодирования. Неизвестная строка режима не мешает записи
+    /// (пишется VBR), но попадает в предупреждения.
+    pub fn rate_mode(&self) -> anyhow::Result<EncodingMode> {
+        EncodingMode::parse(&self.encoding_mode)
+    }
+
+    /// Режимы без потерь не ограничивают битрейт.
+    pub fn is_lossless(&self) -> bool {
+        self.rate_mode().map_or(false, EncodingMode::is_lossless)
+    }
+
+    /// Формат пикселей для кодера: настоящий lossless требует YUV 4:4:4.
+    pub fn pixel_format(&self) -> ffmpeg_next::format::Pixel {
+        if matches!(self.rate_mode(), Ok(EncodingMode::Lossless)) {
+            ffmpeg_next::format::Pixel::YUV444P
+        } else {
+            ffmpeg_next::format::Pixel::YUV420P
+        }
+    }
+
+    /// Размер кадров для кодера при источнике `width`x`height`.
+    /// Размеры округляются до чётных: этого требует YUV 4:2:0.
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |target: u32, from: u32, to: u32| {
+            (target as u64 * to as u64 / from.max(1) as u64) as u32
+        };
+        let (w, h) = match (self.out_width, self.out_height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, scaled(w, width, height)),
+            (None, Some(h)) => (scaled(h, height, width), h),
+            (None, None) => (width, height),
+        };
+        ((w & !1).max(2), (h & !1).max(2))
+    }
+
+    /// Видеокодек записи; неизвестная строка кодека — ошибка конфигурации.
+    pub fn codec(&self) -> anyhow::Result<VideoCodec> {
+        VideoCodec::parse(&self.video_codec)
+    }
+
+    /// Кодек звуковой дорожки с учётом контейнера.
+    pub fn audio_codec(&self) -> anyhow::Result<AudioCodec> {
+        AudioCodec::parse(&self.audio_codec, &self.container)
+    }
+
+    /// Некритичные несоответствия кодека, контейнера и расширения:
+    /// запись возможна, но пользователя стоит предупредить.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = [
+            self.codec()
+                .map(|codec| formats::check_video_codec(&self.container, codec.id()))
+                .unwrap_or(Compatibility::Supported),
+            self.audio_codec()
+                .ok()
+                .filter(|_| !self.audio_device.is_empty() && !self.split_audio)
+                .map(|codec| formats::check_audio_codec(&self.container, codec.id()))
+                .unwrap_or(Compatibility::Supported),
+            formats::check_extension(&self.filename_template, &self.container),
+            self.sample_object_name()
+                .map(|name| naming::check_object_name(&name))
+                .unwrap_or(Compatibility::Supported),
+        ]
+        .into_iter()
+        .filter_map(|check| match check {
+            Compatibility::Warning(reason) => Some(reason),
+            _ => None,
+        })
+        .collect();
+        if let Err(e) = self.rate_mode() {
+            warnings.push(format!("{}; VBR will be used", e));
+        }
+        if self.is_lossless() {
+            warnings.push(format!(
+                "{} recordings are very large: expect tens of gigabytes per hour at desktop resolutions",
+                self.encoding_mode
+            ));
+        }
+        warnings
+    }
+}
+
+/// Можно ли выбрать строку кодека звука при контейнере `container`.
+fn audio_codec_allowed(codec: &str, container: &str) -> bool {
+    AudioCodec::parse(codec, container).map_or(true, |codec| {
+        !matches!(
+            formats::check_audio_codec(container, codec.id()),
+            Compatibility::Unsupported(_)
+        )
+    })
+}
+
+/// Измерение из поля размера: 0 означает «как у источника».
+fn spin_dimension(spin: &SpinButton) -> Option<u32> {
+    match spin.get_value_as_int() {
+        0 => None,
+        value => Some(value as u32),
+    }
+}
+
+/// Длительность из поля секунд: 0 означает «без ограничения».
+fn spin_duration(spin: &SpinButton) -> Option<u64> {
+    match spin.get_value_as_int() {
+        0 => None,
+        value => Some(value as u64),
+    }
+}
+
+/// Показывает модальный диалог с сообщением об ошибке.
+fn show_error(parent: &ApplicationWindow, message: &str) {
+    let dialog = MessageDialog::new(
+        Some(parent),
+        DialogFlags::MODAL,
+        MessageType::Error,
+        ButtonsType::Ok,
+        message,
+    );
+    dialog.run();
+    dialog.close();
+}
+
+/// Показывает модальный диалог с сообщением об успешной операции.
+fn show_info(parent: &ApplicationWindow, message: &str) {
+    let dialog = MessageDialog::new(
+        Some(parent),
+        DialogFlags::MODAL,
+        MessageType::Info,
+        ButtonsType::Ok,
+        message,
+    );
+    dialog.run();
+    dialog.close();
 }
 
-pub fn run_gui<F: Fn(RecordParams) + 'static>(callback: F) {
+/// Показывает предупреждение и спрашивает, продолжать ли. Возвращает true при OK.
+fn confirm_warning(parent: &ApplicationWindow, message: &str) -> bool {
+    let dialog = MessageDialog::new(
+        Some(parent),
+        DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::OkCancel,
+        message,
+    );
+    let response = dialog.run();
+    dialog.close();
+    response == ResponseType::Ok
+}
+
+/// Выбирает в списке строку с данным текстом; если её нет, выбор не меняется.
+fn select_combo_text(combo: &ComboBoxText, text: &str) {
+    let model = match combo.get_model() {
+        Some(model) => model,
+        None => return,
+    };
+    let iter = match model.get_iter_first() {
+        Some(iter) => iter,
+        None => return,
+    };
+    let mut index = 0;
+    loop {
+        if model.get_value(&iter, 0).get::<String>().ok().flatten().as_deref() == Some(text) {
+            combo.set_active(Some(index));
+            return;
+        }
+        index += 1;
+        if !model.iter_next(&iter) {
+            return;
+        }
+    }
+}
+
+/// Строки списка устройств звука: (идентификатор для FFmpeg, подпись).
+/// "default" всегда первый, за ним монитор вывода и источники из pactl.
+fn audio_device_entries() -> Vec<(String, String)> {
+    let mut entries = vec![
+        ("default".to_string(), "default (microphone)".to_string()),
+        (DEFAULT_MONITOR.to_string(), format!("{} (desktop audio)", DEFAULT_MONITOR)),
+    ];
+    for source in audio_capture::list_sources() {
+        let label = if source.description.is_empty() {
+            source.name.clone()
+        } else {
+            source.description
+        };
+        entries.push((source.name, label));
+    }
+    entries
+}
+
+/// Перезаполняет список устройств, если набор устройств изменился, сохраняя
+/// выбор; пропавшее устройство заменяется первой строкой ("default" или,
+/// с `with_none`, пустой строкой "(none)").
+fn refresh_audio_devices(
+    combo: &ComboBoxText,
+    known: &RefCell<Vec<(String, String)>>,
+    with_none: bool,
+) {
+    let mut entries = audio_device_entries();
+    if with_none {
+        entries.insert(0, (String::new(), "(none)".to_string()));
+    }
+    if *known.borrow() == entries {
+        return;
+    }
+    let selected = combo.get_active_id().map(|id| id.to_string());
+    combo.remove_all();
+    for (id, label) in &entries {
+        combo.append(Some(id), label);
+    }
+    *known.borrow_mut() = entries;
+    if !selected.map_or(false, |id| combo.set_active_id(Some(&id))) {
+        combo.set_active(Some(0));
+    }
+}
+
+/// Спрашивает имя пресета. None — пользователь отказался или ввёл пустое имя.
+fn ask_preset_name(parent: &ApplicationWindow, current: &str) -> Option<String> {
+    let dialog = Dialog::with_buttons(
+        Some("Save Preset"),
+        Some(parent),
+        DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Ok)],
+    );
+    let entry = Entry::new();
+    entry.set_placeholder_text(Some("e.g. 1080p stream"));
+    entry.set_text(current);
+    entry.set_activates_default(true);
+    dialog.set_default_response(ResponseType::Ok);
+    let content = dialog.get_content_area();
+    content.set_spacing(5);
+    content.pack_start(&Label::new(Some("Preset name:")), false, false, 0);
+    content.pack_start(&entry, false, false, 0);
+    dialog.show_all();
+    let response = dialog.run();
+    let name = entry.get_text().trim().to_string();
+    dialog.close();
+    if response == ResponseType::Ok && !name.is_empty() {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Спрашивает, какой из потоков портала записывать. None — пользователь отказался.
+fn choose_stream(parent: &ApplicationWindow, streams: &[StreamChoice]) -> Option<usize> {
+    let dialog = Dialog::with_buttons(
+        Some("Choose Stream"),
+        Some(parent),
+        DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Record", ResponseType::Ok)],
+    );
+    let combo = ComboBoxText::new();
+    for (position, stream) in streams.iter().enumerate() {
+        combo.append_text(&stream.label(position));
+    }
+    combo.set_active(Some(0));
+    let content = dialog.get_content_area();
+    content.set_spacing(5);
+    content.pack_start(
+        &Label::new(Some("The portal shared several streams. Which one should be recorded?")),
+        false,
+        false,
+        0,
+    );
+    content.pack_start(&combo, false, false, 0);
+    dialog.show_all();
+    let response = dialog.run();
+    let choice = combo.get_active().map(|index| index as usize);
+    dialog.close();
+    if response == ResponseType::Ok {
+        choice
+    } else {
+        None
+    }
+}
+
+/// `callback` запускает запись, `test_setup` — пробный запуск ("Test Setup");
+/// оба отвечают событиями в переданный канал.
+pub fn run_gui<F, T>(callback: F, test_setup: T)
+where
+    F: Fn(RecordParams, glib::Sender<GuiEvent>) + 'static,
+    T: Fn(RecordParams, glib::Sender<GuiEvent>) + 'static,
+{
+    let callback = Rc::new(callback);
+    let test_setup = Rc::new(test_setup);
     let app = Application::new(
         Some("com.example.screenrecorder"),
         Default::default(),
@@ -42,34 +799,132 @@ pub fn run_gui<F: Fn(RecordParams) + 'static>(callback: F) {
         vbox.set_margin_end(10);
         window.add(&vbox);
 
-        // 1. Выбор «bucket» (выходной папки – OCI bucket)
+        // 0. Пресеты: именованные наборы всех остальных полей формы
+        let preset_hbox = Box::new(Orientation::Horizontal, 5);
+        let preset_label = Label::new(Some("Preset:"));
+        let preset_combo = ComboBoxText::new();
+        let save_preset_button = Button::with_label("Save Preset");
+        let delete_preset_button = Button::with_label("Delete");
+        preset_hbox.pack_start(&preset_label, false, false, 0);
+        preset_hbox.pack_start(&preset_combo, true, true, 0);
+        preset_hbox.pack_start(&save_preset_button, false, false, 0);
+        preset_hbox.pack_start(&delete_preset_button, false, false, 0);
+        vbox.pack_start(&preset_hbox, false, false, 0);
+
+        // 1. Выбор хранилища и «bucket» (выходной папки – OCI или S3 bucket)
         let folder_hbox = Box::new(Orientation::Horizontal, 5);
+        let backend_combo = ComboBoxText::new();
+        for backend in BACKENDS {
+            backend_combo.append_text(backend);
+        }
+        backend_combo.set_active(Some(0));
+        backend_combo.set_tooltip_text(Some(
+            "oci reads OCI_* environment variables or ~/.oci/config, \
+             s3 reads S3_ENDPOINT, S3_REGION and AWS_*; \
+             local saves the recording into the chosen folder",
+        ));
         let folder_label = Label::new(Some("Output Bucket:"));
         let folder_entry = Entry::new();
-        folder_entry.set_editable(false);
         let folder_button = Button::with_label("Choose Bucket");
+        folder_hbox.pack_start(&backend_combo, false, false, 0);
         folder_hbox.pack_start(&folder_label, false, false, 0);
         folder_hbox.pack_start(&folder_entry, true, true, 0);
         folder_hbox.pack_start(&folder_button, false, false, 0);
         vbox.pack_start(&folder_hbox, false, false, 0);
 
+        // Куда уйдёт запись в OCI: регион и namespace из найденных учётных данных,
+        // только для чтения — чтобы убедиться, что выгрузка пойдёт в нужный аккаунт.
+        let oci_target_label = Label::new(None);
+        oci_target_label.set_xalign(0.0);
+        oci_target_label.set_selectable(true);
+        vbox.pack_start(&oci_target_label, false, false, 0);
+        let show_oci_target = move |backend: Option<&str>| {
+            let text = match backend {
+                Some("oci") => match OciCredentials::load() {
+                    Ok(creds) => format!("OCI region: {}, namespace: {}", creds.region(), creds.namespace()),
+                    Err(e) => format!("OCI: {:#}", e),
+                },
+                _ => String::new(),
+            };
+            oci_target_label.set_text(&text);
+        };
+        show_oci_target(backend_combo.get_active_text().as_deref());
+        backend_combo.connect_changed(move |combo| {
+            show_oci_target(combo.get_active_text().as_deref());
+        });
+
         // 2. Шаблон имени объекта
         let filename_hbox = Box::new(Orientation::Horizontal, 5);
         let filename_label = Label::new(Some("Filename Template:"));
         let filename_entry = Entry::new();
+        filename_entry.set_text(DEFAULT_FILENAME_TEMPLATE);
+        filename_entry.set_tooltip_text(Some(
+            "Object name without extension; {date}, {time}, {timestamp} and strftime placeholders \
+             (%Y-%m-%d_%H-%M-%S) are replaced with the recording start time, {uuid} with a random \
+             UUID, {n} with the next free number in the bucket",
+        ));
         filename_hbox.pack_start(&filename_label, false, false, 0);
         filename_hbox.pack_start(&filename_entry, true, true, 0);
         vbox.pack_start(&filename_hbox, false, false, 0);
 
-        // 3. Выбор контейнера: mp4 или mkv
+        // 3. Выбор контейнера: mp4, mkv или webm
         let container_hbox = Box::new(Orientation::Horizontal, 5);
         let container_label = Label::new(Some("Container:"));
         let container_combo = ComboBoxText::new();
-        container_combo.append_text("mp4");
-        container_combo.append_text("mkv");
+        for container in formats::CONTAINERS {
+            container_combo.append_text(container);
+        }
         container_combo.set_active(Some(0));
+        let codec_label = Label::new(Some("Codec:"));
+        let codec_combo = ComboBoxText::new();
+        for codec in VIDEO_CODECS {
+            codec_combo.append_text(codec);
+        }
+        codec_combo.set_active(Some(0));
+        codec_combo.set_tooltip_text(Some(
+            "H265 needs an FFmpeg build with libx265, VP9 one with libvpx",
+        ));
+        // WebM принимает только VP9/AV1: при выборе webm несовместимый кодек
+        // переключается сам.
+        {
+            let codec_combo = codec_combo.clone();
+            container_combo.connect_changed(move |combo| {
+                let container = combo.get_active_text().map(|s| s.to_string()).unwrap_or_default();
+                let current = codec_combo
+                    .get_active_text()
+                    .and_then(|text| VideoCodec::parse(&text).ok());
+                let compatible = current.map_or(false, |codec| {
+                    !matches!(
+                        formats::check_video_codec(&container, codec.id()),
+                        Compatibility::Unsupported(_)
+                    )
+                });
+                match VideoCodec::default_for_container(&container) {
+                    Some(codec) if !compatible => {
+                        select_combo_text(&codec_combo, &format!("{:?}", codec))
+                    }
+                    _ => {}
+                }
+            });
+        }
+        // И наоборот: VP9/AV1 пишутся для веба, поэтому из mp4 запись переходит в WebM.
+        {
+            let container_combo = container_combo.clone();
+            codec_combo.connect_changed(move |combo| {
+                let codec = combo.get_active_text().and_then(|text| VideoCodec::parse(&text).ok());
+                let codec = match codec {
+                    Some(codec) => codec,
+                    None => return,
+                };
+                if container_combo.get_active_text().as_deref() == Some("mp4") {
+                    select_combo_text(&container_combo, codec.default_container());
+                }
+            });
+        }
         container_hbox.pack_start(&container_label, false, false, 0);
         container_hbox.pack_start(&container_combo, false, false, 0);
+        container_hbox.pack_start(&codec_label, false, false, 0);
+        container_hbox.pack_start(&codec_combo, false, false, 0);
         vbox.pack_start(&container_hbox, false, false, 0);
 
         // 4. Задание битрейта (в килобитах)
@@ -77,39 +932,1665 @@ pub fn run_gui<F: Fn(RecordParams) + 'static>(callback: F) {
         let bitrate_label = Label::new(Some("Bitrate (kbps):"));
         let bitrate_spin = SpinButton::new_with_range(100.0, 10000.0, 100.0);
         bitrate_spin.set_value(1000.0);
+        let audio_bitrate_label = Label::new(Some("Audio (kbps):"));
+        let audio_bitrate_spin = SpinButton::new_with_range(32.0, 512.0, 16.0);
+        audio_bitrate_spin.set_value(DEFAULT_AUDIO_BITRATE_KBPS as f64);
+        audio_bitrate_spin.set_tooltip_text(Some("Bitrate of the audio track"));
+        let audio_codec_combo = ComboBoxText::new();
+        for codec in AUDIO_CODECS {
+            audio_codec_combo.append(Some(codec), codec);
+        }
+        audio_codec_combo.set_active_id(Some("auto"));
+        audio_codec_combo.set_tooltip_text(Some(
+            "auto: Opus in webm, AAC otherwise; FLAC is lossless and needs mkv",
+        ));
+        // Кодеки, которые выбранный контейнер не примет (FLAC вне mkv), видны, но серые.
+        for cell in audio_codec_combo.get_cells() {
+            let container_combo = container_combo.clone();
+            audio_codec_combo.set_cell_data_func(
+                &cell,
+                Some(std::boxed::Box::new(move |_, cell, model, iter| {
+                    let container =
+                        container_combo.get_active_text().map(|s| s.to_string()).unwrap_or_default();
+                    let codec = model.get_value(iter, 1).get::<String>().ok().flatten().unwrap_or_default();
+                    cell.set_sensitive(audio_codec_allowed(&codec, &container));
+                })),
+            );
+        }
+        let opus_application_combo = ComboBoxText::new();
+        for application in OPUS_APPLICATIONS {
+            opus_application_combo.append(Some(application), application);
+        }
+        opus_application_combo.set_active_id(Some(DEFAULT_OPUS_APPLICATION));
+        opus_application_combo.set_tooltip_text(Some(
+            "Opus tuning: audio for desktop sound and music, voip for speech (needs libopus)",
+        ));
         bitrate_hbox.pack_start(&bitrate_label, false, false, 0);
         bitrate_hbox.pack_start(&bitrate_spin, false, false, 0);
+        bitrate_hbox.pack_start(&audio_bitrate_label, false, false, 0);
+        bitrate_hbox.pack_start(&audio_bitrate_spin, false, false, 0);
+        bitrate_hbox.pack_start(&audio_codec_combo, false, false, 0);
+        bitrate_hbox.pack_start(&opus_application_combo, false, false, 0);
         vbox.pack_start(&bitrate_hbox, false, false, 0);
 
-        // 5. Режим кодирования: CBR или VBR
+        // Режим Opus доступен, только когда звук пишется в Opus; у FLAC нет битрейта.
+        // Кодек, который новый контейнер не примет, сменяется на auto.
+        let update_opus_application = {
+            let container_combo = container_combo.clone();
+            let audio_codec_combo = audio_codec_combo.clone();
+            let opus_application_combo = opus_application_combo.clone();
+            let audio_bitrate_spin = audio_bitrate_spin.clone();
+            Rc::new(move || {
+                let container = container_combo.get_active_text().map(|s| s.to_string()).unwrap_or_default();
+                let mut codec = audio_codec_combo.get_active_id().map(|s| s.to_string()).unwrap_or_default();
+                if !audio_codec_allowed(&codec, &container) {
+                    audio_codec_combo.set_active_id(Some("auto"));
+                    codec = "auto".to_string();
+                }
+                let codec = AudioCodec::parse(&codec, &container).ok();
+                opus_application_combo.set_sensitive(codec == Some(AudioCodec::Opus));
+                audio_bitrate_spin.set_sensitive(codec != Some(AudioCodec::Flac));
+            })
+        };
+        update_opus_application();
+        {
+            let update = update_opus_application.clone();
+            container_combo.connect_changed(move |_| update());
+        }
+        {
+            let update = update_opus_application.clone();
+            audio_codec_combo.connect_changed(move |_| update());
+        }
+
+        // Частота кадров записи
+        let fps_hbox = Box::new(Orientation::Horizontal, 5);
+        let fps_label = Label::new(Some("Frame rate (fps):"));
+        let fps_spin = SpinButton::new_with_range(MIN_FPS as f64, MAX_FPS as f64, 1.0);
+        fps_spin.set_value(DEFAULT_FPS as f64);
+        fps_hbox.pack_start(&fps_label, false, false, 0);
+        fps_hbox.pack_start(&fps_spin, false, false, 0);
+        vbox.pack_start(&fps_hbox, false, false, 0);
+
+        // Размер записи: 0 — как у источника; одно заданное измерение сохраняет пропорции.
+        let size_hbox = Box::new(Orientation::Horizontal, 5);
+        let size_label = Label::new(Some("Output size:"));
+        let out_width_spin = SpinButton::new_with_range(0.0, 7680.0, 16.0);
+        let size_x_label = Label::new(Some("x"));
+        let out_height_spin = SpinButton::new_with_range(0.0, 4320.0, 16.0);
+        out_width_spin.set_tooltip_text(Some("0 keeps the source width"));
+        out_height_spin.set_tooltip_text(Some("0 keeps the source height"));
+        size_hbox.pack_start(&size_label, false, false, 0);
+        size_hbox.pack_start(&out_width_spin, false, false, 0);
+        size_hbox.pack_start(&size_x_label, false, false, 0);
+        size_hbox.pack_start(&out_height_spin, false, false, 0);
+        vbox.pack_start(&size_hbox, false, false, 0);
+
+        // 4a. Битрейт из целевого размера файла и длительности записи
+        let target_hbox = Box::new(Orientation::Horizontal, 5);
+        let target_check = CheckButton::with_label("Target size (MB):");
+        let target_size_spin = SpinButton::new_with_range(1.0, 100000.0, 10.0);
+        target_size_spin.set_value(100.0);
+        let target_duration_label = Label::new(Some("Duration (s):"));
+        let target_duration_spin = SpinButton::new_with_range(1.0, 86400.0, 60.0);
+        target_duration_spin.set_value(600.0);
+        let target_bitrate_label = Label::new(None);
+        target_hbox.pack_start(&target_check, false, false, 0);
+        target_hbox.pack_start(&target_size_spin, false, false, 0);
+        target_hbox.pack_start(&target_duration_label, false, false, 0);
+        target_hbox.pack_start(&target_duration_spin, false, false, 0);
+        target_hbox.pack_start(&target_bitrate_label, false, false, 0);
+        vbox.pack_start(&target_hbox, false, false, 0);
+
+        // Список устройств звука заполняется ниже, но нужен уже здесь: без звука
+        // весь бюджет размера отдаётся видео.
+        let audio_combo = ComboBoxText::new();
+        // Выведенный битрейт показывается только для чтения, ручной ввод блокируется.
+        let update_target_bitrate = {
+            let target_check = target_check.clone();
+            let target_size_spin = target_size_spin.clone();
+            let target_duration_spin = target_duration_spin.clone();
+            let bitrate_spin = bitrate_spin.clone();
+            let container_combo = container_combo.clone();
+            let audio_combo = audio_combo.clone();
+            let audio_bitrate_spin = audio_bitrate_spin.clone();
+            let audio_codec_combo = audio_codec_combo.clone();
+            move || {
+                let active = target_check.get_active();
+                bitrate_spin.set_sensitive(!active);
+                target_size_spin.set_sensitive(active);
+                target_duration_spin.set_sensitive(active);
+                if !active {
+                    target_bitrate_label.set_text("");
+                    return;
+                }
+                // Звук вычитается из бюджета так же, как при записи: с оценкой для FLAC.
+                let audio_kbps = RecordParams {
+                    container: container_combo.get_active_text().map(|s| s.to_string()).unwrap_or_default(),
+                    audio_device: audio_combo.get_active_id().map(|s| s.to_string()).unwrap_or_default(),
+                    audio_bitrate: audio_bitrate_spin.get_value_as_int() as u32,
+                    audio_codec: audio_codec_combo.get_active_id().map(|s| s.to_string()).unwrap_or_default(),
+                    ..RecordParams::default()
+                }
+                .audio_kbps();
+                let derived = rate_control::bitrate_for_target_size(
+                    target_size_spin.get_value_as_int() as u32,
+                    target_duration_spin.get_value_as_int() as u64,
+                    audio_kbps,
+                );
+                target_bitrate_label.set_text(&match derived {
+                    Some(kbps) => format!("= {} kbps", kbps),
+                    None => "too small".to_string(),
+                });
+            }
+        };
+        update_target_bitrate();
+        let update_target_bitrate = Rc::new(update_target_bitrate);
+        {
+            let update = update_target_bitrate.clone();
+            target_check.connect_toggled(move |_| update());
+        }
+        {
+            let update = update_target_bitrate.clone();
+            target_size_spin.connect_value_changed(move |_| update());
+        }
+        {
+            let update = update_target_bitrate.clone();
+            target_duration_spin.connect_value_changed(move |_| update());
+        }
+        {
+            let update = update_target_bitrate.clone();
+            audio_bitrate_spin.connect_value_changed(move |_| update());
+        }
+        {
+            let update = update_target_bitrate.clone();
+            audio_codec_combo.connect_changed(move |_| update());
+        }
+        {
+            let update = update_target_bitrate.clone();
+            container_combo.connect_changed(move |_| update());
+        }
+
+        // 5. Режим кодирования: CBR, VBR, постоянное качество или пресеты без потерь
         let mode_hbox = Box::new(Orientation::Horizontal, 5);
         let mode_label = Label::new(Some("Encoding Mode:"));
         let cbr_radio = RadioButton::with_label(None, "CBR");
         let vbr_radio = RadioButton::with_label_from_widget(&cbr_radio, "VBR");
+        let crf_radio = RadioButton::with_label_from_widget(&cbr_radio, "CRF");
+        crf_radio.set_tooltip_text(Some(
+            "Constant quality: no bitrate target, size depends on content",
+        ));
+        let crf_spin = SpinButton::new_with_range(0.0, MAX_CRF as f64, 1.0);
+        crf_spin.set_value(DEFAULT_CRF as f64);
+        crf_spin.set_tooltip_text(Some("0 is best quality, 51 is worst; 18-28 is typical"));
+        crf_spin.set_sensitive(false);
+        let lossless_radio = RadioButton::with_label_from_widget(&cbr_radio, "Lossless");
+        lossless_radio.set_tooltip_text(Some("Pixel-perfect (qp=0, YUV 4:4:4); very large files"));
+        let near_lossless_radio = RadioButton::with_label_from_widget(&cbr_radio, "Near-lossless");
+        near_lossless_radio.set_tooltip_text(Some("crf=0 with 4:2:0 chroma; large files"));
         mode_hbox.pack_start(&mode_label, false, false, 0);
         mode_hbox.pack_start(&cbr_radio, false, false, 0);
         mode_hbox.pack_start(&vbr_radio, false, false, 0);
+        mode_hbox.pack_start(&crf_radio, false, false, 0);
+        mode_hbox.pack_start(&crf_spin, false, false, 0);
+        mode_hbox.pack_start(&lossless_radio, false, false, 0);
+        mode_hbox.pack_start(&near_lossless_radio, false, false, 0);
         vbox.pack_start(&mode_hbox, false, false, 0);
 
+        // Битрейт нужен только CBR и VBR; качество CRF — только режиму CRF.
+        {
+            let bitrate_hbox = bitrate_hbox.clone();
+            let target_hbox = target_hbox.clone();
+            let crf_spin = crf_spin.clone();
+            let update = move |uses_bitrate: bool, crf: bool| {
+                bitrate_hbox.set_sensitive(uses_bitrate);
+                target_hbox.set_sensitive(uses_bitrate);
+                crf_spin.set_sensitive(crf);
+            };
+            let update = Rc::new(update);
+            let radios = [&cbr_radio, &vbr_radio, &crf_radio, &lossless_radio, &near_lossless_radio];
+            for radio in &radios {
+                let update = update.clone();
+                let cbr_radio = cbr_radio.clone();
+                let vbr_radio = vbr_radio.clone();
+                let crf_radio = crf_radio.clone();
+                radio.connect_toggled(move |_| {
+                    let uses_bitrate = cbr_radio.get_active() || vbr_radio.get_active();
+                    update(uses_bitrate, crf_radio.get_active());
+                });
+            }
+        }
+
         // 6. Устройство для захвата звука
         let audio_hbox = Box::new(Orientation::Horizontal, 5);
         let audio_label = Label::new(Some("Audio Device:"));
-        let audio_combo = ComboBoxText::new();
-        // Пример заполнения: в реальном приложении можно получить список устройств через API
-        audio_combo.append_text("default");
-        audio_combo.append_text("Device 1");
-        audio_combo.append_text("Device 2");
-        audio_combo.set_active(Some(0));
+        {
+            let update = update_target_bitrate.clone();
+            audio_combo.connect_changed(move |_| update());
+        }
+        // Идентификатор строки — имя устройства для FFmpeg, текст — описание.
+        let audio_devices: Rc<RefCell<Vec<(String, String)>>> = Rc::new(RefCell::new(Vec::new()));
+        refresh_audio_devices(&audio_combo, &audio_devices, false);
+        // Второй источник сводится с первым: например, микрофон к звуку рабочего стола.
+        let audio_secondary_combo = ComboBoxText::new();
+        let audio_secondary_devices: Rc<RefCell<Vec<(String, String)>>> =
+            Rc::new(RefCell::new(Vec::new()));
+        refresh_audio_devices(&audio_secondary_combo, &audio_secondary_devices, true);
+        // Устройства, подключённые после запуска, появляются при открытии списка.
+        {
+            let audio_devices = audio_devices.clone();
+            audio_combo.connect_property_popup_shown_notify(move |combo| {
+                if combo.get_property_popup_shown() {
+                    refresh_audio_devices(combo, &audio_devices, false);
+                }
+            });
+        }
+        {
+            let audio_secondary_devices = audio_secondary_devices.clone();
+            audio_secondary_combo.connect_property_popup_shown_notify(move |combo| {
+                if combo.get_property_popup_shown() {
+                    refresh_audio_devices(combo, &audio_secondary_devices, true);
+                }
+            });
+        }
+        // Явный пересмотр — для тем GTK, где список открывается без popup-shown.
+        let audio_rescan_button = Button::with_label("Rescan");
+        {
+            let audio_combo = audio_combo.clone();
+            let audio_secondary_combo = audio_secondary_combo.clone();
+            audio_rescan_button.connect_clicked(move |_| {
+                refresh_audio_devices(&audio_combo, &audio_devices, false);
+                refresh_audio_devices(&audio_secondary_combo, &audio_secondary_devices, true);
+            });
+        }
+        audio_combo.set_tooltip_text(Some(&format!(
+            "\"default\" records the default microphone, \"{}\" the desktop audio",
+            DEFAULT_MONITOR
+        )));
+        let audio_gain_label = Label::new(Some("Gain (%):"));
+        let audio_gain_spin = SpinButton::new_with_range(0.0, 400.0, 10.0);
+        audio_gain_spin.set_value(100.0);
         audio_hbox.pack_start(&audio_label, false, false, 0);
         audio_hbox.pack_start(&audio_combo, false, false, 0);
+        audio_hbox.pack_start(&audio_gain_label, false, false, 0);
+        audio_hbox.pack_start(&audio_gain_spin, false, false, 0);
+        audio_hbox.pack_start(&audio_rescan_button, false, false, 0);
         vbox.pack_start(&audio_hbox, false, false, 0);
 
-        // Кнопка "Start Recording"
+        let audio_secondary_hbox = Box::new(Orientation::Horizontal, 5);
+        let audio_secondary_label = Label::new(Some("Mix with:"));
+        audio_secondary_combo.set_tooltip_text(Some(
+            "A second source mixed into the same audio track, e.g. the microphone over desktop audio",
+        ));
+        let audio_secondary_gain_label = Label::new(Some("Gain (%):"));
+        let audio_secondary_gain_spin = SpinButton::new_with_range(0.0, 400.0, 10.0);
+        audio_secondary_gain_spin.set_value(100.0);
+        audio_secondary_hbox.pack_start(&audio_secondary_label, false, false, 0);
+        audio_secondary_hbox.pack_start(&audio_secondary_combo, false, false, 0);
+        audio_secondary_hbox.pack_start(&audio_secondary_gain_label, false, false, 0);
+        audio_secondary_hbox.pack_start(&audio_secondary_gain_spin, false, false, 0);
+        vbox.pack_start(&audio_secondary_hbox, false, false, 0);
+
+        // 7. Индикатор уровня микрофона (VU meter): работает, пока выбрано устройство,
+        // в том числе до начала записи.
+        let meter_hbox = Box::new(Orientation::Horizontal, 5);
+        let meter_label = Label::new(Some("Level:"));
+        let meter_bar = LevelBar::new_for_interval(0.0, 1.0);
+        let peak_label = Label::new(Some("peak -inf dB"));
+        meter_hbox.pack_start(&meter_label, false, false, 0);
+        meter_hbox.pack_start(&meter_bar, true, true, 0);
+        meter_hbox.pack_start(&peak_label, false, false, 0);
+        vbox.pack_start(&meter_hbox, false, false, 0);
+
+        let (level_sender, level_receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        level_receiver.attach(None, move |level: AudioLevel| {
+            meter_bar.set_value(AudioLevel::to_meter(level.rms));
+            peak_label.set_text(&format!("peak {:.0} dB", AudioLevel::to_db(level.peak)));
+            glib::Continue(true)
+        });
+
+        // Флаг остановки текущего монитора; при смене устройства старый поток гасится.
+        let meter_stop: Rc<RefCell<Option<Arc<AtomicBool>>>> = Rc::new(RefCell::new(None));
+        let restart_meter = {
+            let meter_stop = meter_stop.clone();
+            move |device: Option<String>| {
+                if let Some(stop) = meter_stop.borrow_mut().take() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                if let Some(device) = device {
+                    let stop = audio_meter::spawn_level_monitor(device, level_sender.clone());
+                    *meter_stop.borrow_mut() = Some(stop);
+                }
+            }
+        };
+        restart_meter(audio_combo.get_active_id().map(|s| s.to_string()));
+        audio_combo.connect_changed(move |combo| {
+            restart_meter(combo.get_active_id().map(|s| s.to_string()));
+        });
+        window.connect_destroy(move |_| {
+            if let Some(stop) = meter_stop.borrow_mut().take() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+
+        // 8. Отложенная выгрузка: запись во временный файл, выгрузка по кнопке
+        let upload_hbox = Box::new(Orientation::Horizontal, 5);
+        let defer_check = CheckButton::with_label("Defer upload");
+        let upload_button = Button::with_label("Upload now");
+        upload_button.set_sensitive(false);
+        // Запись сначала целиком в локальный файл, выгрузка — после его закрытия
+        let finish_check = CheckButton::with_label("Upload after finish");
+        finish_check.set_tooltip_text(Some(
+            "Record into a local file and upload it once the recording is closed: \
+             produces a regular (non-fragmented) mp4 and never leaves a broken object",
+        ));
+        // Видео и звук — отдельными объектами с одним базовым именем
+        let split_check = CheckButton::with_label("Separate audio file");
+        split_check.set_tooltip_text(Some(
+            "Upload video and audio as two objects with the same base name \
+             (e.g. .mp4 and .m4a); their timestamps line up for remuxing",
+        ));
+        upload_hbox.pack_start(&defer_check, false, false, 0);
+        upload_hbox.pack_start(&finish_check, false, false, 0);
+        upload_hbox.pack_start(&split_check, false, false, 0);
+        upload_hbox.pack_start(&upload_button, false, false, 0);
+        vbox.pack_start(&upload_hbox, false, false, 0);
+
+        // 9. Скрываемые области: "x,y,w,h[,blur|black]" через точку с запятой
+        let redact_hbox = Box::new(Orientation::Horizontal, 5);
+        let redact_label = Label::new(Some("Hide Regions:"));
+        let redact_entry = Entry::new();
+        redact_entry.set_placeholder_text(Some("x,y,w,h[,black]; ..."));
+        redact_hbox.pack_start(&redact_label, false, false, 0);
+        let redact_pick_button = Button::with_label("Pick on Screen");
+        redact_hbox.pack_start(&redact_entry, true, true, 0);
+        redact_hbox.pack_start(&redact_pick_button, false, false, 0);
+        vbox.pack_start(&redact_hbox, false, false, 0);
+
+        // Выбор области мышью в оверлее поверх экрана: прямоугольник дописывается в список.
+        {
+            let redact_entry = redact_entry.clone();
+            let win_clone = window.clone();
+            redact_pick_button.connect_clicked(move |_| {
+                let redact_entry = redact_entry.clone();
+                region_picker::pick_region(&win_clone, move |x, y, w, h| {
+                    let current = redact_entry.get_text().trim().to_string();
+                    let region = format!("{},{},{},{}", x, y, w, h);
+                    if current.is_empty() {
+                        redact_entry.set_text(&region);
+                    } else {
+                        redact_entry.set_text(&format!("{}; {}", current, region));
+                    }
+                });
+            });
+        }
+
+        // 10. Теги объекта: небольшой редактор пар ключ/значение
+        let tags_vbox = Box::new(Orientation::Vertical, 5);
+        let tags_header = Box::new(Orientation::Horizontal, 5);
+        let tags_label = Label::new(Some("Object Tags:"));
+        let add_tag_button = Button::with_label("Add Tag");
+        tags_header.pack_start(&tags_label, false, false, 0);
+        tags_header.pack_end(&add_tag_button, false, false, 0);
+        tags_vbox.pack_start(&tags_header, false, false, 0);
+        vbox.pack_start(&tags_vbox, false, false, 0);
+
+        let tag_rows: Rc<RefCell<Vec<(Box, Entry, Entry)>>> = Rc::new(RefCell::new(Vec::new()));
+        let add_tag_row = {
+            let tag_rows = tag_rows.clone();
+            let tags_vbox = tags_vbox.clone();
+            Rc::new(move |key: &str, value: &str| {
+                let row = Box::new(Orientation::Horizontal, 5);
+                let key_entry = Entry::new();
+                key_entry.set_placeholder_text(Some("key"));
+                key_entry.set_text(key);
+                let value_entry = Entry::new();
+                value_entry.set_placeholder_text(Some("value"));
+                value_entry.set_text(value);
+                let remove_button = Button::with_label("Remove");
+                row.pack_start(&key_entry, true, true, 0);
+                row.pack_start(&value_entry, true, true, 0);
+                row.pack_start(&remove_button, false, false, 0);
+                tags_vbox.pack_start(&row, false, false, 0);
+                row.show_all();
+
+                let tag_rows_for_remove = tag_rows.clone();
+                let tags_vbox_for_remove = tags_vbox.clone();
+                let row_for_remove = row.clone();
+                remove_button.connect_clicked(move |_| {
+                    tag_rows_for_remove
+                        .borrow_mut()
+                        .retain(|(r, _, _)| r != &row_for_remove);
+                    tags_vbox_for_remove.remove(&row_for_remove);
+                });
+                tag_rows.borrow_mut().push((row, key_entry, value_entry));
+            })
+        };
+        {
+            let add_tag_row = add_tag_row.clone();
+            add_tag_button.connect_clicked(move |_| add_tag_row("", ""));
+        }
+
+        // 11. Состояние конвейера: очередь кодирования, невыгруженные байты,
+        // выброшенные кадры. Глубину очереди можно менять во время записи.
+        let pipeline_hbox = Box::new(Orientation::Horizontal, 5);
+        let queue_label = Label::new(Some("Queue: -"));
+        let backlog_label = Label::new(Some("Upload backlog: -"));
+        let dropped_label = Label::new(Some("Dropped: -"));
+        let depth_label = Label::new(Some("Queue depth:"));
+        let depth_spin = SpinButton::new_with_range(1.0, 120.0, 1.0);
+        depth_spin.set_value(DEFAULT_QUEUE_DEPTH as f64);
+        pipeline_hbox.pack_start(&queue_label, false, false, 0);
+        pipeline_hbox.pack_start(&backlog_label, false, false, 0);
+        pipeline_hbox.pack_start(&dropped_label, false, false, 0);
+        pipeline_hbox.pack_end(&depth_spin, false, false, 0);
+        pipeline_hbox.pack_end(&depth_label, false, false, 0);
+        vbox.pack_start(&pipeline_hbox, false, false, 0);
+
+        // Ход записи: длительность, записанный объём и фактическая частота кадров.
+        // Частота, упавшая до нуля во время записи, означает, что кодер встал.
+        let recording_label = Label::new(Some("Recording: -"));
+        vbox.pack_start(&recording_label, false, false, 0);
+
+        // Строка состояния: этап последней записи или первая строка ошибки,
+        // чтобы без терминала было видно, что происходит.
+        let status_label = Label::new(Some("Idle"));
+        status_label.set_xalign(0.0);
+        status_label.set_line_wrap(true);
+        vbox.pack_start(&status_label, false, false, 0);
+        let status_label_for_test = status_label.clone();
+
+        // Ход выгрузки последней запущенной записи: выгружено из записанного.
+        let upload_progress = ProgressBar::new();
+        upload_progress.set_show_text(true);
+        upload_progress.set_text(Some("Upload: -"));
+        vbox.pack_start(&upload_progress, false, false, 0);
+        // Итог выгрузки (Done или ошибка) показан: таймер его не затирает.
+        let upload_settled = Rc::new(Cell::new(false));
+
+        let window_hbox = Box::new(Orientation::Horizontal, 5);
+        let window_label = Label::new(Some("Buffer window (ms):"));
+        let window_spin = SpinButton::new_with_range(0.0, 10000.0, 250.0);
+        window_spin.set_value(DEFAULT_BUFFER_WINDOW_MS as f64);
+        window_hbox.pack_end(&window_spin, false, false, 0);
+        window_hbox.pack_end(&window_label, false, false, 0);
+        vbox.pack_start(&window_hbox, false, false, 0);
+
+        let current_stats: Rc<RefCell<Option<Arc<PipelineStats>>>> = Rc::new(RefCell::new(None));
+        {
+            let current_stats = current_stats.clone();
+            depth_spin.connect_value_changed(move |spin| {
+                if let Some(stats) = current_stats.borrow().as_ref() {
+                    stats.set_queue_depth(spin.get_value_as_int() as usize);
+                }
+            });
+        }
+        {
+            let current_stats = current_stats.clone();
+            window_spin.connect_value_changed(move |spin| {
+                if let Some(stats) = current_stats.borrow().as_ref() {
+                    stats.set_buffer_window_ms(spin.get_value_as_int() as u64);
+                }
+            });
+        }
+
+        // Предпросмотр: уменьшенные кадры идущей записи. Кадры появляются, когда
+        // портал отдал поток; флажок включает и выключает их на лету.
+        let preview_check = CheckButton::with_label("Show preview");
+        preview_check.set_tooltip_text(Some(
+            "Show the captured frames while recording (downscaled, up to 15 fps)",
+        ));
+        let preview_image = Image::new();
+        preview_image.set_no_show_all(true);
+        vbox.pack_start(&preview_check, false, false, 0);
+        vbox.pack_start(&preview_image, false, false, 0);
+        {
+            let current_stats = current_stats.clone();
+            let preview_image = preview_image.clone();
+            preview_check.connect_toggled(move |check| {
+                let enabled = check.get_active();
+                if let Some(stats) = current_stats.borrow().as_ref() {
+                    stats.set_preview(enabled);
+                }
+                if !enabled {
+                    preview_image.clear();
+                    preview_image.hide();
+                }
+            });
+        }
+        {
+            let current_stats = current_stats.clone();
+            let upload_progress = upload_progress.clone();
+            let upload_settled = upload_settled.clone();
+            // Кадров закодировано к прошлому тику и когда: для частоты за интервал.
+            let last_tick = Cell::new((0u64, Instant::now()));
+            glib::timeout_add_local(500, move || {
+                if let Some(stats) = current_stats.borrow().as_ref() {
+                    let encoded = stats.frames_encoded.load(Ordering::Relaxed);
+                    let (last_encoded, last_at) = last_tick.replace((encoded, Instant::now()));
+                    let fps = encoded.saturating_sub(last_encoded) as f64
+                        / last_at.elapsed().as_secs_f64().max(0.001);
+                    let elapsed = stats.elapsed().as_secs();
+                    recording_label.set_text(&format!(
+                        "Recording: {:02}:{:02}:{:02}, {:.1} MiB, {:.1} fps ({} frames)",
+                        elapsed / 3600,
+                        elapsed / 60 % 60,
+                        elapsed % 60,
+                        stats.upload.bytes_written.load(Ordering::Relaxed) as f64 / 1048576.0,
+                        fps,
+                        encoded
+                    ));
+                    let written = stats.upload.bytes_written.load(Ordering::Relaxed);
+                    let uploaded = stats.upload.bytes_uploaded.load(Ordering::Relaxed);
+                    if written > 0 && !upload_settled.get() {
+                        upload_progress.set_fraction(uploaded as f64 / written as f64);
+                        upload_progress.set_text(Some(&format!(
+                            "Uploaded {:.1} of {:.1} MiB ({} parts)",
+                            uploaded as f64 / 1048576.0,
+                            written as f64 / 1048576.0,
+                            stats.upload.parts_uploaded.load(Ordering::Relaxed)
+                        )));
+                    }
+                    queue_label.set_text(&format!(
+                        "Queue: {}",
+                        stats.frames_queued.load(Ordering::Relaxed)
+                    ));
+                    backlog_label.set_text(&format!(
+                        "Upload backlog: {} KiB",
+                        stats.upload_bytes_pending.load(Ordering::Relaxed) / 1024
+                    ));
+                    dropped_label.set_text(&format!(
+                        "Dropped: {}",
+                        stats.frames_dropped.load(Ordering::Relaxed)
+                    ));
+                }
+                glib::Continue(true)
+            });
+        }
+
+        // 12. Ключевые кадры на смене сцены (например, при переключении слайдов)
+        let scene_hbox = Box::new(Orientation::Horizontal, 5);
+        let scene_check = CheckButton::with_label("Keyframe on scene change");
+        let scene_label = Label::new(Some("Sensitivity:"));
+        let scene_spin = SpinButton::new_with_range(1.0, 100.0, 1.0);
+        scene_spin.set_value(50.0);
+        scene_spin.set_sensitive(false);
+        scene_hbox.pack_start(&scene_check, false, false, 0);
+        scene_hbox.pack_start(&scene_label, false, false, 0);
+        scene_hbox.pack_start(&scene_spin, false, false, 0);
+        vbox.pack_start(&scene_hbox, false, false, 0);
+        {
+            let scene_spin = scene_spin.clone();
+            scene_check.connect_toggled(move |check| {
+                scene_spin.set_sensitive(check.get_active());
+            });
+        }
+
+        // 13. Переменная частота кадров: новый кадр только при изменении экрана
+        let damage_check = CheckButton::with_label("Damage-driven frame rate (VFR)");
+        vbox.pack_start(&damage_check, false, false, 0);
+
+        // 14. Следование за активным окном (где композитор это позволяет)
+        let follow_check = CheckButton::with_label("Follow active window");
+        follow_check.set_tooltip_text(Some(
+            "Crops the recording to the focused window as focus changes (X11/XWayland only)",
+        ));
+        vbox.pack_start(&follow_check, false, false, 0);
+
+        // Что записывать: тип источника для диалога портала
+        let source_hbox = Box::new(Orientation::Horizontal, 5);
+        let source_label = Label::new(Some("Source:"));
+        let source_combo = ComboBoxText::new();
+        for source in portal::SOURCE_TYPES {
+            source_combo.append_text(source);
+        }
+        source_combo.set_active(Some(0));
+        source_combo.set_tooltip_text(Some(
+            "Which sources the screen sharing dialog offers: whole monitors, single windows or both",
+        ));
+        source_hbox.pack_start(&source_label, false, false, 0);
+        source_hbox.pack_start(&source_combo, false, false, 0);
+        vbox.pack_start(&source_hbox, false, false, 0);
+
+        // Курсор в записи: портал встраивает его в кадр
+        let cursor_hbox = Box::new(Orientation::Horizontal, 5);
+        let cursor_label = Label::new(Some("Mouse cursor:"));
+        let cursor_combo = ComboBoxText::new();
+        for mode in portal::CURSOR_MODES {
+            cursor_combo.append_text(mode);
+        }
+        select_combo_text(&cursor_combo, "embedded");
+        cursor_combo.set_tooltip_text(Some(
+            "metadata is recorded as embedded for now: a separate cursor layer is not composited yet",
+        ));
+        cursor_hbox.pack_start(&cursor_label, false, false, 0);
+        cursor_hbox.pack_start(&cursor_combo, false, false, 0);
+        vbox.pack_start(&cursor_hbox, false, false, 0);
+
+        // Дополнительные настройки, которые нужны редко
+        let advanced_expander = Expander::new(Some("Advanced"));
+        let advanced_vbox = Box::new(Orientation::Vertical, 5);
+        advanced_expander.add(&advanced_vbox);
+        vbox.pack_start(&advanced_expander, false, false, 0);
+
+        // Соотношение сторон: SAR (пикселя) или DAR (кадра) в виде "num:den"
+        let aspect_hbox = Box::new(Orientation::Horizontal, 5);
+        let aspect_label = Label::new(Some("Aspect Ratio:"));
+        let aspect_kind_combo = ComboBoxText::new();
+        aspect_kind_combo.append_text("SAR");
+        aspect_kind_combo.append_text("DAR");
+        aspect_kind_combo.set_active(Some(0));
+        let aspect_entry = Entry::new();
+        aspect_entry.set_placeholder_text(Some("1:1"));
+        aspect_hbox.pack_start(&aspect_label, false, false, 0);
+        aspect_hbox.pack_start(&aspect_kind_combo, false, false, 0);
+        aspect_hbox.pack_start(&aspect_entry, true, true, 0);
+        advanced_vbox.pack_start(&aspect_hbox, false, false, 0);
+
+        // Диагностика производительности: CSV с временем этапов по кадрам
+        let timing_check = CheckButton::with_label("Log per-frame timings (diagnostics)");
+        advanced_vbox.pack_start(&timing_check, false, false, 0);
+
+        // Записи, которые потом склеиваются без перекодирования
+        let concat_check = CheckButton::with_label("Concatenation-friendly keyframes");
+        advanced_vbox.pack_start(&concat_check, false, false, 0);
+
+        // Метки (главы) от внешней автоматизации через управляющий сокет
+        let control_check = CheckButton::with_label("Accept markers on control socket");
+        control_check.set_tooltip_text(Some(
+            "Listens on $XDG_RUNTIME_DIR/rscap-control-<pid>-<n>.sock (shown in the status line); \
+             send \"MARK <label>\" to add a chapter",
+        ));
+        advanced_vbox.pack_start(&control_check, false, false, 0);
+
+        // Повторы CreateSession для нестабильных бэкендов портала
+        let attempts_hbox = Box::new(Orientation::Horizontal, 5);
+        let attempts_label = Label::new(Some("Portal session attempts:"));
+        let attempts_spin = SpinButton::new_with_range(1.0, 10.0, 1.0);
+        attempts_spin.set_value(portal::DEFAULT_CREATE_SESSION_ATTEMPTS as f64);
+        attempts_hbox.pack_start(&attempts_label, false, false, 0);
+        attempts_hbox.pack_start(&attempts_spin, false, false, 0);
+        advanced_vbox.pack_start(&attempts_hbox, false, false, 0);
+
+        // GPU для аппаратного кодирования (встроенная или дискретная)
+        let gpu_hbox = Box::new(Orientation::Horizontal, 5);
+        let gpu_label = Label::new(Some("GPU:"));
+        let gpu_combo = ComboBoxText::new();
+        gpu_combo.append(None, "Default");
+        let render_nodes = gpu::render_nodes();
+        for node in &render_nodes {
+            gpu_combo.append(Some(&node.path.to_string_lossy()), &node.label());
+        }
+        gpu_combo.set_active(Some(0));
+        gpu_hbox.pack_start(&gpu_label, false, false, 0);
+        gpu_hbox.pack_start(&gpu_combo, true, true, 0);
+        advanced_vbox.pack_start(&gpu_hbox, false, false, 0);
+
+        // Аппаратное кодирование на выбранной выше GPU
+        let hw_accel_hbox = Box::new(Orientation::Horizontal, 5);
+        let hw_accel_label = Label::new(Some("Hardware encoding:"));
+        let hw_accel_combo = ComboBoxText::new();
+        for mode in gpu::HW_ACCELS {
+            hw_accel_combo.append_text(mode);
+        }
+        hw_accel_combo.set_active(Some(0));
+        hw_accel_combo.set_tooltip_text(Some(
+            "VAAPI (Intel/AMD) or NVENC (NVIDIA) encodes H.264/H.265 on the GPU; \
+             falls back to software if it cannot start",
+        ));
+        hw_accel_hbox.pack_start(&hw_accel_label, false, false, 0);
+        hw_accel_hbox.pack_start(&hw_accel_combo, false, false, 0);
+        advanced_vbox.pack_start(&hw_accel_hbox, false, false, 0);
+
+        // HTTP-статус для headless-использования: GET /status, POST /stop
+        let status_hbox = Box::new(Orientation::Horizontal, 5);
+        let status_check = CheckButton::with_label("Status endpoint on localhost port");
+        let status_port_spin = SpinButton::new_with_range(1024.0, 65535.0, 1.0);
+        status_port_spin.set_value(status::DEFAULT_STATUS_PORT as f64);
+        status_port_spin.set_sensitive(false);
+        {
+            let status_port_spin = status_port_spin.clone();
+            status_check.connect_toggled(move |check| {
+                status_port_spin.set_sensitive(check.get_active());
+            });
+        }
+        status_hbox.pack_start(&status_check, false, false, 0);
+        status_hbox.pack_start(&status_port_spin, false, false, 0);
+        advanced_vbox.pack_start(&status_hbox, false, false, 0);
+
+        // Миниатюры для перемотки: интервал (с) и ширина (px)
+        let thumbs_hbox = Box::new(Orientation::Horizontal, 5);
+        let thumbs_check = CheckButton::with_label("Thumbnail sprite every");
+        let thumbs_interval_spin = SpinButton::new_with_range(1.0, 600.0, 1.0);
+        thumbs_interval_spin.set_value(10.0);
+        let thumbs_width_label = Label::new(Some("s, width:"));
+        let thumbs_width_spin = SpinButton::new_with_range(64.0, 640.0, 16.0);
+        thumbs_width_spin.set_value(160.0);
+        thumbs_hbox.pack_start(&thumbs_check, false, false, 0);
+        thumbs_hbox.pack_start(&thumbs_interval_spin, false, false, 0);
+        thumbs_hbox.pack_start(&thumbs_width_label, false, false, 0);
+        thumbs_hbox.pack_start(&thumbs_width_spin, false, false, 0);
+        advanced_vbox.pack_start(&thumbs_hbox, false, false, 0);
+
+        // Предел одновременных записей
+        let max_recordings_hbox = Box::new(Orientation::Horizontal, 5);
+        let max_recordings_label = Label::new(Some("Max concurrent recordings:"));
+        let max_recordings_spin = SpinButton::new_with_range(1.0, 16.0, 1.0);
+        max_recordings_spin.set_value(DEFAULT_MAX_RECORDINGS as f64);
+        max_recordings_hbox.pack_start(&max_recordings_label, false, false, 0);
+        max_recordings_hbox.pack_start(&max_recordings_spin, false, false, 0);
+        advanced_vbox.pack_start(&max_recordings_hbox, false, false, 0);
+
+        // Параметры кодирования внутри самого битстрима (SEI)
+        let provenance_check = CheckButton::with_label("Embed encoding parameters as SEI");
+        provenance_check.set_tooltip_text(Some(
+            "Keeps codec, rate mode and source size in the H.264 stream even if container metadata is stripped",
+        ));
+        advanced_vbox.pack_start(&provenance_check, false, false, 0);
+
+        // Суперсэмплинг: захват в полном разрешении и качественное уменьшение
+        let supersample_hbox = Box::new(Orientation::Horizontal, 5);
+        let supersample_label = Label::new(Some("Supersample (downscale factor):"));
+        let supersample_spin = SpinButton::new_with_range(1.0, MAX_SUPERSAMPLE_FACTOR as f64, 1.0);
+        supersample_spin.set_value(1.0);
+        supersample_spin.set_tooltip_text(Some(
+            "Records the full source resolution and downscales it with Lanczos for crisper text; \
+             limited by the source size",
+        ));
+        supersample_hbox.pack_start(&supersample_label, false, false, 0);
+        supersample_hbox.pack_start(&supersample_spin, false, false, 0);
+        advanced_vbox.pack_start(&supersample_hbox, false, false, 0);
+
+        // Перезапуск после временных сбоев (для записи без присмотра)
+        let restarts_hbox = Box::new(Orientation::Horizontal, 5);
+        let restarts_label = Label::new(Some("Restart on transient failure (times):"));
+        let restarts_spin = SpinButton::new_with_range(0.0, 100.0, 1.0);
+        restarts_spin.set_value(0.0);
+        restarts_hbox.pack_start(&restarts_label, false, false, 0);
+        restarts_hbox.pack_start(&restarts_spin, false, false, 0);
+        advanced_vbox.pack_start(&restarts_hbox, false, false, 0);
+
+        // Размер части многочастной выгрузки
+        let part_size_hbox = Box::new(Orientation::Horizontal, 5);
+        let part_size_label = Label::new(Some("Upload part size (MB):"));
+        let part_size_spin = SpinButton::new_with_range(1.0, 1024.0, 1.0);
+        part_size_spin.set_value(DEFAULT_PART_SIZE_MB as f64);
+        part_size_spin.set_tooltip_text(Some(
+            "Recordings are uploaded in parts of this size while recording; \
+             a larger part uses more memory but fewer requests",
+        ));
+        part_size_hbox.pack_start(&part_size_label, false, false, 0);
+        part_size_hbox.pack_start(&part_size_spin, false, false, 0);
+        advanced_vbox.pack_start(&part_size_hbox, false, false, 0);
+
+        // Повторы временных сбоев выгрузки; ошибки доступа и 4xx не повторяются
+        let retries_hbox = Box::new(Orientation::Horizontal, 5);
+        let retries_label = Label::new(Some("Upload retries:"));
+        let retries_spin = SpinButton::new_with_range(0.0, 20.0, 1.0);
+        retries_spin.set_value(DEFAULT_MAX_RETRIES as f64);
+        retries_spin.set_tooltip_text(Some(
+            "How many times a failed part is re-sent after a network error or a 5xx response, \
+             with exponentially growing pauses",
+        ));
+        retries_hbox.pack_start(&retries_label, false, false, 0);
+        retries_hbox.pack_start(&retries_spin, false, false, 0);
+        advanced_vbox.pack_start(&retries_hbox, false, false, 0);
+
+        // Ограничение длительности для записи по расписанию
+        let duration_hbox = Box::new(Orientation::Horizontal, 5);
+        let duration_label = Label::new(Some("Stop after (seconds, 0 = never):"));
+        let duration_spin = SpinButton::new_with_range(0.0, 7.0 * 24.0 * 3600.0, 60.0);
+        duration_spin.set_value(0.0);
+        duration_hbox.pack_start(&duration_label, false, false, 0);
+        duration_hbox.pack_start(&duration_spin, false, false, 0);
+        advanced_vbox.pack_start(&duration_hbox, false, false, 0);
+
+        // Запись части экрана: прямоугольник в пикселях источника
+        let crop_hbox = Box::new(Orientation::Horizontal, 5);
+        let crop_label = Label::new(Some("Crop (x, y, width, height):"));
+        crop_hbox.pack_start(&crop_label, false, false, 0);
+        let crop_spins: Vec<SpinButton> = (0..4)
+            .map(|_| {
+                let spin = SpinButton::new_with_range(0.0, 16384.0, 2.0);
+                spin.set_value(0.0);
+                crop_hbox.pack_start(&spin, false, false, 0);
+                spin
+            })
+            .collect();
+        crop_hbox.set_tooltip_text(Some(
+            "Records only this rectangle of the source; all zeros records the full frame",
+        ));
+        advanced_vbox.pack_start(&crop_hbox, false, false, 0);
+
+        // Интервал ключевых кадров: перемотка и размер фрагментов при выгрузке
+        let keyframe_hbox = Box::new(Orientation::Horizontal, 5);
+        let keyframe_label = Label::new(Some("Keyframe every (seconds):"));
+        let keyframe_spin = SpinButton::new_with_range(1.0, 60.0, 1.0);
+        keyframe_spin.set_value(DEFAULT_KEYFRAME_INTERVAL_SECS as f64);
+        keyframe_spin.set_tooltip_text(Some(
+            "Shorter intervals seek faster but make the file larger",
+        ));
+        keyframe_hbox.pack_start(&keyframe_label, false, false, 0);
+        keyframe_hbox.pack_start(&keyframe_spin, false, false, 0);
+        let gop_label = Label::new(Some("or GOP (frames):"));
+        let gop_spin = SpinButton::new_with_range(0.0, 10000.0, 1.0);
+        gop_spin.set_value(0.0);
+        gop_spin.set_tooltip_text(Some(
+            "Exact keyframe distance in frames; 0 derives it from seconds",
+        ));
+        keyframe_hbox.pack_start(&gop_label, false, false, 0);
+        keyframe_hbox.pack_start(&gop_spin, false, false, 0);
+        advanced_vbox.pack_start(&keyframe_hbox, false, false, 0);
+
+        // Скорость программного H.264: медленные пресеты теряют кадры на слабом CPU
+        let x264_hbox = Box::new(Orientation::Horizontal, 5);
+        let x264_label = Label::new(Some("x264 preset / tune:"));
+        let x264_preset_combo = ComboBoxText::new();
+        for preset in X264_PRESETS {
+            x264_preset_combo.append_text(preset);
+        }
+        select_combo_text(&x264_preset_combo, DEFAULT_X264_PRESET);
+        let x264_tune_combo = ComboBoxText::new();
+        for tune in X264_TUNES {
+            x264_tune_combo.append(Some(tune), if tune.is_empty() { "(none)" } else { tune });
+        }
+        x264_tune_combo.set_active(Some(0));
+        x264_hbox.pack_start(&x264_label, false, false, 0);
+        x264_hbox.pack_start(&x264_preset_combo, false, false, 0);
+        x264_hbox.pack_start(&x264_tune_combo, false, false, 0);
+        advanced_vbox.pack_start(&x264_hbox, false, false, 0);
+
+        // Глобальное сочетание для запуска и остановки записи; пустое — без сочетания
+        let hotkey_hbox = Box::new(Orientation::Horizontal, 5);
+        let hotkey_label = Label::new(Some("Global shortcut:"));
+        let hotkey_entry = Entry::new();
+        hotkey_entry.set_text(DEFAULT_HOTKEY);
+        hotkey_entry.set_tooltip_text(Some(
+            "For example <Super>r or Ctrl+Alt+F9; leave empty to disable. \
+             On Wayland the desktop asks to confirm the shortcut",
+        ));
+        let hotkey_button = Button::with_label("Bind");
+        let hotkey_status_label = Label::new(None);
+        hotkey_hbox.pack_start(&hotkey_label, false, false, 0);
+        hotkey_hbox.pack_start(&hotkey_entry, false, false, 0);
+        hotkey_hbox.pack_start(&hotkey_button, false, false, 0);
+        hotkey_hbox.pack_start(&hotkey_status_label, false, false, 0);
+        advanced_vbox.pack_start(&hotkey_hbox, false, false, 0);
+
+        // Параметры записи из формы (Err — текст ошибки разбора поля) и обратное
+        // заполнение формы из пресета. Пресет и настройки строятся из тех же параметров.
+        let read_params = {
+            let backend_combo = backend_combo.clone();
+            let folder_entry = folder_entry.clone();
+            let filename_entry = filename_entry.clone();
+            let container_combo = container_combo.clone();
+            let codec_combo = codec_combo.clone();
+            let bitrate_spin = bitrate_spin.clone();
+            let fps_spin = fps_spin.clone();
+            let out_width_spin = out_width_spin.clone();
+            let out_height_spin = out_height_spin.clone();
+            let target_check = target_check.clone();
+            let target_size_spin = target_size_spin.clone();
+            let target_duration_spin = target_duration_spin.clone();
+            let cbr_radio = cbr_radio.clone();
+            let lossless_radio = lossless_radio.clone();
+            let near_lossless_radio = near_lossless_radio.clone();
+            let crf_radio = crf_radio.clone();
+            let audio_combo = audio_combo.clone();
+            let audio_secondary_combo = audio_secondary_combo.clone();
+            let audio_gain_spin = audio_gain_spin.clone();
+            let audio_secondary_gain_spin = audio_secondary_gain_spin.clone();
+            let audio_bitrate_spin = audio_bitrate_spin.clone();
+            let audio_codec_combo = audio_codec_combo.clone();
+            let opus_application_combo = opus_application_combo.clone();
+            let defer_check = defer_check.clone();
+            let redact_entry = redact_entry.clone();
+            let tag_rows = tag_rows.clone();
+            let depth_spin = depth_spin.clone();
+            let window_spin = window_spin.clone();
+            let scene_check = scene_check.clone();
+            let scene_spin = scene_spin.clone();
+            let damage_check = damage_check.clone();
+            let follow_check = follow_check.clone();
+            let aspect_kind_combo = aspect_kind_combo.clone();
+            let aspect_entry = aspect_entry.clone();
+            let timing_check = timing_check.clone();
+            let concat_check = concat_check.clone();
+            let control_check = control_check.clone();
+            let attempts_spin = attempts_spin.clone();
+            let gpu_combo = gpu_combo.clone();
+            let status_check = status_check.clone();
+            let status_port_spin = status_port_spin.clone();
+            let thumbs_check = thumbs_check.clone();
+            let thumbs_interval_spin = thumbs_interval_spin.clone();
+            let thumbs_width_spin = thumbs_width_spin.clone();
+            let max_recordings_spin = max_recordings_spin.clone();
+            let provenance_check = provenance_check.clone();
+            let supersample_spin = supersample_spin.clone();
+            let restarts_spin = restarts_spin.clone();
+            let finish_check = finish_check.clone();
+            let split_check = split_check.clone();
+            let retries_spin = retries_spin.clone();
+            let crf_spin = crf_spin.clone();
+            let x264_preset_combo = x264_preset_combo.clone();
+            let x264_tune_combo = x264_tune_combo.clone();
+            let keyframe_spin = keyframe_spin.clone();
+            let gop_spin = gop_spin.clone();
+            let hw_accel_combo = hw_accel_combo.clone();
+            let crop_spins = crop_spins.clone();
+            let duration_spin = duration_spin.clone();
+            let source_combo = source_combo.clone();
+            let cursor_combo = cursor_combo.clone();
+            let part_size_spin = part_size_spin.clone();
+            let preview_check = preview_check.clone();
+            Rc::new(move || -> Result<RecordParams, String> {
+                let output_folder = folder_entry.get_text().trim().to_string();
+                let backend = backend_combo
+                    .get_active_text()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "oci".to_string());
+                let filename_template = filename_entry.get_text().trim().to_string();
+                let container = container_combo
+                    .get_active_text()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "mp4".to_string());
+                let video_codec = codec_combo
+                    .get_active_text()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "H264".to_string());
+                let bitrate = bitrate_spin.get_value_as_int() as u32;
+                let encoding_mode = if cbr_radio.get_active() {
+                    "CBR".to_string()
+                } else if crf_radio.get_active() {
+                    "CRF".to_string()
+                } else if lossless_radio.get_active() {
+                    "Lossless".to_string()
+                } else if near_lossless_radio.get_active() {
+                    "Near-lossless".to_string()
+                } else {
+                    "VBR".to_string()
+                };
+                let audio_device = audio_combo
+                    .get_active_id()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "default".to_string());
+                let audio_device_secondary = audio_secondary_combo
+                    .get_active_id()
+                    .map(|s| s.to_string())
+                    .unwrap_or_default();
+
+                let redact_regions = match RedactRegion::parse_list(&redact_entry.get_text()) {
+                    Ok(regions) => regions,
+                    Err(e) => {
+                        return Err(e.to_string());
+                    }
+                };
+
+                let aspect_ratio = match AspectRatio::parse(
+                    &aspect_entry.get_text(),
+                    aspect_kind_combo.get_active() == Some(1),
+                ) {
+                    Ok(ratio) => ratio,
+                    Err(e) => {
+                        return Err(e.to_string());
+                    }
+                };
+
+                let params = RecordParams {
+                    output_folder,
+                    backend,
+                    filename_template,
+                    container,
+                    video_codec,
+                    bitrate,
+                    fps: fps_spin.get_value_as_int() as u32,
+                    out_width: spin_dimension(&out_width_spin),
+                    out_height: spin_dimension(&out_height_spin),
+                    target_size: if target_check.get_active() {
+                        Some(TargetSize {
+                            size_mb: target_size_spin.get_value_as_int() as u32,
+                            duration_secs: target_duration_spin.get_value_as_int() as u64,
+                        })
+                    } else {
+                        None
+                    },
+                    encoding_mode,
+                    audio_device,
+                    audio_device_secondary,
+                    audio_gain_percent: audio_gain_spin.get_value_as_int() as u32,
+                    audio_secondary_gain_percent: audio_secondary_gain_spin.get_value_as_int() as u32,
+                    audio_bitrate: audio_bitrate_spin.get_value_as_int() as u32,
+                    audio_codec: audio_codec_combo
+                        .get_active_id()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| "auto".to_string()),
+                    opus_application: opus_application_combo
+                        .get_active_id()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| DEFAULT_OPUS_APPLICATION.to_string()),
+                    defer_upload: defer_check.get_active(),
+                    redact_regions,
+                    queue_depth: depth_spin.get_value_as_int() as usize,
+                    buffer_window_ms: window_spin.get_value_as_int() as u64,
+                    damage_driven: damage_check.get_active(),
+                    follow_active_window: follow_check.get_active(),
+                    aspect_ratio,
+                    timing_log: timing_check.get_active(),
+                    concat_friendly: concat_check.get_active(),
+                    control_socket: control_check.get_active(),
+                    embed_provenance: provenance_check.get_active(),
+                    supersample_factor: supersample_spin.get_value_as_int() as u32,
+                    max_restarts: restarts_spin.get_value_as_int() as u32,
+                    upload_after_finish: finish_check.get_active(),
+                    split_audio: split_check.get_active(),
+                    upload_retries: retries_spin.get_value_as_int() as u32,
+                    crf: crf_spin.get_value_as_int() as u32,
+                    x264_preset: x264_preset_combo
+                        .get_active_text()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| DEFAULT_X264_PRESET.to_string()),
+                    x264_tune: x264_tune_combo
+                        .get_active_id()
+                        .map(|s| s.to_string())
+                        .unwrap_or_default(),
+                    keyframe_interval_secs: keyframe_spin.get_value_as_int() as u32,
+                    gop_size: spin_dimension(&gop_spin),
+                    hw_accel: hw_accel_combo
+                        .get_active_text()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| "none".to_string()),
+                    crop_x: crop_spins[0].get_value_as_int() as u32,
+                    crop_y: crop_spins[1].get_value_as_int() as u32,
+                    crop_w: crop_spins[2].get_value_as_int() as u32,
+                    crop_h: crop_spins[3].get_value_as_int() as u32,
+                    max_duration_secs: spin_duration(&duration_spin),
+                    source_types: source_combo
+                        .get_active_text()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| "any".to_string()),
+                    cursor_mode: cursor_combo
+                        .get_active_text()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| "embedded".to_string()),
+                    node_id: None,
+                    upload_part_size_mb: part_size_spin.get_value_as_int() as u32,
+                    preview: preview_check.get_active(),
+                    max_concurrent_recordings: max_recordings_spin.get_value_as_int() as usize,
+                    portal_attempts: attempts_spin.get_value_as_int() as u32,
+                    status_port: if status_check.get_active() {
+                        Some(status_port_spin.get_value_as_int() as u16)
+                    } else {
+                        None
+                    },
+                    thumbnails: if thumbs_check.get_active() {
+                        Some(ThumbnailSettings {
+                            interval_secs: thumbs_interval_spin.get_value_as_int() as u32,
+                            width: thumbs_width_spin.get_value_as_int() as u32,
+                        })
+                    } else {
+                        None
+                    },
+                    render_node: gpu_combo.get_active_id().map(|id| PathBuf::from(id.as_str())),
+                    scene_change_sensitivity: if scene_check.get_active() {
+                        Some(scene_spin.get_value_as_int() as u32)
+                    } else {
+                        None
+                    },
+                    object_metadata: tag_rows
+                        .borrow()
+                        .iter()
+                        .map(|(_, key, value)| {
+                            (key.get_text().trim().to_string(), value.get_text().to_string())
+                        })
+                        .filter(|(key, value)| !(key.is_empty() && value.is_empty()))
+                        .collect(),
+                };
+                Ok(params)
+            })
+        };
+        let apply_preset = {
+            let backend_combo = backend_combo.clone();
+            let folder_entry = folder_entry.clone();
+            let filename_entry = filename_entry.clone();
+            let container_combo = container_combo.clone();
+            let codec_combo = codec_combo.clone();
+            let bitrate_spin = bitrate_spin.clone();
+            let fps_spin = fps_spin.clone();
+            let out_width_spin = out_width_spin.clone();
+            let out_height_spin = out_height_spin.clone();
+            let target_check = target_check.clone();
+            let target_size_spin = target_size_spin.clone();
+            let target_duration_spin = target_duration_spin.clone();
+            let cbr_radio = cbr_radio.clone();
+            let vbr_radio = vbr_radio.clone();
+            let lossless_radio = lossless_radio.clone();
+            let near_lossless_radio = near_lossless_radio.clone();
+            let crf_radio = crf_radio.clone();
+            let audio_combo = audio_combo.clone();
+            let audio_secondary_combo = audio_secondary_combo.clone();
+            let audio_gain_spin = audio_gain_spin.clone();
+            let audio_secondary_gain_spin = audio_secondary_gain_spin.clone();
+            let audio_bitrate_spin = audio_bitrate_spin.clone();
+            let audio_codec_combo = audio_codec_combo.clone();
+            let opus_application_combo = opus_application_combo.clone();
+            let defer_check = defer_check.clone();
+            let redact_entry = redact_entry.clone();
+            let tag_rows = tag_rows.clone();
+            let tags_vbox = tags_vbox.clone();
+            let add_tag_row = add_tag_row.clone();
+            let depth_spin = depth_spin.clone();
+            let window_spin = window_spin.clone();
+            let scene_check = scene_check.clone();
+            let scene_spin = scene_spin.clone();
+            let damage_check = damage_check.clone();
+            let follow_check = follow_check.clone();
+            let aspect_kind_combo = aspect_kind_combo.clone();
+            let aspect_entry = aspect_entry.clone();
+            let timing_check = timing_check.clone();
+            let concat_check = concat_check.clone();
+            let control_check = control_check.clone();
+            let attempts_spin = attempts_spin.clone();
+            let gpu_combo = gpu_combo.clone();
+            let status_check = status_check.clone();
+            let status_port_spin = status_port_spin.clone();
+            let thumbs_check = thumbs_check.clone();
+            let thumbs_interval_spin = thumbs_interval_spin.clone();
+            let thumbs_width_spin = thumbs_width_spin.clone();
+            let max_recordings_spin = max_recordings_spin.clone();
+            let provenance_check = provenance_check.clone();
+            let supersample_spin = supersample_spin.clone();
+            let restarts_spin = restarts_spin.clone();
+            let finish_check = finish_check.clone();
+            let split_check = split_check.clone();
+            let retries_spin = retries_spin.clone();
+            let crf_spin = crf_spin.clone();
+            let x264_preset_combo = x264_preset_combo.clone();
+            let x264_tune_combo = x264_tune_combo.clone();
+            let hotkey_entry = hotkey_entry.clone();
+            let keyframe_spin = keyframe_spin.clone();
+            let gop_spin = gop_spin.clone();
+            let hw_accel_combo = hw_accel_combo.clone();
+            let crop_spins = crop_spins.clone();
+            let duration_spin = duration_spin.clone();
+            let source_combo = source_combo.clone();
+            let cursor_combo = cursor_combo.clone();
+            let part_size_spin = part_size_spin.clone();
+            let preview_check = preview_check.clone();
+            move |preset: &Preset| {
+                folder_entry.set_text(&preset.output_folder);
+                // Пресеты до появления S3 хранилище не сохраняли: это OCI.
+                select_combo_text(
+                    &backend_combo,
+                    if preset.backend.is_empty() { "oci" } else { &preset.backend },
+                );
+                filename_entry.set_text(&preset.filename_template);
+                // Кодек раньше контейнера: выбор VP9/AV1 сам переключает mp4 на WebM.
+                select_combo_text(&codec_combo, &preset.video_codec);
+                select_combo_text(&container_combo, &preset.container);
+                bitrate_spin.set_value(preset.bitrate as f64);
+                fps_spin.set_value(if preset.fps == 0 { DEFAULT_FPS } else { preset.fps } as f64);
+                out_width_spin.set_value(preset.out_width.unwrap_or(0) as f64);
+                out_height_spin.set_value(preset.out_height.unwrap_or(0) as f64);
+                target_check.set_active(preset.target_size.is_some());
+                if let Some(target) = preset.target_size {
+                    target_size_spin.set_value(target.size_mb as f64);
+                    target_duration_spin.set_value(target.duration_secs as f64);
+                }
+                match preset.encoding_mode.as_str() {
+                    "VBR" => vbr_radio.set_active(true),
+                    "CRF" => crf_radio.set_active(true),
+                    "Lossless" => lossless_radio.set_active(true),
+                    "Near-lossless" => near_lossless_radio.set_active(true),
+                    _ => cbr_radio.set_active(true),
+                }
+                audio_combo.set_active_id(Some(&preset.audio_device));
+                if !audio_secondary_combo.set_active_id(Some(&preset.audio_device_secondary)) {
+                    audio_secondary_combo.set_active(Some(0));
+                }
+                audio_gain_spin.set_value(preset.audio_gain_percent.unwrap_or(100) as f64);
+                audio_secondary_gain_spin
+                    .set_value(preset.audio_secondary_gain_percent.unwrap_or(100) as f64);
+                // В пресетах старых версий битрейта звука нет (0).
+                audio_bitrate_spin.set_value(if preset.audio_bitrate == 0 {
+                    DEFAULT_AUDIO_BITRATE_KBPS
+                } else {
+                    preset.audio_bitrate
+                } as f64);
+                // Пустые строки — пресет без выбора кодека звука.
+                audio_codec_combo.set_active_id(Some(if preset.audio_codec.is_empty() {
+                    "auto"
+                } else {
+                    &preset.audio_codec
+                }));
+                opus_application_combo.set_active_id(Some(if preset.opus_application.is_empty() {
+                    DEFAULT_OPUS_APPLICATION
+                } else {
+                    &preset.opus_application
+                }));
+                defer_check.set_active(preset.defer_upload);
+                redact_entry.set_text(&preset.redact_regions);
+                for (row, _, _) in tag_rows.borrow_mut().drain(..) {
+                    tags_vbox.remove(&row);
+                }
+                for (key, value) in &preset.object_metadata {
+                    add_tag_row(key, value);
+                }
+                depth_spin.set_value(preset.queue_depth as f64);
+                window_spin.set_value(preset.buffer_window_ms as f64);
+                scene_check.set_active(preset.scene_change_sensitivity.is_some());
+                if let Some(sensitivity) = preset.scene_change_sensitivity {
+                    scene_spin.set_value(sensitivity as f64);
+                }
+                damage_check.set_active(preset.damage_driven);
+                follow_check.set_active(preset.follow_active_window);
+                aspect_kind_combo.set_active(Some(if preset.aspect_display { 1 } else { 0 }));
+                aspect_entry.set_text(&preset.aspect_ratio);
+                timing_check.set_active(preset.timing_log);
+                concat_check.set_active(preset.concat_friendly);
+                control_check.set_active(preset.control_socket);
+                attempts_spin.set_value(preset.portal_attempts as f64);
+                // Узла из пресета может не быть на этой машине: тогда выбор по умолчанию.
+                if !preset
+                    .render_node
+                    .as_deref()
+                    .map_or(false, |node| gpu_combo.set_active_id(Some(node)))
+                {
+                    gpu_combo.set_active(Some(0));
+                }
+                status_check.set_active(preset.status_port.is_some());
+                if let Some(port) = preset.status_port {
+                    status_port_spin.set_value(port as f64);
+                }
+                thumbs_check.set_active(preset.thumbnails.is_some());
+                if let Some(thumbnails) = preset.thumbnails {
+                    thumbs_interval_spin.set_value(thumbnails.interval_secs as f64);
+                    thumbs_width_spin.set_value(thumbnails.width as f64);
+                }
+                max_recordings_spin.set_value(preset.max_concurrent_recordings as f64);
+                provenance_check.set_active(preset.embed_provenance);
+                supersample_spin.set_value(preset.supersample_factor as f64);
+                restarts_spin.set_value(preset.max_restarts as f64);
+                finish_check.set_active(preset.upload_after_finish);
+                split_check.set_active(preset.split_audio);
+                retries_spin.set_value(preset.upload_retries.unwrap_or(DEFAULT_MAX_RETRIES) as f64);
+                crf_spin.set_value(if preset.encoding_mode == "CRF" {
+                    preset.crf
+                } else {
+                    DEFAULT_CRF
+                } as f64);
+                select_combo_text(&x264_preset_combo, &preset.x264_preset);
+                x264_tune_combo.set_active_id(Some(&preset.x264_tune));
+                // В пресетах старых версий интервала нет (0).
+                keyframe_spin.set_value(if preset.keyframe_interval_secs == 0 {
+                    DEFAULT_KEYFRAME_INTERVAL_SECS
+                } else {
+                    preset.keyframe_interval_secs
+                } as f64);
+                gop_spin.set_value(preset.gop_size.unwrap_or(0) as f64);
+                select_combo_text(&hw_accel_combo, &preset.hw_accel);
+                let crop = [preset.crop_x, preset.crop_y, preset.crop_w, preset.crop_h];
+                for (spin, value) in crop_spins.iter().zip(crop) {
+                    spin.set_value(value as f64);
+                }
+                duration_spin.set_value(preset.max_duration_secs.unwrap_or(0) as f64);
+                select_combo_text(
+                    &source_combo,
+                    if preset.source_types.is_empty() { "any" } else { &preset.source_types },
+                );
+                select_combo_text(
+                    &cursor_combo,
+                    if preset.cursor_mode.is_empty() { "embedded" } else { &preset.cursor_mode },
+                );
+                part_size_spin.set_value(if preset.upload_part_size_mb == 0 {
+                    DEFAULT_PART_SIZE_MB
+                } else {
+                    preset.upload_part_size_mb
+                } as f64);
+                // None — пресет старой версии, сочетание по умолчанию.
+                hotkey_entry.set_text(preset.hotkey.as_deref().unwrap_or(DEFAULT_HOTKEY));
+                preview_check.set_active(preset.preview);
+            }
+        };
+        // Пресет — те же параметры, что уходят в запись, плюс сочетание клавиш.
+        let snapshot_preset = {
+            let hotkey_entry = hotkey_entry.clone();
+            Rc::new(move |params: &RecordParams| Preset {
+                hotkey: Some(hotkey_entry.get_text().to_string()),
+                ..Preset::from(params)
+            })
+        };
+        // Форма открывается такой, какой была при последнем старте записи.
+        if let Some(settings) = config::load_settings() {
+            apply_preset(&settings);
+        }
+
+        let presets = Rc::new(RefCell::new(match presets::load_presets() {
+            Ok(presets) => presets,
+            Err(e) => {
+                warn!("Failed to load presets: {:?}", e);
+                Default::default()
+            }
+        }));
+        let refill_preset_combo = {
+            let preset_combo = preset_combo.clone();
+            let presets = presets.clone();
+            Rc::new(move |active: Option<&str>| {
+                preset_combo.remove_all();
+                for name in presets.borrow().keys() {
+                    preset_combo.append(Some(name), name);
+                }
+                if let Some(active) = active {
+                    preset_combo.set_active_id(Some(active));
+                }
+            })
+        };
+        refill_preset_combo(None);
+        {
+            let presets = presets.clone();
+            preset_combo.connect_changed(move |combo| {
+                let name = match combo.get_active_id() {
+                    Some(name) => name,
+                    None => return,
+                };
+                if let Some(preset) = presets.borrow().get(name.as_str()) {
+                    apply_preset(preset);
+                }
+            });
+        }
+        {
+            let presets = presets.clone();
+            let preset_combo = preset_combo.clone();
+            let refill = refill_preset_combo.clone();
+            let read_params = read_params.clone();
+            let snapshot_preset = snapshot_preset.clone();
+            let win_clone = window.clone();
+            save_preset_button.connect_clicked(move |_| {
+                let params = match read_params() {
+                    Ok(params) => params,
+                    Err(e) => {
+                        show_error(&win_clone, &e);
+                        return;
+                    }
+                };
+                let current = preset_combo.get_active_id().map(|id| id.to_string());
+                let name = match ask_preset_name(&win_clone, current.as_deref().unwrap_or("")) {
+                    Some(name) => name,
+                    None => return,
+                };
+                presets.borrow_mut().insert(name.clone(), snapshot_preset(&params));
+                if let Err(e) = presets::save_presets(&presets.borrow()) {
+                    show_error(&win_clone, &format!("Failed to save presets: {:?}", e));
+                }
+                refill(Some(&name));
+            });
+        }
+        {
+            let win_clone = window.clone();
+            delete_preset_button.connect_clicked(move |_| {
+                let name = match preset_combo.get_active_id() {
+                    Some(name) => name.to_string(),
+                    None => return,
+                };
+                presets.borrow_mut().remove(&name);
+                if let Err(e) = presets::save_presets(&presets.borrow()) {
+                    show_error(&win_clone, &format!("Failed to save presets: {:?}", e));
+                }
+                refill_preset_combo(None);
+            });
+        }
+
+        // Кнопки "Start Recording" / "Stop Recording" и пробный запуск без записи
+        let record_hbox = Box::new(Orientation::Horizontal, 5);
         let start_button = Button::with_label("Start Recording");
-        vbox.pack_start(&start_button, false, false, 0);
+        let stop_button = Button::with_label("Stop Recording");
+        let test_button = Button::with_label("Test Setup");
+        test_button.set_tooltip_text(Some(
+            "Check the encoder, storage and screen sharing portal without recording",
+        ));
+        stop_button.set_sensitive(false);
+        record_hbox.pack_start(&start_button, true, true, 0);
+        record_hbox.pack_start(&stop_button, true, true, 0);
+        record_hbox.pack_start(&test_button, false, false, 0);
+        vbox.pack_start(&record_hbox, false, false, 0);
+
+        // Кнопка старта недоступна, пока идёт предельное число записей;
+        // кнопка остановки доступна, пока идёт хотя бы одна.
+        let active_recordings: Rc<RefCell<Vec<Arc<PipelineStats>>>> = Rc::new(RefCell::new(Vec::new()));
+        // Значок в трее нажимает те же кнопки, поэтому запись из трея идёт
+        // тем же путём, что из окна, а окно можно свернуть.
+        let tray = {
+            let start_button = start_button.clone();
+            let stop_button = stop_button.clone();
+            let window = window.clone();
+            Rc::new(RefCell::new(Tray::new(
+                move || start_button.clicked(),
+                move || stop_button.clicked(),
+                move || window.close(),
+            )))
+        };
+        let update_start_button = {
+            let start_button = start_button.clone();
+            let stop_button = stop_button.clone();
+            let max_recordings_spin = max_recordings_spin.clone();
+            let active_recordings = active_recordings.clone();
+            let tray = tray.clone();
+            Rc::new(move || {
+                let limit = max_recordings_spin.get_value_as_int() as usize;
+                let running = active_recordings.borrow().len();
+                let allowed = running < limit;
+                start_button.set_sensitive(allowed);
+                if allowed {
+                    start_button.set_tooltip_text(None);
+                } else {
+                    start_button.set_tooltip_text(Some(&format!(
+                        "{} recording(s) already running; the limit is set in Advanced",
+                        running
+                    )));
+                }
+                stop_button.set_sensitive(running > 0);
+                tray.borrow_mut().update(allowed, running > 0);
+            })
+        };
+        // Остановка не обрывает запись: поток захвата выходит из цикла, кодер
+        // дописывает буферизованные кадры, контейнер закрывается и выгружается.
+        // Кнопки вернутся в исходное состояние по событию RecordingFinished.
+        {
+            let active_recordings = active_recordings.clone();
+            stop_button.connect_clicked(move |button| {
+                for stats in active_recordings.borrow().iter() {
+                    stats.request_stop();
+                }
+                button.set_sensitive(false);
+            });
+        }
+        {
+            let update = update_start_button.clone();
+            max_recordings_spin.connect_value_changed(move |_| update());
+        }
+
+        // События от потоков записи/выгрузки обрабатываются в главном потоке.
+        let (event_sender, event_receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let pending_uploads: Rc<RefCell<Vec<PendingUpload>>> = Rc::new(RefCell::new(Vec::new()));
+        {
+            let pending_uploads = pending_uploads.clone();
+            let upload_button = upload_button.clone();
+            let test_button = test_button.clone();
+            let start_button = start_button.clone();
+            let stop_button = stop_button.clone();
+            let hotkey_status_label = hotkey_status_label.clone();
+            let preview_check = preview_check.clone();
+            let preview_image = preview_image.clone();
+            let window = window.clone();
+            event_receiver.attach(None, move |event: GuiEvent| {
+                match event {
+                    GuiEvent::RecordingStarted(stats) => {
+                        status_label.set_text("Waiting for screen selection…");
+                        upload_settled.set(false);
+                        upload_progress.set_fraction(0.0);
+                        upload_progress.set_text(Some("Upload: -"));
+                        *current_stats.borrow_mut() = Some(stats.clone());
+                        active_recordings.borrow_mut().push(stats);
+                        update_start_button();
+                    }
+                    GuiEvent::RecordingFinished(stats) => {
+                        active_recordings.borrow_mut().retain(|s| !Arc::ptr_eq(s, &stats));
+                        update_start_button();
+                        preview_image.clear();
+                        preview_image.hide();
+                    }
+                    GuiEvent::Preview(image) => {
+                        // Кадр, отправленный до выключения флажка, уже не нужен.
+                        if preview_check.get_active() {
+                            let rowstride = image.width as i32 * 3;
+                            let pixbuf = Pixbuf::from_mut_slice(
+                                image.pixels,
+                                Colorspace::Rgb,
+                                false,
+                                8,
+                                image.width as i32,
+                                image.height as i32,
+                                rowstride,
+                            );
+                            preview_image.set_from_pixbuf(Some(&pixbuf));
+                            preview_image.show();
+                        }
+                    }
+                    GuiEvent::UploadPending(pending) => {
+                        status_label.set_text(&format!("Saved locally: {}", pending.object_name));
+                        pending_uploads.borrow_mut().push(pending);
+                    }
+                    GuiEvent::ChooseStream { streams, reply } => {
+                        let _ = reply.send(choose_stream(&window, &streams));
+                    }
+                    GuiEvent::Uploaded(object_name) => {
+                        upload_settled.set(true);
+                        upload_progress.set_fraction(1.0);
+                        upload_progress.set_text(Some("Done"));
+                        status_label.set_text(&format!("Saved as {}", object_name));
+                        show_info(&window, &format!("Recording uploaded as {}", object_name));
+                    }
+                    GuiEvent::Cancelled(message) => {
+                        upload_progress.set_text(Some("Cancelled"));
+                        status_label.set_text("Cancelled");
+                        show_info(&window, &format!("{}. Press Start to try again.", message));
+                    }
+                    GuiEvent::Status(status) => {
+                        status_label.set_text(&status);
+                    }
+                    GuiEvent::SetupChecked(Ok(checks)) => {
+                        test_button.set_sensitive(true);
+                        status_label.set_text("Setup OK");
+                        show_info(&window, &format!("Setup OK:\n\n{}", checks.join("\n")));
+                    }
+                    GuiEvent::SetupChecked(Err(message)) => {
+                        test_button.set_sensitive(true);
+                        let summary = message.lines().next().unwrap_or(&message);
+                        status_label.set_text(&format!("Setup problem: {}", summary));
+                        show_error(&window, &format!("Setup check failed: {}", message));
+                    }
+                    // Сочетание нажимает те же кнопки, что и пользователь.
+                    GuiEvent::Hotkey(HotkeyEvent::Activated) => {
+                        if stop_button.get_sensitive() {
+                            stop_button.clicked();
+                        } else if start_button.get_sensitive() {
+                            start_button.clicked();
+                        }
+                    }
+                    GuiEvent::Hotkey(HotkeyEvent::Bound(description)) => {
+                        hotkey_status_label.set_text(&format!("Bound: {}", description));
+                    }
+                    GuiEvent::Hotkey(HotkeyEvent::Unavailable(message)) => {
+                        hotkey_status_label.set_text(&format!("Unavailable: {}", message));
+                    }
+                    GuiEvent::Hotkey(HotkeyEvent::Denied(message)) => {
+                        hotkey_status_label.set_text("Not bound");
+                        show_error(&window, &format!("Global shortcut was not bound: {}", message));
+                    }
+                    GuiEvent::Error(message) => {
+                        upload_settled.set(true);
+                        let summary = message.lines().next().unwrap_or(&message);
+                        upload_progress.set_text(Some(summary));
+                        status_label.set_text(&format!("Error: {}", summary));
+                        show_error(&window, &message);
+                    }
+                }
+                let count = pending_uploads.borrow().len();
+                upload_button.set_label(&format!("Upload now ({})", count));
+                upload_button.set_sensitive(count > 0);
+                glib::Continue(true)
+            });
+        }
+
+        // "Upload now": выгружаем все отложенные записи в фоновом потоке.
+        // Неудачные выгрузки возвращаются в очередь, чтобы их можно было повторить.
+        let events_for_upload = event_sender.clone();
+        upload_button.connect_clicked(move |button| {
+            let uploads: Vec<PendingUpload> = pending_uploads.borrow_mut().drain(..).collect();
+            button.set_label("Upload now");
+            button.set_sensitive(false);
+            let events = events_for_upload.clone();
+            thread::spawn(move || {
+                for pending in uploads {
+                    match pending.upload() {
+                        Ok(()) => {
+                            let _ = events.send(GuiEvent::Uploaded(pending.object_name.clone()));
+                        }
+                        Err(e) => {
+                            error!("Error uploading {}: {:?}", pending.object_name, e);
+                            let _ = events.send(GuiEvent::Error(format!(
+                                "Upload of {} failed, it stays queued: {:?}",
+                                pending.object_name, e
+                            )));
+                            let _ = events.send(GuiEvent::UploadPending(pending));
+                        }
+                    }
+                }
+            });
+        });
+
+        // Сочетание привязывается при запуске и по кнопке Bind; новое сменяет
+        // старое. События из потока сочетания идут тем же каналом, что и от записи.
+        let hotkey: Rc<RefCell<Option<Hotkey>>> = Rc::new(RefCell::new(None));
+        let bind_hotkey = {
+            let hotkey_entry = hotkey_entry.clone();
+            let hotkey_status_label = hotkey_status_label.clone();
+            let events = event_sender.clone();
+            Rc::new(move || {
+                hotkey.borrow_mut().take();
+                let text = hotkey_entry.get_text().to_string();
+                if text.trim().is_empty() {
+                    hotkey_status_label.set_text("Disabled");
+                    return;
+                }
+                match Binding::parse(&text) {
+                    Ok(binding) => {
+                        hotkey_status_label.set_text("Binding…");
+                        let events = events.clone();
+                        *hotkey.borrow_mut() = Some(Hotkey::start(binding, move |event| {
+                            let _ = events.send(GuiEvent::Hotkey(event));
+                        }));
+                    }
+                    Err(e) => hotkey_status_label.set_text(&e.to_string()),
+                }
+            })
+        };
+        bind_hotkey();
+        {
+            let read_params = read_params.clone();
+            let snapshot_preset = snapshot_preset.clone();
+            hotkey_button.connect_clicked(move |_| {
+                bind_hotkey();
+                let saved = read_params()
+                    .map_err(|e| anyhow::anyhow!(e))
+                    .and_then(|params| config::save_settings(&snapshot_preset(&params)));
+                if let Err(e) = saved {
+                    warn!("Failed to save settings: {:?}", e);
+                }
+            });
+        }
 
         // Выбор «bucket» через диалог (FileChooserDialog в режиме выбора папки)
         let folder_entry_clone = folder_entry.clone();
+        let backend_combo_clone = backend_combo.clone();
         let win_clone = window.clone();
         folder_button.connect_clicked(move |_| {
             let dialog = FileChooserDialog::new(
@@ -120,8 +2601,16 @@ pub fn run_gui<F: Fn(RecordParams) + 'static>(callback: F) {
             dialog.add_button("Cancel", ResponseType::Cancel);
             dialog.add_button("Select", ResponseType::Accept);
             if dialog.run() == ResponseType::Accept {
+                // Имя bucket — это имя выбранной папки, а не полный путь к ней;
+                // для записи на диск нужен как раз полный путь.
+                let local = backend_combo_clone.get_active_text().as_deref() == Some("local");
                 if let Some(folder) = dialog.get_filename() {
-                    if let Some(folder_str) = folder.to_str() {
+                    let folder_str = if local {
+                        folder.to_str()
+                    } else {
+                        folder.file_name().and_then(|n| n.to_str())
+                    };
+                    if let Some(folder_str) = folder_str {
                         folder_entry_clone.set_text(folder_str);
                     }
                 }
@@ -129,34 +2618,58 @@ pub fn run_gui<F: Fn(RecordParams) + 'static>(callback: F) {
             dialog.close();
         });
 
+        // "Test Setup": те же параметры, но вместо записи — проверки в фоне.
+        {
+            let win_clone = window.clone();
+            let events = event_sender.clone();
+            let read_params = read_params.clone();
+            let test_setup = test_setup.clone();
+            test_button.connect_clicked(move |button| {
+                let params = match read_params() {
+                    Ok(params) => params,
+                    Err(e) => {
+                        show_error(&win_clone, &e);
+                        return;
+                    }
+                };
+                button.set_sensitive(false);
+                status_label_for_test.set_text("Testing setup…");
+                test_setup(params, events.clone());
+            });
+        }
+
         // При клике по кнопке собираем параметры и вызываем callback
+        let win_clone = window.clone();
+        let callback = callback.clone();
+        let events_for_start = event_sender.clone();
+        let read_params_for_start = read_params.clone();
         start_button.connect_clicked(move |_| {
-            let output_folder = folder_entry.get_text().to_string();
-            let filename_template = filename_entry.get_text().to_string();
-            let container = container_combo
-                .get_active_text()
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "mp4".to_string());
-            let bitrate = bitrate_spin.get_value_as_int() as u32;
-            let encoding_mode = if cbr_radio.get_active() {
-                "CBR".to_string()
-            } else {
-                "VBR".to_string()
-            };
-            let audio_device = audio_combo
-                .get_active_text()
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "default".to_string());
-
-            let params = RecordParams {
-                output_folder,
-                filename_template,
-                container,
-                bitrate,
-                encoding_mode,
-                audio_device,
+            let params = match read_params_for_start() {
+                Ok(params) => params,
+                Err(e) => {
+                    show_error(&win_clone, &e);
+                    return;
+                }
             };
-            callback(params);
+            // Не запускаем запись с пустым или некорректным bucket:
+            // иначе ошибка всплывёт только при выгрузке.
+            if let Err(e) = params.validate() {
+                show_error(&win_clone, &e.to_string());
+                return;
+            }
+            let warnings = params.warnings();
+            if !warnings.is_empty()
+                && !confirm_warning(
+                    &win_clone,
+                    &format!("{}\n\nRecord anyway?", warnings.join("\n")),
+                )
+            {
+                return;
+            }
+            if let Err(e) = config::save_settings(&snapshot_preset(&params)) {
+                warn!("Failed to save settings: {:?}", e);
+            }
+            callback(params, events_for_start.clone());
         });
 
         window.show_all();
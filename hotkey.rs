@@ -0,0 +1,426 @@
+// src/hotkey.rs
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{ConnectionExt as _, GrabMode, ModMask};
+use x11rb::protocol::Event;
+use zbus::zvariant::{Array, ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, Proxy, ProxyBuilder};
+
+use crate::portal::{self, PortalCancelled};
+
+/// Сочетание по умолчанию (синтаксис ускорителей GTK).
+pub const DEFAULT_HOTKEY: &str = "<Super>r";
+
+/// Идентификатор сочетания в портале GlobalShortcuts.
+const SHORTCUT_ID: &str = "toggle-recording";
+
+/// Портал показывает диалог привязки, пользователь отвечает сам.
+const BIND_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Как часто поток сочетания проверяет, не пора ли остановиться.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Сколько раз пытаться перехватить клавишу у X-сервера.
+const GRAB_ATTEMPTS: u32 = 5;
+
+/// Модификаторы сочетания.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Modifiers {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    logo: bool,
+}
+
+/// Глобальное сочетание клавиш: "<Super>r", "<Ctrl><Alt>F9" или "Super+R".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    modifiers: Modifiers,
+    /// Имя клавиши в терминах xkb ("r", "F9", "Print").
+    key: String,
+    /// Keysym X11 той же клавиши.
+    keysym: u32,
+}
+
+impl Binding {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rest = text.trim();
+        let mut parts = Vec::new();
+        while let Some(after) = rest.strip_prefix('<') {
+            let (name, tail) = after
+                .split_once('>')
+                .ok_or_else(|| anyhow::anyhow!("Shortcut '{}' has an unclosed '<'", text))?;
+            parts.push(name);
+            rest = tail;
+        }
+        parts.extend(rest.split('+').map(str::trim));
+        let (key, names) = match parts.split_last() {
+            Some((key, names)) if !key.is_empty() => (*key, names),
+            _ => anyhow::bail!("Shortcut '{}' has no key", text),
+        };
+        let mut modifiers = Modifiers::default();
+        for name in names {
+            match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" | "primary" => modifiers.ctrl = true,
+                "alt" | "mod1" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                "super" | "logo" | "meta" | "win" => modifiers.logo = true,
+                other => anyhow::bail!("Unknown modifier '{}' in shortcut '{}'", other, text),
+            }
+        }
+        let (key, keysym) = key_symbol(key)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported key '{}' in shortcut '{}'", key, text))?;
+        Ok(Self {
+            modifiers,
+            key,
+            keysym,
+        })
+    }
+
+    /// preferred_trigger для портала (спецификация XDG shortcuts): "LOGO+r".
+    fn portal_trigger(&self) -> String {
+        let mut parts = Vec::new();
+        if self.modifiers.ctrl {
+            parts.push("CTRL");
+        }
+        if self.modifiers.alt {
+            parts.push("ALT");
+        }
+        if self.modifiers.shift {
+            parts.push("SHIFT");
+        }
+        if self.modifiers.logo {
+            parts.push("LOGO");
+        }
+        parts.push(&self.key);
+        parts.join("+")
+    }
+
+    fn x11_modifiers(&self) -> ModMask {
+        let mut mask = ModMask::from(0u16);
+        if self.modifiers.ctrl {
+            mask = mask | ModMask::CONTROL;
+        }
+        if self.modifiers.alt {
+            mask = mask | ModMask::M1;
+        }
+        if self.modifiers.shift {
+            mask = mask | ModMask::SHIFT;
+        }
+        if self.modifiers.logo {
+            mask = mask | ModMask::M4;
+        }
+        mask
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modifiers = [
+            (self.modifiers.ctrl, "Ctrl+"),
+            (self.modifiers.alt, "Alt+"),
+            (self.modifiers.shift, "Shift+"),
+            (self.modifiers.logo, "Super+"),
+        ];
+        for (_, name) in modifiers.iter().filter(|(on, _)| *on) {
+            f.write_str(name)?;
+        }
+        if self.key.len() == 1 {
+            write!(f, "{}", self.key.to_ascii_uppercase())
+        } else {
+            f.write_str(&self.key)
+        }
+    }
+}
+
+/// Имя xkb и keysym X11 для клавиши: буквы и цифры (keysym совпадает с кодом
+/// Latin-1), F1–F24 и несколько клавиш, которые обычно отдают под запись.
+fn key_symbol(key: &str) -> Option<(String, u32)> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            let c = c.to_ascii_lowercase();
+            return Some((c.to_string(), c as u32));
+        }
+    }
+    if let Some(number) = key.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u32>().ok()) {
+        if (1..=24).contains(&number) {
+            return Some((format!("F{}", number), 0xffbe + number - 1));
+        }
+    }
+    const NAMED: &[(&str, u32)] = &[
+        ("space", 0x0020),
+        ("Print", 0xff61),
+        ("Pause", 0xff13),
+        ("Scroll_Lock", 0xff14),
+        ("Escape", 0xff1b),
+        ("Home", 0xff50),
+        ("End", 0xff57),
+        ("Insert", 0xff63),
+        ("Delete", 0xffff),
+    ];
+    NAMED
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(name, keysym)| (name.to_string(), *keysym))
+}
+
+/// Что происходит с глобальным сочетанием; приходит из потока сочетания.
+#[derive(Debug, Clone)]
+pub enum HotkeyEvent {
+    /// Сочетание привязано; текст — какое и через что.
+    Bound(String),
+    /// Нажато сочетание.
+    Activated,
+    /// Ни портала GlobalShortcuts, ни X11: привязать сочетание нечем.
+    Unavailable(String),
+    /// Портал или X-сервер отказал (пользователь отклонил, клавиша занята).
+    Denied(String),
+}
+
+/// Глобальное сочетание клавиш для запуска и остановки записи.
+///
+/// На Wayland привязка идёт через портал GlobalShortcuts (пользователь
+/// подтверждает её в диалоге и может назначить другую клавишу); если портала
+/// нет, сочетание перехватывается у X-сервера (XGrabKey на корневом окне).
+/// Обработчик вызывается из фонового потока, поэтому GUI пересылает события
+/// в главный поток сам. Сочетание снимается при drop.
+pub struct Hotkey {
+    stop: Arc<AtomicBool>,
+}
+
+impl Hotkey {
+    pub fn start(binding: Binding, on_event: impl Fn(HotkeyEvent) + Send + 'static) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        thread::spawn(move || run(&binding, &stop_clone, &on_event));
+        Self { stop }
+    }
+}
+
+impl Drop for Hotkey {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Клавишу уже перехватило другое приложение.
+#[derive(Debug)]
+struct GrabDenied(String);
+
+impl fmt::Display for GrabDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is already taken by another application", self.0)
+    }
+}
+
+impl std::error::Error for GrabDenied {}
+
+/// Сначала портал, без него — X11. Ошибка портала, который есть, означает
+/// отказ: второй раз спрашивать через X11 в обход пользователя незачем.
+fn run(binding: &Binding, stop: &AtomicBool, on_event: &dyn Fn(HotkeyEvent)) {
+    let portal = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the shortcut runtime")
+        .and_then(|rt| rt.block_on(listen_portal(binding, stop, on_event)));
+    match portal {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Global shortcut {} was not bound: {:?}", binding, e);
+            on_event(HotkeyEvent::Denied(format!("{:#}", e)));
+            return;
+        }
+    }
+    if env::var_os("DISPLAY").is_none() {
+        on_event(HotkeyEvent::Unavailable(
+            "The desktop has no GlobalShortcuts portal and there is no X11 display".to_string(),
+        ));
+        return;
+    }
+    if let Err(e) = listen_x11(binding, stop, on_event) {
+        warn!("Global shortcut {} was not grabbed: {:?}", binding, e);
+        let message = format!("{:#}", e);
+        on_event(if e.is::<GrabDenied>() {
+            HotkeyEvent::Denied(message)
+        } else {
+            HotkeyEvent::Unavailable(message)
+        });
+    }
+}
+
+/// Привязывает сочетание через портал и ждёт сигналов Activated до остановки.
+/// false — портала GlobalShortcuts нет (старый xdg-desktop-portal или бэкенд).
+async fn listen_portal(
+    binding: &Binding,
+    stop: &AtomicBool,
+    on_event: &dyn Fn(HotkeyEvent),
+) -> Result<bool> {
+    let connection = match Connection::session().await {
+        Ok(connection) => connection,
+        Err(e) => {
+            info!("No session bus for the GlobalShortcuts portal: {:?}", e);
+            return Ok(false);
+        }
+    };
+    let proxy = shortcuts_proxy(&connection).await?;
+    if proxy.get_property::<u32>("version").await.is_err() {
+        info!("GlobalShortcuts portal is not available");
+        return Ok(false);
+    }
+
+    let token = portal::new_token();
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    options.insert("session_handle_token", Value::from(token.as_str()));
+    let results = portal::request(&connection, &proxy, "CreateSession", &token, &options, BIND_TIMEOUT)
+        .await?;
+    let session_handle = results
+        .get("session_handle")
+        .and_then(|v| v.downcast_ref::<str>())
+        .ok_or_else(|| anyhow::anyhow!("CreateSession response has no session_handle"))?
+        .to_string();
+
+    let result = bind_and_listen(&connection, &proxy, &session_handle, binding, stop, on_event).await;
+    portal::close_session(&connection, &session_handle).await;
+    result.map(|()| true)
+}
+
+async fn bind_and_listen(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    session_handle: &str,
+    binding: &Binding,
+    stop: &AtomicBool,
+    on_event: &dyn Fn(HotkeyEvent),
+) -> Result<()> {
+    let mut shortcut: HashMap<&str, Value> = HashMap::new();
+    shortcut.insert("description", Value::from("Start or stop recording"));
+    shortcut.insert("preferred_trigger", Value::from(binding.portal_trigger()));
+    let token = portal::new_token();
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    let session = ObjectPath::try_from(session_handle)?;
+    let body = (session, vec![(SHORTCUT_ID, shortcut)], "", options);
+    let mut activations = proxy.receive_signal("Activated").await?;
+    let results = portal::request(connection, proxy, "BindShortcuts", &token, &body, BIND_TIMEOUT)
+        .await
+        .map_err(|e| {
+            if e.is::<PortalCancelled>() {
+                anyhow::anyhow!("The desktop did not allow the shortcut {}", binding)
+            } else {
+                e
+            }
+        })?;
+    // Пользователь может закрыть диалог, не назначив клавишу: тогда список пуст.
+    let bound = results
+        .get("shortcuts")
+        .and_then(|v| v.downcast_ref::<Array>())
+        .map_or(false, |shortcuts| !shortcuts.get().is_empty());
+    if !bound {
+        anyhow::bail!("No key was assigned to the shortcut {}", binding);
+    }
+    info!("Global shortcut {} bound through the portal", binding);
+    on_event(HotkeyEvent::Bound(format!("{} (desktop shortcut settings)", binding)));
+
+    while !stop.load(Ordering::Relaxed) {
+        let message = match tokio::time::timeout(POLL_INTERVAL, activations.next()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => anyhow::bail!("The GlobalShortcuts portal closed the session"),
+            Err(_) => continue,
+        };
+        let (session, id, _, _): ActivatedBody = match message.body() {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Malformed Activated signal: {:?}", e);
+                continue;
+            }
+        };
+        if session.as_str() == session_handle && id == SHORTCUT_ID {
+            on_event(HotkeyEvent::Activated);
+        }
+    }
+    Ok(())
+}
+
+/// Сигнал Activated: сессия, id сочетания, время нажатия и опции.
+type ActivatedBody = (OwnedObjectPath, String, u64, HashMap<String, OwnedValue>);
+
+async fn shortcuts_proxy(connection: &Connection) -> Result<Proxy<'static>> {
+    Ok(ProxyBuilder::new_bare(connection)
+        .destination("org.freedesktop.portal.Desktop")?
+        .path("/org/freedesktop/portal/desktop")?
+        .interface("org.freedesktop.portal.GlobalShortcuts")?
+        .build()
+        .await?)
+}
+
+/// Перехват сочетания у X-сервера. Захват делается и со всеми комбинациями
+/// CapsLock и NumLock, иначе включённый NumLock "ломает" сочетание.
+fn listen_x11(binding: &Binding, stop: &AtomicBool, on_event: &dyn Fn(HotkeyEvent)) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to X server")?;
+    let root = conn.setup().roots[screen_num].root;
+    let (min_keycode, max_keycode) = (conn.setup().min_keycode, conn.setup().max_keycode);
+    let mapping = conn
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+        .reply()
+        .context("Failed to read the keyboard mapping")?;
+    let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+    let keycode = mapping
+        .keysyms
+        .chunks(per_keycode)
+        .position(|keysyms| keysyms.contains(&binding.keysym))
+        .map(|index| min_keycode + index as u8)
+        .ok_or_else(|| anyhow::anyhow!("The keyboard has no key for {}", binding))?;
+
+    let modifiers = binding.x11_modifiers();
+    let locks = [
+        ModMask::from(0u16),
+        ModMask::LOCK,
+        ModMask::M2,
+        ModMask::LOCK | ModMask::M2,
+    ];
+    // Предыдущий Hotkey того же процесса отпускает клавишу не сразу, а на
+    // следующем опросе, поэтому отказ повторяется несколько раз.
+    let mut attempts = 0;
+    loop {
+        let denied = locks.iter().any(|lock| {
+            conn.grab_key(false, root, modifiers | *lock, keycode, GrabMode::ASYNC, GrabMode::ASYNC)
+                .map_or(true, |cookie| cookie.check().is_err())
+        });
+        if !denied {
+            break;
+        }
+        attempts += 1;
+        if attempts >= GRAB_ATTEMPTS {
+            return Err(GrabDenied(binding.to_string()).into());
+        }
+        thread::sleep(POLL_INTERVAL * 2);
+    }
+    info!("Global shortcut {} grabbed on the X server", binding);
+    on_event(HotkeyEvent::Bound(format!("{} (X11)", binding)));
+
+    while !stop.load(Ordering::Relaxed) {
+        match conn.poll_for_event()? {
+            Some(Event::KeyPress(event)) if event.detail == keycode => on_event(HotkeyEvent::Activated),
+            Some(_) => {}
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+    for lock in locks {
+        let _ = conn.ungrab_key(keycode, root, modifiers | lock);
+    }
+    let _ = conn.flush();
+    Ok(())
+}
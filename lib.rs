@@ -0,0 +1,51 @@
+// src/lib.rs
+
+//! Запись экрана Wayland через портал ScreenCast и PipeWire с кодированием
+//! FFmpeg и выгрузкой в объектное хранилище. Приложение rscap (GUI и CLI)
+//! построено поверх этих модулей; для встраивания есть `Recorder`.
+
+pub mod audio_capture;
+pub mod audio_meter;
+pub mod cli;
+pub mod config;
+pub mod control;
+pub mod convert;
+pub mod crop;
+pub mod damage;
+pub mod file_sink;
+pub mod filters;
+pub mod follow;
+pub mod formats;
+pub mod framerate;
+pub mod gpu;
+pub mod gui;
+pub mod hotkey;
+pub mod logging;
+pub mod memory_sink;
+pub mod naming;
+pub mod notify;
+pub mod oci_uploader;
+pub mod paths;
+pub mod pipeline;
+pub mod portal;
+pub mod presets;
+pub mod preview;
+pub mod provenance;
+pub mod rate_control;
+pub mod recorder;
+pub mod redact;
+pub mod region_picker;
+pub mod resample;
+pub mod s3_uploader;
+pub mod scene;
+pub mod status;
+pub mod storage;
+pub mod supersample;
+pub mod supervisor;
+pub mod thumbnails;
+pub mod timings;
+pub mod tray;
+
+pub use memory_sink::MemorySink;
+pub use pipeline::{FrameProcessor, PipelineStats};
+pub use recorder::Recorder;
@@ -0,0 +1,21 @@
+// src/logging.rs
+
+use tracing_subscriber::EnvFilter;
+
+/// Уровень журнала, если не заданы ни --log-level, ни RUST_LOG.
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// Включает журнал в stderr. `level` из --log-level важнее RUST_LOG; оба
+/// понимают синтаксис фильтров tracing ("debug", "rscap=trace,zbus=warn").
+/// Запись идёт в span "recording" с именем объекта, так что журнал одной
+/// записи легко вырезать для отчёта об ошибке.
+pub fn init(level: Option<&str>) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL)),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
@@ -1,239 +1,275 @@
 // src/main.rs
 
-mod gui;
-mod oci_uploader;
-
-use anyhow::Result;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
 use tokio::runtime::Runtime;
-use uuid::Uuid;
-use gui::RecordParams;
-use pipewire::prelude::*;
-use zbus::{Connection, ProxyBuilder};
-use zbus::zvariant::Value;
-use serde::Deserialize;
-use libc;
-use ffmpeg_next as ffmpeg;
-use ffmpeg::format::io::IO;
-use oci_uploader::OciUploader;
+use clap::Parser;
+use rscap::cli::CliArgs;
+use rscap::gui::{self, GuiEvent, RecordParams};
+use rscap::oci_uploader::{self, OciCredentials, PendingUpload};
+use rscap::pipeline::PipelineStats;
+use rscap::portal::PortalCancelled;
+use rscap::recorder::{dry_run, start_recording};
+use rscap::storage::Backend;
+use rscap::{file_sink, logging, naming, notify, s3_uploader, supervisor};
+use tracing::{error, info, warn};
 
-/// Структура для десериализации ответа метода Start портала.
-#[derive(Debug, Deserialize)]
-struct StartResponse {
-    streams: Vec<StreamInfo>,
-}
+static ACTIVE_RECORDINGS: AtomicUsize = AtomicUsize::new(0);
 
-/// Информация о потоке (поле fd – файловый дескриптор).
-#[derive(Debug, Deserialize)]
-struct StreamInfo {
-    fd: zbus::zvariant::Fd,
-    node_id: u32,
+fn main() {
+    if CliArgs::requested() {
+        let args = CliArgs::parse();
+        logging::init(args.log_level.as_deref());
+        std::process::exit(run_cli(args));
+    }
+    logging::init(None);
+    gui::run_gui(move |params, events| {
+        info!("GUI callback received parameters: {:?}", params);
+        // Кнопка Start уже недоступна при достижении предела, но события GUI
+        // приходят асинхронно, поэтому предел проверяется и здесь.
+        let limit = params.max_concurrent_recordings.max(1);
+        let active = ACTIVE_RECORDINGS.fetch_add(1, Ordering::SeqCst);
+        if active >= limit {
+            ACTIVE_RECORDINGS.fetch_sub(1, Ordering::SeqCst);
+            let _ = events.send(GuiEvent::Error(format!(
+                "Not starting: {} recording(s) already running (limit {})",
+                active, limit
+            )));
+            return;
+        }
+        // Запускаем процесс записи в отдельном потоке с собственным tokio-рантаймом,
+        // чтобы не блокировать GUI.
+        let stats = Arc::new(PipelineStats::new(params.queue_depth, params.buffer_window_ms));
+        stats.set_preview(params.preview);
+        let _ = events.send(GuiEvent::RecordingStarted(stats.clone()));
+        thread::spawn(move || {
+            let rt = Runtime::new().unwrap();
+            let pending_events = events.clone();
+            let result = run_supervised(&rt, &params, &stats, Some(events.clone()), |pending| {
+                let _ = pending_events.send(GuiEvent::UploadPending(pending));
+                Ok(())
+            });
+            notify_finished(&rt, &params, &stats, &result);
+            match result {
+                Ok(Some(object_name)) => {
+                    let _ = events.send(GuiEvent::Uploaded(object_name));
+                }
+                Ok(None) => {}
+                Err(e) if e.is::<PortalCancelled>() => {
+                    info!("{}", e);
+                    let _ = events.send(GuiEvent::Cancelled(e.to_string()));
+                }
+                Err(e) => {
+                    error!("Error during recording: {:?}", e);
+                    let _ = events.send(GuiEvent::Error(format!("Recording failed: {:?}", e)));
+                }
+            }
+            ACTIVE_RECORDINGS.fetch_sub(1, Ordering::SeqCst);
+            let _ = events.send(GuiEvent::RecordingFinished(stats));
+        });
+    }, |params, events| {
+        thread::spawn(move || {
+            let rt = Runtime::new().unwrap();
+            let result = rt.block_on(dry_run(&params)).map_err(|e| format!("{:?}", e));
+            let _ = events.send(GuiEvent::SetupChecked(result));
+        });
+    });
 }
 
-/// Асинхронная функция, реализующая процесс захвата, кодирования и «записи» в OCI Object Storage.
-async fn start_recording(params: RecordParams) -> Result<()> {
-    println!("Starting screen recording with parameters: {:?}", params);
-
-    // Формируем имя объекта: например, [filename_template].[container]
-    let object_name = format!("{}.{}", params.filename_template, params.container);
-    // Параметр output_folder здесь интерпретируется как имя OCI bucket.
-    let bucket = params.output_folder; 
-
-    // 1. Инициализируем Pipewire.
-    pipewire::init();
-    let _context = pipewire::Context::new()?;
-    println!("Pipewire initialized.");
-
-    // 2. Подключаемся к сеансовой шине D-Bus.
-    let connection = Connection::session().await?;
-    let proxy = ProxyBuilder::new_bare(&connection)
-        .destination("org.freedesktop.portal.Desktop")?
-        .path("/org/freedesktop/portal/desktop")?
-        .interface("org.freedesktop.portal.ScreenCast")?
-        .build()
-        .await?;
-
-    // 3. Создаём сессию с уникальным токеном.
-    let session_token = Uuid::new_v4().to_string();
-    let mut create_options: HashMap<&str, Value> = HashMap::new();
-    create_options.insert("session_handle_token", Value::from(session_token));
-    create_options.insert("types", Value::U32(3)); // захватываем экран и окна
-    let (session_handle,): (String,) = proxy.call("CreateSession", &(create_options)).await?;
-    println!("Session created: {}", session_handle);
-
-    // 4. Вызываем SelectSources для выбора источников.
-    let select_options: HashMap<&str, Value> = HashMap::new();
-    let _ = proxy
-        .call("SelectSources", &(session_handle.clone(), select_options))
-        .await?;
-    println!("SelectSources called.");
-
-    // 5. Запускаем захват.
-    let start_options: HashMap<&str, Value> = HashMap::new();
-    let start_response: StartResponse = proxy
-        .call("Start", &(session_handle.clone(), "rust_screen_recorder", start_options))
-        .await?;
-    println!("Start response: {:?}", start_response);
-
-    let stream_info = start_response
-        .streams
-        .get(0)
-        .ok_or_else(|| anyhow::anyhow!("No available streams in Start response"))?;
-    println!("Using stream node_id: {}", stream_info.node_id);
-
-    // Дублируем файловый дескриптор потока.
-    let raw_fd = stream_info.fd.as_raw_fd();
-    let dup_fd = unsafe { libc::dup(raw_fd) };
-    if dup_fd < 0 {
-        return Err(anyhow::anyhow!("Failed to duplicate file descriptor"));
+/// Уведомление рабочего стола об итоге записи: окно может быть свёрнуто,
+/// а запись — идти часами без присмотра. Отмена в портале не сообщается.
+fn notify_finished(
+    rt: &Runtime,
+    params: &RecordParams,
+    stats: &PipelineStats,
+    result: &Result<Option<String>>,
+) {
+    let summary = notify::summary(
+        stats.upload.bytes_written.load(Ordering::Relaxed),
+        stats.recorded_duration(),
+    );
+    let (title, body, urgent) = match result {
+        Ok(Some(object_name)) => {
+            let location = object_url(params, object_name).unwrap_or_else(|| object_name.clone());
+            ("Recording uploaded", format!("{}\n{}", location, summary), false)
+        }
+        Ok(None) => (
+            "Recording saved",
+            format!("Waiting for Upload now\n{}", summary),
+            false,
+        ),
+        Err(e) if e.is::<PortalCancelled>() => return,
+        Err(e) => ("Recording failed", format!("{}\n{}", e, summary), true),
+    };
+    if let Err(e) = rt.block_on(notify::send(title, &body, urgent)) {
+        warn!("Cannot show a desktop notification: {:?}", e);
     }
-    println!("Duplicated FD: {}", dup_fd);
-
-    // 6. Инициализируем FFmpeg.
-    ffmpeg::init().map_err(|e| anyhow::anyhow!("FFmpeg init error: {:?}", e))?;
-    let device_path = format!("/proc/self/fd/{}", dup_fd);
-    println!("Opening input with ffmpeg: {}", device_path);
-
-    let mut ictx = ffmpeg::format::input_with_format(&device_path, "pipewire")
-        .map_err(|e| anyhow::anyhow!("Failed to open input stream: {:?}", e))?;
-
-    let input_video_stream = ictx
-        .streams()
-        .best(ffmpeg::media::Type::Video)
-        .ok_or_else(|| anyhow::anyhow!("No video stream found in input"))?;
-    let input_index = input_video_stream.index();
-    println!("Input video stream index: {}", input_index);
-
-    let mut decoder = input_video_stream
-        .codec()
-        .decoder()
-        .video()
-        .map_err(|e| anyhow::anyhow!("Failed to open video decoder: {:?}", e))?;
-
-    // 7. Создаём объект-выгружатель (OciUploader) и оборачиваем его в Arc/Mutex.
-    let uploader = Arc::new(Mutex::new(OciUploader::new(&bucket, &object_name)));
-    // Создаём FFmpeg IO-контекст, который пишет в наш uploader.
-    let io = IO::from_write(uploader.clone())
-        .map_err(|e| anyhow::anyhow!("Failed to create FFmpeg IO: {:?}", e))?;
-    // Создаём выходной формат с кастомным IO.
-    let mut octx = ffmpeg::format::output_with_io(io)
-        .map_err(|e| anyhow::anyhow!("Failed to create output context: {:?}", e))?;
-    
-    // 8. Настраиваем вывод: контейнер, кодек H264 и параметры из GUI.
-    let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+}
 
-    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::H264)
-        .ok_or_else(|| anyhow::anyhow!("H264 encoder not found"))?;
-    let mut ostream = octx.add_stream(codec)
-        .map_err(|e| anyhow::anyhow!("Failed to add stream: {:?}", e))?;
-    
-    {
-        let mut encoder = ostream
-            .codec()
-            .encoder()
-            .video()
-            .map_err(|e| anyhow::anyhow!("Failed to get video encoder: {:?}", e))?;
-        encoder.set_width(decoder.width());
-        encoder.set_height(decoder.height());
-        encoder.set_format(ffmpeg::format::Pixel::YUV420P);
-        encoder.set_time_base(decoder.time_base());
-        encoder.set_bit_rate(params.bitrate as i64 * 1000); // битрейт в бит/с
-        if global_header {
-            encoder.set_flags(ffmpeg::codec::flag::Flags::GLOBAL_HEADER);
-        }
-        encoder.open_as(codec)
-            .map_err(|e| anyhow::anyhow!("Failed to open video encoder: {:?}", e))?;
+/// Подставляет вместо `{n}` в имени следующий свободный номер: на единицу больше
+/// наибольшего среди уже лежащих в хранилище объектов с тем же шаблоном.
+fn with_free_counter(mut params: RecordParams) -> Result<RecordParams> {
+    let object_name = params.object_name();
+    if !naming::has_counter(&object_name) {
+        return Ok(params);
     }
+    let prefix = naming::counter_prefix(&object_name);
+    let bucket = params.bucket();
+    let existing = match params.storage_backend()? {
+        Backend::Oci => oci_uploader::list_objects(&OciCredentials::load()?, &bucket, prefix)?,
+        Backend::S3 => s3_uploader::list_objects(&bucket, prefix)?,
+        Backend::Local => file_sink::list_files(&params.local_folder(), prefix)?,
+    };
+    let n = naming::next_counter(&object_name, &existing);
+    params.filename_template = naming::with_counter(&params.filename_template, n);
+    info!(
+        "{} existing object(s) match '{}'; recording as number {}",
+        existing.len(),
+        object_name,
+        n
+    );
+    Ok(params)
+}
 
-    octx.write_header()
-        .map_err(|e| anyhow::anyhow!("Failed to write header: {:?}", e))?;
-    println!("Encoding started...");
+/// Адрес выгруженной записи; None, если его не построить (нет учётных данных).
+fn object_url(params: &RecordParams, object_name: &str) -> Option<String> {
+    match params.storage_backend().ok()? {
+        Backend::Oci => OciCredentials::load()
+            .ok()
+            .map(|creds| oci_uploader::object_url(&creds, &params.bucket(), object_name)),
+        Backend::S3 => s3_uploader::object_url(&params.bucket(), object_name).ok(),
+        Backend::Local => Some(format!(
+            "file://{}",
+            params.local_folder().join(object_name).display()
+        )),
+    }
+}
 
-    // 9. Обрабатываем пакеты: декодируем, кодируем и передаем в наш кастомный вывод (OCI uploader).
-    for (stream, packet) in ictx.packets() {
-        if stream.index() == input_index {
-            decoder.send_packet(&packet)
-                .map_err(|e| anyhow::anyhow!("Error sending packet to decoder: {:?}", e))?;
-            loop {
-                match decoder.receive_frame() {
-                    Ok(mut frame) => {
-                        let mut encoder = ostream
-                            .codec()
-                            .encoder()
-                            .video()
-                            .map_err(|e| anyhow::anyhow!("Error getting encoder: {:?}", e))?;
-                        encoder.send_frame(&frame)
-                            .map_err(|e| anyhow::anyhow!("Error sending frame to encoder: {:?}", e))?;
-                        loop {
-                            match encoder.receive_packet() {
-                                Ok(mut encoded) => {
-                                    encoded.set_stream(ostream.index());
-                                    encoded.rescale_ts(decoder.time_base(), ostream.time_base());
-                                    octx.write_packet(&encoded)
-                                        .map_err(|e| anyhow::anyhow!("Error writing packet: {:?}", e))?;
-                                },
-                                Err(ffmpeg::Error::Other { .. })
-                                | Err(ffmpeg::Error::Eof) => break,
-                                Err(e) => return Err(anyhow::anyhow!("Error receiving encoded packet: {:?}", e)),
-                            }
-                        }
-                    },
-                    Err(ffmpeg::Error::Other { .. }) | Err(ffmpeg::Error::Eof) => break,
-                    Err(e) => return Err(anyhow::anyhow!("Error receiving frame: {:?}", e)),
+/// Запись без GUI: параметры из командной строки, отложенные записи
+/// выгружаются сразу. Код выхода 2 — ошибка параметров, 1 — ошибка записи.
+fn run_cli(args: CliArgs) -> i32 {
+    let dry_run_only = args.dry_run;
+    let params = args.into_params();
+    if let Err(e) = params.validate() {
+        eprintln!("Error: {}", e);
+        return 2;
+    }
+    for warning in params.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    let rt = Runtime::new().unwrap();
+    if dry_run_only {
+        return match rt.block_on(dry_run(&params)) {
+            Ok(checks) => {
+                for check in checks {
+                    println!("{}", check);
                 }
+                println!("Setup OK");
+                0
             }
-        }
+            Err(e) => {
+                eprintln!("Setup check failed: {:?}", e);
+                1
+            }
+        };
     }
-
-    decoder.send_eof()
-        .map_err(|e| anyhow::anyhow!("Error sending EOF to decoder: {:?}", e))?;
+    let stats = Arc::new(PipelineStats::new(params.queue_depth, params.buffer_window_ms));
+    // Ctrl-C — то же, что кнопка Stop: запись дописывается и выгружается.
+    // Второй Ctrl-C прерывает процесс, не дожидаясь выгрузки.
     {
-        let mut encoder = ostream
-            .codec()
-            .encoder()
-            .video()
-            .map_err(|e| anyhow::anyhow!("Error getting encoder for finishing: {:?}", e))?;
-        encoder.send_eof()
-            .map_err(|e| anyhow::anyhow!("Error sending EOF to encoder: {:?}", e))?;
-        loop {
-            match encoder.receive_packet() {
-                Ok(mut encoded) => {
-                    encoded.set_stream(ostream.index());
-                    octx.write_packet(&encoded)
-                        .map_err(|e| anyhow::anyhow!("Error writing final packet: {:?}", e))?;
-                }
-                Err(ffmpeg::Error::Other { .. })
-                | Err(ffmpeg::Error::Eof) => break,
-                Err(e) => return Err(anyhow::anyhow!("Error receiving final packet: {:?}", e)),
+        let stats = stats.clone();
+        rt.spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
             }
-        }
+            eprintln!("Stopping recording, press Ctrl-C again to abort...");
+            stats.request_stop();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        });
     }
-
-    octx.write_trailer()
-        .map_err(|e| anyhow::anyhow!("Error writing trailer: {:?}", e))?;
-    println!("Encoding finished.");
-
-    // После завершения записи вызываем finalize_upload, чтобы «отправить» данные в OCI.
-    {
-        let mut uploader = uploader.lock().unwrap();
-        uploader.finalize_upload()
-            .map_err(|e| anyhow::anyhow!("Error finalizing OCI upload: {:?}", e))?;
+    // Без GUI отложенная выгрузка идёт сразу; её сбой — сбой всей записи.
+    let result = run_supervised(&rt, &params, &stats, None, |pending| {
+        pending
+            .upload()
+            .with_context(|| format!("Error uploading {}", pending.object_name))
+    });
+    match result {
+        Ok(_) => 0,
+        Err(e) if e.is::<PortalCancelled>() => {
+            eprintln!("{}", e);
+            1
+        }
+        Err(e) => {
+            eprintln!("Error during recording: {:?}", e);
+            1
+        }
     }
-    Ok(())
 }
 
-fn main() {
-    gui::run_gui(move |params| {
-        println!("GUI callback received parameters: {:?}", params);
-        // Запускаем процесс записи в отдельном потоке с собственным tokio-рантаймом,
-        // чтобы не блокировать GUI.
-        thread::spawn(move || {
-            let rt = Runtime::new().unwrap();
-            if let Err(e) = rt.block_on(start_recording(params)) {
-                eprintln!("Error during recording: {:?}", e);
+/// Ведёт запись с перезапусками для долгих сессий без присмотра: временный сбой
+/// портала или PipeWire перезапускает запись новым сегментом, пока не исчерпан
+/// лимит перезапусков. Отложенные выгрузки каждого сегмента уходят в `on_pending`;
+/// ошибка выгрузки возвращается, когда запись закончится.
+/// Возвращает имя выгруженного объекта; None, если выгрузка отложена.
+fn run_supervised<F: FnMut(PendingUpload) -> Result<()>>(
+    rt: &Runtime,
+    params: &RecordParams,
+    stats: &Arc<PipelineStats>,
+    events: Option<glib::Sender<GuiEvent>>,
+    mut on_pending: F,
+) -> Result<Option<String>> {
+    // Время и номер в имени — на момент старта: сегменты перезапусков
+    // отличаются суффиксом -partN.
+    let params = &with_free_counter(params.with_expanded_template()?)?;
+    let mut restarts = 0;
+    // Первый сбой отложенной выгрузки прерванного сегмента.
+    let mut upload_error = None;
+    loop {
+        let attempt = if restarts == 0 {
+            params.clone()
+        } else {
+            supervisor::restart_params(params, restarts)
+        };
+        let object_name = attempt.object_name();
+        let recording = start_recording(attempt, stats.clone(), None, None, events.clone());
+        // Прерванный сегмент уже дописан: его отложенная выгрузка не теряется,
+        // даже если перезапусков больше не будет.
+        let result = rt.block_on(recording).map_err(|mut e| {
+            if let Some(pending) = e
+                .downcast_mut::<supervisor::SegmentInterrupted>()
+                .and_then(|segment| segment.pending.take())
+            {
+                if let Err(upload) = on_pending(pending) {
+                    error!("{:?}", upload);
+                    upload_error.get_or_insert(upload);
+                }
             }
+            e
         });
-    });
+        match result {
+            Ok(Some(pending)) => {
+                on_pending(pending)?;
+                return upload_error.map_or(Ok(None), Err);
+            }
+            Ok(None) => return upload_error.map_or(Ok(Some(object_name)), Err),
+            Err(e)
+                if restarts < params.max_restarts
+                    && !stats.stop_requested()
+                    && supervisor::is_recoverable(&e) =>
+            {
+                restarts += 1;
+                warn!(
+                    "Recording failed with a transient error, restarting ({}/{}): {:?}",
+                    restarts, params.max_restarts, e
+                );
+                thread::sleep(supervisor::RESTART_DELAY);
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
@@ -0,0 +1,44 @@
+// src/memory_sink.rs
+
+use std::io::{self, Write};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+/// Вывод закодированного потока в память вызывающей стороны вместо OCI.
+/// Либо накапливает байты в общем буфере, либо отдаёт каждый записанный
+/// кусок в канал — для встраивания rscap как кодирующего компонента.
+#[derive(Clone)]
+pub enum MemorySink {
+    /// Все байты дописываются в общий буфер; читать его стоит после окончания записи.
+    Buffer(Arc<Mutex<Vec<u8>>>),
+    /// Каждый кусок уходит в канал по мере записи мультиплексором.
+    Channel(Sender<Vec<u8>>),
+}
+
+impl MemorySink {
+    /// Буфер, который вызывающая сторона заберёт по окончании записи.
+    pub fn buffer() -> (Self, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        (MemorySink::Buffer(buffer.clone()), buffer)
+    }
+
+    pub fn channel(sender: Sender<Vec<u8>>) -> Self {
+        MemorySink::Channel(sender)
+    }
+}
+
+impl Write for MemorySink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MemorySink::Buffer(buffer) => buffer.lock().unwrap().extend_from_slice(buf),
+            MemorySink::Channel(sender) => sender.send(buf.to_vec()).map_err(|_| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "memory sink receiver dropped")
+            })?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+// src/naming.rs
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, TimeZone};
+use std::fmt::Display;
+
+use crate::formats::Compatibility;
+
+/// Предельная длина имени объекта в OCI Object Storage (байт UTF-8).
+pub const MAX_OBJECT_NAME_BYTES: usize = 1024;
+
+/// Символы, которые OCI принимает, но не советует: их приходится кодировать
+/// в URL, а часть инструментов (oci-cli, s3fs, браузерные консоли) с ними спотыкается.
+const DISCOURAGED_CHARS: &[char] = &['\\', '#', '?', '*', '"', '<', '>', '|', ':', '{', '}', '^', '`', '[', ']'];
+
+/// Шаблон имени по умолчанию: каждая запись получает своё имя и не затирает
+/// предыдущую.
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "recording_%Y%m%d_%H%M%S";
+
+/// Номер записи в шаблоне имени: следующий свободный среди объектов bucket.
+pub const COUNTER_PLACEHOLDER: &str = "{n}";
+
+/// Именованные подстановки времени и их strftime-эквиваленты.
+const TIME_PLACEHOLDERS: &[(&str, &str)] = &[
+    ("{date}", "%Y-%m-%d"),
+    ("{time}", "%H-%M-%S"),
+    ("{timestamp}", "%s"),
+];
+
+/// Случайный UUID записи: уникален и без обращения к bucket.
+pub const UUID_PLACEHOLDER: &str = "{uuid}";
+
+/// Раскрывает подстановки времени на момент `now`: `{date}`, `{time}`,
+/// `{timestamp}` (секунды Unix) и strftime (`%Y-%m-%d_%H-%M-%S`), а также `{uuid}`.
+/// `{n}` остаётся на месте — номер выбирается по содержимому bucket.
+/// Пробелы по краям отбрасываются.
+pub fn expand_template<Tz: TimeZone>(template: &str, now: &DateTime<Tz>) -> anyhow::Result<String>
+where
+    Tz::Offset: Display,
+{
+    let template = TIME_PLACEHOLDERS
+        .iter()
+        .fold(template.trim().to_string(), |text, (placeholder, format)| {
+            text.replace(placeholder, format)
+        })
+        .replace(UUID_PLACEHOLDER, &uuid::Uuid::new_v4().to_string());
+    let template = template.as_str();
+    let items: Vec<Item> = StrftimeItems::new(template).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        anyhow::bail!(
+            "Filename template '{}' has an unknown % placeholder (use e.g. %Y-%m-%d_%H-%M-%S)",
+            template
+        );
+    }
+    Ok(now.format_with_items(items.into_iter()).to_string().trim().to_string())
+}
+
+/// Есть ли в имени подстановка номера.
+pub fn has_counter(name: &str) -> bool {
+    name.contains(COUNTER_PLACEHOLDER)
+}
+
+/// Имя с номером `n` вместо `{n}`.
+pub fn with_counter(name: &str, n: u32) -> String {
+    name.replace(COUNTER_PLACEHOLDER, &n.to_string())
+}
+
+/// Общее начало всех имён, которые может дать шаблон: по нему ищутся
+/// уже существующие объекты.
+pub fn counter_prefix(name: &str) -> &str {
+    name.split(COUNTER_PLACEHOLDER).next().unwrap_or(name)
+}
+
+/// Следующий свободный номер для `name` с `{n}`: на единицу больше наибольшего
+/// среди `existing`, начиная с 1. Номер ищется по первой подстановке; если их
+/// несколько, остальные должны совпадать с ней.
+pub fn next_counter(name: &str, existing: &[String]) -> u32 {
+    let (before, after) = name.split_once(COUNTER_PLACEHOLDER).unwrap_or((name, ""));
+    existing
+        .iter()
+        .filter_map(|object| {
+            let rest = object.strip_prefix(before)?;
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let n: u32 = rest[..digits].parse().ok()?;
+            Some(n).filter(|n| with_counter(after, *n) == rest[digits..])
+        })
+        .max()
+        .map_or(1, |n| n.saturating_add(1))
+}
+
+/// Проверяет имя объекта до записи: ошибка в имени иначе всплывёт только
+/// при выгрузке, когда запись уже сделана.
+pub fn check_object_name(name: &str) -> Compatibility {
+    if name.is_empty() {
+        return Compatibility::Unsupported("Filename template is empty".to_string());
+    }
+    if name.len() > MAX_OBJECT_NAME_BYTES {
+        return Compatibility::Unsupported(format!(
+            "Object name is {} bytes long; at most {} are allowed",
+            name.len(),
+            MAX_OBJECT_NAME_BYTES
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Compatibility::Unsupported(format!(
+            "Object name '{}' contains control characters (line breaks, tabs), which object storage rejects",
+            name.escape_default()
+        ));
+    }
+    // Знак процента ломает URL объекта в части клиентов; кроме того, без него
+    // раскрытый шаблон раскрывается повторно без изменений.
+    if name.contains('%') {
+        return Compatibility::Unsupported(format!(
+            "Object name '{}' contains '%': use only placeholders like %Y-%m-%d_%H-%M-%S",
+            name
+        ));
+    }
+    if name.starts_with('/') || name.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+        return Compatibility::Unsupported(format!(
+            "Object name '{}' has an empty, '.' or '..' path segment",
+            name
+        ));
+    }
+    let discouraged: String = name.chars().filter(|c| DISCOURAGED_CHARS.contains(c)).collect();
+    if !discouraged.is_empty() {
+        return Compatibility::Warning(format!(
+            "Object name '{}' contains '{}', which OCI recommends avoiding in object names",
+            name, discouraged
+        ));
+    }
+    Compatibility::Supported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn expand(template: &str) -> String {
+        let now = Utc.with_ymd_and_hms(2025, 4, 9, 14, 30, 0).unwrap();
+        expand_template(template, &now).unwrap()
+    }
+
+    #[test]
+    fn expands_strftime_placeholders() {
+        assert_eq!(expand("%Y"), "2025");
+        assert_eq!(expand("%m"), "04");
+        assert_eq!(expand("%d"), "09");
+        assert_eq!(expand("%H"), "14");
+        assert_eq!(expand("%M"), "30");
+        assert_eq!(expand("%S"), "00");
+        assert_eq!(expand(DEFAULT_FILENAME_TEMPLATE), "recording_20250409_143000");
+    }
+
+    #[test]
+    fn expands_named_time_placeholders() {
+        assert_eq!(expand("{date}"), "2025-04-09");
+        assert_eq!(expand("{time}"), "14-30-00");
+        assert_eq!(expand("{timestamp}"), "1744209000");
+        assert_eq!(expand("demo/{date}_{time}"), "demo/2025-04-09_14-30-00");
+    }
+
+    #[test]
+    fn expands_uuid_once_per_recording() {
+        let first = expand("rec-{uuid}");
+        let second = expand("rec-{uuid}");
+        let id = first.strip_prefix("rec-").unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok(), "{} is not a UUID", id);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn leaves_template_without_placeholders_as_is() {
+        assert_eq!(expand("meeting-notes"), "meeting-notes");
+        assert_eq!(expand("  meeting-notes \n"), "meeting-notes");
+    }
+
+    #[test]
+    fn keeps_counter_for_the_bucket_lookup() {
+        assert_eq!(expand("rec-{date}-{n}"), "rec-2025-04-09-{n}");
+    }
+
+    #[test]
+    fn rejects_unknown_percent_placeholder() {
+        let now = Utc.with_ymd_and_hms(2025, 4, 9, 14, 30, 0).unwrap();
+        assert!(expand_template("rec-%Q", &now).is_err());
+    }
+
+    #[test]
+    fn next_counter_follows_the_largest_existing_number() {
+        let existing = vec![
+            "rec-1.mp4".to_string(),
+            "rec-7.mp4".to_string(),
+            "rec-3.mkv".to_string(),
+            "other-9.mp4".to_string(),
+        ];
+        assert_eq!(next_counter("rec-{n}.mp4", &existing), 8);
+        assert_eq!(next_counter("rec-{n}.webm", &existing), 1);
+        assert_eq!(with_counter("rec-{n}.mp4", 8), "rec-8.mp4");
+        assert_eq!(counter_prefix("rec-{n}.mp4"), "rec-");
+    }
+}
@@ -0,0 +1,81 @@
+// src/notify.rs
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::info;
+use zbus::zvariant::Value;
+use zbus::Connection;
+
+/// Итог записи для уведомления: размер файла и длительность.
+pub fn summary(bytes: u64, duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{:.1} MiB, {:02}:{:02}:{:02}",
+        bytes as f64 / 1048576.0,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Показывает уведомление рабочего стола.
+///
+/// Сначала через портал Notification, без него (или если портал не принимает
+/// приложение без app id, как бывает вне Flatpak) — через
+/// org.freedesktop.Notifications, тот же сервис, что использует libnotify.
+pub async fn send(title: &str, body: &str, urgent: bool) -> Result<()> {
+    let connection = Connection::session()
+        .await
+        .context("Failed to connect to the session bus")?;
+    match send_portal(&connection, title, body, urgent).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            info!("Notification portal is not available, using the notification service: {:?}", e);
+            send_service(&connection, title, body, urgent).await
+        }
+    }
+}
+
+async fn send_portal(connection: &Connection, title: &str, body: &str, urgent: bool) -> Result<()> {
+    let mut notification: HashMap<&str, Value> = HashMap::new();
+    notification.insert("title", Value::from(title));
+    notification.insert("body", Value::from(body));
+    notification.insert("priority", Value::from(if urgent { "high" } else { "normal" }));
+    let id = format!("rscap-{}", uuid::Uuid::new_v4().simple());
+    connection
+        .call_method(
+            Some("org.freedesktop.portal.Desktop"),
+            "/org/freedesktop/portal/desktop",
+            Some("org.freedesktop.portal.Notification"),
+            "AddNotification",
+            &(id.as_str(), notification),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Срочность по спецификации Desktop Notifications: 1 — обычная, 2 — критическая.
+const URGENCY_NORMAL: u8 = 1;
+const URGENCY_CRITICAL: u8 = 2;
+
+async fn send_service(connection: &Connection, title: &str, body: &str, urgent: bool) -> Result<()> {
+    let mut hints: HashMap<&str, Value> = HashMap::new();
+    hints.insert(
+        "urgency",
+        Value::U8(if urgent { URGENCY_CRITICAL } else { URGENCY_NORMAL }),
+    );
+    // Notify(app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout)
+    let actions: Vec<&str> = Vec::new();
+    connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &("rscap", 0u32, "media-record", title, body, actions, hints, -1i32),
+        )
+        .await
+        .context("Failed to send a desktop notification")?;
+    Ok(())
+}
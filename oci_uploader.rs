@@ -0,0 +1,859 @@
+// src/oci_uploader.rs
+
+use anyhow::{Context, Result};
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{RandomizedSigner, SignatureEncoding};
+use rsa::RsaPrivateKey;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::storage::{check_status, RetryPolicy, StorageSink, UploadProgress, DEFAULT_PART_SIZE_MB};
+
+/// Учётные данные и адрес OCI Object Storage.
+/// Берутся из переменных окружения OCI_TENANCY, OCI_USER, OCI_FINGERPRINT,
+/// OCI_KEY_FILE, OCI_REGION и OCI_NAMESPACE, а если OCI_TENANCY не задана —
+/// из профиля конфигурации oci-cli (см. `load`).
+#[derive(Clone)]
+pub struct OciCredentials {
+    tenancy: String,
+    user: String,
+    fingerprint: String,
+    key: RsaPrivateKey,
+    region: String,
+    namespace: String,
+}
+
+impl OciCredentials {
+    /// Переменные окружения, если задана OCI_TENANCY, иначе профиль
+    /// `OCI_CLI_PROFILE` (по умолчанию DEFAULT) из `OCI_CONFIG_FILE`
+    /// (по умолчанию ~/.oci/config). Namespace в профилях oci-cli не хранится:
+    /// он берётся из OCI_NAMESPACE или из ключа `namespace` профиля.
+    pub fn load() -> Result<Self> {
+        if env::var_os("OCI_TENANCY").is_some() {
+            return Self::from_env();
+        }
+        let path = config_file_path()?;
+        if !path.exists() {
+            anyhow::bail!(
+                "No OCI credentials found: set OCI_TENANCY, OCI_USER, OCI_FINGERPRINT, OCI_KEY_FILE, \
+                 OCI_REGION and OCI_NAMESPACE, or create {}",
+                path.display()
+            );
+        }
+        Self::from_config_file(&path)
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| env::var(name).with_context(|| format!("{} is not set", name));
+        Ok(Self {
+            tenancy: var("OCI_TENANCY")?,
+            user: var("OCI_USER")?,
+            fingerprint: var("OCI_FINGERPRINT")?,
+            key: read_api_key(Path::new(&var("OCI_KEY_FILE")?))?,
+            region: var("OCI_REGION")?,
+            namespace: var("OCI_NAMESPACE")?,
+        })
+    }
+
+    fn from_config_file(path: &Path) -> Result<Self> {
+        let profile_name = env::var("OCI_CLI_PROFILE").unwrap_or_else(|_| "DEFAULT".to_string());
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OCI config {}", path.display()))?;
+        let profile = parse_profile(&text, &profile_name)
+            .with_context(|| format!("Profile [{}] not found in {}", profile_name, path.display()))?;
+        let field = |name: &str| {
+            profile.get(name).cloned().with_context(|| {
+                format!("{} is missing in profile [{}] of {}", name, profile_name, path.display())
+            })
+        };
+        let namespace = env::var("OCI_NAMESPACE")
+            .ok()
+            .or_else(|| profile.get("namespace").cloned())
+            .with_context(|| {
+                format!(
+                    "OCI namespace is not set: add namespace=... to profile [{}] of {} or set OCI_NAMESPACE",
+                    profile_name,
+                    path.display()
+                )
+            })?;
+        Ok(Self {
+            tenancy: field("tenancy")?,
+            user: field("user")?,
+            fingerprint: field("fingerprint")?,
+            key: read_api_key(&expand_home(&field("key_file")?))?,
+            region: field("region")?,
+            namespace,
+        })
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn host(&self) -> String {
+        format!("objectstorage.{}.oraclecloud.com", self.region)
+    }
+}
+
+/// Файл конфигурации oci-cli: $OCI_CONFIG_FILE или ~/.oci/config.
+fn config_file_path() -> Result<PathBuf> {
+    match env::var_os("OCI_CONFIG_FILE") {
+        Some(path) if !path.is_empty() => Ok(expand_home(&path.to_string_lossy())),
+        _ => Ok(PathBuf::from(env::var_os("HOME").context("HOME is not set")?)
+            .join(".oci")
+            .join("config")),
+    }
+}
+
+/// Пути в конфигурации oci-cli обычно начинаются с "~/".
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn read_api_key(path: &Path) -> Result<RsaPrivateKey> {
+    let pem = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read OCI API key {}", path.display()))?;
+    RsaPrivateKey::from_pkcs8_pem(&pem)
+        .with_context(|| format!("Failed to parse OCI API key {}", path.display()))
+}
+
+/// Ключи профиля `[name]` INI-файла oci-cli. Ключи из DEFAULT наследуются
+/// остальными профилями, как в oci-cli; None — профиля нет.
+fn parse_profile(text: &str, name: &str) -> Option<HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(section.trim().to_string());
+            sections.entry(section.trim().to_string()).or_default();
+        } else if let (Some(section), Some((key, value))) = (current.as_ref(), line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    let mut profile = sections.get("DEFAULT").cloned().unwrap_or_default();
+    if name != "DEFAULT" {
+        profile.extend(sections.remove(name)?);
+    } else if !sections.contains_key("DEFAULT") {
+        return None;
+    }
+    Some(profile)
+}
+
+/// Ответ CreateMultipartUpload.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MultipartUpload {
+    upload_id: String,
+}
+
+/// Страница ответа ListObjects.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectList {
+    objects: Vec<ObjectSummary>,
+    next_start_with: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectSummary {
+    name: String,
+}
+
+/// Минимальный клиент REST API Object Storage с подписью запросов
+/// (OCI HTTP Signature, rsa-sha256).
+struct OciClient {
+    creds: OciCredentials,
+    http: reqwest::blocking::Client,
+}
+
+impl OciClient {
+    fn new(creds: OciCredentials) -> Self {
+        Self {
+            creds,
+            http: reqwest::blocking::Client::new(),
+        }
+    }
+
+    fn object_path(&self, bucket: &str, object_name: &str) -> String {
+        format!(
+            "/n/{}/b/{}/o/{}",
+            self.creds.namespace,
+            bucket,
+            urlencoding::encode(object_name)
+        )
+    }
+
+    fn upload_path(&self, bucket: &str, object_name: &str) -> String {
+        format!(
+            "/n/{}/b/{}/u/{}",
+            self.creds.namespace,
+            bucket,
+            urlencoding::encode(object_name)
+        )
+    }
+
+    /// Подписывает запрос. Для PUT в Object Storage тело не подписывается,
+    /// поэтому достаточно заголовков date, (request-target) и host.
+    fn authorization(&self, method: &str, path: &str, date: &str) -> String {
+        let host = self.creds.host();
+        let signing_string = format!(
+            "date: {}\n(request-target): {} {}\nhost: {}",
+            date,
+            method.to_lowercase(),
+            path,
+            host
+        );
+        self.signature("date (request-target) host", &signing_string)
+    }
+
+    /// Подпись для POST: здесь OCI требует подписывать и тело — через его
+    /// SHA-256, длину и тип. Возвращает заголовок authorization и x-content-sha256.
+    fn authorization_with_body(&self, path: &str, date: &str, body: &[u8]) -> (String, String) {
+        let content_sha256 = base64::encode(Sha256::digest(body));
+        let signing_string = format!(
+            "date: {}\n(request-target): post {}\nhost: {}\nx-content-sha256: {}\n\
+             content-type: application/json\ncontent-length: {}",
+            date,
+            path,
+            self.creds.host(),
+            content_sha256,
+            body.len()
+        );
+        let authorization = self.signature(
+            "date (request-target) host x-content-sha256 content-type content-length",
+            &signing_string,
+        );
+        (authorization, content_sha256)
+    }
+
+    fn signature(&self, headers: &str, signing_string: &str) -> String {
+        let signing_key = SigningKey::<Sha256>::new(self.creds.key.clone());
+        let signature = signing_key.sign_with_rng(&mut rand::thread_rng(), signing_string.as_bytes());
+        format!(
+            "Signature version=\"1\",keyId=\"{}/{}/{}\",algorithm=\"rsa-sha256\",\
+             headers=\"{}\",signature=\"{}\"",
+            self.creds.tenancy,
+            self.creds.user,
+            self.creds.fingerprint,
+            headers,
+            base64::encode(signature.to_bytes())
+        )
+    }
+
+    /// POST с JSON-телом (подписанным вместе с заголовками).
+    fn post_json(&self, path: &str, body: &serde_json::Value) -> Result<reqwest::blocking::Response> {
+        let body = serde_json::to_vec(body)?;
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        let (authorization, content_sha256) = self.authorization_with_body(path, &date, &body);
+        let response = self
+            .http
+            .post(format!("https://{}{}", self.creds.host(), path))
+            .header("date", &date)
+            .header("x-content-sha256", content_sha256)
+            .header("content-type", "application/json")
+            .header("content-length", body.len())
+            .header("authorization", authorization)
+            .body(body)
+            .send()?;
+        check_status(response)
+    }
+
+    fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        metadata: &[(String, String)],
+    ) -> Result<String> {
+        let path = format!("/n/{}/b/{}/u", self.creds.namespace, bucket);
+        let metadata: serde_json::Map<String, serde_json::Value> = metadata
+            .iter()
+            .map(|(key, value)| (format!("opc-meta-{}", key), value.clone().into()))
+            .collect();
+        let body = serde_json::json!({ "object": object_name, "metadata": metadata });
+        let upload: MultipartUpload = self
+            .post_json(&path, &body)
+            .and_then(|response| Ok(response.json()?))
+            .with_context(|| format!("CreateMultipartUpload for {} failed", object_name))?;
+        Ok(upload.upload_id)
+    }
+
+    /// Выгружает часть номер `part_num` (с 1) и возвращает её ETag для коммита.
+    fn upload_part(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        upload_id: &str,
+        part_num: u32,
+        body: &[u8],
+    ) -> Result<String> {
+        let path = format!(
+            "{}?uploadId={}&uploadPartNum={}",
+            self.upload_path(bucket, object_name),
+            urlencoding::encode(upload_id),
+            part_num
+        );
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        let response = self
+            .http
+            .put(format!("https://{}{}", self.creds.host(), path))
+            .header("date", &date)
+            .header("authorization", self.authorization("PUT", &path, &date))
+            .body(body.to_vec())
+            .send()
+            .map_err(anyhow::Error::from)
+            .and_then(check_status)
+            .with_context(|| format!("UploadPart {} for {} failed", part_num, object_name))?;
+        response
+            .headers()
+            .get("etag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string())
+            .ok_or_else(|| anyhow::anyhow!("UploadPart {} for {} returned no ETag", part_num, object_name))
+    }
+
+    fn commit_multipart_upload(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> Result<()> {
+        let path = format!(
+            "{}?uploadId={}",
+            self.upload_path(bucket, object_name),
+            urlencoding::encode(upload_id)
+        );
+        let parts: Vec<serde_json::Value> = parts
+            .iter()
+            .map(|(num, etag)| serde_json::json!({ "partNum": num, "etag": etag }))
+            .collect();
+        self.post_json(&path, &serde_json::json!({ "partsToCommit": parts }))
+            .with_context(|| format!("CommitMultipartUpload for {} failed", object_name))?;
+        Ok(())
+    }
+
+    fn abort_multipart_upload(&self, bucket: &str, object_name: &str, upload_id: &str) -> Result<()> {
+        let path = format!(
+            "{}?uploadId={}",
+            self.upload_path(bucket, object_name),
+            urlencoding::encode(upload_id)
+        );
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        self.http
+            .delete(format!("https://{}{}", self.creds.host(), path))
+            .header("date", &date)
+            .header("authorization", self.authorization("DELETE", &path, &date))
+            .send()
+            .map_err(anyhow::Error::from)
+            .and_then(check_status)
+            .with_context(|| format!("AbortMultipartUpload for {} failed", object_name))?;
+        Ok(())
+    }
+
+    fn put_object(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        metadata: &[(String, String)],
+        body: reqwest::blocking::Body,
+    ) -> Result<()> {
+        let path = self.object_path(bucket, object_name);
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        let mut request = self
+            .http
+            .put(format!("https://{}{}", self.creds.host(), path))
+            .header("date", &date)
+            .header("authorization", self.authorization("PUT", &path, &date));
+        for (key, value) in metadata {
+            request = request.header(format!("opc-meta-{}", key), value);
+        }
+        request
+            .body(body)
+            .send()
+            .map_err(anyhow::Error::from)
+            .and_then(check_status)
+            .with_context(|| format!("PutObject for {} failed", object_name))?;
+        Ok(())
+    }
+
+    /// Имена объектов bucket, начинающиеся с `prefix`, по всем страницам ListObjects.
+    fn list_objects(&self, bucket: &str, prefix: &str) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut start: Option<String> = None;
+        loop {
+            let mut path = format!(
+                "/n/{}/b/{}/o?prefix={}",
+                self.creds.namespace,
+                bucket,
+                urlencoding::encode(prefix)
+            );
+            if let Some(start) = &start {
+                path.push_str(&format!("&start={}", urlencoding::encode(start)));
+            }
+            let date = httpdate::fmt_http_date(SystemTime::now());
+            let page: ObjectList = self
+                .http
+                .get(format!("https://{}{}", self.creds.host(), path))
+                .header("date", &date)
+                .header("authorization", self.authorization("GET", &path, &date))
+                .send()
+                .map_err(anyhow::Error::from)
+                .and_then(check_status)
+                .and_then(|response| Ok(response.json()?))
+                .with_context(|| format!("ListObjects in {} failed", bucket))?;
+            names.extend(page.objects.into_iter().map(|object| object.name));
+            match page.next_start_with {
+                Some(next) => start = Some(next),
+                None => return Ok(names),
+            }
+        }
+    }
+
+    fn head_bucket(&self, bucket: &str) -> Result<()> {
+        let path = format!("/n/{}/b/{}", self.creds.namespace, bucket);
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        self.http
+            .head(format!("https://{}{}", self.creds.host(), path))
+            .header("date", &date)
+            .header("authorization", self.authorization("HEAD", &path, &date))
+            .send()
+            .map_err(anyhow::Error::from)
+            .and_then(check_status)
+            .with_context(|| format!("HeadBucket for {} failed", bucket))?;
+        Ok(())
+    }
+}
+
+/// Проверяет, что bucket существует и доступен с этими учётными данными.
+pub fn check_bucket(creds: &OciCredentials, bucket: &str) -> Result<()> {
+    OciClient::new(creds.clone()).head_bucket(bucket)
+}
+
+/// Имена объектов bucket, начинающиеся с `prefix`.
+pub fn list_objects(creds: &OciCredentials, bucket: &str, prefix: &str) -> Result<Vec<String>> {
+    OciClient::new(creds.clone()).list_objects(bucket, prefix)
+}
+
+/// Адрес объекта в Object Storage (открывается с правами на bucket).
+pub fn object_url(creds: &OciCredentials, bucket: &str, object_name: &str) -> String {
+    format!(
+        "https://{}/n/{}/b/{}/o/{}",
+        creds.host(),
+        creds.namespace,
+        bucket,
+        urlencoding::encode(object_name)
+    )
+}
+
+/// Максимальный суммарный размер пользовательских метаданных объекта в OCI.
+const MAX_METADATA_BYTES: usize = 2000;
+
+/// Проверяет теги объекта (opc-meta-*) по правилам OCI: ключ — непустое имя
+/// HTTP-заголовка из латиницы, цифр, '-' и '_', значение — печатный ASCII,
+/// суммарный размер не больше 2 КБ.
+pub fn validate_metadata(metadata: &[(String, String)]) -> Result<()> {
+    let mut total = 0;
+    for (key, value) in metadata {
+        if key.is_empty() {
+            anyhow::bail!("Metadata key must not be empty (value '{}')", value);
+        }
+        if !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            anyhow::bail!(
+                "Metadata key '{}' is invalid: use letters, digits, '-' and '_' only",
+                key
+            );
+        }
+        if !value.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+            anyhow::bail!("Metadata value for '{}' must be printable ASCII", key);
+        }
+        if metadata.iter().filter(|(k, _)| k.eq_ignore_ascii_case(key)).count() > 1 {
+            anyhow::bail!("Metadata key '{}' is specified more than once", key);
+        }
+        total += key.len() + value.len();
+    }
+    if total > MAX_METADATA_BYTES {
+        anyhow::bail!(
+            "Object metadata is {} bytes, OCI allows at most {}",
+            total,
+            MAX_METADATA_BYTES
+        );
+    }
+    Ok(())
+}
+
+/// Многочастная выгрузка по ходу записи: в памяти держится не больше одной части.
+struct Multipart {
+    part_size: usize,
+    buffer: Vec<u8>,
+    /// Клиент и uploadId создаются при отправке первой части; запись короче
+    /// одной части уходит обычным PutObject в finalize_upload.
+    upload: Option<(OciClient, String)>,
+    /// Номера и ETag уже выгруженных частей — для CommitMultipartUpload.
+    parts: Vec<(u32, String)>,
+}
+
+/// Куда складываются данные записи до выгрузки.
+enum Staging {
+    /// Данные уходят в OCI частями по мере записи.
+    Multipart(Multipart),
+    /// Запись идёт во временный файл, выгрузка запускается отдельно.
+    File { path: PathBuf, file: File },
+}
+
+/// Принимает закодированный поток через `Write` и выгружает его в OCI bucket.
+pub struct OciUploader {
+    bucket: String,
+    object_name: String,
+    metadata: Vec<(String, String)>,
+    staging: Staging,
+    bytes_written: u64,
+    /// Сопутствующие объекты (миниатюры и т.п.), выгружаемые вместе с записью.
+    sidecars: Vec<(String, Vec<u8>)>,
+    retry: RetryPolicy,
+    progress: Arc<UploadProgress>,
+    creds: OciCredentials,
+}
+
+impl OciUploader {
+    /// Выгружатель, который отправляет запись в OCI частями прямо во время записи
+    /// и завершает выгрузку в `finalize_upload`.
+    pub fn new(bucket: &str, object_name: &str, creds: OciCredentials) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            object_name: object_name.to_string(),
+            metadata: Vec::new(),
+            staging: Staging::Multipart(Multipart {
+                part_size: DEFAULT_PART_SIZE_MB as usize * 1024 * 1024,
+                buffer: Vec::new(),
+                upload: None,
+                parts: Vec::new(),
+            }),
+            bytes_written: 0,
+            sidecars: Vec::new(),
+            retry: RetryPolicy::default(),
+            progress: Arc::default(),
+            creds,
+        }
+    }
+
+    /// Размер части многочастной выгрузки; на отложенную выгрузку не влияет.
+    pub fn with_part_size(mut self, part_size_mb: u32) -> Self {
+        if let Staging::Multipart(multipart) = &mut self.staging {
+            multipart.part_size = part_size_mb.max(1) as usize * 1024 * 1024;
+        }
+        self
+    }
+
+    /// Выгружатель с отложенной выгрузкой: запись пишется во временный файл,
+    /// а когда её отправить, решает вызывающая сторона через `PendingUpload`.
+    pub fn deferred(bucket: &str, object_name: &str, creds: OciCredentials) -> Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "rscap-{}-{}",
+            uuid::Uuid::new_v4(),
+            object_name.replace('/', "_")
+        ));
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create temp file {}", path.display()))?;
+        Ok(Self {
+            bucket: bucket.to_string(),
+            object_name: object_name.to_string(),
+            metadata: Vec::new(),
+            staging: Staging::File { path, file },
+            bytes_written: 0,
+            sidecars: Vec::new(),
+            retry: RetryPolicy::default(),
+            progress: Arc::default(),
+            creds,
+        })
+    }
+
+    /// Счётчики, в которых выгружатель публикует ход записи и выгрузки.
+    pub fn with_progress(mut self, progress: Arc<UploadProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Сколько раз и с какой начальной задержкой повторять временные сбои выгрузки.
+    pub fn with_retry(mut self, max_retries: u32, base_delay: Duration) -> Self {
+        self.retry = RetryPolicy {
+            max_retries,
+            base_delay,
+        };
+        self
+    }
+
+    /// Теги (opc-meta-*), которые будут установлены на создаваемом объекте.
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Завершает захват и выгрузку: дописывает последнюю часть и коммитит
+    /// многочастную выгрузку (или отправляет отложенную запись целиком).
+    #[tracing::instrument(skip_all, fields(object = %self.object_name))]
+    fn finalize_upload(&mut self) -> Result<()> {
+        let multipart = match &mut self.staging {
+            Staging::Multipart(multipart) => multipart,
+            Staging::File { .. } => return self.finish_capture()?.upload(),
+        };
+        let client = match multipart.upload.take() {
+            // Запись меньше одной части: многочастная выгрузка не нужна.
+            None => {
+                let client = OciClient::new(self.creds.clone());
+                info!("Uploading {} to bucket {}", self.object_name, self.bucket);
+                let what = format!("PutObject {}", self.object_name);
+                self.retry.run(&what, || {
+                    client.put_object(
+                        &self.bucket,
+                        &self.object_name,
+                        &self.metadata,
+                        multipart.buffer.clone().into(),
+                    )
+                })?;
+                self.progress.uploaded(multipart.buffer.len());
+                multipart.buffer.clear();
+                client
+            }
+            Some((client, upload_id)) => {
+                let result = complete_multipart(
+                    &client,
+                    &self.retry,
+                    &self.progress,
+                    &self.bucket,
+                    &self.object_name,
+                    &upload_id,
+                    multipart,
+                );
+                if let Err(e) = result {
+                    abort_upload(&client, &self.bucket, &self.object_name, &upload_id);
+                    return Err(e);
+                }
+                client
+            }
+        };
+        self.bytes_written = 0;
+        for (object_name, data) in std::mem::take(&mut self.sidecars) {
+            self.retry.run(&format!("PutObject {}", object_name), || {
+                client.put_object(&self.bucket, &object_name, &self.metadata, data.clone().into())
+            })?;
+        }
+        info!("Upload of {} finished", self.object_name);
+        Ok(())
+    }
+
+    /// Отправляет накопленную часть, начиная многочастную выгрузку при первой части.
+    #[tracing::instrument(skip_all, fields(object = %self.object_name))]
+    fn upload_full_part(&mut self) -> Result<()> {
+        let multipart = match &mut self.staging {
+            Staging::Multipart(multipart) => multipart,
+            Staging::File { .. } => return Ok(()),
+        };
+        if multipart.upload.is_none() {
+            let client = OciClient::new(self.creds.clone());
+            let upload_id = self.retry.run(&format!("CreateMultipartUpload {}", self.object_name), || {
+                client.create_multipart_upload(&self.bucket, &self.object_name, &self.metadata)
+            })?;
+            info!("Started multipart upload of {} to bucket {}", self.object_name, self.bucket);
+            multipart.upload = Some((client, upload_id));
+        }
+        let (client, upload_id) = multipart.upload.as_ref().expect("upload is started above");
+        let part_num = multipart.parts.len() as u32 + 1;
+        let body = &multipart.buffer[..multipart.part_size];
+        let what = format!("UploadPart {} of {}", part_num, self.object_name);
+        let etag = self.retry.run(&what, || {
+            client.upload_part(&self.bucket, &self.object_name, upload_id, part_num, body)
+        })?;
+        multipart.parts.push((part_num, etag));
+        self.progress.uploaded_part(multipart.part_size);
+        multipart.buffer.drain(..multipart.part_size);
+        self.bytes_written -= multipart.part_size as u64;
+        Ok(())
+    }
+}
+
+/// Отправляет остаток буфера последней частью и коммитит выгрузку.
+fn complete_multipart(
+    client: &OciClient,
+    retry: &RetryPolicy,
+    progress: &UploadProgress,
+    bucket: &str,
+    object_name: &str,
+    upload_id: &str,
+    multipart: &mut Multipart,
+) -> Result<()> {
+    if !multipart.buffer.is_empty() {
+        let part_num = multipart.parts.len() as u32 + 1;
+        let body = &multipart.buffer;
+        let etag = retry.run(&format!("UploadPart {} of {}", part_num, object_name), || {
+            client.upload_part(bucket, object_name, upload_id, part_num, body)
+        })?;
+        multipart.parts.push((part_num, etag));
+        progress.uploaded_part(multipart.buffer.len());
+        multipart.buffer.clear();
+    }
+    retry.run(&format!("CommitMultipartUpload {}", object_name), || {
+        client.commit_multipart_upload(bucket, object_name, upload_id, &multipart.parts)
+    })
+}
+
+/// Отменяет многочастную выгрузку, чтобы в bucket не остались висящие части.
+fn abort_upload(client: &OciClient, bucket: &str, object_name: &str, upload_id: &str) {
+    warn!("Aborting multipart upload of {}", object_name);
+    if let Err(e) = client.abort_multipart_upload(bucket, object_name, upload_id) {
+        warn!("Failed to abort multipart upload of {}: {:?}", object_name, e);
+    }
+}
+
+impl Write for OciUploader {
+    /// Запись идёт из потока кодирования: пока отправляется часть, кадры
+    /// копятся в очереди конвейера, а в памяти остаётся не больше части.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match &mut self.staging {
+            Staging::Multipart(multipart) => {
+                multipart.buffer.extend_from_slice(buf);
+                buf.len()
+            }
+            Staging::File { file, .. } => file.write(buf)?,
+        };
+        self.bytes_written += written as u64;
+        self.progress.wrote(written);
+        let part_ready = matches!(
+            &self.staging,
+            Staging::Multipart(multipart) if multipart.buffer.len() >= multipart.part_size
+        );
+        if part_ready {
+            if let Err(e) = self.upload_full_part() {
+                if let Staging::Multipart(multipart) = &mut self.staging {
+                    if let Some((client, upload_id)) = multipart.upload.take() {
+                        abort_upload(&client, &self.bucket, &self.object_name, &upload_id);
+                    }
+                }
+                return Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e)));
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.staging {
+            Staging::Multipart(_) => Ok(()),
+            Staging::File { file, .. } => file.flush(),
+        }
+    }
+}
+
+impl StorageSink for OciUploader {
+    fn add_sidecar(&mut self, object_name: &str, data: Vec<u8>) {
+        self.sidecars.push((object_name.to_string(), data));
+    }
+
+    fn pending_bytes(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn is_deferred(&self) -> bool {
+        matches!(self.staging, Staging::File { .. })
+    }
+
+    /// Завершает захват отложенной записи: дальнейшие записи не ожидаются.
+    /// Возвращает готовую к выгрузке запись, не отправляя её.
+    fn finish_capture(&mut self) -> Result<PendingUpload> {
+        let path = match &mut self.staging {
+            Staging::Multipart(_) => {
+                anyhow::bail!("{} is already being uploaded and cannot be deferred", self.object_name)
+            }
+            Staging::File { path, file } => {
+                file.flush()?;
+                file.sync_all()?;
+                path.clone()
+            }
+        };
+        Ok(PendingUpload {
+            bucket: self.bucket.clone(),
+            object_name: self.object_name.clone(),
+            metadata: self.metadata.clone(),
+            path,
+            sidecars: std::mem::take(&mut self.sidecars),
+            retry: self.retry,
+            creds: self.creds.clone(),
+        })
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        self.finalize_upload()
+    }
+}
+
+impl Drop for OciUploader {
+    /// Запись прервалась до finalize_upload: уже выгруженные части не нужны.
+    fn drop(&mut self) {
+        if let Staging::Multipart(multipart) = &mut self.staging {
+            if let Some((client, upload_id)) = multipart.upload.take() {
+                abort_upload(&client, &self.bucket, &self.object_name, &upload_id);
+            }
+        }
+    }
+}
+
+/// Запись, захват которой завершён, но которая ещё не выгружена.
+pub struct PendingUpload {
+    pub bucket: String,
+    pub object_name: String,
+    metadata: Vec<(String, String)>,
+    /// Временный файл с записью.
+    path: PathBuf,
+    sidecars: Vec<(String, Vec<u8>)>,
+    retry: RetryPolicy,
+    creds: OciCredentials,
+}
+
+impl PendingUpload {
+    /// Выгружает запись в OCI. Временный файл удаляется только после успешной
+    /// выгрузки, так что при ошибке попытку можно повторить.
+    #[tracing::instrument(skip_all, fields(object = %self.object_name))]
+    pub fn upload(&self) -> Result<()> {
+        let client = OciClient::new(self.creds.clone());
+        info!("Uploading {} to bucket {}", self.object_name, self.bucket);
+        // Файл открывается заново на каждую попытку: тело запроса читается потоком.
+        self.retry.run(&format!("PutObject {}", self.object_name), || {
+            let file = File::open(&self.path)
+                .with_context(|| format!("Failed to open {}", self.path.display()))?;
+            client.put_object(&self.bucket, &self.object_name, &self.metadata, file.into())
+        })?;
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove temp file {}: {:?}", self.path.display(), e);
+        }
+        for (object_name, data) in &self.sidecars {
+            self.retry.run(&format!("PutObject {}", object_name), || {
+                client.put_object(&self.bucket, object_name, &self.metadata, data.clone().into())
+            })?;
+        }
+        info!("Upload of {} finished", self.object_name);
+        Ok(())
+    }
+}
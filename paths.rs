@@ -0,0 +1,54 @@
+// src/paths.rs
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Каталог состояния приложения: $XDG_STATE_HOME/rscap или ~/.local/state/rscap.
+/// Создаётся при первом обращении.
+pub fn state_dir() -> Result<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").context("HOME is not set")?)
+            .join(".local")
+            .join("state"),
+    };
+    let dir = base.join("rscap");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Каталог для сокетов и прочих файлов времени выполнения:
+/// $XDG_RUNTIME_DIR, а при его отсутствии — системный временный каталог.
+pub fn runtime_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::temp_dir(),
+    }
+}
+
+/// Каталог настроек: $XDG_CONFIG_HOME/rscap или ~/.config/rscap.
+/// Создаётся при первом обращении.
+pub fn config_dir() -> Result<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").context("HOME is not set")?).join(".config"),
+    };
+    let dir = base.join("rscap");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Записывает файл через временный рядом с ним, чтобы сбой посреди записи
+/// не оставил файл наполовину перезаписанным.
+pub fn write_atomically(path: &Path, text: &str) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, text).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
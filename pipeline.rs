@@ -0,0 +1,281 @@
+// src/pipeline.rs
+
+use ffmpeg_next as ffmpeg;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::storage::UploadProgress;
+use tracing::warn;
+
+/// Глубина очереди кадров между захватом и кодированием по умолчанию.
+pub const DEFAULT_QUEUE_DEPTH: usize = 8;
+
+/// Окно буферизации по умолчанию: столько отставания кодера терпится сверх
+/// `queue_depth`, прежде чем начнут выбрасываться кадры.
+pub const DEFAULT_BUFFER_WINDOW_MS: u64 = 2000;
+
+/// Жёсткий предел памяти под очередь кадров. Считается в байтах, а не в кадрах:
+/// несжатый кадр 4K BGRx весит около 33 МБ, 1080p — около 8 МБ. Предел действует
+/// и на `queue_depth`, а окно буферизации урезается до того, что в него помещается.
+pub const MAX_QUEUED_BYTES: usize = 1 << 30;
+
+/// Счётчики состояния конвейера. Пишутся потоками записи, читаются GUI;
+/// `queue_depth` можно менять на лету.
+#[derive(Debug, Default)]
+pub struct PipelineStats {
+    /// Кадров ждёт кодирования.
+    pub frames_queued: AtomicUsize,
+    /// Кадров выброшено из-за переполнения очереди.
+    pub frames_dropped: AtomicU64,
+    /// Кадров отдано кодеру; по приросту GUI считает фактическую частоту кадров.
+    pub frames_encoded: AtomicU64,
+    /// Начало записи (первый захват); перезапуски сегментов его не сбрасывают.
+    pub started_at: Mutex<Option<Instant>>,
+    /// Конец захвата последнего сегмента.
+    pub finished_at: Mutex<Option<Instant>>,
+    /// Байт записано, но ещё не выгружено.
+    pub upload_bytes_pending: AtomicU64,
+    /// Ход выгрузки записи; его публикует приёмник записи.
+    pub upload: Arc<UploadProgress>,
+    /// Длина очереди кадров, после которой включается окно буферизации.
+    pub queue_depth: AtomicUsize,
+    /// Сколько миллисекунд отставания допускается сверх `queue_depth`.
+    pub buffer_window_ms: AtomicU64,
+    /// Запрошена остановка записи (извне, например через HTTP-статус).
+    pub stop: AtomicBool,
+    /// Отдавать ли кадры в предпросмотр GUI; переключается на лету.
+    pub preview: AtomicBool,
+}
+
+impl PipelineStats {
+    pub fn new(queue_depth: usize, buffer_window_ms: u64) -> Self {
+        Self {
+            queue_depth: AtomicUsize::new(queue_depth.max(1)),
+            buffer_window_ms: AtomicU64::new(buffer_window_ms),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.store(depth.max(1), Ordering::Relaxed);
+    }
+
+    pub fn set_buffer_window_ms(&self, window_ms: u64) {
+        self.buffer_window_ms.store(window_ms, Ordering::Relaxed);
+    }
+
+    /// Отмечает начало записи, если оно ещё не отмечено.
+    pub fn mark_started(&self) {
+        self.started_at.lock().unwrap().get_or_insert_with(Instant::now);
+    }
+
+    /// Сколько идёт запись; ноль, пока захват не начался.
+    pub fn elapsed(&self) -> Duration {
+        self.started_at
+            .lock()
+            .unwrap()
+            .map_or(Duration::ZERO, |started| started.elapsed())
+    }
+
+    /// Отмечает конец захвата; у перезапущенной записи — конец последнего сегмента.
+    pub fn mark_finished(&self) {
+        *self.finished_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Длительность записи без выгрузки: от начала до конца захвата.
+    pub fn recorded_duration(&self) -> Duration {
+        let started = *self.started_at.lock().unwrap();
+        let finished = *self.finished_at.lock().unwrap();
+        match (started, finished) {
+            (Some(started), Some(finished)) => finished.saturating_duration_since(started),
+            _ => self.elapsed(),
+        }
+    }
+
+    /// Просит поток захвата прекратить чтение; уже захваченные кадры дописываются.
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn set_preview(&self, enabled: bool) {
+        self.preview.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn preview_enabled(&self) -> bool {
+        self.preview.load(Ordering::Relaxed)
+    }
+}
+
+/// Пользовательская обработка кадров перед кодированием: оверлеи, аналитика,
+/// скрытие областей и т.п.
+///
+/// Вызывается для каждого кадра в потоке кодирования, поэтому должна быть быстрой:
+/// всё время, потраченное в `process`, напрямую задерживает кодер, а при
+/// переполнении очереди приводит к выбрасыванию кадров. Тяжёлую работу следует
+/// выносить в собственный поток, передавая туда копию кадра.
+pub trait FrameProcessor: Send {
+    /// Может читать и изменять кадр. Ошибка прерывает запись.
+    fn process(&mut self, frame: &mut ffmpeg::frame::Video) -> anyhow::Result<()>;
+}
+
+struct QueueState {
+    /// Кадры вместе с моментом постановки в очередь.
+    frames: VecDeque<(Instant, ffmpeg::frame::Video)>,
+    /// Сколько байт данных кадров лежит в очереди.
+    bytes: usize,
+    closed: bool,
+    /// Об урезанном окне буферизации уже предупредили.
+    window_clamped: bool,
+}
+
+/// Размер данных кадра во всех плоскостях.
+fn frame_bytes(frame: &ffmpeg::frame::Video) -> usize {
+    (0..frame.planes()).map(|plane| frame.data(plane).len()).sum()
+}
+
+/// Самое длинное окно буферизации, которое при частоте `fps` и кадрах
+/// по `frame_bytes` байт помещается в `max_bytes`.
+fn max_buffer_window_ms(max_bytes: usize, frame_bytes: usize, fps: u32) -> u64 {
+    let frames = (max_bytes / frame_bytes.max(1)) as u64;
+    frames * 1000 / fps.max(1) as u64
+}
+
+/// Ограниченная очередь декодированных кадров. Если кодер не успевает,
+/// очередь сначала растёт сверх `queue_depth`, пока отставание укладывается
+/// в окно буферизации (кратковременные всплески на сложных кадрах), а затем
+/// выбрасываются самые старые кадры, чтобы захват не отставал от экрана.
+/// Сверх `MAX_QUEUED_BYTES` очередь не растёт ни при каком окне и глубине.
+pub struct FrameQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    stats: Arc<PipelineStats>,
+    fps: u32,
+    max_bytes: usize,
+}
+
+impl FrameQueue {
+    /// `fps` — частота кадров записи: по ней окно буферизации пересчитывается в память.
+    pub fn new(stats: Arc<PipelineStats>, fps: u32) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                frames: VecDeque::new(),
+                bytes: 0,
+                closed: false,
+                window_clamped: false,
+            }),
+            ready: Condvar::new(),
+            stats,
+            fps,
+            max_bytes: MAX_QUEUED_BYTES,
+        }
+    }
+
+    pub fn push(&self, frame: ffmpeg::frame::Video) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let size = frame_bytes(&frame);
+        let depth = self.stats.queue_depth.load(Ordering::Relaxed);
+        let window_ms = self.stats.buffer_window_ms.load(Ordering::Relaxed);
+        let max_window_ms = max_buffer_window_ms(self.max_bytes, size, self.fps);
+        if window_ms > max_window_ms && !state.window_clamped {
+            state.window_clamped = true;
+            warn!(
+                "Buffer window of {} ms does not fit in {} MB of frames, using {} ms",
+                window_ms,
+                self.max_bytes >> 20,
+                max_window_ms
+            );
+        }
+        let window = Duration::from_millis(window_ms.min(max_window_ms));
+        while let Some((queued_at, _)) = state.frames.front() {
+            let fits = state.bytes + size <= self.max_bytes;
+            let within_window = now - *queued_at < window;
+            if fits && (state.frames.len() < depth || within_window) {
+                break;
+            }
+            if let Some((_, dropped)) = state.frames.pop_front() {
+                state.bytes -= frame_bytes(&dropped);
+            }
+            self.stats.frames_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        state.bytes += size;
+        state.frames.push_back((now, frame));
+        self.stats
+            .frames_queued
+            .store(state.frames.len(), Ordering::Relaxed);
+        self.ready.notify_one();
+    }
+
+    /// Ждёт следующий кадр. `None` — очередь закрыта и пуста.
+    pub fn pop(&self) -> Option<ffmpeg::frame::Video> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some((_, frame)) = state.frames.pop_front() {
+                state.bytes -= frame_bytes(&frame);
+                self.stats
+                    .frames_queued
+                    .store(state.frames.len(), Ordering::Relaxed);
+                return Some(frame);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    /// Сообщает кодеру, что новых кадров не будет.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Кадр BGRZ 64x48: 12 КБ данных.
+    fn small_frame() -> ffmpeg::frame::Video {
+        ffmpeg::frame::Video::new(ffmpeg::format::Pixel::BGRZ, 64, 48)
+    }
+
+    #[test]
+    fn the_default_window_is_clamped_for_4k() {
+        let bytes = 3840 * 2160 * 4;
+        let max_window = max_buffer_window_ms(MAX_QUEUED_BYTES, bytes, 60);
+        assert!(max_window < DEFAULT_BUFFER_WINDOW_MS);
+        assert!((max_window * 60 / 1000) as usize * bytes <= MAX_QUEUED_BYTES);
+        assert!(max_buffer_window_ms(MAX_QUEUED_BYTES, 64 * 48 * 4, 30) > 60_000);
+    }
+
+    #[test]
+    fn the_queue_never_grows_past_the_byte_budget() {
+        let stats = Arc::new(PipelineStats::new(1000, 60_000));
+        let mut queue = FrameQueue::new(stats.clone(), 60);
+        let bytes = frame_bytes(&small_frame());
+        queue.max_bytes = bytes * 5;
+        for _ in 0..15 {
+            queue.push(small_frame());
+        }
+        assert_eq!(stats.frames_queued.load(Ordering::Relaxed), 5);
+        assert_eq!(stats.frames_dropped.load(Ordering::Relaxed), 10);
+        assert_eq!(queue.state.lock().unwrap().bytes, bytes * 5);
+    }
+
+    #[test]
+    fn popped_frames_release_their_bytes() {
+        let stats = Arc::new(PipelineStats::new(DEFAULT_QUEUE_DEPTH, DEFAULT_BUFFER_WINDOW_MS));
+        let queue = FrameQueue::new(stats, 30);
+        queue.push(small_frame());
+        queue.push(small_frame());
+        queue.pop().unwrap();
+        assert_eq!(queue.state.lock().unwrap().bytes, 64 * 48 * 4);
+    }
+}
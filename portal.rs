@@ -0,0 +1,456 @@
+// src/portal.rs
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+use zbus::zvariant::{OwnedFd, OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, Proxy, ProxyBuilder};
+use tracing::{info, warn};
+
+use crate::paths;
+
+/// Пользователь закрыл диалог портала или ничего не выбрал. Это не сбой:
+/// GUI сообщает об отмене и позволяет начать заново, супервизор не перезапускает.
+#[derive(Debug)]
+pub struct PortalCancelled;
+
+impl std::fmt::Display for PortalCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Screen sharing was cancelled: no screen or window was shared")
+    }
+}
+
+impl std::error::Error for PortalCancelled {}
+
+/// Сколько раз по умолчанию пытаться создать сессию ScreenCast.
+pub const DEFAULT_CREATE_SESSION_ATTEMPTS: u32 = 3;
+
+/// Сколько ждать ответа на одну попытку CreateSession.
+const CREATE_SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Пауза между попытками: нестабильным бэкендам портала нужно время прийти в себя.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Сколько ждать ответа на запрос с диалогом: пользователь выбирает экран сам.
+const DIALOG_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Результаты запроса портала: словарь a{sv} из сигнала Response.
+pub type Results = HashMap<String, OwnedValue>;
+
+pub fn new_token() -> String {
+    format!("rscap_{}", Uuid::new_v4().simple())
+}
+
+/// Вызывает метод портала, который отвечает объектом Request, и ждёт его сигнал
+/// Response: немедленный ответ метода — лишь путь запроса, а результат (потоки,
+/// дескриптор сессии) приходит позже.
+///
+/// Подписка оформляется до вызова, по пути, который портал строит из
+/// `handle_token`: иначе быстрый ответ может прийти раньше подписки.
+/// Код ответа 1 — отмена пользователем (`PortalCancelled`), 2 — прочий сбой.
+pub async fn request<B>(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    method: &str,
+    token: &str,
+    body: &B,
+    timeout: Duration,
+) -> Result<Results>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+{
+    let mut request_object = request_proxy(connection, request_path(connection, token)).await?;
+    let mut responses = request_object.receive_signal("Response").await?;
+    let handle: OwnedObjectPath = match proxy.call(method, body).await {
+        Ok(handle) => handle,
+        Err(e) if is_cancelled(&e) => return Err(PortalCancelled.into()),
+        Err(e) => return Err(anyhow::Error::from(e).context(format!("{} failed", method))),
+    };
+    // Порталы до версии 0.9 не учитывают handle_token: слушаем путь, который вернули.
+    if handle.as_str() != request_object.path().as_str() {
+        request_object = request_proxy(connection, handle.as_str().to_string()).await?;
+        responses = request_object.receive_signal("Response").await?;
+    }
+    let message = match tokio::time::timeout(timeout, responses.next()).await {
+        Ok(Some(message)) => message,
+        Ok(None) => anyhow::bail!("{} request was closed without a response", method),
+        Err(_) => {
+            let _: zbus::Result<()> = request_object.call("Close", &()).await;
+            anyhow::bail!("{} got no response from the portal within {:?}", method, timeout)
+        }
+    };
+    let (code, results): (u32, Results) = message
+        .body()
+        .map_err(|e| anyhow::anyhow!("Malformed {} response: {:?}", method, e))?;
+    match code {
+        0 => Ok(results),
+        1 => Err(PortalCancelled.into()),
+        other => anyhow::bail!("{} failed in the portal (response code {})", method, other),
+    }
+}
+
+/// Путь объекта Request для токена (см. документацию org.freedesktop.portal.Request).
+fn request_path(connection: &Connection, token: &str) -> String {
+    format!("/org/freedesktop/portal/desktop/request/{}/{}", sender_path(connection), token)
+}
+
+async fn request_proxy(connection: &Connection, path: String) -> Result<Proxy<'static>> {
+    Ok(ProxyBuilder::new_bare(connection)
+        .destination("org.freedesktop.portal.Desktop")?
+        .path(path)?
+        .interface("org.freedesktop.portal.Request")?
+        .build()
+        .await?)
+}
+
+/// Создаёт сессию ScreenCast, повторяя вызов до `attempts` раз.
+///
+/// Каждая попытка идёт со свежим `session_handle_token`: повтор со старым токеном
+/// отклоняется порталом, если предыдущий вызов успел создать сессию. Между попытками
+/// такая полусозданная сессия закрывается. Отказ пользователя не повторяется.
+#[tracing::instrument(skip_all)]
+pub async fn create_session(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    attempts: u32,
+) -> Result<String> {
+    let attempts = attempts.max(1);
+    let mut last_error = None;
+    for attempt in 1..=attempts {
+        let token = new_token();
+        let mut options: HashMap<&str, Value> = HashMap::new();
+        options.insert("handle_token", Value::from(token.as_str()));
+        options.insert("session_handle_token", Value::from(token.as_str()));
+        let call = request(
+            connection,
+            proxy,
+            "CreateSession",
+            &token,
+            &options,
+            CREATE_SESSION_TIMEOUT,
+        );
+        let error = match call.await {
+            Ok(results) => {
+                match results.get("session_handle").and_then(|v| v.downcast_ref::<str>()) {
+                    Some(session_handle) => return Ok(session_handle.to_string()),
+                    None => anyhow::anyhow!("CreateSession response has no session_handle"),
+                }
+            }
+            Err(e) if e.is::<PortalCancelled>() => return Err(e),
+            Err(e) => e,
+        };
+        warn!("Portal session attempt {}/{} failed: {:?}", attempt, attempts, error);
+        close_session(connection, &session_path(connection, &token)).await;
+        last_error = Some(error);
+        if attempt < attempts {
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("CreateSession was not attempted")))
+}
+
+/// persist_mode = 2: разрешение действует, пока пользователь его не отзовёт.
+const PERSIST_UNTIL_REVOKED: u32 = 2;
+
+/// Файл с restore_token последней сессии: $XDG_CONFIG_HOME/rscap/restore_token.
+fn restore_token_path() -> Result<PathBuf> {
+    Ok(paths::config_dir()?.join("restore_token"))
+}
+
+fn load_restore_token() -> Option<String> {
+    let token = fs::read_to_string(restore_token_path().ok()?).ok()?;
+    let token = token.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// Сохраняет токен из ответа Start. Токен одноразовый: портал выдаёт новый
+/// при каждом запуске, поэтому файл перезаписывается после каждой записи.
+pub fn save_restore_token(token: &str) {
+    let result = restore_token_path().and_then(|path| {
+        fs::write(&path, token).with_context(|| format!("Failed to write {}", path.display()))
+    });
+    if let Err(e) = result {
+        warn!("Cannot save portal restore token: {:?}", e);
+    }
+}
+
+fn clear_restore_token() {
+    if let Ok(path) = restore_token_path() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Типы источников для выбора в GUI и CLI.
+pub const SOURCE_TYPES: &[&str] = &["any", "monitor", "window", "virtual"];
+
+/// Маска `types` для SelectSources: 1 — монитор, 2 — окно, 4 — виртуальный экран.
+pub fn source_types_mask(text: &str) -> Result<u32> {
+    match text {
+        "any" => Ok(1 | 2),
+        "monitor" => Ok(1),
+        "window" => Ok(2),
+        "virtual" => Ok(4),
+        other => anyhow::bail!(
+            "Unknown source type '{}' (expected any, monitor, window or virtual)",
+            other
+        ),
+    }
+}
+
+/// Режимы курсора ScreenCast (битовая маска AvailableCursorModes).
+const CURSOR_HIDDEN: u32 = 1;
+const CURSOR_EMBEDDED: u32 = 2;
+const CURSOR_METADATA: u32 = 4;
+
+/// Режимы курсора для выбора в GUI и CLI.
+pub const CURSOR_MODES: &[&str] = &["hidden", "embedded", "metadata"];
+
+/// Значение cursor_mode портала для строки из GUI/CLI.
+pub fn cursor_mode_value(text: &str) -> Result<u32> {
+    match text {
+        "hidden" => Ok(CURSOR_HIDDEN),
+        "embedded" => Ok(CURSOR_EMBEDDED),
+        "metadata" => Ok(CURSOR_METADATA),
+        other => anyhow::bail!(
+            "Unknown cursor mode '{}' (expected hidden, embedded or metadata)",
+            other
+        ),
+    }
+}
+
+/// Режим курсора для SelectSources: запрошенный, а если портал его не умеет —
+/// ближайший поддерживаемый. None — портал не сообщает режимы (старая версия),
+/// и cursor_mode не передаётся.
+///
+/// Курсор в метаданных пришлось бы рисовать поверх кадра самим, а такого
+/// наложения пока нет, поэтому metadata записывается как embedded.
+async fn choose_cursor_mode(proxy: &Proxy<'_>, requested: u32) -> Option<u32> {
+    let requested = if requested == CURSOR_METADATA {
+        warn!("Metadata cursor is not composited yet, recording an embedded cursor");
+        CURSOR_EMBEDDED
+    } else {
+        requested
+    };
+    let available: u32 = match proxy.get_property("AvailableCursorModes").await {
+        Ok(modes) => modes,
+        Err(e) => {
+            warn!("Portal does not report cursor modes, using its default: {:?}", e);
+            return None;
+        }
+    };
+    let preferred: &[u32] = if requested == CURSOR_HIDDEN {
+        &[CURSOR_HIDDEN, CURSOR_METADATA, CURSOR_EMBEDDED]
+    } else {
+        &[CURSOR_EMBEDDED, CURSOR_METADATA, CURSOR_HIDDEN]
+    };
+    let mode = preferred.iter().copied().find(|mode| available & mode != 0)?;
+    if mode != preferred[0] {
+        info!(
+            "Cursor mode {} is not supported by the portal (available: {}), using {}",
+            preferred[0], available, mode
+        );
+    }
+    Some(mode)
+}
+
+/// Вызывает SelectSources с persist_mode и сохранённым restore_token, чтобы
+/// портал не спрашивал разрешение при каждом запуске. Если токен отвергнут
+/// (разрешение отозвано или токен устарел), он удаляется и выбор источника
+/// повторяется без него — с обычным диалогом портала.
+#[tracing::instrument(skip_all)]
+pub async fn select_sources(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    session_handle: &str,
+    source_types: u32,
+    cursor_mode: u32,
+) -> Result<()> {
+    // Портал отвергает типы, которых не умеет (например, виртуальные экраны).
+    let types = match proxy.get_property::<u32>("AvailableSourceTypes").await {
+        Ok(available) if available & source_types == 0 => anyhow::bail!(
+            "The screen cast portal cannot capture the requested sources (types {}, available {})",
+            source_types,
+            available
+        ),
+        Ok(available) => available & source_types,
+        Err(_) => source_types,
+    };
+    let options = SelectOptions {
+        types,
+        cursor_mode: choose_cursor_mode(proxy, cursor_mode).await,
+        restore_token: load_restore_token(),
+    };
+    match call_select_sources(connection, proxy, session_handle, &options).await {
+        Ok(()) => Ok(()),
+        Err(e) if options.restore_token.is_some() && !e.is::<PortalCancelled>() => {
+            warn!("Portal rejected the saved restore token, asking again: {:?}", e);
+            clear_restore_token();
+            let options = SelectOptions {
+                restore_token: None,
+                ..options
+            };
+            call_select_sources(connection, proxy, session_handle, &options).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Параметры SelectSources.
+struct SelectOptions {
+    types: u32,
+    cursor_mode: Option<u32>,
+    restore_token: Option<String>,
+}
+
+async fn call_select_sources(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    session_handle: &str,
+    select: &SelectOptions,
+) -> Result<()> {
+    let token = new_token();
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    options.insert("types", Value::U32(select.types));
+    // Пользователь может отдать несколько источников; какой из потоков
+    // записывать, решается после Start (node_id из параметров или диалог GUI).
+    options.insert("multiple", Value::Bool(true));
+    options.insert("persist_mode", Value::U32(PERSIST_UNTIL_REVOKED));
+    if let Some(mode) = select.cursor_mode {
+        options.insert("cursor_mode", Value::U32(mode));
+    }
+    if let Some(token) = select.restore_token.as_deref() {
+        options.insert("restore_token", Value::from(token));
+    }
+    let body = (session_handle, options);
+    request(connection, proxy, "SelectSources", &token, &body, DIALOG_TIMEOUT).await?;
+    Ok(())
+}
+
+/// Ответ Start: отданные порталом потоки и токен для следующей сессии без диалога.
+#[derive(Debug)]
+pub struct Started {
+    pub streams: Vec<StreamChoice>,
+    pub restore_token: Option<String>,
+}
+
+/// Запускает захват: портал показывает диалог выбора (или берёт разрешение по
+/// restore_token) и отвечает списком потоков PipeWire.
+#[tracing::instrument(skip_all)]
+pub async fn start(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    session_handle: &str,
+) -> Result<Started> {
+    let token = new_token();
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    let body = (session_handle, "rust_screen_recorder", options);
+    let results = request(connection, proxy, "Start", &token, &body, DIALOG_TIMEOUT).await?;
+    // streams: a(ua{sv}) — узел PipeWire и его свойства.
+    let raw_streams: Vec<(u32, Results)> = match results.get("streams") {
+        Some(streams) => streams
+            .clone()
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("Malformed streams in Start response: {:?}", e))?,
+        None => Vec::new(),
+    };
+    let streams = raw_streams
+        .into_iter()
+        .map(|(node_id, props)| StreamChoice {
+            node_id,
+            size: props.get("size").and_then(|v| v.clone().try_into().ok()),
+            source_type: props.get("source_type").and_then(|v| v.downcast_ref::<u32>().copied()),
+        })
+        .collect();
+    let restore_token = results
+        .get("restore_token")
+        .and_then(|v| v.downcast_ref::<str>())
+        .map(str::to_string);
+    Ok(Started {
+        streams,
+        restore_token,
+    })
+}
+
+/// Дескриптор удалённого PipeWire с доступом к потокам сессии.
+#[tracing::instrument(skip_all)]
+pub async fn open_pipewire_remote(proxy: &Proxy<'_>, session_handle: &str) -> Result<OwnedFd> {
+    let options: HashMap<&str, Value> = HashMap::new();
+    proxy
+        .call("OpenPipeWireRemote", &(session_handle, options))
+        .await
+        .map_err(|e| anyhow::Error::from(e).context("OpenPipeWireRemote failed"))
+}
+
+/// Отказ пользователя или запрет политики: повторять бессмысленно.
+pub fn is_cancelled(error: &zbus::Error) -> bool {
+    match error {
+        zbus::Error::MethodError(name, _, _) => {
+            let name = name.as_str();
+            name.ends_with(".Cancelled") || name.ends_with(".AccessDenied")
+        }
+        _ => false,
+    }
+}
+
+/// Путь объекта сессии, который портал создаёт для данного токена
+/// (см. документацию org.freedesktop.portal.Session).
+fn session_path(connection: &Connection, token: &str) -> String {
+    format!("/org/freedesktop/portal/desktop/session/{}/{}", sender_path(connection), token)
+}
+
+/// Уникальное имя соединения в виде элемента пути: ":1.42" -> "1_42".
+fn sender_path(connection: &Connection) -> String {
+    connection
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default()
+}
+
+/// Закрывает сессию, если она существует. Ошибки не важны: чаще всего
+/// сессия просто не успела появиться (или портал уже закрыл её сам).
+pub async fn close_session(connection: &Connection, path: &str) {
+    let proxy = match ProxyBuilder::new_bare(connection)
+        .destination("org.freedesktop.portal.Desktop")
+        .and_then(|builder| builder.path(path.to_string()))
+        .and_then(|builder| builder.interface("org.freedesktop.portal.Session"))
+    {
+        Ok(builder) => builder.build().await,
+        Err(e) => Err(e),
+    };
+    if let Ok(proxy) = proxy {
+        let _: zbus::Result<()> = proxy.call("Close", &()).await;
+    }
+}
+
+/// Поток, отданный порталом после Start, в виде для выбора пользователем.
+#[derive(Debug, Clone)]
+pub struct StreamChoice {
+    pub node_id: u32,
+    /// Размер источника в пикселях, если портал его сообщил.
+    pub size: Option<(i32, i32)>,
+    /// Тип источника: 1 — монитор, 2 — окно, 4 — виртуальный экран.
+    pub source_type: Option<u32>,
+}
+
+impl StreamChoice {
+    /// Подпись для списка: "#2 window, node 57, 1280x720".
+    pub fn label(&self, position: usize) -> String {
+        let kind = match self.source_type {
+            Some(1) => "monitor",
+            Some(2) => "window",
+            Some(4) => "virtual",
+            _ => "stream",
+        };
+        let mut label = format!("#{} {}, node {}", position + 1, kind, self.node_id);
+        if let Some((width, height)) = self.size {
+            label.push_str(&format!(", {}x{}", width, height));
+        }
+        label
+    }
+}
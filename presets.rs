@@ -0,0 +1,206 @@
+// src/presets.rs
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::gui::RecordParams;
+use crate::paths;
+use crate::rate_control::TargetSize;
+use crate::redact;
+use crate::thumbnails::ThumbnailSettings;
+
+/// Именованный набор настроек формы ("1080p stream", "4K archive").
+///
+/// Строится из тех же `RecordParams`, что уходят в запись (`From<&RecordParams>`),
+/// но области и соотношение сторон хранит текстом, как в полях формы.
+/// Отсутствующие в файле поля получают значения по умолчанию, поэтому пресеты
+/// старых версий продолжают загружаться.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub output_folder: String,
+    pub backend: String,
+    pub filename_template: String,
+    pub container: String,
+    pub video_codec: String,
+    pub bitrate: u32,
+    pub fps: u32,
+    pub out_width: Option<u32>,
+    pub out_height: Option<u32>,
+    pub target_size: Option<TargetSize>,
+    pub encoding_mode: String,
+    pub audio_device: String,
+    pub audio_device_secondary: String,
+    /// Усиление источников; None — пресет старой версии (100%).
+    pub audio_gain_percent: Option<u32>,
+    pub audio_secondary_gain_percent: Option<u32>,
+    pub audio_bitrate: u32,
+    pub audio_codec: String,
+    pub opus_application: String,
+    pub defer_upload: bool,
+    pub redact_regions: String,
+    pub object_metadata: Vec<(String, String)>,
+    pub queue_depth: usize,
+    pub buffer_window_ms: u64,
+    pub scene_change_sensitivity: Option<u32>,
+    pub damage_driven: bool,
+    pub follow_active_window: bool,
+    /// Соотношение сторон задано как DAR (кадра), а не SAR (пикселя).
+    pub aspect_display: bool,
+    pub aspect_ratio: String,
+    pub timing_log: bool,
+    pub concat_friendly: bool,
+    pub control_socket: bool,
+    pub portal_attempts: u32,
+    pub render_node: Option<String>,
+    pub status_port: Option<u16>,
+    pub thumbnails: Option<ThumbnailSettings>,
+    pub max_concurrent_recordings: usize,
+    pub embed_provenance: bool,
+    pub supersample_factor: u32,
+    pub max_restarts: u32,
+    pub upload_after_finish: bool,
+    pub split_audio: bool,
+    pub upload_retries: Option<u32>,
+    pub crf: u32,
+    pub x264_preset: String,
+    pub x264_tune: String,
+    pub keyframe_interval_secs: u32,
+    pub gop_size: Option<u32>,
+    pub hw_accel: String,
+    pub crop_x: u32,
+    pub crop_y: u32,
+    pub crop_w: u32,
+    pub crop_h: u32,
+    pub max_duration_secs: Option<u64>,
+    pub source_types: String,
+    pub cursor_mode: String,
+    pub upload_part_size_mb: u32,
+    /// Глобальное сочетание записи; None — по умолчанию, пустая строка — выключено.
+    pub hotkey: Option<String>,
+    pub preview: bool,
+}
+
+/// Сочетание клавиш в `RecordParams` не входит и остаётся None.
+impl From<&RecordParams> for Preset {
+    fn from(params: &RecordParams) -> Self {
+        let (aspect_ratio, aspect_display) = params.aspect_ratio.to_text();
+        Self {
+            output_folder: params.output_folder.clone(),
+            backend: params.backend.clone(),
+            filename_template: params.filename_template.clone(),
+            container: params.container.clone(),
+            video_codec: params.video_codec.clone(),
+            bitrate: params.bitrate,
+            fps: params.fps,
+            out_width: params.out_width,
+            out_height: params.out_height,
+            target_size: params.target_size,
+            encoding_mode: params.encoding_mode.clone(),
+            audio_device: params.audio_device.clone(),
+            audio_device_secondary: params.audio_device_secondary.clone(),
+            audio_gain_percent: Some(params.audio_gain_percent),
+            audio_secondary_gain_percent: Some(params.audio_secondary_gain_percent),
+            audio_bitrate: params.audio_bitrate,
+            audio_codec: params.audio_codec.clone(),
+            opus_application: params.opus_application.clone(),
+            defer_upload: params.defer_upload,
+            redact_regions: redact::format_list(&params.redact_regions),
+            object_metadata: params.object_metadata.clone(),
+            queue_depth: params.queue_depth,
+            buffer_window_ms: params.buffer_window_ms,
+            scene_change_sensitivity: params.scene_change_sensitivity,
+            damage_driven: params.damage_driven,
+            follow_active_window: params.follow_active_window,
+            aspect_display,
+            aspect_ratio,
+            timing_log: params.timing_log,
+            concat_friendly: params.concat_friendly,
+            control_socket: params.control_socket,
+            portal_attempts: params.portal_attempts,
+            render_node: params
+                .render_node
+                .as_ref()
+                .map(|node| node.to_string_lossy().into_owned()),
+            status_port: params.status_port,
+            thumbnails: params.thumbnails,
+            max_concurrent_recordings: params.max_concurrent_recordings,
+            embed_provenance: params.embed_provenance,
+            supersample_factor: params.supersample_factor,
+            max_restarts: params.max_restarts,
+            upload_after_finish: params.upload_after_finish,
+            split_audio: params.split_audio,
+            upload_retries: Some(params.upload_retries),
+            crf: params.crf,
+            x264_preset: params.x264_preset.clone(),
+            x264_tune: params.x264_tune.clone(),
+            keyframe_interval_secs: params.keyframe_interval_secs,
+            gop_size: params.gop_size,
+            hw_accel: params.hw_accel.clone(),
+            crop_x: params.crop_x,
+            crop_y: params.crop_y,
+            crop_w: params.crop_w,
+            crop_h: params.crop_h,
+            max_duration_secs: params.max_duration_secs,
+            source_types: params.source_types.clone(),
+            cursor_mode: params.cursor_mode.clone(),
+            upload_part_size_mb: params.upload_part_size_mb,
+            hotkey: None,
+            preview: params.preview,
+        }
+    }
+}
+
+/// Файл пресетов: $XDG_CONFIG_HOME/rscap/presets.json.
+fn presets_path() -> Result<PathBuf> {
+    Ok(paths::config_dir()?.join("presets.json"))
+}
+
+/// Все сохранённые пресеты по имени. Нет файла — нет пресетов.
+pub fn load_presets() -> Result<BTreeMap<String, Preset>> {
+    let path = presets_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read presets {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("Failed to parse presets {}", path.display()))
+}
+
+/// Перезаписывает файл пресетов целиком (через временный файл, чтобы сбой
+/// посреди записи не уничтожил остальные пресеты).
+pub fn save_presets(presets: &BTreeMap<String, Preset>) -> Result<()> {
+    write_json(&presets_path()?, presets)
+}
+
+fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    paths::write_atomically(path, &serde_json::to_string_pretty(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::AspectRatio;
+    use crate::redact::RedactRegion;
+
+    #[test]
+    fn preset_from_params_keeps_the_form_text() {
+        let params = RecordParams {
+            redact_regions: RedactRegion::parse_list("10,20,300,40; 0,0,8,8,black").unwrap(),
+            aspect_ratio: AspectRatio::Display(16, 9),
+            render_node: Some(PathBuf::from("/dev/dri/renderD129")),
+            ..RecordParams::default()
+        };
+        let preset = Preset::from(&params);
+        assert_eq!(preset.redact_regions, "10,20,300,40; 0,0,8,8,black");
+        assert_eq!(preset.aspect_ratio, "16:9");
+        assert!(preset.aspect_display);
+        assert_eq!(preset.render_node.as_deref(), Some("/dev/dri/renderD129"));
+        assert_eq!(preset.audio_device, params.audio_device);
+        assert_eq!(preset.encoding_mode, params.encoding_mode);
+        assert_eq!(preset.hotkey, None);
+    }
+}
@@ -0,0 +1,109 @@
+// src/preview.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::software::scaling;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::convert::VideoFormat;
+use crate::gui::GuiEvent;
+use crate::pipeline::PipelineStats;
+
+/// Частота кадров предпросмотра: для контроля картинки хватает, а кодеру
+/// остаётся почти всё время потока.
+pub const PREVIEW_FPS: u32 = 15;
+
+/// Рамка, в которую вписывается кадр предпросмотра с сохранением пропорций.
+const PREVIEW_MAX_WIDTH: u32 = 480;
+const PREVIEW_MAX_HEIGHT: u32 = 270;
+
+/// Уменьшенный кадр для окна: RGB24, строки без выравнивания.
+#[derive(Debug)]
+pub struct PreviewImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Отдаёт GUI уменьшенные копии записываемых кадров.
+///
+/// Работает в потоке кодирования, поэтому дёшев: кадры прореживаются до
+/// `PREVIEW_FPS` до всякой обработки, а уменьшение и перевод в RGB делаются
+/// одним проходом swscale (FAST_BILINEAR). Пока предпросмотр выключен
+/// (`PipelineStats::preview_enabled`), кадры не трогаются вовсе.
+pub struct Preview {
+    events: glib::Sender<GuiEvent>,
+    stats: Arc<PipelineStats>,
+    /// Масштабировщик под формат входа и размер предпросмотра.
+    scaler: Option<(VideoFormat, scaling::Context, u32, u32)>,
+    next_at: Instant,
+}
+
+impl Preview {
+    pub fn new(events: glib::Sender<GuiEvent>, stats: Arc<PipelineStats>) -> Self {
+        Self {
+            events,
+            stats,
+            scaler: None,
+            next_at: Instant::now(),
+        }
+    }
+
+    /// Отправляет кадр в окно, если предпросмотр включён и подошла его очередь.
+    pub fn offer(&mut self, frame: &ffmpeg::frame::Video) -> Result<()> {
+        let now = Instant::now();
+        if !self.stats.preview_enabled() || now < self.next_at {
+            return Ok(());
+        }
+        self.next_at = now + Duration::from_secs(1) / PREVIEW_FPS;
+        let input = VideoFormat::of_frame(frame);
+        if self.scaler.as_ref().map(|(format, ..)| *format) != Some(input) {
+            let (width, height) = fit(input.width, input.height);
+            let scaler = scaling::Context::get(
+                input.pixel,
+                input.width,
+                input.height,
+                ffmpeg::format::Pixel::RGB24,
+                width,
+                height,
+                scaling::Flags::FAST_BILINEAR,
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to create preview scaler: {:?}", e))?;
+            self.scaler = Some((input, scaler, width, height));
+        }
+        let (_, scaler, width, height) = self.scaler.as_mut().expect("scaler is created above");
+        let mut scaled = ffmpeg::frame::Video::empty();
+        scaler
+            .run(frame, &mut scaled)
+            .map_err(|e| anyhow::anyhow!("Error scaling preview frame: {:?}", e))?;
+        let row = *width as usize * 3;
+        let stride = scaled.stride(0);
+        let mut pixels = Vec::with_capacity(row * *height as usize);
+        for y in 0..*height as usize {
+            pixels.extend_from_slice(&scaled.data(0)[y * stride..y * stride + row]);
+        }
+        // Окно уже закрыто — предпросмотр просто больше некому показывать.
+        let _ = self.events.send(GuiEvent::Preview(PreviewImage {
+            width: *width,
+            height: *height,
+            pixels,
+        }));
+        Ok(())
+    }
+}
+
+/// Размер предпросмотра для кадра `width`x`height`: вписан в рамку, не больше
+/// самого кадра.
+fn fit(width: u32, height: u32) -> (u32, u32) {
+    let (width, height) = (width.max(1) as u64, height.max(1) as u64);
+    let scale_w = PREVIEW_MAX_WIDTH as u64 * height;
+    let scale_h = PREVIEW_MAX_HEIGHT as u64 * width;
+    let (w, h) = if scale_w <= scale_h {
+        // Упирается в ширину рамки.
+        (PREVIEW_MAX_WIDTH as u64, height * PREVIEW_MAX_WIDTH as u64 / width)
+    } else {
+        (width * PREVIEW_MAX_HEIGHT as u64 / height, PREVIEW_MAX_HEIGHT as u64)
+    };
+    (w.min(width).max(1) as u32, h.min(height).max(1) as u32)
+}
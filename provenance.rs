@@ -0,0 +1,52 @@
+// src/provenance.rs
+
+use ffmpeg_next as ffmpeg;
+use tracing::warn;
+
+use crate::gui::RecordParams;
+use crate::rate_control::EncodingMode;
+
+/// UUID полезной нагрузки SEI user_data_unregistered, по которому
+/// нижестоящие инструменты узнают сведения rscap среди прочих SEI.
+const PROVENANCE_UUID: [u8; 16] = [
+    0x72, 0x73, 0x63, 0x61, 0x70, 0x2d, 0x4e, 0x8b, 0x9a, 0x3e, 0x51, 0x0c, 0x6d, 0x24, 0xf1, 0x07,
+];
+
+/// Параметры кодирования в виде строки "ключ=значение" через пробел.
+pub fn describe(params: &RecordParams, width: u32, height: u32) -> String {
+    let mut text = format!(
+        "rscap codec={} mode={} source={}x{} fps={}",
+        params.video_codec,
+        params.encoding_mode,
+        width,
+        height,
+        params.fps
+    );
+    match params.rate_mode() {
+        Ok(EncodingMode::Crf) => text.push_str(&format!(" crf={}", params.crf)),
+        Ok(mode) if !mode.uses_bitrate() => {}
+        _ => {
+            if let Ok(bitrate) = params.video_bitrate() {
+                text.push_str(&format!(" bitrate={}k", bitrate));
+            }
+        }
+    }
+    text
+}
+
+/// Прикрепляет описание к кадру как SEI user_data_unregistered: libx264 вписывает
+/// такие данные прямо в элементарный поток, поэтому они переживают перепаковку
+/// и удаление метаданных контейнера.
+pub fn attach(frame: &mut ffmpeg::frame::Video, text: &str) {
+    let mut payload = PROVENANCE_UUID.to_vec();
+    payload.extend_from_slice(text.as_bytes());
+    payload.push(0);
+    if let Some(mut side_data) = frame.new_side_data(
+        ffmpeg::frame::side_data::Type::SEI_UNREGISTERED,
+        payload.len(),
+    ) {
+        side_data.data_mut().copy_from_slice(&payload);
+    } else {
+        warn!("Failed to attach provenance SEI to frame");
+    }
+}
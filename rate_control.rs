@@ -0,0 +1,77 @@
+// src/rate_control.rs
+
+use serde::{Deserialize, Serialize};
+
+/// Доля размера файла, закладываемая на служебные данные контейнера.
+const CONTAINER_OVERHEAD: f64 = 0.02;
+
+/// Средний битрейт видео (кбит/с), при котором запись длительностью
+/// `duration_secs` уложится в `size_mb` мегабайт с учётом звука `audio_kbps`.
+/// Возвращает None, если при таком размере на видео ничего не остаётся.
+pub fn bitrate_for_target_size(size_mb: u32, duration_secs: u64, audio_kbps: u32) -> Option<u32> {
+    if duration_secs == 0 {
+        return None;
+    }
+    let total_kbits = size_mb as f64 * 1024.0 * 1024.0 * 8.0 / 1000.0;
+    let total_kbps = total_kbits * (1.0 - CONTAINER_OVERHEAD) / duration_secs as f64;
+    let video_kbps = total_kbps - audio_kbps as f64;
+    if video_kbps < 1.0 {
+        None
+    } else {
+        Some(video_kbps as u32)
+    }
+}
+
+/// Цель по размеру файла при известной длительности записи.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TargetSize {
+    pub size_mb: u32,
+    pub duration_secs: u64,
+}
+
+/// Качество CRF по умолчанию: визуально близко к источнику для x264.
+pub const DEFAULT_CRF: u32 = 23;
+
+/// Наибольшее значение CRF у x264/x265 (чем больше, тем хуже качество).
+pub const MAX_CRF: u32 = 51;
+
+/// Режим управления битрейтом видео.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodingMode {
+    /// Постоянный битрейт: min = max = буфер = целевому (HRD CBR у x264).
+    Cbr,
+    /// Переменный битрейт: целевой битрейт в среднем, пики не ограничены.
+    Vbr,
+    /// Постоянное качество: crf из параметров, битрейт не задаётся.
+    Crf,
+    /// Без потерь: qp=0 в YUV 4:4:4.
+    Lossless,
+    /// Почти без потерь: crf=0 в 4:2:0.
+    NearLossless,
+}
+
+impl EncodingMode {
+    /// Разбирает строку режима из GUI ("CBR", "VBR", "CRF", "Lossless", "Near-lossless").
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        match text {
+            "CBR" => Ok(EncodingMode::Cbr),
+            "VBR" => Ok(EncodingMode::Vbr),
+            "CRF" => Ok(EncodingMode::Crf),
+            "Lossless" => Ok(EncodingMode::Lossless),
+            "Near-lossless" => Ok(EncodingMode::NearLossless),
+            other => anyhow::bail!(
+                "Unknown encoding mode '{}' (expected CBR, VBR, CRF, Lossless or Near-lossless)",
+                other
+            ),
+        }
+    }
+
+    /// Режимы, в которых кодеру задаётся битрейт (и работает цель по размеру).
+    pub fn uses_bitrate(self) -> bool {
+        matches!(self, EncodingMode::Cbr | EncodingMode::Vbr)
+    }
+
+    pub fn is_lossless(self) -> bool {
+        matches!(self, EncodingMode::Lossless | EncodingMode::NearLossless)
+    }
+}
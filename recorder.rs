@@ -0,0 +1,1820 @@
+// src/recorder.rs
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::{Runtime, RuntimeFlavor};
+use pipewire::prelude::*;
+use zbus::{Connection, Proxy, ProxyBuilder};
+use libc;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::format::io::IO;
+use ffmpeg::Rescale;
+
+use crate::audio_capture::{AudioCapture, AudioInput, AudioOutput, AudioSources, VideoStart};
+use crate::control::ControlServer;
+use crate::convert::{VideoConverter, VideoFormat};
+use crate::crop::CropFilter;
+use crate::damage::DamageMonitor;
+use crate::file_sink::{self, FileSink};
+use crate::filters::{apply_stage, VideoInput};
+use crate::follow::FollowFilter;
+use crate::formats::VideoCodec;
+use crate::framerate::{FrameRateFilter, MonotonicPts};
+use crate::gpu::{self, HwEncoder};
+use crate::gui::{GuiEvent, RecordParams};
+use crate::memory_sink::MemorySink;
+use crate::oci_uploader::{self, OciCredentials, OciUploader, PendingUpload};
+use crate::pipeline::{FrameProcessor, FrameQueue, PipelineStats};
+use crate::portal::{self, PortalCancelled};
+use crate::preview::Preview;
+use crate::provenance;
+use crate::rate_control::EncodingMode;
+use crate::redact::RedactFilter;
+use crate::resample::AudioFormat;
+use crate::s3_uploader::{self, S3Uploader};
+use crate::scene::{DamageTracker, SceneDetector};
+use crate::status::StatusServer;
+use crate::storage::{self, Backend, StorageSink};
+use crate::supersample::SupersampleFilter;
+use crate::supervisor::{self, StreamInterrupted};
+use crate::thumbnails::ThumbnailSprite;
+use crate::timings::{Stage, TimingLog};
+use tracing::{info, warn};
+
+/// Одна запись экрана для приложений, встраивающих rscap: выбор источника
+/// в диалоге портала, захват, кодирование и вывод в хранилище из `RecordParams`
+/// или, с `with_sink`, в память вызывающей стороны.
+///
+/// Запись идёт до `PipelineStats::request_stop` (через `stats`), конца потока
+/// или `max_duration_secs`. Перезапусков после сбоев здесь нет — их делает
+/// приложение поверх `start_recording`.
+pub struct Recorder {
+    params: RecordParams,
+    stats: Arc<PipelineStats>,
+    sink: Option<MemorySink>,
+    frame_processor: Option<Box<dyn FrameProcessor>>,
+}
+
+impl Recorder {
+    pub fn new(params: RecordParams) -> Self {
+        let stats = Arc::new(PipelineStats::new(params.queue_depth, params.buffer_window_ms));
+        Self {
+            params,
+            stats,
+            sink: None,
+            frame_processor: None,
+        }
+    }
+
+    /// Закодированный поток уходит в `sink` вместо хранилища: bucket и учётные
+    /// данные не нужны, выгрузки нет.
+    pub fn with_sink(mut self, sink: MemorySink) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Каждый кадр перед кодированием проходит через `processor`: оверлеи,
+    /// аналитика, скрытие областей.
+    ///
+    /// `process` вызывается в потоке кодирования, после всех фильтров записи
+    /// и в их формате кадра, до перевода в формат кодера. Он должен укладываться
+    /// в малую долю интервала между кадрами: пока он работает, кодер стоит,
+    /// очередь кадров растёт, а сверх `queue_depth` и окна буферизации старые
+    /// кадры выбрасываются (см. `PipelineStats::frames_dropped`). Тяжёлую работу
+    /// выносите в свой поток с копией кадра. Ошибка из `process` прерывает запись.
+    pub fn with_frame_processor(mut self, processor: impl FrameProcessor + 'static) -> Self {
+        self.frame_processor = Some(Box::new(processor));
+        self
+    }
+
+    /// Счётчики записи; через них же запись останавливается из другого потока.
+    pub fn stats(&self) -> Arc<PipelineStats> {
+        self.stats.clone()
+    }
+
+    /// Записывает до остановки. Отложенная выгрузка (`defer_upload`)
+    /// возвращается как `PendingUpload`.
+    ///
+    /// Кодирование блокирует поток, поэтому запись идёт через `run`
+    /// в `spawn_blocking`: рантайм вызывающей стороны не занят, какого бы
+    /// вида он ни был.
+    pub async fn record(self) -> Result<Option<PendingUpload>> {
+        tokio::task::spawn_blocking(move || self.run())
+            .await
+            .map_err(|e| anyhow::anyhow!("Recording thread failed: {}", e))?
+    }
+
+    /// То же, что `record`, на собственном tokio-рантайме — для вызова
+    /// из синхронного кода. Блокирует поток до конца записи.
+    pub fn run(self) -> Result<Option<PendingUpload>> {
+        Runtime::new()?.block_on(start_recording(
+            self.params,
+            self.stats,
+            self.frame_processor,
+            self.sink,
+            None,
+        ))
+    }
+}
+
+/// Асинхронная функция, реализующая процесс захвата, кодирования и «записи» в OCI Object Storage.
+/// При отложенной выгрузке (`defer_upload`) запись остаётся во временном файле
+/// и возвращается как `PendingUpload`; иначе она выгружается здесь же.
+/// Состояние очереди кадров и выгрузки публикуется в `stats`.
+/// `frame_processor`, если задан, получает каждый кадр перед кодированием.
+/// `memory_sink`, если задан, получает закодированный поток вместо OCI:
+/// bucket тогда не нужен, а выгрузка не выполняется.
+/// Через `events` GUI спрашивает пользователя, если портал отдал несколько потоков;
+/// без GUI пишется первый поток.
+/// Если поток PipeWire оборвался, записанное до обрыва всё равно дописывается
+/// и выгружается, а ошибкой возвращается `supervisor::SegmentInterrupted`.
+/// Кодирование блокирует поток, который опрашивает эту future, поэтому её
+/// запускают через `block_on` многопоточного рантайма, как `Recorder::run`.
+#[tracing::instrument(name = "recording", skip_all, fields(object = %params.object_name()))]
+pub async fn start_recording(
+    params: RecordParams,
+    stats: Arc<PipelineStats>,
+    mut frame_processor: Option<Box<dyn FrameProcessor>>,
+    memory_sink: Option<MemorySink>,
+    events: Option<glib::Sender<GuiEvent>>,
+) -> Result<Option<PendingUpload>> {
+    info!("Starting screen recording with parameters: {:?}", params);
+    if memory_sink.is_some() {
+        params.validate_encoding()?;
+        if params.split_audio {
+            anyhow::bail!("A separate audio file cannot be recorded into memory");
+        }
+    } else {
+        for check in validate_setup(&params)? {
+            info!("{}", check);
+        }
+    }
+    for warning in params.warnings() {
+        warn!("{}", warning);
+    }
+    // Учётные данные OCI читаются до диалога портала: без них запись всё равно не выгрузить.
+    let oci_credentials = match (&memory_sink, params.storage_backend()?) {
+        (None, Backend::Oci) => Some(OciCredentials::load()?),
+        _ => None,
+    };
+
+    // Формируем имя объекта: например, [filename_template].[container]
+    let object_name = params.object_name();
+    // Параметр output_folder здесь — имя bucket или s3:// / oci:// назначение
+    // (для local — путь к папке).
+    let bucket = params.bucket();
+
+    // 1. Инициализируем Pipewire.
+    let _context = {
+        let _span = tracing::info_span!("pipewire_init").entered();
+        pipewire::init();
+        pipewire::Context::new()?
+    };
+    info!("Pipewire initialized.");
+
+    // 2. Подключаемся к сеансовой шине D-Bus.
+    let connection = Connection::session().await?;
+    let proxy = screencast_proxy(&connection).await?;
+
+    // 3. Создаём сессию: каждая попытка со свежим токеном, с ограниченным числом повторов.
+    let session_handle =
+        portal::create_session(&connection, &proxy, params.portal_attempts).await?;
+    info!("Session created: {}", session_handle);
+    // С этого места сессия закрывается при любом выходе, в том числе по `?`.
+    let mut guard = RecordingGuard::new(&connection, &session_handle);
+
+    // 4–5. Выбор источника в диалоге портала и запуск захвата.
+    let (started, stream_index) =
+        match share_screen(&connection, &proxy, &session_handle, &params, events.as_ref()).await {
+            Ok(shared) => shared,
+            Err(e) => {
+                // Сессия без потока не нужна: закрываем её сразу, в том числе после отмены.
+                guard.close_session().await;
+                return Err(e);
+            }
+        };
+    let stream_info = &started.streams[stream_index];
+    info!("Using stream node_id: {}", stream_info.node_id);
+    send_status(events.as_ref(), "Recording…");
+
+    // Дублируем файловый дескриптор удалённого PipeWire с потоками сессии.
+    let pipewire_remote = portal::open_pipewire_remote(&proxy, &session_handle).await?;
+    let raw_fd = pipewire_remote.as_raw_fd();
+    let dup_fd = unsafe { libc::dup(raw_fd) };
+    if dup_fd < 0 {
+        return Err(anyhow::anyhow!("Failed to duplicate file descriptor"));
+    }
+    info!("Duplicated FD: {}", dup_fd);
+    // FFmpeg открывает поток заново через /proc/self/fd, поэтому свою копию дескриптора
+    // закрываем сами: File закроет её при выходе из функции, в том числе по ошибке.
+    let _pipewire_fd = unsafe { File::from_raw_fd(dup_fd) };
+
+    // 6. Инициализируем FFmpeg.
+    ffmpeg::init().context("FFmpeg init error")?;
+    let device_path = format!("/proc/self/fd/{}", dup_fd);
+    info!("Opening input with ffmpeg: {}", device_path);
+
+    let mut ictx = ffmpeg::format::input_with_format(&device_path, "pipewire")
+        .context("Failed to open input stream")?;
+
+    // Звук пишется с отдельного устройства и от портала не зависит: без видео
+    // записываем только его. Ошибка — лишь когда нет ни видео, ни звука.
+    let input_video_stream = match ictx.streams().best(ffmpeg::media::Type::Video) {
+        Some(stream) => stream,
+        None if !params.audio_device.is_empty() => {
+            warn!("Input provides no video stream, recording audio only");
+            // Сессия без видео не нужна: индикатор записи экрана убирается сразу.
+            guard.close_session().await;
+            return record_audio_only(&params, &stats, memory_sink, oci_credentials, events.as_ref())
+                .await;
+        }
+        None => return Err(anyhow::anyhow!("Input provides no video stream and no audio device is selected")),
+    };
+    let input_index = input_video_stream.index();
+    info!("Input video stream index: {}", input_index);
+
+    let mut decoder = input_video_stream
+        .codec()
+        .decoder()
+        .video()
+        .context("Failed to open video decoder")?;
+
+    // Фильтр скрытия областей экрана строится один раз под размеры источника.
+    let mut redact_filter = if params.redact_regions.is_empty() {
+        None
+    } else {
+        Some(RedactFilter::new(&params.redact_regions, &decoder)?)
+    };
+    // Слежение за активным окном возможно не везде: без него пишем весь экран.
+    let mut follow_filter = if params.follow_active_window {
+        match FollowFilter::new(&decoder) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                warn!("Cannot follow active window, recording full source: {:?}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // Кадрирование части экрана — после скрытия областей: их координаты экранные.
+    let mut crop_filter = match params.crop() {
+        Some(rect) => Some(CropFilter::new(rect, VideoInput::from_decoder(&decoder))?),
+        None => None,
+    };
+    let cropped_input = crop_filter
+        .as_ref()
+        .map_or_else(|| VideoInput::from_decoder(&decoder), CropFilter::output);
+    // Суперсэмплинг идёт последним этапом: области и окно заданы в координатах экрана.
+    let mut supersample_filter = if params.supersample_factor > 1 {
+        Some(SupersampleFilter::new(params.supersample_factor, cropped_input)?)
+    } else {
+        None
+    };
+    // Частота кадров приводится к заданной после всех фильтров; при записи по damage
+    // пропуски не заполняются повторами, чтобы не потерять переменную частоту.
+    let mut frame_rate = FrameRateFilter::new(decoder.time_base(), params.fps, !params.damage_driven);
+    let encoder_time_base = frame_rate.time_base();
+    // Размер кадров после фильтров и размер, до которого их приведёт конвертер перед кодером.
+    let (filtered_width, filtered_height) = match supersample_filter.as_ref() {
+        Some(filter) => (filter.width(), filter.height()),
+        None => (cropped_input.width, cropped_input.height),
+    };
+    let (output_width, output_height) = params.output_size(filtered_width, filtered_height);
+
+    // Звук пишется с отдельного устройства. Если оно не открылось,
+    // записываем только видео, а не прерываем запись целиком.
+    let audio_input = if params.audio_device.is_empty() {
+        None
+    } else {
+        match AudioInput::open(&params.audio_device) {
+            Ok(input) => Some(input),
+            Err(e) => {
+                warn!("Audio capture unavailable, recording video only: {:?}", e);
+                None
+            }
+        }
+    };
+    // Второй источник (обычно микрофон к звуку рабочего стола) сводится с первым.
+    // Если он не открылся, пишется только основной.
+    let mut audio_secondary = if audio_input.is_none() || params.audio_device_secondary.is_empty() {
+        None
+    } else {
+        match AudioInput::open(&params.audio_device_secondary) {
+            Ok(input) => Some(input),
+            Err(e) => {
+                warn!("Secondary audio source unavailable, recording the primary only: {:?}", e);
+                None
+            }
+        }
+    };
+
+    // 7. Создаём выгружатель в выбранное хранилище и оборачиваем его в Arc/Mutex.
+    // При выводе в память выгружатель не создаётся вовсе.
+    let uploader = match memory_sink {
+        Some(_) => None,
+        None => Some(Arc::new(Mutex::new(storage_sink(
+            &params,
+            &bucket,
+            &object_name,
+            oci_credentials.clone(),
+            &stats,
+        )?))),
+    };
+    let local_output = local_output_path(&params, memory_sink.is_some());
+    let mut octx = open_output(memory_sink, uploader.as_ref(), local_output.as_deref())?;
+    
+    // 8. Настраиваем вывод: контейнер, кодек (H264/H265/VP9) и параметры из GUI.
+    let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+
+    let video_codec = params.codec()?;
+    let codec = video_codec.find_encoder()?;
+    // Аппаратный кодер готовится заранее; любой сбой — запись программным кодером.
+    let node = params
+        .render_node
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(gpu::DEFAULT_RENDER_NODE));
+    // Узел рендеринга выбирает устройство VAAPI; NVENC и программный кодер его не используют.
+    if params.render_node.is_some() && params.hw_accel != "vaapi" {
+        warn!("Render node selection applies only to VAAPI encoding and is ignored");
+    }
+    let mut hw_encoder = if params.hw_accel != "none" && params.is_lossless() {
+        warn!("Hardware encoders cannot encode losslessly, using software encoding");
+        None
+    } else {
+        match HwEncoder::open(&params.hw_accel, &node, video_codec, output_width, output_height) {
+            Ok(Some(hw)) => {
+                match &hw {
+                    HwEncoder::Vaapi(vaapi) => info!(
+                        "Using hardware encoder {} on {}",
+                        hw.codec().name(),
+                        vaapi.node.display()
+                    ),
+                    HwEncoder::Nvenc(_) => info!("Using hardware encoder {}", hw.codec().name()),
+                }
+                Some(hw)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("{} is not available, using software encoding: {:?}", params.hw_accel, e);
+                None
+            }
+        }
+    };
+    let mut ostream = octx.add_stream(codec)
+        .context("Failed to add stream")?;
+
+    // Кодер открывается один раз и живёт всю запись: кадры и EOF уходят в один
+    // и тот же экземпляр, иначе теряются буферизованные кадры и непрерывность PTS.
+    let encoder_size = (output_width, output_height);
+    let mut encoder = match hw_encoder.as_ref() {
+        Some(hw) => match open_video_encoder(&mut ostream, &params, Some(hw), encoder_size, encoder_time_base, global_header) {
+            Ok(encoder) => encoder,
+            Err(e) => {
+                warn!("{} failed to open, using software encoding: {:?}", hw.codec().name(), e);
+                hw_encoder = None;
+                open_video_encoder(&mut ostream, &params, None, encoder_size, encoder_time_base, global_header)?
+            }
+        },
+        None => open_video_encoder(&mut ostream, &params, None, encoder_size, encoder_time_base, global_header)?,
+    };
+    ostream.set_parameters(&encoder);
+    // QuickTime и Safari играют HEVC в mp4 только с тегом hvc1 (FFmpeg по умолчанию пишет hev1).
+    if video_codec == VideoCodec::H265 && params.container == "mp4" {
+        unsafe {
+            (*(*ostream.as_mut_ptr()).codecpar).codec_tag = u32::from_le_bytes(*b"hvc1");
+        }
+    }
+    // r_frame_rate — шаг сетки PTS. Средняя частота записи по damage заранее
+    // неизвестна: с 0/1 mkv не пишет DefaultDuration, и проигрыватели берут
+    // длительность кадра из PTS, а не считают каждый кадр длиной 1/fps.
+    ostream.set_rate(ffmpeg::Rational(params.fps as i32, 1));
+    ostream.set_avg_frame_rate(if params.damage_driven {
+        ffmpeg::Rational(0, 1)
+    } else {
+        ffmpeg::Rational(params.fps as i32, 1)
+    });
+    let video_index = ostream.index();
+
+    // В режиме split_audio звук уходит в свой объект со своим контейнером.
+    let mut split_audio = None;
+    let audio = match audio_input {
+        Some(input) if params.split_audio => {
+            match SplitAudio::open(&params, &bucket, oci_credentials, &stats, input.format()) {
+                Ok((split, output)) => {
+                    split_audio = Some(split);
+                    Some((input, output))
+                }
+                Err(e) => {
+                    warn!("Cannot open the separate audio file, recording video only: {:?}", e);
+                    None
+                }
+            }
+        }
+        Some(input) => match AudioOutput::add_to(
+            &mut octx,
+            input.format(),
+            params.audio_codec()?,
+            params.audio_bitrate,
+            &params.opus_application,
+            global_header,
+        ) {
+            Ok(output) => Some((input, output)),
+            Err(e) => {
+                warn!("Cannot encode audio, recording video only: {:?}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    octx.write_header_with(header_options(&params, local_output.as_deref()))
+        .context("Failed to write header")?;
+    info!("Encoding started...");
+    // Мультиплексор мог сменить базу времени потока в write_header.
+    let video_time_base = octx
+        .stream(video_index)
+        .map(|stream| stream.time_base())
+        .unwrap_or(encoder_time_base);
+
+    // Звук кодируется в своём потоке; его PTS отсчитываются от первого видеокадра.
+    let video_start: VideoStart = Arc::new(Mutex::new(None));
+    let mut audio_capture = None;
+    let mut audio_stream = None;
+    if let Some((input, output)) = audio {
+        let stream_time_base = audio_context(&mut octx, &mut split_audio)
+            .stream(output.stream_index)
+            .map(|stream| stream.time_base())
+            .unwrap_or(output.time_base);
+        audio_stream = Some((output.stream_index, output.time_base, stream_time_base));
+        let sources = AudioSources::new(input, params.audio_gain_percent as f64 / 100.0)
+            .with_secondary(
+                audio_secondary.take(),
+                params.audio_secondary_gain_percent as f64 / 100.0,
+            );
+        audio_capture = Some(AudioCapture::start(sources, output, video_start.clone()));
+    }
+
+    // 9. Захват и кодирование разведены по потокам: поток захвата читает пакеты,
+    // декодирует их и кладёт кадры в ограниченную очередь, а этот поток кодирует
+    // кадры и передаёт их в наш кастомный вывод (OCI uploader).
+    let queue = Arc::new(FrameQueue::new(stats.clone(), params.fps));
+    let (decoder_width, decoder_height) = (decoder.width(), decoder.height());
+    // Миниатюры для перемотки снимаются из уже отфильтрованных кадров,
+    // формат которых совпадает с декодером.
+    let mut thumbnail_sprite = match params.thumbnails {
+        Some(settings) => Some(ThumbnailSprite::new(
+            settings,
+            decoder.format(),
+            filtered_width,
+            filtered_height,
+        )?),
+        None => None,
+    };
+    // Предпросмотр есть только у записи из окна; включается и выключается на лету.
+    let mut preview = events.clone().map(|events| Preview::new(events, stats.clone()));
+    let capture_queue = queue.clone();
+    // В режиме damage_driven кодируются только кадры, в которых композитор
+    // сообщил об изменениях: получается VFR с честными паузами в PTS
+    // (не длиннее keepalive). Монитор живёт, пока живёт трекер в потоке захвата.
+    let mut damage_tracker = if params.damage_driven {
+        match DamageMonitor::start(raw_fd, stream_info.node_id) {
+            Ok(monitor) => Some((DamageTracker::new(decoder.time_base(), monitor.count()), monitor)),
+            Err(e) => {
+                warn!("Compositor damage is unavailable, recording every frame it sends: {:?}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // Диагностика: время этапов по кадрам (выключено по умолчанию).
+    let timing_log = if params.timing_log {
+        Some(Arc::new(Mutex::new(TimingLog::create(&object_name)?)))
+    } else {
+        None
+    };
+    let capture_timing = timing_log.clone();
+    let capture_stats = stats.clone();
+    let capture_video_start = video_start.clone();
+    // Длительность считается от начала всей записи: перезапуски сегментов
+    // после сбоев не продлевают её сверх предела.
+    stats.mark_started();
+    // Поток захвата пишет в журнал в том же span записи.
+    let capture_span = tracing::Span::current();
+    let capture_stop = Arc::new(AtomicBool::new(false));
+    let stop_condition = CaptureStop {
+        stats: stats.clone(),
+        failed: capture_stop.clone(),
+        max_duration: params.max_duration_secs.map(Duration::from_secs),
+    };
+    // На статичном экране PipeWire не шлёт пакетов, и чтение ниже ждёт их
+    // сколько угодно: Stop и предел длительности закрывают сессию сами,
+    // а закрытая сессия обрывает поток.
+    guard.close_when(stop_condition.clone());
+    let capture = thread::spawn(move || -> Result<()> {
+        let _span = capture_span.entered();
+        let result = (|| -> Result<()> {
+            let record = |stage: Stage, started: Instant| {
+                if let Some(log) = capture_timing.as_ref() {
+                    log.lock().unwrap().record(stage, started.elapsed());
+                }
+            };
+            // PTS видео отсчитываются от первого кадра, как и PTS звука.
+            let mut first_capture_pts = None;
+            let mut push_frame = |mut frame: ffmpeg::frame::Video| -> Result<()> {
+                if let Some(pts) = frame.pts() {
+                    let start = *first_capture_pts.get_or_insert_with(|| {
+                        *capture_video_start.lock().unwrap() = Some(Instant::now());
+                        pts
+                    });
+                    frame.set_pts(Some(pts - start));
+                }
+                if let Some((tracker, _)) = damage_tracker.as_mut() {
+                    if !tracker.is_damaged(&frame) {
+                        return Ok(());
+                    }
+                }
+                let mut frames = vec![frame];
+                if redact_filter.is_some()
+                    || crop_filter.is_some()
+                    || follow_filter.is_some()
+                    || supersample_filter.is_some()
+                {
+                    // Сначала скрываем области (их координаты экранные), потом кадрируем.
+                    let started = Instant::now();
+                    if let Some(filter) = redact_filter.as_mut() {
+                        frames = apply_stage(frames, |f| filter.apply(f))?;
+                    }
+                    if let Some(filter) = crop_filter.as_mut() {
+                        frames = apply_stage(frames, |f| filter.apply(f))?;
+                    }
+                    if let Some(filter) = follow_filter.as_mut() {
+                        frames = apply_stage(frames, |f| filter.apply(f))?;
+                    }
+                    if let Some(filter) = supersample_filter.as_mut() {
+                        frames = apply_stage(frames, |f| filter.apply(f))?;
+                    }
+                    record(Stage::Filter, started);
+                }
+                frames = apply_stage(frames, |f| frame_rate.apply(f))?;
+                frames.into_iter().for_each(|f| capture_queue.push(f));
+                Ok(())
+            };
+            // Условия остановки проверяются на каждом пакете, а если пакетов нет,
+            // сессию закрывает `RecordingGuard::close_when`, и чтение заканчивается.
+            let mut packet = ffmpeg::Packet::empty();
+            loop {
+                if let Some(reason) = stop_condition.reason() {
+                    info!("{}, finishing capture.", reason);
+                    break;
+                }
+                match packet.read(&mut ictx) {
+                    Ok(()) => {}
+                    Err(ffmpeg::Error::Eof) => break,
+                    Err(ffmpeg::Error::Other { errno: libc::EAGAIN }) => continue,
+                    // Поток оборвался из-за закрытия сессии по Stop или пределу длительности.
+                    Err(_) if stop_condition.reason().is_some() => continue,
+                    Err(e) => return Err(StreamInterrupted(e).into()),
+                }
+                if packet.stream() == input_index {
+                    let mut decode_started = Instant::now();
+                    decoder.send_packet(&packet).map_err(StreamInterrupted)?;
+                    loop {
+                        match decoder.receive_frame() {
+                            Ok(frame) => {
+                                record(Stage::Decode, decode_started);
+                                push_frame(frame)?;
+                                decode_started = Instant::now();
+                            }
+                            Err(e) if is_drained(&e) => break,
+                            Err(e) => return Err(StreamInterrupted(e).into()),
+                        }
+                    }
+                }
+            }
+            decoder.send_eof()
+                .context("Error sending EOF to decoder")?;
+            loop {
+                match decoder.receive_frame() {
+                    Ok(frame) => push_frame(frame)?,
+                    Err(e) if is_drained(&e) => break,
+                    Err(e) => return Err(anyhow::Error::new(e).context("Error receiving frame")),
+                }
+            }
+            Ok(())
+        })();
+        capture_queue.close();
+        result
+    });
+    guard.attach_capture(capture, capture_stop, queue.clone());
+
+    // Ключевые кадры на смене сцены ставятся в дополнение к обычному GOP.
+    let mut scene_detector = params.scene_change_sensitivity.map(SceneDetector::new);
+    // Сведения о кодировании для SEI первого кадра (если включено).
+    let provenance = if params.embed_provenance {
+        Some(provenance::describe(&params, decoder_width, decoder_height))
+    } else {
+        None
+    };
+    let mut first_frame = true;
+    // Позиция записи в мс для меток, поставленных через управляющий сокет.
+    let position_ms = Arc::new(AtomicI64::new(0));
+    let control_server = if params.control_socket {
+        let path = ControlServer::next_path();
+        let server = ControlServer::start(path.clone(), position_ms.clone())?;
+        send_status(
+            events.as_ref(),
+            &format!("Recording… (markers: {})", path.display()),
+        );
+        Some(server)
+    } else {
+        None
+    };
+    let _status_server = match params.status_port {
+        Some(port) => Some(StatusServer::start(port, stats.clone(), position_ms.clone())?),
+        None => None,
+    };
+    // Декодер может отдавать BGRx/RGBA/NV12, а кодеру нужен его собственный формат
+    // и, если задан размер записи, другой размер кадра.
+    // Аппаратным кодерам кадры готовятся в NV12 (для VAAPI затем выгружаются в GPU).
+    let mut converter = VideoConverter::new(VideoFormat {
+        pixel: match hw_encoder.as_ref() {
+            Some(hw) => hw.input_format(),
+            None => params.pixel_format(),
+        },
+        width: output_width,
+        height: output_height,
+    });
+    let mut first_pts = None;
+    let mut monotonic_pts = MonotonicPts::default();
+    while let Some(mut frame) = queue.pop() {
+        monotonic_pts.assign(&mut frame);
+        if let Some(pts) = frame.pts() {
+            let start = *first_pts.get_or_insert(pts);
+            position_ms.store(
+                (pts - start).rescale(encoder_time_base, ffmpeg::Rational(1, 1000)),
+                Ordering::Relaxed,
+            );
+        }
+        if let Some(sprite) = thumbnail_sprite.as_mut() {
+            sprite.offer(&frame, position_ms.load(Ordering::Relaxed))?;
+        }
+        if let Some(preview) = preview.as_mut() {
+            preview.offer(&frame)?;
+        }
+        if let Some(processor) = frame_processor.as_mut() {
+            processor.process(&mut frame)?;
+        }
+        let mut frame = converter.convert(frame)?;
+        // Первый пакет записи должен быть ключевым, иначе начало не декодируется
+        // до первого GOP; forced-idr делает его к тому же IDR.
+        if first_frame {
+            frame.set_kind(ffmpeg::picture::Type::I);
+        }
+        if let (Some(text), true) = (provenance.as_ref(), first_frame) {
+            provenance::attach(&mut frame, text);
+        }
+        first_frame = false;
+        if let Some(detector) = scene_detector.as_mut() {
+            if detector.is_scene_change(&frame) {
+                frame.set_kind(ffmpeg::picture::Type::I);
+            }
+        }
+        if let Some(hw) = hw_encoder.as_ref() {
+            frame = hw.prepare(frame)?;
+        }
+        let encode_started = Instant::now();
+        tracing::trace!(pts = ?frame.pts(), "Sending frame to encoder");
+        encoder.send_frame(&frame)
+            .context("Error sending frame to encoder")?;
+        stats.frames_encoded.fetch_add(1, Ordering::Relaxed);
+        write_encoded_packets(&mut encoder, &mut octx, video_index, encoder_time_base, video_time_base)?;
+        if let (Some(capture), Some(stream)) = (audio_capture.as_ref(), audio_stream) {
+            write_audio_packets(audio_context(&mut octx, &mut split_audio), capture.ready_packets(), stream)?;
+        }
+        if let Some(log) = timing_log.as_ref() {
+            log.lock().unwrap().record(Stage::Encode, encode_started.elapsed());
+        }
+        if let Some(uploader) = uploader.as_ref() {
+            stats.upload_bytes_pending.store(uploader.lock().unwrap().pending_bytes(), Ordering::Relaxed);
+        }
+    }
+    // Захват закончен (поток иссяк или нажат Stop): отпускаем сессию портала,
+    // чтобы композитор убрал индикатор записи ещё до выгрузки.
+    // Обрыв потока PipeWire не отменяет уже записанное: сегмент дописывается
+    // и выгружается как обычно, а ошибка возвращается в самом конце, и супервизор
+    // продолжает запись следующим сегментом.
+    let interrupted = match guard.finish().await {
+        Ok(()) => None,
+        Err(e) if supervisor::is_stream_interruption(&e) => {
+            warn!("Capture interrupted, finishing the segment recorded so far: {:?}", e);
+            Some(e)
+        }
+        Err(e) => return Err(e),
+    };
+    if let Some(log) = timing_log.as_ref() {
+        log.lock().unwrap().finish();
+    }
+    // Видео кончилось — останавливаем звук и дописываем его хвост.
+    if let (Some(capture), Some(stream)) = (audio_capture.take(), audio_stream) {
+        match capture.finish() {
+            Ok(packets) => write_audio_packets(audio_context(&mut octx, &mut split_audio), packets, stream)?,
+            Err(e) => warn!("Audio capture failed, audio may be truncated: {:?}", e),
+        }
+    }
+
+    encoder.send_eof()
+        .context("Error sending EOF to encoder")?;
+    write_encoded_packets(&mut encoder, &mut octx, video_index, encoder_time_base, video_time_base)?;
+
+    // Метки от внешней автоматизации становятся главами: каждая длится до следующей.
+    if let Some(server) = control_server.as_ref() {
+        let markers = server.markers();
+        let end_ms = position_ms.load(Ordering::Relaxed);
+        for (i, marker) in markers.iter().enumerate() {
+            let chapter_end = markers
+                .get(i + 1)
+                .map_or(end_ms, |next| next.time_ms)
+                .max(marker.time_ms);
+            octx.add_chapter(
+                i as i64,
+                ffmpeg::Rational(1, 1000),
+                marker.time_ms,
+                chapter_end,
+                &marker.label,
+            )
+            .with_context(|| format!("Failed to add chapter '{}'", marker.label))?;
+        }
+    }
+
+    stats.mark_finished();
+    octx.write_trailer()
+        .context("Error writing trailer")?;
+    if let Some(split) = split_audio.as_mut() {
+        split.octx.write_trailer()
+            .context("Error writing audio trailer")?;
+    }
+    // Закрываем вывод: локальный файл дочитывается для выгрузки ниже.
+    drop(octx);
+    info!("Encoding finished.");
+    send_status(events.as_ref(), "Uploading…");
+
+    // После завершения записи либо откладываем выгрузку, либо вызываем finalize,
+    // чтобы «отправить» данные в хранилище. Вывод в память уже у вызывающей стороны.
+    let uploader = match uploader {
+        Some(uploader) => uploader,
+        None => return supervisor::finish_segment(None, interrupted),
+    };
+    let mut uploader = uploader.lock().unwrap();
+    // Спрайт миниатюр и WebVTT-индекс ложатся рядом с записью, под тем же префиксом.
+    if let Some(sprite) = thumbnail_sprite.take() {
+        let base_name = params.object_base_name();
+        let sprite_name = format!("{}-thumbs.jpg", base_name);
+        let sprite_file = sprite_name.rsplit('/').next().unwrap_or(&sprite_name).to_string();
+        if let Some(track) = sprite.finish(&sprite_file, position_ms.load(Ordering::Relaxed))? {
+            uploader.add_sidecar(&sprite_name, track.sprite);
+            uploader.add_sidecar(
+                &format!("{}-thumbs.vtt", base_name),
+                track.vtt.into_bytes(),
+            );
+        }
+    }
+    let pending = complete_upload(&mut **uploader, &stats, local_output.as_deref())?;
+    if let Some(split) = split_audio.take() {
+        split.finalize()?;
+    }
+    supervisor::finish_segment(pending, interrupted)
+}
+
+/// Запись без видео: портал не дал видеопотока, а звук идёт с отдельного
+/// устройства и от портала не зависит. Объект получает только звуковую
+/// дорожку (с split_audio — только отдельный объект звука). Пишется до Stop
+/// или предела длительности.
+async fn record_audio_only(
+    params: &RecordParams,
+    stats: &Arc<PipelineStats>,
+    memory_sink: Option<MemorySink>,
+    oci_credentials: Option<OciCredentials>,
+    events: Option<&glib::Sender<GuiEvent>>,
+) -> Result<Option<PendingUpload>> {
+    let input = AudioInput::open(&params.audio_device)
+        .context("Input provides no video stream and the audio device cannot be opened")?;
+    let secondary = if params.audio_device_secondary.is_empty() {
+        None
+    } else {
+        match AudioInput::open(&params.audio_device_secondary) {
+            Ok(input) => Some(input),
+            Err(e) => {
+                warn!("Secondary audio source unavailable, recording the primary only: {:?}", e);
+                None
+            }
+        }
+    };
+    let bucket = params.bucket();
+    let object_name = params.object_name();
+
+    let mut uploader = None;
+    let mut local_output = None;
+    let (mut octx, output, split_audio) = if params.split_audio {
+        let (split, output) =
+            SplitAudio::open(params, &bucket, oci_credentials, stats, input.format())?;
+        let SplitAudio { octx, sink } = split;
+        (octx, output, Some(sink))
+    } else {
+        if memory_sink.is_none() {
+            uploader = Some(Arc::new(Mutex::new(storage_sink(
+                params,
+                &bucket,
+                &object_name,
+                oci_credentials,
+                stats,
+            )?)));
+        }
+        local_output = local_output_path(params, memory_sink.is_some());
+        let mut octx = open_output(memory_sink, uploader.as_ref(), local_output.as_deref())?;
+        let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+        let output = AudioOutput::add_to(
+            &mut octx,
+            input.format(),
+            params.audio_codec()?,
+            params.audio_bitrate,
+            &params.opus_application,
+            global_header,
+        )
+        .context("Cannot encode audio")?;
+        octx.write_header_with(header_options(params, local_output.as_deref()))
+            .context("Failed to write header")?;
+        (octx, output, None)
+    };
+    let stream_time_base = octx
+        .stream(output.stream_index)
+        .map(|stream| stream.time_base())
+        .unwrap_or(output.time_base);
+    let stream = (output.stream_index, output.time_base, stream_time_base);
+
+    // Дорожка начинается сразу: первого видеокадра, к которому её привязать, не будет.
+    let _status_server = match params.status_port {
+        Some(port) => Some(StatusServer::start(port, stats.clone(), Arc::new(AtomicI64::new(0)))?),
+        None => None,
+    };
+    stats.mark_started();
+    let sources = AudioSources::new(input, params.audio_gain_percent as f64 / 100.0)
+        .with_secondary(secondary, params.audio_secondary_gain_percent as f64 / 100.0);
+    let capture = AudioCapture::start(sources, output, Arc::new(Mutex::new(Some(Instant::now()))));
+    info!("Recording audio only...");
+    send_status(events, "Recording audio only…");
+    let stop_condition = CaptureStop {
+        stats: stats.clone(),
+        failed: Arc::new(AtomicBool::new(false)),
+        max_duration: params.max_duration_secs.map(Duration::from_secs),
+    };
+    let reason = loop {
+        if let Some(reason) = stop_condition.reason() {
+            break reason;
+        }
+        write_audio_packets(&mut octx, capture.ready_packets(), stream)?;
+        tokio::time::sleep(STOP_POLL_INTERVAL).await;
+    };
+    info!("{}, finishing audio recording.", reason);
+    write_audio_packets(&mut octx, capture.finish()?, stream)?;
+    stats.mark_finished();
+    octx.write_trailer()
+        .context("Error writing trailer")?;
+    drop(octx);
+    info!("Encoding finished.");
+    send_status(events, "Uploading…");
+
+    if let Some(sink) = split_audio {
+        sink.lock()
+            .unwrap()
+            .finalize()
+            .context("Error finalizing audio upload")?;
+        stats.upload_bytes_pending.store(0, Ordering::Relaxed);
+        return Ok(None);
+    }
+    let uploader = match uploader {
+        Some(uploader) => uploader,
+        None => return Ok(None),
+    };
+    let mut uploader = uploader.lock().unwrap();
+    complete_upload(&mut **uploader, stats, local_output.as_deref())
+}
+
+/// С upload_after_finish запись идёт в обычный временный файл с перемоткой,
+/// а в хранилище уходит только после write_trailer.
+fn local_output_path(params: &RecordParams, in_memory: bool) -> Option<PathBuf> {
+    if params.upload_after_finish && !in_memory {
+        Some(std::env::temp_dir().join(format!(
+            "rscap-{}.{}",
+            uuid::Uuid::new_v4(),
+            params.container
+        )))
+    } else {
+        None
+    }
+}
+
+/// Контекст вывода записи: локальный файл (upload_after_finish) или FFmpeg IO,
+/// который пишет в выгружатель или в память.
+fn open_output(
+    memory_sink: Option<MemorySink>,
+    uploader: Option<&Arc<Mutex<Box<dyn StorageSink>>>>,
+    local_output: Option<&Path>,
+) -> Result<ffmpeg::format::context::Output> {
+    if let Some(path) = local_output {
+        info!("Recording into {} before upload", path.display());
+        return ffmpeg::format::output(&path)
+            .with_context(|| format!("Failed to create {}", path.display()));
+    }
+    // Создаём FFmpeg IO-контекст, который пишет в наш uploader или в память.
+    let io = match (memory_sink, uploader) {
+        (Some(sink), _) => IO::from_write(Arc::new(Mutex::new(sink))),
+        (None, Some(uploader)) => IO::from_write(uploader.clone()),
+        (None, None) => unreachable!("uploader is created whenever memory sink is absent"),
+    }
+    .context("Failed to create FFmpeg IO")?;
+    // Создаём выходной формат с кастомным IO.
+    ffmpeg::format::output_with_io(io)
+        .context("Failed to create output context")
+}
+
+/// Вывод идёт в поток без перемотки (хранилище, файл через Write или память),
+/// а обычный mp4 дописывает индекс moov в начало файла в write_trailer.
+/// Фрагментированный mp4 пишется строго последовательно. Локальному файлу
+/// (upload_after_finish) перемотка доступна, и mp4 остаётся обычным.
+fn header_options(params: &RecordParams, local_output: Option<&Path>) -> ffmpeg::Dictionary<'static> {
+    let mut options = ffmpeg::Dictionary::new();
+    if params.container == "mp4" && local_output.is_none() {
+        options.set("movflags", "+frag_keyframe+empty_moov+default_base_moof");
+    }
+    options
+}
+
+/// Отправляет дописанную запись в хранилище или, при отложенной выгрузке,
+/// возвращает её. Готовый локальный файл уходит тем же путём, что и поток
+/// при обычной записи.
+fn complete_upload(
+    uploader: &mut dyn StorageSink,
+    stats: &PipelineStats,
+    local_output: Option<&Path>,
+) -> Result<Option<PendingUpload>> {
+    if let Some(path) = local_output {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        std::io::copy(&mut file, uploader).with_context(|| {
+            format!("Error uploading {}, the recording is kept there", path.display())
+        })?;
+    }
+    if uploader.is_deferred() {
+        let pending = uploader.finish_capture()
+            .context("Error finishing capture")?;
+        // Отложенная выгрузка держит свою копию записи.
+        remove_local_output(local_output);
+        info!("Recording {} kept locally until upload is requested.", pending.object_name);
+        return Ok(Some(pending));
+    }
+    uploader.finalize().with_context(|| match local_output {
+        Some(path) => format!("Error finalizing upload, the recording is kept in {}", path.display()),
+        None => "Error finalizing upload".to_string(),
+    })?;
+    stats.upload_bytes_pending.store(0, Ordering::Relaxed);
+    remove_local_output(local_output);
+    Ok(None)
+}
+
+/// Приёмник объекта `object_name` в выбранном хранилище.
+fn storage_sink(
+    params: &RecordParams,
+    bucket: &str,
+    object_name: &str,
+    oci_credentials: Option<OciCredentials>,
+    stats: &PipelineStats,
+) -> Result<Box<dyn StorageSink>> {
+    let metadata = params.object_metadata.clone();
+    let sink: Box<dyn StorageSink> = match params.storage_backend()? {
+        Backend::Oci => {
+            let creds = oci_credentials.expect("OCI credentials are loaded for the OCI backend");
+            if params.defer_upload {
+                Box::new(
+                    OciUploader::deferred(bucket, object_name, creds)?
+                        .with_retry(params.upload_retries, storage::DEFAULT_RETRY_BASE_DELAY)
+                        .with_metadata(metadata)
+                        .with_progress(stats.upload.clone()),
+                )
+            } else {
+                Box::new(
+                    OciUploader::new(bucket, object_name, creds)
+                        .with_part_size(params.upload_part_size_mb)
+                        .with_retry(params.upload_retries, storage::DEFAULT_RETRY_BASE_DELAY)
+                        .with_metadata(metadata)
+                        .with_progress(stats.upload.clone()),
+                )
+            }
+        }
+        Backend::S3 => Box::new(
+            S3Uploader::new(bucket, object_name)
+                .with_part_size(params.upload_part_size_mb)
+                .with_retry(params.upload_retries, storage::DEFAULT_RETRY_BASE_DELAY)
+                .with_metadata(metadata)
+                .with_progress(stats.upload.clone()),
+        ),
+        Backend::Local => Box::new(
+            FileSink::create(&params.local_folder(), object_name)?
+                .with_progress(stats.upload.clone()),
+        ),
+    };
+    Ok(sink)
+}
+
+/// Звуковая дорожка в отдельном объекте (режим split_audio): свой контейнер
+/// и свой приёмник с тем же базовым именем, что у видео. PTS звука отсчитываются
+/// от первого видеокадра, как и PTS видео, поэтому объекты сводятся обратно
+/// простым `ffmpeg -i video -i audio -c copy` без сдвигов.
+struct SplitAudio {
+    octx: ffmpeg::format::context::Output,
+    sink: Arc<Mutex<Box<dyn StorageSink>>>,
+}
+
+impl SplitAudio {
+    /// Создаёт объект звука, добавляет в него поток и пишет заголовок.
+    fn open(
+        params: &RecordParams,
+        bucket: &str,
+        oci_credentials: Option<OciCredentials>,
+        stats: &PipelineStats,
+        input: AudioFormat,
+    ) -> Result<(Self, AudioOutput)> {
+        let audio_codec = params.audio_codec()?;
+        let (extension, muxer) = audio_codec.split_container();
+        let object_name = params.audio_object_name()?;
+        let sink = Arc::new(Mutex::new(storage_sink(
+            params,
+            bucket,
+            &object_name,
+            oci_credentials,
+            stats,
+        )?));
+        let io = IO::from_write(sink.clone())
+            .context("Failed to create FFmpeg IO for audio")?;
+        let mut octx = ffmpeg::format::output_as_with_io(io, muxer)
+            .with_context(|| format!("Failed to create {} output", muxer))?;
+        let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+        let output = AudioOutput::add_to(
+            &mut octx,
+            input,
+            audio_codec,
+            params.audio_bitrate,
+            &params.opus_application,
+            global_header,
+        )?;
+        // Вывод без перемотки: m4a, как и mp4, пишется фрагментами.
+        let mut header_options = ffmpeg::Dictionary::new();
+        if extension == "m4a" {
+            header_options.set("movflags", "+frag_keyframe+empty_moov+default_base_moof");
+        }
+        octx.write_header_with(header_options)
+            .context("Failed to write audio header")?;
+        info!("Audio is recorded separately into {}", object_name);
+        Ok((Self { octx, sink }, output))
+    }
+
+    /// Закрывает контейнер звука и выгружает объект.
+    fn finalize(self) -> Result<()> {
+        drop(self.octx);
+        self.sink
+            .lock()
+            .unwrap()
+            .finalize()
+            .context("Error finalizing audio upload")
+    }
+}
+
+/// Контекст, в который мультиплексируется звук: отдельный в режиме split_audio.
+fn audio_context<'a>(
+    octx: &'a mut ffmpeg::format::context::Output,
+    split_audio: &'a mut Option<SplitAudio>,
+) -> &'a mut ffmpeg::format::context::Output {
+    match split_audio {
+        Some(split) => &mut split.octx,
+        None => octx,
+    }
+}
+
+/// Как часто `RecordingGuard::close_when` проверяет условия остановки.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Когда захват должен закончиться: Stop, сбой кодирования или предел длительности.
+#[derive(Clone)]
+struct CaptureStop {
+    stats: Arc<PipelineStats>,
+    /// Кодирование прервалось ошибкой: кадры больше некому забирать.
+    failed: Arc<AtomicBool>,
+    max_duration: Option<Duration>,
+}
+
+impl CaptureStop {
+    /// Причина остановки для журнала; None, пока захват должен продолжаться.
+    fn reason(&self) -> Option<&'static str> {
+        if self.stats.stop_requested() {
+            Some("Stop requested")
+        } else if self.failed.load(Ordering::Relaxed) {
+            Some("Encoding stopped")
+        } else if self.max_duration.map_or(false, |limit| self.stats.elapsed() >= limit) {
+            Some("Maximum duration reached")
+        } else {
+            None
+        }
+    }
+}
+
+/// То, что запись обязана отпустить при любом выходе из `start_recording`:
+/// сессию портала и поток захвата. Без этого ошибка кодера или выгрузки
+/// оставляла поток читать PipeWire и держала сессию (и индикатор записи)
+/// до конца процесса, а каждый перезапуск супервизора добавлял ещё по одному.
+/// Обычный путь вызывает `finish`; при ошибке то же делает `Drop`.
+struct RecordingGuard {
+    connection: Connection,
+    session_handle: Option<String>,
+    capture: Option<(thread::JoinHandle<Result<()>>, Arc<AtomicBool>, Arc<FrameQueue>)>,
+    watcher: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl RecordingGuard {
+    fn new(connection: &Connection, session_handle: &str) -> Self {
+        Self {
+            connection: connection.clone(),
+            session_handle: Some(session_handle.to_string()),
+            capture: None,
+            watcher: None,
+        }
+    }
+
+    /// Закрывает сессию, как только `stop` назовёт причину остановки. Захват
+    /// ждёт пакетов PipeWire, и без этого Stop и предел длительности
+    /// срабатывали бы только со следующим изменением на экране.
+    fn close_when(&mut self, stop: CaptureStop) {
+        if let Some(session_handle) = self.session_handle.clone() {
+            let connection = self.connection.clone();
+            self.watcher = Some(tokio::spawn(async move {
+                while stop.reason().is_none() {
+                    tokio::time::sleep(STOP_POLL_INTERVAL).await;
+                }
+                portal::close_session(&connection, &session_handle).await;
+            }));
+        }
+    }
+
+    /// Поток захвата, который надо остановить вместе с записью, и его флаг остановки.
+    fn attach_capture(
+        &mut self,
+        handle: thread::JoinHandle<Result<()>>,
+        stop: Arc<AtomicBool>,
+        queue: Arc<FrameQueue>,
+    ) {
+        self.capture = Some((handle, stop, queue));
+    }
+
+    /// Дожидается конца захвата и закрывает сессию; ошибка захвата возвращается.
+    async fn finish(&mut self) -> Result<()> {
+        let result = match self.capture.take() {
+            Some((handle, _, _)) => handle
+                .join()
+                .map_err(|_| anyhow::anyhow!("Capture thread panicked"))
+                .and_then(|result| result),
+            None => Ok(()),
+        };
+        self.close_session().await;
+        result
+    }
+
+    async fn close_session(&mut self) {
+        if let Some(watcher) = self.watcher.take() {
+            watcher.abort();
+        }
+        if let Some(session_handle) = self.session_handle.take() {
+            portal::close_session(&self.connection, &session_handle).await;
+        }
+    }
+}
+
+impl Drop for RecordingGuard {
+    fn drop(&mut self) {
+        // Сессия закрывается до join: закрытая сессия обрывает поток PipeWire,
+        // и захват, ждущий пакета, не зависнет на join ниже.
+        if let Some(watcher) = self.watcher.take() {
+            watcher.abort();
+        }
+        if let Some(session_handle) = self.session_handle.take() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) if runtime.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                    let connection = self.connection.clone();
+                    tokio::task::block_in_place(|| {
+                        runtime.block_on(portal::close_session(&connection, &session_handle))
+                    });
+                }
+                _ => warn!(
+                    "Portal session {} is left open: start_recording needs a multi-thread runtime",
+                    session_handle
+                ),
+            }
+        }
+        if let Some((handle, stop, queue)) = self.capture.take() {
+            stop.store(true, Ordering::Relaxed);
+            queue.close();
+            match handle.join() {
+                Ok(Err(e)) => warn!("Capture stopped with an error: {:?}", e),
+                Err(_) => warn!("Capture thread panicked"),
+                Ok(Ok(())) => {}
+            }
+        }
+    }
+}
+
+/// Удаляет временный файл записи (upload_after_finish), когда он больше не нужен.
+fn remove_local_output(path: Option<&std::path::Path>) {
+    if let Some(path) = path {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Failed to remove temp file {}: {:?}", path.display(), e);
+        }
+    }
+}
+
+/// Прокси интерфейса ScreenCast портала на сеансовой шине.
+async fn screencast_proxy(connection: &Connection) -> Result<Proxy<'static>> {
+    Ok(ProxyBuilder::new_bare(connection)
+        .destination("org.freedesktop.portal.Desktop")?
+        .path("/org/freedesktop/portal/desktop")?
+        .interface("org.freedesktop.portal.ScreenCast")?
+        .build()
+        .await?)
+}
+
+/// Проверки до записи, которым не нужен захват: параметры, кодер, учётные
+/// данные и доступность хранилища. Их проходит каждая запись и пробный запуск;
+/// возвращает по строке на пройденную проверку.
+pub fn validate_setup(params: &RecordParams) -> Result<Vec<String>> {
+    params.validate()?;
+    let mut checks = Vec::new();
+    let encoder = params.codec()?.find_encoder()?;
+    checks.push(format!("Encoder: {} in {}", encoder.name(), params.container));
+    if !params.audio_device.is_empty() {
+        let audio_encoder = params.audio_codec()?.find_encoder()?;
+        checks.push(match audio_encoder.name() {
+            "libopus" => format!("Audio encoder: libopus ({})", params.opus_application),
+            // Встроенный кодер Opus экспериментальный и не знает режимов libopus.
+            "opus" => "Audio encoder: built-in opus (libopus is not in this FFmpeg build)".to_string(),
+            name => format!("Audio encoder: {}", name),
+        });
+    }
+    match params.storage_backend()? {
+        Backend::Oci => {
+            let creds = OciCredentials::load()?;
+            oci_uploader::check_bucket(&creds, &params.bucket())?;
+            checks.push(format!(
+                "Storage: OCI bucket {} (region {}, namespace {}) is reachable",
+                params.bucket(),
+                creds.region(),
+                creds.namespace()
+            ));
+        }
+        Backend::S3 => {
+            s3_uploader::check_bucket(&params.bucket())?;
+            checks.push(format!("Storage: S3 bucket {} is reachable", params.bucket()));
+        }
+        Backend::Local => {
+            let folder = params.local_folder();
+            file_sink::check_folder(&folder)?;
+            checks.push(format!("Storage: folder {} is writable", folder.display()));
+        }
+    }
+    Ok(checks)
+}
+
+/// Пробный запуск: `validate_setup`, проба аппаратного кодера и сессия портала,
+/// которая сразу закрывается. Ничего не записывает и не выгружает.
+pub async fn dry_run(params: &RecordParams) -> Result<Vec<String>> {
+    ffmpeg::init().context("FFmpeg init error")?;
+    let mut checks = validate_setup(params)?;
+    if params.hw_accel != "none" {
+        let node = params
+            .render_node
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(gpu::DEFAULT_RENDER_NODE));
+        let (width, height) = params.output_size(1920, 1080);
+        // Запись без аппаратного кодера не падает, поэтому это не ошибка, а примечание.
+        checks.push(match HwEncoder::open(&params.hw_accel, &node, params.codec()?, width, height) {
+            Ok(Some(hw)) => format!("Hardware encoder: {}", hw.codec().name()),
+            Ok(None) => "Hardware encoder: none".to_string(),
+            Err(e) => format!("Hardware encoder unavailable, software will be used: {:#}", e),
+        });
+    }
+    let connection = Connection::session().await?;
+    let proxy = screencast_proxy(&connection).await?;
+    let session_handle = portal::create_session(&connection, &proxy, params.portal_attempts).await?;
+    portal::close_session(&connection, &session_handle).await;
+    checks.push("Screen sharing portal: session created".to_string());
+    Ok(checks)
+}
+
+/// Сообщает GUI этап записи; без GUI (`events` нет) ничего не делает.
+fn send_status(events: Option<&glib::Sender<GuiEvent>>, status: &str) {
+    if let Some(events) = events {
+        let _ = events.send(GuiEvent::Status(status.to_string()));
+    }
+}
+
+/// Выбор источника в диалоге портала (SelectSources), запуск захвата (Start)
+/// и выбор одного из отданных потоков. Отмена на любом шаге — `PortalCancelled`.
+/// Возвращает ответ Start и индекс записываемого потока.
+async fn share_screen(
+    connection: &Connection,
+    proxy: &Proxy<'_>,
+    session_handle: &str,
+    params: &RecordParams,
+    events: Option<&glib::Sender<GuiEvent>>,
+) -> Result<(portal::Started, usize)> {
+    // 4. Вызываем SelectSources для выбора источников (с сохранённым разрешением, если есть).
+    portal::select_sources(
+        connection,
+        proxy,
+        session_handle,
+        portal::source_types_mask(&params.source_types)?,
+        portal::cursor_mode_value(&params.cursor_mode)?,
+    )
+    .await?;
+    info!("SelectSources called.");
+
+    // 5. Запускаем захват: результат приходит сигналом Response после диалога.
+    let started = portal::start(connection, proxy, session_handle).await?;
+    info!("Start response: {:?}", started);
+    if let Some(token) = started.restore_token.as_deref() {
+        portal::save_restore_token(token);
+    }
+
+    // Несколько потоков: до настройки кодера даём пользователю выбрать нужный.
+    for (position, stream) in started.streams.iter().enumerate() {
+        info!("Portal stream {}", stream.label(position));
+    }
+    // Диалог закрыт без выбора: портал может ответить и пустым списком потоков.
+    if started.streams.is_empty() {
+        return Err(PortalCancelled.into());
+    }
+    let stream_index = match (params.node_id, events) {
+        // Узел задан заранее (CLI, скрипты): берём его без вопросов.
+        (Some(node_id), _) => started
+            .streams
+            .iter()
+            .position(|stream| stream.node_id == node_id)
+            .ok_or_else(|| anyhow::anyhow!("The portal returned no stream with node {}", node_id))?,
+        (None, Some(events)) if started.streams.len() > 1 => {
+            let choices = started.streams.clone();
+            let (reply, answer) = std::sync::mpsc::channel();
+            events
+                .send(GuiEvent::ChooseStream { streams: choices, reply })
+                .map_err(|_| anyhow::anyhow!("GUI is closed, cannot choose a stream"))?;
+            answer
+                .recv()
+                .ok()
+                .flatten()
+                .ok_or(PortalCancelled)?
+        }
+        _ => 0,
+    };
+    Ok((started, stream_index))
+}
+
+/// Настраивает и открывает видеокодер потока `ostream`: программный кодер
+/// кодека записи или, если передан `hw`, аппаратный.
+fn open_video_encoder(
+    ostream: &mut ffmpeg::format::stream::StreamMut,
+    params: &RecordParams,
+    hw: Option<&HwEncoder>,
+    (width, height): (u32, u32),
+    time_base: ffmpeg::Rational,
+    global_header: bool,
+) -> Result<ffmpeg::encoder::video::Encoder> {
+    let video_codec = params.codec()?;
+    let mut encoder = ostream
+        .codec()
+        .encoder()
+        .video()
+        .context("Failed to get video encoder")?;
+    encoder.set_width(width);
+    encoder.set_height(height);
+    let codec = match hw {
+        Some(hw) => {
+            hw.attach(&mut encoder)?;
+            hw.codec()
+        }
+        None => {
+            // После неудачной попытки VAAPI в контексте кодера мог остаться пул кадров.
+            unsafe { ffmpeg::ffi::av_buffer_unref(&mut (*encoder.as_mut_ptr()).hw_frames_ctx) };
+            encoder.set_format(params.pixel_format());
+            video_codec.find_encoder()?
+        }
+    };
+    encoder.set_time_base(time_base);
+    encoder.set_frame_rate(Some(ffmpeg::Rational(params.fps as i32, 1)));
+    // B-кадры при коротком GOP почти не экономят, а задержку добавляют.
+    let gop = params.gop();
+    encoder.set_gop(gop);
+    encoder.set_max_b_frames(if gop >= 8 { 2 } else { 0 });
+    let mut encoder_options = ffmpeg::Dictionary::new();
+    // Программные VP9/AV1 по умолчанию настроены на качество, а не на скорость,
+    // и не успевают за экраном.
+    match (video_codec, hw) {
+        (VideoCodec::VP9, None) => {
+            encoder_options.set("deadline", "realtime");
+            encoder_options.set("cpu-used", "8");
+            encoder_options.set("row-mt", "1");
+        }
+        (VideoCodec::AV1, None) if codec.name() == "libsvtav1" => {
+            // Пресеты SVT-AV1: 0 — самый медленный, 13 — самый быстрый.
+            encoder_options.set("preset", "10");
+        }
+        (VideoCodec::AV1, None) => {
+            encoder_options.set("usage", "realtime");
+            encoder_options.set("cpu-used", "8");
+            encoder_options.set("row-mt", "1");
+        }
+        _ => {}
+    }
+    // Частные опции libx264 (av_opt_set через словарь open_as_with).
+    if video_codec == VideoCodec::H264 && hw.is_none() {
+        encoder_options.set("preset", &params.x264_preset);
+        if !params.x264_tune.is_empty() {
+            encoder_options.set("tune", &params.x264_tune);
+        }
+    }
+    configure_rate_control(&mut encoder, &mut encoder_options, params)?;
+    if let Some(hw) = hw {
+        let mode = params.rate_mode().unwrap_or(EncodingMode::Vbr);
+        hw.configure_rate_control(&mut encoder_options, mode, params.crf);
+    }
+    // Соотношение сторон пикселя: без него несквадратные источники растягиваются.
+    let sample_aspect = params.aspect_ratio.sample_aspect(width, height);
+    encoder.set_aspect_ratio(sample_aspect);
+    unsafe {
+        (*ostream.as_mut_ptr()).sample_aspect_ratio = sample_aspect.into();
+    }
+    let mut flags = ffmpeg::codec::flag::Flags::empty();
+    if global_header {
+        flags |= ffmpeg::codec::flag::Flags::GLOBAL_HEADER;
+    }
+    // Принудительные ключевые кадры (первый кадр, смена сцены) пишутся как IDR:
+    // с них начинается декодирование фрагмента mp4 и перемотка.
+    encoder_options.set("forced-idr", "1");
+    // Для склейки без перекодирования: закрытые GOP, чтобы ни один кадр не ссылался
+    // на соседний файл.
+    if params.concat_friendly {
+        flags |= ffmpeg::codec::flag::Flags::CLOSED_GOP;
+    }
+    encoder.set_flags(flags);
+    encoder.open_as_with(codec, encoder_options)
+        .context("Failed to open video encoder")
+}
+
+/// Настраивает управление битрейтом кодера по режиму из параметров.
+/// Неизвестный режим не прерывает запись: пишем VBR и предупреждаем.
+fn configure_rate_control(
+    encoder: &mut ffmpeg::encoder::Video,
+    options: &mut ffmpeg::Dictionary,
+    params: &RecordParams,
+) -> Result<()> {
+    let mode = params.rate_mode().unwrap_or_else(|e| {
+        warn!("{}; falling back to VBR", e);
+        EncodingMode::Vbr
+    });
+    match mode {
+        // Без потерь: qp=0 в YUV 4:4:4, битрейт не ограничивается.
+        EncodingMode::Lossless => match params.codec() {
+            // У x265 qp=0 не означает «без потерь»: нужен отдельный режим.
+            Ok(VideoCodec::H265) => options.set("x265-params", "lossless=1"),
+            Ok(VideoCodec::VP9) => options.set("lossless", "1"),
+            _ => options.set("qp", "0"),
+        },
+        // Почти без потерь: crf=0 в 4:2:0 — яркость без потерь, цветность прорежена.
+        EncodingMode::NearLossless => options.set("crf", "0"),
+        // Постоянное качество: битрейт не задаётся, кодер тратит столько, сколько нужно.
+        // У libvpx и libaom crf без нулевого битрейта — ограниченное качество (CQ), а не Q.
+        EncodingMode::Crf => {
+            options.set("crf", &params.crf.to_string());
+            if let Ok(VideoCodec::VP9 | VideoCodec::AV1) = params.codec() {
+                options.set("b", "0");
+            }
+        }
+        // Переменный битрейт: целевой в среднем, min/max и буфер VBV не заданы,
+        // так что сложные сцены получают сколько нужно.
+        EncodingMode::Vbr => {
+            encoder.set_bit_rate(params.video_bitrate()? as i64 * 1000); // битрейт в бит/с
+            unsafe {
+                let context = encoder.as_mut_ptr();
+                (*context).rc_min_rate = 0;
+                (*context).rc_max_rate = 0;
+                (*context).rc_buffer_size = 0;
+            }
+        }
+        // Постоянный битрейт: min = max = размер буфера VBV = целевому,
+        // а x264 дополнительно выравнивает поток заполнением (nal-hrd=cbr).
+        EncodingMode::Cbr => {
+            let bit_rate = params.video_bitrate()? as i64 * 1000;
+            encoder.set_bit_rate(bit_rate);
+            encoder.set_max_bit_rate(bit_rate as usize);
+            unsafe {
+                let context = encoder.as_mut_ptr();
+                (*context).rc_min_rate = bit_rate;
+                (*context).rc_max_rate = bit_rate;
+                (*context).rc_buffer_size = bit_rate as i32;
+            }
+            options.set("nal-hrd", "cbr");
+        }
+    }
+    Ok(())
+}
+
+/// Кодек или декодер отдал всё, что было готово: EAGAIN (нужен следующий вход)
+/// или EOF (поток закончен). Прочие ошибки — настоящие, их нельзя глотать.
+fn is_drained(error: &ffmpeg::Error) -> bool {
+    match error {
+        ffmpeg::Error::Eof => true,
+        ffmpeg::Error::Other { errno } => *errno == ffmpeg::util::error::EAGAIN,
+        _ => false,
+    }
+}
+
+/// Забирает из кодера все готовые пакеты и пишет их в выход.
+/// Кодер с B-кадрами отдаёт первые пакеты с задержкой в несколько кадров,
+/// а последние — только после EOF, поэтому вызывается и после каждого кадра,
+/// и при сбросе; метки времени пересчитываются одинаково в обоих случаях.
+fn write_encoded_packets(
+    encoder: &mut ffmpeg::encoder::Video,
+    octx: &mut ffmpeg::format::context::Output,
+    stream_index: usize,
+    encoder_time_base: ffmpeg::Rational,
+    stream_time_base: ffmpeg::Rational,
+) -> Result<()> {
+    loop {
+        match encoder.receive_packet() {
+            Ok(mut encoded) => {
+                tracing::trace!(pts = ?encoded.pts(), size = encoded.size(), "Writing video packet");
+                encoded.set_stream(stream_index);
+                encoded.rescale_ts(encoder_time_base, stream_time_base);
+                // Чередование со звуком по времени делает мультиплексор.
+                encoded.write_interleaved(octx)
+                    .context("Error writing packet")?;
+            }
+            Err(e) if is_drained(&e) => return Ok(()),
+            Err(e) => return Err(anyhow::Error::new(e).context("Error receiving encoded packet")),
+        }
+    }
+}
+
+/// Пишет пакеты звука: `stream` — индекс потока, база времени кодера и база потока
+/// (у звука своя база 1/частота, её нельзя путать с базой видео).
+fn write_audio_packets(
+    octx: &mut ffmpeg::format::context::Output,
+    packets: Vec<ffmpeg::Packet>,
+    stream: (usize, ffmpeg::Rational, ffmpeg::Rational),
+) -> Result<()> {
+    let (stream_index, encoder_time_base, stream_time_base) = stream;
+    for mut packet in packets {
+        packet.set_stream(stream_index);
+        packet.rescale_ts(encoder_time_base, stream_time_base);
+        packet.write_interleaved(octx)
+            .context("Error writing audio packet")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_control::DEFAULT_CRF;
+
+    /// Путь к временному файлу теста с расширением `extension`.
+    fn temp_path(extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rscap-test-{}.{}", uuid::Uuid::new_v4(), extension))
+    }
+
+    /// Кодирует в mkv по `path` синтетические кадры 64x48 с PTS `pts` тем же
+    /// кодером и теми же шагами, что и цикл кодирования записи.
+    fn encode_synthetic(path: &Path, pts: &[Option<i64>]) {
+        ffmpeg::init().unwrap();
+        let params = RecordParams {
+            container: "mkv".to_string(),
+            ..RecordParams::default()
+        };
+        let mut octx = ffmpeg::format::output(&path).unwrap();
+        let global_header = octx.format().flags().contains(ffmpeg::format::flag::Flags::GLOBAL_HEADER);
+        let mut ostream = octx.add_stream(params.codec().unwrap().find_encoder().unwrap()).unwrap();
+        let time_base = ffmpeg::Rational(1, params.fps as i32);
+        let mut encoder =
+            open_video_encoder(&mut ostream, &params, None, (64, 48), time_base, global_header).unwrap();
+        ostream.set_parameters(&encoder);
+        let index = ostream.index();
+        octx.write_header().unwrap();
+        let stream_time_base = octx.stream(index).unwrap().time_base();
+        let mut monotonic_pts = MonotonicPts::default();
+        for (i, pts) in pts.iter().enumerate() {
+            let mut frame = ffmpeg::frame::Video::new(params.pixel_format(), 64, 48);
+            for plane in 0..frame.planes() {
+                frame.data_mut(plane).fill((i * 16) as u8);
+            }
+            frame.set_pts(*pts);
+            monotonic_pts.assign(&mut frame);
+            encoder.send_frame(&frame).unwrap();
+            write_encoded_packets(&mut encoder, &mut octx, index, time_base, stream_time_base).unwrap();
+        }
+        encoder.send_eof().unwrap();
+        write_encoded_packets(&mut encoder, &mut octx, index, time_base, stream_time_base).unwrap();
+        octx.write_trailer().unwrap();
+    }
+
+    /// PTS кадров, декодированных из видеопотока файла, в порядке вывода.
+    fn decoded_pts(path: &Path) -> Vec<i64> {
+        let mut ictx = ffmpeg::format::input(&path).unwrap();
+        let stream = ictx.streams().best(ffmpeg::media::Type::Video).unwrap();
+        let index = stream.index();
+        let mut decoder = stream.codec().decoder().video().unwrap();
+        let mut pts = Vec::new();
+        let mut drain = |decoder: &mut ffmpeg::decoder::Video| {
+            while let Ok(frame) = decoder.receive_frame() {
+                pts.push(frame.pts().unwrap());
+            }
+        };
+        for (stream, packet) in ictx.packets() {
+            if stream.index() == index {
+                decoder.send_packet(&packet).unwrap();
+                drain(&mut decoder);
+            }
+        }
+        decoder.send_eof().unwrap();
+        drain(&mut decoder);
+        pts
+    }
+
+    #[test]
+    fn one_encoder_codes_every_frame_in_order() {
+        let path = temp_path("mkv");
+        let input: Vec<Option<i64>> = (0..30).map(Some).collect();
+        encode_synthetic(&path, &input);
+        let pts = decoded_pts(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pts.len(), input.len());
+        assert!(pts.windows(2).all(|pair| pair[0] < pair[1]), "PTS out of order: {:?}", pts);
+    }
+
+    #[test]
+    fn missing_and_repeated_pts_give_increasing_dts() {
+        let path = temp_path("mkv");
+        let input = [Some(0), None, Some(1), Some(1), None, Some(3), Some(2), Some(10), None];
+        encode_synthetic(&path, &input);
+        let mut ictx = ffmpeg::format::input(&path).unwrap();
+        let dts: Vec<i64> = ictx.packets().filter_map(|(_, packet)| packet.dts()).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dts.len(), input.len());
+        assert!(dts.windows(2).all(|pair| pair[0] < pair[1]), "DTS not increasing: {:?}", dts);
+    }
+
+    /// Кодер (не открытый) с управлением битрейтом для режима `mode` при 4000 кбит/с.
+    fn rate_controlled(mode: &str) -> (ffmpeg::encoder::Video, ffmpeg::Dictionary<'static>) {
+        let params = RecordParams {
+            encoding_mode: mode.to_string(),
+            bitrate: 4000,
+            ..RecordParams::default()
+        };
+        let mut encoder = ffmpeg::codec::context::Context::new()
+            .encoder()
+            .video()
+            .unwrap();
+        let mut options = ffmpeg::Dictionary::new();
+        configure_rate_control(&mut encoder, &mut options, &params).unwrap();
+        (encoder, options)
+    }
+
+    /// bit_rate, rc_min_rate, rc_max_rate и rc_buffer_size кодера.
+    fn rate_fields(encoder: &ffmpeg::encoder::Video) -> (i64, i64, i64, i32) {
+        unsafe {
+            let context = encoder.as_ptr();
+            (
+                (*context).bit_rate,
+                (*context).rc_min_rate,
+                (*context).rc_max_rate,
+                (*context).rc_buffer_size,
+            )
+        }
+    }
+
+    #[test]
+    fn cbr_pins_the_rate_to_the_target() {
+        let (encoder, options) = rate_controlled("CBR");
+        assert_eq!(rate_fields(&encoder), (4_000_000, 4_000_000, 4_000_000, 4_000_000));
+        assert_eq!(options.get("nal-hrd"), Some("cbr"));
+    }
+
+    #[test]
+    fn vbr_sets_only_the_average_rate() {
+        let (encoder, options) = rate_controlled("VBR");
+        assert_eq!(rate_fields(&encoder), (4_000_000, 0, 0, 0));
+        assert_eq!(options.get("nal-hrd"), None);
+    }
+
+    #[test]
+    fn crf_sets_quality_and_leaves_bit_rate_unset() {
+        let (encoder, options) = rate_controlled("CRF");
+        assert_eq!(rate_fields(&encoder), (0, 0, 0, 0));
+        assert_eq!(options.get("crf"), Some(DEFAULT_CRF.to_string().as_str()));
+        assert_eq!(options.get("nal-hrd"), None);
+    }
+
+    #[test]
+    fn unknown_mode_is_rejected_and_recorded_as_vbr() {
+        assert!(EncodingMode::parse("ABR").is_err());
+        let (encoder, _) = rate_controlled("ABR");
+        assert_eq!(rate_fields(&encoder), (4_000_000, 0, 0, 0));
+    }
+
+    #[test]
+    fn split_audio_object_shares_the_base_name() {
+        let params = RecordParams {
+            output_folder: "s3://recordings/team/demo".to_string(),
+            filename_template: "standup".to_string(),
+            container: "mp4".to_string(),
+            audio_device: "default-monitor".to_string(),
+            split_audio: true,
+            ..RecordParams::default()
+        };
+        assert_eq!(params.object_name(), "team/demo/standup.mp4");
+        assert_eq!(params.audio_object_name().unwrap(), "team/demo/standup.m4a");
+        let flac = RecordParams {
+            audio_codec: "FLAC".to_string(),
+            ..params.clone()
+        };
+        assert_eq!(flac.audio_object_name().unwrap(), "team/demo/standup.flac");
+    }
+
+    #[test]
+    fn split_audio_rejects_modes_it_cannot_stream() {
+        let params = RecordParams {
+            output_folder: "oci://recordings".to_string(),
+            audio_device: "default-monitor".to_string(),
+            split_audio: true,
+            ..RecordParams::default()
+        };
+        let deferred = RecordParams {
+            defer_upload: true,
+            ..params.clone()
+        };
+        assert!(deferred.validate().is_err());
+        let after_finish = RecordParams {
+            upload_after_finish: true,
+            ..params.clone()
+        };
+        assert!(after_finish.validate().is_err());
+        let silent = RecordParams {
+            audio_device: String::new(),
+            ..params
+        };
+        assert!(silent.validate().is_err());
+    }
+
+    #[test]
+    fn object_base_name_keeps_the_destination_prefix() {
+        let params = RecordParams {
+            output_folder: "oci://recordings/2024/q3".to_string(),
+            filename_template: "demo".to_string(),
+            ..RecordParams::default()
+        };
+        assert_eq!(params.object_base_name(), "2024/q3/demo");
+        let local = RecordParams {
+            output_folder: "/tmp/records".to_string(),
+            ..params
+        };
+        assert_eq!(local.object_base_name(), "demo");
+    }
+
+    #[test]
+    fn capture_stops_without_waiting_for_packets() {
+        let stats = Arc::new(PipelineStats::new(4, 0));
+        let stop = CaptureStop {
+            stats: stats.clone(),
+            failed: Arc::new(AtomicBool::new(false)),
+            max_duration: Some(Duration::from_secs(3600)),
+        };
+        stats.mark_started();
+        assert_eq!(stop.reason(), None);
+        let expired = CaptureStop { max_duration: Some(Duration::ZERO), ..stop.clone() };
+        assert_eq!(expired.reason(), Some("Maximum duration reached"));
+        stop.failed.store(true, Ordering::Relaxed);
+        assert_eq!(stop.reason(), Some("Encoding stopped"));
+        stats.request_stop();
+        assert_eq!(stop.reason(), Some("Stop requested"));
+    }
+}
@@ -0,0 +1,245 @@
+// src/redact.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+
+use crate::filters::{VideoGraph, VideoInput};
+
+/// Чем закрывается скрываемая область.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedactMode {
+    Blur,
+    Black,
+}
+
+/// Прямоугольник экрана, который скрывается в записи (координаты в пикселях кадра).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedactRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub mode: RedactMode,
+}
+
+impl RedactRegion {
+    /// Разбирает список областей вида "x,y,w,h[,blur|black]; x,y,w,h ...".
+    /// По умолчанию область размывается.
+    pub fn parse_list(text: &str) -> Result<Vec<RedactRegion>> {
+        text.split(';')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(RedactRegion::parse)
+            .collect()
+    }
+
+    fn parse(item: &str) -> Result<RedactRegion> {
+        let parts: Vec<&str> = item.split(',').map(str::trim).collect();
+        if parts.len() != 4 && parts.len() != 5 {
+            anyhow::bail!("Region '{}' must look like x,y,w,h or x,y,w,h,black", item);
+        }
+        let number = |s: &str| {
+            s.parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("Region '{}': '{}' is not a number", item, s))
+        };
+        let mode = match parts.get(4).copied() {
+            None | Some("blur") => RedactMode::Blur,
+            Some("black") => RedactMode::Black,
+            Some(other) => anyhow::bail!("Region '{}': unknown mode '{}'", item, other),
+        };
+        let region = RedactRegion {
+            x: number(parts[0])?,
+            y: number(parts[1])?,
+            width: number(parts[2])?,
+            height: number(parts[3])?,
+            mode,
+        };
+        if region.width == 0 || region.height == 0 {
+            anyhow::bail!("Region '{}' has zero size", item);
+        }
+        Ok(region)
+    }
+}
+
+/// Область в том же виде, в каком её принимает `parse_list`: "x,y,w,h" для
+/// размытия и "x,y,w,h,black" для закраски.
+impl std::fmt::Display for RedactRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)?;
+        if self.mode == RedactMode::Black {
+            write!(f, ",black")?;
+        }
+        Ok(())
+    }
+}
+
+/// Список областей в виде для `RedactRegion::parse_list`.
+pub fn format_list(regions: &[RedactRegion]) -> String {
+    regions
+        .iter()
+        .map(RedactRegion::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Фильтр-граф, который скрывает заданные области в каждом кадре.
+pub struct RedactFilter {
+    graph: VideoGraph,
+}
+
+impl RedactFilter {
+    /// Строит граф под размеры и формат декодера. Области, выходящие за кадр,
+    /// считаются ошибкой конфигурации, а не молча обрезаются.
+    pub fn new(regions: &[RedactRegion], decoder: &ffmpeg::decoder::Video) -> Result<Self> {
+        for region in regions {
+            if region.x as u64 + region.width as u64 > decoder.width() as u64
+                || region.y as u64 + region.height as u64 > decoder.height() as u64
+            {
+                anyhow::bail!(
+                    "Redact region {}x{}+{}+{} does not fit into {}x{} frame",
+                    region.width,
+                    region.height,
+                    region.x,
+                    region.y,
+                    decoder.width(),
+                    decoder.height()
+                );
+            }
+        }
+        let input = VideoInput::from_decoder(decoder);
+        let graph = VideoGraph::new(input, &filter_spec(regions, input.format))?;
+        Ok(Self { graph })
+    }
+
+    /// Пропускает кадр через граф и возвращает обработанные кадры.
+    pub fn apply(&mut self, frame: &ffmpeg::frame::Video) -> Result<Vec<ffmpeg::frame::Video>> {
+        self.graph.apply(frame)
+    }
+}
+
+/// Радиус размытия, если область позволяет.
+const BLUR_RADIUS: u32 = 10;
+
+/// Радиусы boxblur для области: яркости и цветности. boxblur отказывается
+/// настраиваться, если радиус больше половины меньшей стороны плоскости.
+/// Упакованный BGRx boxblur не принимает, и FFmpeg сам переводит кадры в
+/// планарный формат, который может оказаться и 4:2:0 с вдвое меньшими
+/// плоскостями цветности; радиус цветности считается под этот худший случай.
+/// Узкие области размываются слабее, а не обрывают запись.
+fn blur_radii(region: &RedactRegion) -> (u32, u32) {
+    let side = region.width.min(region.height);
+    let chroma_side = (side + 1) / 2;
+    (BLUR_RADIUS.min(side / 2), BLUR_RADIUS.min(chroma_side / 2))
+}
+
+/// Описание графа: drawbox для чёрных областей, crop+boxblur+overlay для размытых.
+/// overlay по умолчанию выдаёт yuv420p, а кадрирование, суперсэмплинг и миниатюры
+/// дальше построены под формат декодера, поэтому выход приводится к `format`.
+fn filter_spec(regions: &[RedactRegion], format: ffmpeg::format::Pixel) -> String {
+    let mut chains = Vec::new();
+    let mut label = "in".to_string();
+    for (i, region) in regions.iter().enumerate() {
+        let next = format!("r{}", i);
+        let chain = match region.mode {
+            RedactMode::Black => format!(
+                "[{}]drawbox=x={}:y={}:w={}:h={}:color=black:t=fill[{}]",
+                label, region.x, region.y, region.width, region.height, next
+            ),
+            RedactMode::Blur => {
+                let (luma, chroma) = blur_radii(region);
+                format!(
+                    "[{l}]split[{n}b][{n}t];[{n}t]crop={w}:{h}:{x}:{y},\
+                     boxblur=luma_radius={lr}:chroma_radius={cr}[{n}c];\
+                     [{n}b][{n}c]overlay={x}:{y}[{n}]",
+                    l = label,
+                    n = next,
+                    w = region.width,
+                    h = region.height,
+                    x = region.x,
+                    y = region.y,
+                    lr = luma,
+                    cr = chroma
+                )
+            }
+        };
+        chains.push(chain);
+        label = next;
+    }
+    if chains.is_empty() {
+        return "null".to_string();
+    }
+    // Последняя метка должна уходить в выход графа без имени.
+    let spec = chains.join(";");
+    let suffix = format!("[{}]", label);
+    format!(
+        "{},format=pix_fmts={}",
+        &spec[..spec.len() - suffix.len()],
+        ffmpeg::ffi::AVPixelFormat::from(format) as i32
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blurred(width: u32, height: u32) -> RedactRegion {
+        RedactRegion {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            mode: RedactMode::Blur,
+        }
+    }
+
+    #[test]
+    fn formatted_regions_parse_back() {
+        let regions = RedactRegion::parse_list("10,20,300,40; 0,0,8,8,black").unwrap();
+        assert_eq!(format_list(&regions), "10,20,300,40; 0,0,8,8,black");
+        assert_eq!(RedactRegion::parse_list(&format_list(&regions)).unwrap(), regions);
+    }
+
+    #[test]
+    fn large_regions_keep_the_full_radius() {
+        assert_eq!(blur_radii(&blurred(200, 100)), (BLUR_RADIUS, BLUR_RADIUS));
+    }
+
+    #[test]
+    fn narrow_regions_get_a_radius_boxblur_accepts() {
+        assert_eq!(blur_radii(&blurred(12, 300)), (6, 3));
+        assert_eq!(blur_radii(&blurred(30, 3)), (1, 1));
+        assert_eq!(blur_radii(&blurred(1, 1)), (0, 0));
+    }
+
+    #[test]
+    fn a_tiny_region_still_builds_a_graph() {
+        ffmpeg::init().unwrap();
+        let regions = RedactRegion::parse_list("4,4,6,6").unwrap();
+        let input = VideoInput {
+            width: 64,
+            height: 48,
+            format: ffmpeg::format::Pixel::YUV420P,
+            time_base: ffmpeg::Rational(1, 30),
+        };
+        assert!(VideoGraph::new(input, &filter_spec(&regions, input.format)).is_ok());
+    }
+
+    #[test]
+    fn redacted_frames_keep_the_input_format() {
+        ffmpeg::init().unwrap();
+        let regions = RedactRegion::parse_list("8,8,32,16; 0,0,4,4,black").unwrap();
+        let input = VideoInput {
+            width: 64,
+            height: 48,
+            format: ffmpeg::format::Pixel::BGRZ,
+            time_base: ffmpeg::Rational(1, 30),
+        };
+        let mut graph = VideoGraph::new(input, &filter_spec(&regions, input.format)).unwrap();
+        let mut frame = ffmpeg::frame::Video::new(input.format, input.width, input.height);
+        frame.data_mut(0).fill(128);
+        frame.set_pts(Some(0));
+        let frames = graph.apply(&frame).unwrap();
+        assert!(!frames.is_empty());
+        assert!(frames.iter().all(|f| f.format() == ffmpeg::format::Pixel::BGRZ));
+    }
+}
@@ -0,0 +1,110 @@
+// src/region_picker.rs
+
+use gtk::prelude::*;
+use gtk::{ApplicationWindow, DrawingArea, Inhibit, Window, WindowType};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Выделение мышью: точка нажатия и текущая точка.
+#[derive(Default)]
+struct Selection {
+    start: Option<(f64, f64)>,
+    current: (f64, f64),
+}
+
+impl Selection {
+    fn rect(&self) -> Option<(f64, f64, f64, f64)> {
+        let (x0, y0) = self.start?;
+        let (x1, y1) = self.current;
+        Some((x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs()))
+    }
+}
+
+/// Открывает полупрозрачное полноэкранное окно поверх рабочего стола, в котором
+/// пользователь мышью выделяет прямоугольник. По отпусканию кнопки вызывается
+/// `on_picked(x, y, w, h)` в пикселях экрана (с учётом масштаба), Esc отменяет выбор.
+pub fn pick_region<F: Fn(u32, u32, u32, u32) + 'static>(parent: &ApplicationWindow, on_picked: F) {
+    let overlay = Window::new(WindowType::Toplevel);
+    overlay.set_transient_for(Some(parent));
+    overlay.set_decorated(false);
+    overlay.set_app_paintable(true);
+    if let Some(visual) = overlay.get_screen().and_then(|screen| screen.get_rgba_visual()) {
+        overlay.set_visual(Some(&visual));
+    }
+
+    let area = DrawingArea::new();
+    area.add_events(
+        gdk::EventMask::BUTTON_PRESS_MASK
+            | gdk::EventMask::BUTTON_RELEASE_MASK
+            | gdk::EventMask::POINTER_MOTION_MASK,
+    );
+    overlay.add(&area);
+
+    let selection = Rc::new(RefCell::new(Selection::default()));
+
+    {
+        let selection = selection.clone();
+        area.connect_draw(move |_, cr| {
+            cr.set_source_rgba(0.0, 0.0, 0.0, 0.35);
+            cr.paint();
+            if let Some((x, y, w, h)) = selection.borrow().rect() {
+                cr.set_source_rgba(1.0, 0.2, 0.2, 0.9);
+                cr.set_line_width(2.0);
+                cr.rectangle(x, y, w, h);
+                cr.stroke();
+            }
+            Inhibit(false)
+        });
+    }
+    {
+        let selection = selection.clone();
+        area.connect_button_press_event(move |_, event| {
+            let position = event.get_position();
+            let mut selection = selection.borrow_mut();
+            selection.start = Some(position);
+            selection.current = position;
+            Inhibit(true)
+        });
+    }
+    {
+        let selection = selection.clone();
+        area.connect_motion_notify_event(move |area, event| {
+            let mut selection = selection.borrow_mut();
+            if selection.start.is_some() {
+                selection.current = event.get_position();
+                area.queue_draw();
+            }
+            Inhibit(true)
+        });
+    }
+    {
+        let selection = selection.clone();
+        let overlay_clone = overlay.clone();
+        area.connect_button_release_event(move |area, event| {
+            selection.borrow_mut().current = event.get_position();
+            let rect = selection.borrow().rect();
+            if let Some((x, y, w, h)) = rect {
+                let scale = area.get_scale_factor() as f64;
+                if w >= 1.0 && h >= 1.0 {
+                    on_picked(
+                        (x * scale) as u32,
+                        (y * scale) as u32,
+                        (w * scale) as u32,
+                        (h * scale) as u32,
+                    );
+                }
+            }
+            overlay_clone.close();
+            Inhibit(true)
+        });
+    }
+    overlay.connect_key_press_event(|window, event| {
+        if event.get_keyval() == gdk::keys::constants::Escape {
+            window.close();
+        }
+        Inhibit(false)
+    });
+
+    overlay.show_all();
+    overlay.fullscreen();
+}
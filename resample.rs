@@ -0,0 +1,78 @@
+// src/resample.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::software::resampling;
+use tracing::info;
+
+/// Формат звука: сэмплы, раскладка каналов и частота.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioFormat {
+    pub sample: ffmpeg::format::Sample,
+    pub layout: ffmpeg::ChannelLayout,
+    pub rate: u32,
+}
+
+impl AudioFormat {
+    pub fn of_frame(frame: &ffmpeg::frame::Audio) -> Self {
+        Self {
+            sample: frame.format(),
+            layout: frame.channel_layout(),
+            rate: frame.rate(),
+        }
+    }
+}
+
+/// Приводит звук к постоянному выходному формату.
+///
+/// Некоторые источники (PulseAudio/PipeWire при смене профиля устройства, Bluetooth)
+/// меняют формат посреди потока; ресемплер, созданный под исходный формат,
+/// тогда ломается. Здесь формат каждого кадра сверяется с текущим входом,
+/// и при расхождении ресемплер пересоздаётся с тем же выходом.
+pub struct AudioConverter {
+    output: AudioFormat,
+    input: Option<AudioFormat>,
+    context: Option<resampling::Context>,
+}
+
+impl AudioConverter {
+    pub fn new(output: AudioFormat) -> Self {
+        Self {
+            output,
+            input: None,
+            context: None,
+        }
+    }
+
+    /// Переводит кадр в выходной формат, при необходимости пересоздавая ресемплер.
+    pub fn convert(&mut self, frame: &ffmpeg::frame::Audio) -> Result<ffmpeg::frame::Audio> {
+        let input = AudioFormat::of_frame(frame);
+        if self.input != Some(input) || self.context.is_none() {
+            if let Some(previous) = self.input {
+                info!(
+                    "Audio format changed from {:?} to {:?}; resampling to {:?}",
+                    previous, input, self.output
+                );
+            }
+            self.context = Some(
+                resampling::Context::get(
+                    input.sample,
+                    input.layout,
+                    input.rate,
+                    self.output.sample,
+                    self.output.layout,
+                    self.output.rate,
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to create audio resampler: {:?}", e))?,
+            );
+            self.input = Some(input);
+        }
+        let mut converted = ffmpeg::frame::Audio::empty();
+        self.context
+            .as_mut()
+            .expect("resampler is created above")
+            .run(frame, &mut converted)
+            .map_err(|e| anyhow::anyhow!("Error resampling audio: {:?}", e))?;
+        Ok(converted)
+    }
+}
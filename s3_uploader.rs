@@ -0,0 +1,561 @@
+// src/s3_uploader.rs
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::storage::{
+    check_status, RetryPolicy, StatusError, StorageSink, UploadProgress, DEFAULT_PART_SIZE_MB,
+};
+
+/// Минимальный размер части многочастной выгрузки в S3 (кроме последней).
+const MIN_PART_SIZE_MB: u32 = 5;
+
+/// Учётные данные и адрес S3-совместимого хранилища (AWS, MinIO, Ceph и т.п.).
+/// Берутся из переменных окружения S3_ENDPOINT (например,
+/// https://s3.eu-central-1.amazonaws.com), S3_REGION (по умолчанию us-east-1),
+/// AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY и необязательной AWS_SESSION_TOKEN.
+#[derive(Clone)]
+pub struct S3Credentials {
+    endpoint: reqwest::Url,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Credentials {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| env::var(name).with_context(|| format!("{} is not set", name));
+        let endpoint = var("S3_ENDPOINT")?;
+        let endpoint = reqwest::Url::parse(&endpoint)
+            .with_context(|| format!("S3_ENDPOINT '{}' is not a valid URL", endpoint))?;
+        Ok(Self {
+            endpoint,
+            region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    fn host(&self) -> String {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Значение первого элемента `<tag>` в XML-ответе S3.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].to_string())
+}
+
+/// Значения всех элементов `<tag>` в XML-ответе S3, со снятым XML-экранированием.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let end = match after.find(&close) {
+            Some(end) => end,
+            None => break,
+        };
+        values.push(
+            after[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &after[end + close.len()..];
+    }
+    values
+}
+
+/// Путь объекта; '/' в имени остаётся разделителем «папок».
+fn object_path(endpoint: &reqwest::Url, bucket: &str, object_name: &str) -> String {
+    let key: Vec<String> = object_name
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect();
+    format!(
+        "{}/{}/{}",
+        endpoint.path().trim_end_matches('/'),
+        urlencoding::encode(bucket),
+        key.join("/")
+    )
+}
+
+/// Минимальный клиент S3 REST API с подписью запросов AWS Signature Version 4.
+/// Адресация path-style (`endpoint/bucket/key`): её понимают все S3-совместимые
+/// хранилища, а не только AWS.
+struct S3Client {
+    creds: S3Credentials,
+    http: reqwest::blocking::Client,
+}
+
+impl S3Client {
+    fn new(creds: S3Credentials) -> Self {
+        Self {
+            creds,
+            http: reqwest::blocking::Client::new(),
+        }
+    }
+
+    fn object_path(&self, bucket: &str, object_name: &str) -> String {
+        object_path(&self.creds.endpoint, bucket, object_name)
+    }
+
+    /// Подписывает и отправляет запрос. `query` передаётся отсортированным по
+    /// имени, `headers` — x-amz-* заголовки в нижнем регистре: их S3 требует
+    /// подписывать вместе с host, датой и SHA-256 тела.
+    fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::blocking::Response> {
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", urlencoding::encode(name), urlencoding::encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut signed = vec![
+            ("host".to_string(), self.creds.host()),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(token) = &self.creds.session_token {
+            signed.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        signed.extend(headers.iter().cloned());
+        signed.sort();
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method.as_str(),
+            path,
+            query,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.creds.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = hmac_sha256(format!("AWS4{}", self.creds.secret_key).as_bytes(), &date);
+        let key = hmac_sha256(&key, &self.creds.region);
+        let key = hmac_sha256(&key, "s3");
+        let key = hmac_sha256(&key, "aws4_request");
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+            self.creds.access_key,
+            scope,
+            signed_headers,
+            hex(&hmac_sha256(&key, &string_to_sign))
+        );
+
+        let mut url = format!("{}://{}{}", self.creds.endpoint.scheme(), self.creds.host(), path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let mut request = self.http.request(method, url).header("authorization", authorization);
+        for (name, value) in signed.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        let response = request.body(body).send()?;
+        check_status(response)
+    }
+
+    fn metadata_headers(metadata: &[(String, String)]) -> Vec<(String, String)> {
+        metadata
+            .iter()
+            .map(|(key, value)| (format!("x-amz-meta-{}", key.to_lowercase()), value.clone()))
+            .collect()
+    }
+
+    fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        metadata: &[(String, String)],
+    ) -> Result<String> {
+        let path = self.object_path(bucket, object_name);
+        let headers = Self::metadata_headers(metadata);
+        let response = self
+            .send(reqwest::Method::POST, &path, &[("uploads", "")], &headers, Vec::new())
+            .with_context(|| format!("CreateMultipartUpload for {} failed", object_name))?;
+        let text = response.text()?;
+        xml_value(&text, "UploadId")
+            .ok_or_else(|| anyhow::anyhow!("CreateMultipartUpload for {} returned no UploadId", object_name))
+    }
+
+    /// Выгружает часть номер `part_num` (с 1) и возвращает её ETag для завершения.
+    fn upload_part(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        upload_id: &str,
+        part_num: u32,
+        body: &[u8],
+    ) -> Result<String> {
+        let path = self.object_path(bucket, object_name);
+        let part_num_text = part_num.to_string();
+        let query = [("partNumber", part_num_text.as_str()), ("uploadId", upload_id)];
+        let response = self
+            .send(reqwest::Method::PUT, &path, &query, &[], body.to_vec())
+            .with_context(|| format!("UploadPart {} for {} failed", part_num, object_name))?;
+        response
+            .headers()
+            .get("etag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string())
+            .ok_or_else(|| anyhow::anyhow!("UploadPart {} for {} returned no ETag", part_num, object_name))
+    }
+
+    fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> Result<()> {
+        let path = self.object_path(bucket, object_name);
+        let parts: String = parts
+            .iter()
+            .map(|(num, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", num, etag))
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+        let response = self
+            .send(reqwest::Method::POST, &path, &[("uploadId", upload_id)], &[], body.into_bytes())
+            .with_context(|| format!("CompleteMultipartUpload for {} failed", object_name))?;
+        // S3 может ответить 200 и при этом вернуть ошибку в теле; такой ответ
+        // по документации следует повторить, как 5xx.
+        let status = response.status();
+        let text = response.text()?;
+        if xml_value(&text, "Code").is_some() {
+            let error = StatusError {
+                status: reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+                body: text,
+            };
+            return Err(anyhow::Error::from(error)
+                .context(format!("CompleteMultipartUpload for {} failed ({})", object_name, status)));
+        }
+        Ok(())
+    }
+
+    fn abort_multipart_upload(&self, bucket: &str, object_name: &str, upload_id: &str) -> Result<()> {
+        let path = self.object_path(bucket, object_name);
+        self.send(reqwest::Method::DELETE, &path, &[("uploadId", upload_id)], &[], Vec::new())
+            .with_context(|| format!("AbortMultipartUpload for {} failed", object_name))?;
+        Ok(())
+    }
+
+    fn put_object(
+        &self,
+        bucket: &str,
+        object_name: &str,
+        metadata: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<()> {
+        let path = self.object_path(bucket, object_name);
+        let headers = Self::metadata_headers(metadata);
+        self.send(reqwest::Method::PUT, &path, &[], &headers, body)
+            .with_context(|| format!("PutObject for {} failed", object_name))?;
+        Ok(())
+    }
+
+    /// Ключи bucket, начинающиеся с `prefix` (ListObjectsV2 по всем страницам).
+    fn list_objects(&self, bucket: &str, prefix: &str) -> Result<Vec<String>> {
+        let path = format!(
+            "{}/{}",
+            self.creds.endpoint.path().trim_end_matches('/'),
+            urlencoding::encode(bucket)
+        );
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            // Параметры запроса — в порядке имён, как требует подпись.
+            let mut query = Vec::new();
+            if let Some(token) = token.as_deref() {
+                query.push(("continuation-token", token));
+            }
+            query.push(("list-type", "2"));
+            query.push(("prefix", prefix));
+            let text = self
+                .send(reqwest::Method::GET, &path, &query, &[], Vec::new())
+                .and_then(|response| Ok(response.text()?))
+                .with_context(|| format!("ListObjectsV2 in {} failed", bucket))?;
+            keys.extend(xml_values(&text, "Key"));
+            match xml_value(&text, "NextContinuationToken") {
+                Some(next) if xml_value(&text, "IsTruncated").as_deref() == Some("true") => {
+                    token = Some(next)
+                }
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    fn head_bucket(&self, bucket: &str) -> Result<()> {
+        let path = format!(
+            "{}/{}",
+            self.creds.endpoint.path().trim_end_matches('/'),
+            urlencoding::encode(bucket)
+        );
+        self.send(reqwest::Method::HEAD, &path, &[], &[], Vec::new())
+            .with_context(|| format!("HeadBucket for {} failed", bucket))?;
+        Ok(())
+    }
+}
+
+/// Проверяет, что bucket существует и доступен с учётными данными из окружения.
+pub fn check_bucket(bucket: &str) -> Result<()> {
+    S3Client::new(S3Credentials::from_env()?).head_bucket(bucket)
+}
+
+/// Ключи bucket, начинающиеся с `prefix`, с учётными данными из окружения.
+pub fn list_objects(bucket: &str, prefix: &str) -> Result<Vec<String>> {
+    S3Client::new(S3Credentials::from_env()?).list_objects(bucket, prefix)
+}
+
+/// Адрес объекта (path-style, как и все запросы клиента).
+pub fn object_url(bucket: &str, object_name: &str) -> Result<String> {
+    let creds = S3Credentials::from_env()?;
+    Ok(format!(
+        "{}://{}{}",
+        creds.endpoint.scheme(),
+        creds.host(),
+        object_path(&creds.endpoint, bucket, object_name)
+    ))
+}
+
+/// Принимает закодированный поток через `Write` и выгружает его в bucket
+/// S3-совместимого хранилища многочастной выгрузкой по ходу записи.
+/// Отложенная выгрузка не поддерживается: она есть только у OCI.
+pub struct S3Uploader {
+    bucket: String,
+    object_name: String,
+    metadata: Vec<(String, String)>,
+    part_size: usize,
+    buffer: Vec<u8>,
+    /// Клиент и UploadId создаются при отправке первой части; запись короче
+    /// одной части уходит обычным PutObject в `finalize`.
+    upload: Option<(S3Client, String)>,
+    /// Номера и ETag уже выгруженных частей — для CompleteMultipartUpload.
+    parts: Vec<(u32, String)>,
+    bytes_written: u64,
+    /// Сопутствующие объекты (миниатюры и т.п.), выгружаемые вместе с записью.
+    sidecars: Vec<(String, Vec<u8>)>,
+    retry: RetryPolicy,
+    progress: Arc<UploadProgress>,
+}
+
+impl S3Uploader {
+    pub fn new(bucket: &str, object_name: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            object_name: object_name.to_string(),
+            metadata: Vec::new(),
+            part_size: DEFAULT_PART_SIZE_MB as usize * 1024 * 1024,
+            buffer: Vec::new(),
+            upload: None,
+            parts: Vec::new(),
+            bytes_written: 0,
+            sidecars: Vec::new(),
+            retry: RetryPolicy::default(),
+            progress: Arc::default(),
+        }
+    }
+
+    /// Размер части; S3 не принимает части меньше 5 МБ, кроме последней.
+    pub fn with_part_size(mut self, part_size_mb: u32) -> Self {
+        self.part_size = part_size_mb.max(MIN_PART_SIZE_MB) as usize * 1024 * 1024;
+        self
+    }
+
+    /// Сколько раз и с какой начальной задержкой повторять временные сбои выгрузки.
+    pub fn with_retry(mut self, max_retries: u32, base_delay: Duration) -> Self {
+        self.retry = RetryPolicy {
+            max_retries,
+            base_delay,
+        };
+        self
+    }
+
+    /// Счётчики, в которых выгружатель публикует ход записи и выгрузки.
+    pub fn with_progress(mut self, progress: Arc<UploadProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Метаданные (x-amz-meta-*), которые будут установлены на создаваемом объекте.
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Отправляет накопленную часть, начиная многочастную выгрузку при первой части.
+    #[tracing::instrument(skip_all, fields(object = %self.object_name))]
+    fn upload_full_part(&mut self) -> Result<()> {
+        if self.upload.is_none() {
+            let client = S3Client::new(S3Credentials::from_env()?);
+            let upload_id = self.retry.run(&format!("CreateMultipartUpload {}", self.object_name), || {
+                client.create_multipart_upload(&self.bucket, &self.object_name, &self.metadata)
+            })?;
+            info!("Started multipart upload of {} to bucket {}", self.object_name, self.bucket);
+            self.upload = Some((client, upload_id));
+        }
+        let (client, upload_id) = self.upload.as_ref().expect("upload is started above");
+        let part_num = self.parts.len() as u32 + 1;
+        let body = &self.buffer[..self.part_size];
+        let what = format!("UploadPart {} of {}", part_num, self.object_name);
+        let etag = self.retry.run(&what, || {
+            client.upload_part(&self.bucket, &self.object_name, upload_id, part_num, body)
+        })?;
+        self.parts.push((part_num, etag));
+        self.progress.uploaded_part(self.part_size);
+        self.buffer.drain(..self.part_size);
+        self.bytes_written -= self.part_size as u64;
+        Ok(())
+    }
+
+    /// Отправляет остаток буфера последней частью и завершает выгрузку.
+    fn complete_multipart(&mut self, client: &S3Client, upload_id: &str) -> Result<()> {
+        if !self.buffer.is_empty() {
+            let part_num = self.parts.len() as u32 + 1;
+            let body = &self.buffer;
+            let etag = self.retry.run(&format!("UploadPart {} of {}", part_num, self.object_name), || {
+                client.upload_part(&self.bucket, &self.object_name, upload_id, part_num, body)
+            })?;
+            self.parts.push((part_num, etag));
+            self.progress.uploaded_part(self.buffer.len());
+            self.buffer.clear();
+        }
+        self.retry.run(&format!("CompleteMultipartUpload {}", self.object_name), || {
+            client.complete_multipart_upload(&self.bucket, &self.object_name, upload_id, &self.parts)
+        })
+    }
+
+    /// Отменяет многочастную выгрузку, чтобы в bucket не остались висящие части
+    /// (за них S3 берёт плату, пока их не удалит правило жизненного цикла).
+    fn abort(&mut self) {
+        if let Some((client, upload_id)) = self.upload.take() {
+            warn!("Aborting multipart upload of {}", self.object_name);
+            if let Err(e) = client.abort_multipart_upload(&self.bucket, &self.object_name, &upload_id) {
+                warn!("Failed to abort multipart upload of {}: {:?}", self.object_name, e);
+            }
+        }
+    }
+}
+
+impl StorageSink for S3Uploader {
+    fn add_sidecar(&mut self, object_name: &str, data: Vec<u8>) {
+        self.sidecars.push((object_name.to_string(), data));
+    }
+
+    fn pending_bytes(&self) -> u64 {
+        self.bytes_written
+    }
+
+    #[tracing::instrument(skip_all, fields(object = %self.object_name))]
+    fn finalize(&mut self) -> Result<()> {
+        let client = match self.upload.take() {
+            // Запись меньше одной части: многочастная выгрузка не нужна.
+            None => {
+                let client = S3Client::new(S3Credentials::from_env()?);
+                info!("Uploading {} to bucket {}", self.object_name, self.bucket);
+                self.retry.run(&format!("PutObject {}", self.object_name), || {
+                    client.put_object(&self.bucket, &self.object_name, &self.metadata, self.buffer.clone())
+                })?;
+                self.progress.uploaded(self.buffer.len());
+                self.buffer.clear();
+                client
+            }
+            Some((client, upload_id)) => {
+                if let Err(e) = self.complete_multipart(&client, &upload_id) {
+                    self.upload = Some((client, upload_id));
+                    self.abort();
+                    return Err(e);
+                }
+                client
+            }
+        };
+        self.bytes_written = 0;
+        for (object_name, data) in std::mem::take(&mut self.sidecars) {
+            self.retry.run(&format!("PutObject {}", object_name), || {
+                client.put_object(&self.bucket, &object_name, &self.metadata, data.clone())
+            })?;
+        }
+        info!("Upload of {} finished", self.object_name);
+        Ok(())
+    }
+}
+
+impl Write for S3Uploader {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.bytes_written += buf.len() as u64;
+        self.progress.wrote(buf.len());
+        if self.buffer.len() >= self.part_size {
+            if let Err(e) = self.upload_full_part() {
+                self.abort();
+                return Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e)));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for S3Uploader {
+    /// Запись прервалась до `finalize`: уже выгруженные части не нужны.
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
@@ -0,0 +1,157 @@
+// src/scene.rs
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg::Rescale;
+use std::sync::atomic::Ordering;
+
+use crate::damage::DamageCount;
+
+/// Шаг выборки по строкам и по байтам в строке: сравнивается лишь малая часть
+/// кадра, чтобы детектор не замедлял кодирование.
+const ROW_STEP: usize = 8;
+const BYTE_STEP: usize = 16;
+
+/// Дешёвый детектор смены сцены (например, переключения слайдов) по разнице
+/// выборочных байтов первой плоскости соседних кадров.
+pub struct SceneDetector {
+    previous: Vec<u8>,
+    threshold: f64,
+}
+
+impl SceneDetector {
+    /// `sensitivity` от 1 до 100: чем больше, тем меньшие изменения считаются сменой сцены.
+    pub fn new(sensitivity: u32) -> Self {
+        let sensitivity = sensitivity.clamp(1, 100) as f64;
+        Self {
+            previous: Vec::new(),
+            // Средняя разница байтов (0..255), выше которой кадр считается новой сценой.
+            threshold: (101.0 - sensitivity) * 0.5,
+        }
+    }
+
+    /// Возвращает true, если кадр заметно отличается от предыдущего.
+    /// Первый кадр сменой сцены не считается: он и так станет ключевым.
+    pub fn is_scene_change(&mut self, frame: &ffmpeg::frame::Video) -> bool {
+        let data = frame.data(0);
+        let stride = frame.stride(0);
+        let mut samples = Vec::with_capacity(self.previous.len());
+        for row in (0..frame.height() as usize).step_by(ROW_STEP) {
+            let start = row * stride;
+            let end = (start + stride).min(data.len());
+            samples.extend(data[start..end].iter().step_by(BYTE_STEP));
+        }
+
+        let changed = if samples.len() == self.previous.len() && !samples.is_empty() {
+            let total: u64 = samples
+                .iter()
+                .zip(&self.previous)
+                .map(|(a, b)| (*a as i32 - *b as i32).unsigned_abs() as u64)
+                .sum();
+            total as f64 / samples.len() as f64 > self.threshold
+        } else {
+            false
+        };
+        self.previous = samples;
+        changed
+    }
+}
+
+/// Как часто запись по damage пропускает кадр даже без изменений: паузы
+/// в потоке не длиннее этого, и проигрыватели не «замирают» на длинном кадре.
+pub const DAMAGE_KEEPALIVE_SECS: i64 = 1;
+
+/// Решает, какие кадры записывать в режиме damage_driven.
+///
+/// Об изменениях сообщает композитор: `DamageMonitor` считает буферы потока
+/// с метаданными SPA_META_VideoDamage. Кадр записывается, если с прошлого
+/// записанного кадра пришёл хоть один такой буфер. Монитор — отдельный
+/// потребитель того же узла, и его буфер может прийти чуть позже кадра FFmpeg:
+/// тогда изменение попадает в запись со следующим кадром.
+/// PTS источника сохраняются, поэтому пропуски становятся длинными кадрами,
+/// но не длиннее `DAMAGE_KEEPALIVE_SECS`.
+pub struct DamageTracker {
+    time_base: ffmpeg::Rational,
+    damage: DamageCount,
+    /// Значение счётчика на момент прошлого записанного кадра.
+    seen: Option<u64>,
+    last_pts: Option<i64>,
+}
+
+impl DamageTracker {
+    /// `time_base` — база времени PTS кадров, по ней отмеряется keepalive.
+    pub fn new(time_base: ffmpeg::Rational, damage: DamageCount) -> Self {
+        Self {
+            time_base,
+            damage,
+            seen: None,
+            last_pts: None,
+        }
+    }
+
+    /// Возвращает true, если кадр нужно записать: композитор сообщил об изменениях
+    /// или с прошлого записанного кадра прошло больше keepalive.
+    pub fn is_damaged(&mut self, frame: &ffmpeg::frame::Video) -> bool {
+        let count = self.damage.load(Ordering::Relaxed);
+        let pts = frame.pts();
+        let damaged = self.seen != Some(count)
+            || match (pts, self.last_pts) {
+                (Some(pts), Some(last)) => {
+                    (pts - last).rescale(self.time_base, ffmpeg::Rational(1, 1000))
+                        >= DAMAGE_KEEPALIVE_SECS * 1000
+                }
+                _ => false,
+            };
+        if damaged {
+            self.seen = Some(count);
+            self.last_pts = pts;
+        }
+        damaged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Кадр BGRZ 64x48 с PTS в миллисекундах.
+    fn frame(pts_ms: i64) -> ffmpeg::frame::Video {
+        let mut frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::BGRZ, 64, 48);
+        frame.set_pts(Some(pts_ms));
+        frame
+    }
+
+    fn tracker() -> (DamageTracker, DamageCount) {
+        let damage = DamageCount::default();
+        (DamageTracker::new(ffmpeg::Rational(1, 1000), damage.clone()), damage)
+    }
+
+    #[test]
+    fn frames_without_damage_are_skipped() {
+        let (mut tracker, damage) = tracker();
+        assert!(tracker.is_damaged(&frame(0)));
+        for i in 1..=20 {
+            assert!(!tracker.is_damaged(&frame(i * 16)));
+        }
+        damage.fetch_add(1, Ordering::Relaxed);
+        assert!(tracker.is_damaged(&frame(400)));
+        assert!(!tracker.is_damaged(&frame(416)));
+    }
+
+    #[test]
+    fn several_damage_events_make_one_frame() {
+        let (mut tracker, damage) = tracker();
+        assert!(tracker.is_damaged(&frame(0)));
+        damage.fetch_add(3, Ordering::Relaxed);
+        assert!(tracker.is_damaged(&frame(16)));
+        assert!(!tracker.is_damaged(&frame(32)));
+    }
+
+    #[test]
+    fn a_static_screen_still_gets_a_keepalive_frame() {
+        let (mut tracker, _damage) = tracker();
+        assert!(tracker.is_damaged(&frame(0)));
+        assert!(!tracker.is_damaged(&frame(999)));
+        assert!(tracker.is_damaged(&frame(1000)));
+        assert!(!tracker.is_damaged(&frame(1500)));
+    }
+}
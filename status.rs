@@ -0,0 +1,159 @@
+// src/status.rs
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::pipeline::PipelineStats;
+
+/// Порт HTTP-статуса по умолчанию.
+pub const DEFAULT_STATUS_PORT: u16 = 8787;
+
+/// Крошечный HTTP-сервер статуса записи для headless-использования.
+///
+/// - `GET /status` — JSON с позицией записи и счётчиками конвейера
+///   (те же, что показывает панель статистики GUI);
+/// - `POST /stop` — остановить запись; ответ `{"stopping":true}`.
+///
+/// Слушает только 127.0.0.1: удалённый доступ — через SSH-туннель или прокси.
+/// Запросы с заголовком `Origin` отклоняются: их шлёт браузер от имени
+/// открытой страницы, а `POST /stop` без тела — простой запрос, который
+/// любая страница может отправить на localhost без preflight.
+pub struct StatusServer {
+    stop: Arc<AtomicBool>,
+}
+
+impl StatusServer {
+    pub fn start(port: u16, stats: Arc<PipelineStats>, position_ms: Arc<AtomicI64>) -> Result<Self> {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("Failed to bind status endpoint on {}", address))?;
+        listener.set_nonblocking(true)?;
+        info!("Status endpoint listening on http://{}/status", address);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = handle_request(stream, &stats, &position_ms) {
+                            warn!("Status request error: {:?}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        warn!("Status endpoint accept failed: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { stop })
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn handle_request(stream: TcpStream, stats: &PipelineStats, position_ms: &AtomicI64) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Из заголовков нужен только Origin, но дочитать их надо все,
+    // иначе клиент может получить RST.
+    let mut from_browser = false;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        from_browser |= header
+            .split_once(':')
+            .map_or(false, |(name, _)| name.trim().eq_ignore_ascii_case("origin"));
+        header.clear();
+    }
+
+    let (status, body) = respond(&request_line, from_browser, stats, position_ms);
+    let body = body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Ответ на запрос `request_line`; `from_browser` — у запроса был заголовок Origin.
+fn respond(
+    request_line: &str,
+    from_browser: bool,
+    stats: &PipelineStats,
+    position_ms: &AtomicI64,
+) -> (&'static str, serde_json::Value) {
+    if from_browser {
+        warn!("Rejected a status request sent by a web page");
+        return ("403 Forbidden", serde_json::json!({ "error": "browser requests are not allowed" }));
+    }
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/status")) => ("200 OK", status_json(stats, position_ms)),
+        (Some("POST"), Some("/stop")) => {
+            info!("Stop requested via status endpoint");
+            stats.request_stop();
+            ("200 OK", serde_json::json!({ "stopping": true }))
+        }
+        (Some(_), Some("/status")) | (Some(_), Some("/stop")) => (
+            "405 Method Not Allowed",
+            serde_json::json!({ "error": "method not allowed" }),
+        ),
+        _ => ("404 Not Found", serde_json::json!({ "error": "not found" })),
+    }
+}
+
+fn status_json(stats: &PipelineStats, position_ms: &AtomicI64) -> serde_json::Value {
+    serde_json::json!({
+        "recording": !stats.stop_requested(),
+        "position_ms": position_ms.load(Ordering::Relaxed),
+        "frames_queued": stats.frames_queued.load(Ordering::Relaxed),
+        "frames_dropped": stats.frames_dropped.load(Ordering::Relaxed),
+        "upload_bytes_pending": stats.upload_bytes_pending.load(Ordering::Relaxed),
+        "queue_depth": stats.queue_depth.load(Ordering::Relaxed),
+        "buffer_window_ms": stats.buffer_window_ms.load(Ordering::Relaxed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_from_a_web_page_is_rejected() {
+        let stats = PipelineStats::default();
+        let position = AtomicI64::new(0);
+        let (status, _) = respond("POST /stop HTTP/1.1", true, &stats, &position);
+        assert_eq!(status, "403 Forbidden");
+        assert!(!stats.stop_requested());
+    }
+
+    #[test]
+    fn stop_from_a_script_stops_the_recording() {
+        let stats = PipelineStats::default();
+        let position = AtomicI64::new(0);
+        let (status, _) = respond("POST /stop HTTP/1.1", false, &stats, &position);
+        assert_eq!(status, "200 OK");
+        assert!(stats.stop_requested());
+    }
+}
@@ -0,0 +1,289 @@
+// src/storage.rs
+
+use anyhow::Result;
+use std::env;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::oci_uploader::PendingUpload;
+
+/// Хранилища, в которые можно выгружать запись (строки из GUI и CLI).
+pub const BACKENDS: &[&str] = &["oci", "s3", "local"];
+
+/// Хранилище записи.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// OCI Object Storage (переменные окружения OCI_*).
+    Oci,
+    /// Любое S3-совместимое хранилище (переменные окружения S3_* и AWS_*).
+    S3,
+    /// Файл на диске: bucket трактуется как путь к папке.
+    Local,
+}
+
+impl Backend {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text {
+            "oci" => Ok(Backend::Oci),
+            "s3" => Ok(Backend::S3),
+            "local" => Ok(Backend::Local),
+            other => anyhow::bail!("Unknown storage backend '{}' (expected oci, s3 or local)", other),
+        }
+    }
+}
+
+/// Назначение, которое само по себе указывает на диск: `file://...`, абсолютный
+/// или явно относительный путь, `~/...`. Имя bucket не может содержать '/',
+/// так что такое назначение не спутать с bucket.
+pub fn local_destination(destination: &str) -> Option<PathBuf> {
+    let destination = destination.trim();
+    if let Some(path) = destination.strip_prefix("file://") {
+        return Some(PathBuf::from(path));
+    }
+    if let Some(path) = destination.strip_prefix("~/") {
+        return env::var_os("HOME").map(|home| PathBuf::from(home).join(path));
+    }
+    if destination.starts_with('/') || destination.starts_with("./") || destination.starts_with("../") {
+        return Some(PathBuf::from(destination));
+    }
+    None
+}
+
+/// Назначение со схемой хранилища: `s3://bucket/prefix` или `oci://bucket/prefix`.
+/// Схема выбирает хранилище вместо поля backend, а путь после bucket
+/// становится префиксом имени объекта ("папкой" внутри bucket).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectDestination {
+    pub backend: Backend,
+    pub bucket: String,
+    pub prefix: String,
+}
+
+pub fn object_destination(destination: &str) -> Option<ObjectDestination> {
+    let destination = destination.trim();
+    let (backend, rest) = if let Some(rest) = destination.strip_prefix("s3://") {
+        (Backend::S3, rest)
+    } else if let Some(rest) = destination.strip_prefix("oci://") {
+        (Backend::Oci, rest)
+    } else {
+        return None;
+    };
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    Some(ObjectDestination {
+        backend,
+        bucket: bucket.to_string(),
+        prefix: prefix.trim_matches('/').to_string(),
+    })
+}
+
+/// Приёмник закодированного потока: принимает данные через `Write` по ходу
+/// записи и выгружает их в хранилище. Конвейер записи работает только через
+/// этот трейт и не знает, куда именно уходит запись.
+pub trait StorageSink: Write + Send {
+    /// Добавляет объект, который выгрузится в тот же bucket сразу после записи.
+    fn add_sidecar(&mut self, object_name: &str, data: Vec<u8>);
+
+    /// Сколько байт записано, но ещё не отправлено в хранилище.
+    fn pending_bytes(&self) -> u64;
+
+    /// Откладывает ли приёмник выгрузку до явного запроса (`finish_capture`).
+    fn is_deferred(&self) -> bool {
+        false
+    }
+
+    /// Завершает захват отложенной записи, не выгружая её.
+    fn finish_capture(&mut self) -> Result<PendingUpload> {
+        anyhow::bail!("This storage backend does not support deferred upload")
+    }
+
+    /// Завершает захват и выгрузку записи вместе с сопутствующими объектами.
+    fn finalize(&mut self) -> Result<()>;
+}
+
+/// Ход выгрузки: пишется выгружателем, читается GUI по таймеру. Счётчики
+/// атомарные, а не канал: поток кодирования не ждёт, даже если GUI занят.
+#[derive(Debug, Default)]
+pub struct UploadProgress {
+    /// Байт записи принято от мультиплексора.
+    pub bytes_written: AtomicU64,
+    /// Байт записи уже в хранилище (или на диске).
+    pub bytes_uploaded: AtomicU64,
+    /// Выгружено частей многочастной выгрузки.
+    pub parts_uploaded: AtomicU32,
+}
+
+impl UploadProgress {
+    pub fn wrote(&self, bytes: usize) {
+        self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn uploaded(&self, bytes: usize) {
+        self.bytes_uploaded.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn uploaded_part(&self, bytes: usize) {
+        self.uploaded(bytes);
+        self.parts_uploaded.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Размер части многочастной выгрузки по умолчанию.
+pub const DEFAULT_PART_SIZE_MB: u32 = 16;
+
+/// Повторы запросов к хранилищу по умолчанию.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Ответ хранилища с кодом ошибки.
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Object storage returned {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Превращает неуспешный ответ в `StatusError`, чтобы по коду решать о повторе.
+pub fn check_status(response: reqwest::blocking::Response) -> Result<reqwest::blocking::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().unwrap_or_default();
+    Err(StatusError { status, body }.into())
+}
+
+/// Повторы временных сбоев выгрузки: обрыв соединения, таймаут, 429 и 5xx.
+/// Задержка растёт вдвое с каждой попыткой, к ней добавляется случайная
+/// добавка до половины задержки, чтобы параллельные выгрузки не били в такт.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: DEFAULT_RETRY_BASE_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Выполняет `op`, повторяя временные сбои. Ошибки авторизации, неверный
+    /// bucket и прочие 4xx возвращаются сразу. `what` называет операцию в ошибке.
+    pub fn run<T>(&self, what: &str, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_retries && is_retryable(&e) => {
+                    let delay = self.base_delay * 2u32.saturating_pow(attempt);
+                    let jitter = delay.mul_f64(rand::random::<f64>() * 0.5);
+                    attempt += 1;
+                    warn!(
+                        "{} failed, retrying in {:?} ({}/{}): {:?}",
+                        what,
+                        delay + jitter,
+                        attempt,
+                        self.max_retries,
+                        e
+                    );
+                    thread::sleep(delay + jitter);
+                }
+                Err(e) => {
+                    return Err(e.context(format!("{} failed after {} attempt(s)", what, attempt + 1)))
+                }
+            }
+        }
+    }
+}
+
+/// Сбой транспорта, 429 (Too Many Requests) и 5xx считаются временными.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(status) = cause.downcast_ref::<StatusError>() {
+            return status.status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status.status.is_server_error();
+        }
+        cause.is::<reqwest::Error>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Хранилище, которое отвечает `failures` по очереди, а затем принимает часть.
+    struct MockSink {
+        failures: Vec<reqwest::StatusCode>,
+        attempts: u32,
+        stored: Vec<u8>,
+    }
+
+    impl MockSink {
+        fn new(failures: &[reqwest::StatusCode]) -> Self {
+            Self {
+                failures: failures.to_vec(),
+                attempts: 0,
+                stored: Vec::new(),
+            }
+        }
+
+        fn put_part(&mut self, part: &[u8]) -> Result<()> {
+            self.attempts += 1;
+            if !self.failures.is_empty() {
+                let status = self.failures.remove(0);
+                return Err(StatusError { status, body: String::new() }.into());
+            }
+            self.stored.extend_from_slice(part);
+            Ok(())
+        }
+    }
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn retries_transient_failures_until_the_part_is_stored() {
+        let unavailable = reqwest::StatusCode::SERVICE_UNAVAILABLE;
+        let mut sink = MockSink::new(&[unavailable, reqwest::StatusCode::TOO_MANY_REQUESTS]);
+        policy(3).run("Uploading part", || sink.put_part(b"part")).unwrap();
+        assert_eq!(sink.attempts, 3);
+        assert_eq!(sink.stored, b"part");
+    }
+
+    #[test]
+    fn does_not_retry_permanent_failures() {
+        let mut sink = MockSink::new(&[reqwest::StatusCode::FORBIDDEN]);
+        let error = policy(3).run("Uploading part", || sink.put_part(b"part")).unwrap_err();
+        assert_eq!(sink.attempts, 1);
+        assert!(error.to_string().contains("after 1 attempt(s)"), "{}", error);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let unavailable = reqwest::StatusCode::SERVICE_UNAVAILABLE;
+        let mut sink = MockSink::new(&[unavailable; 3]);
+        assert!(policy(2).run("Uploading part", || sink.put_part(b"part")).is_err());
+        assert_eq!(sink.attempts, 3);
+        assert!(sink.stored.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+// src/supervisor.rs
+
+use ffmpeg_next as ffmpeg;
+use std::fmt;
+use std::time::Duration;
+
+use crate::gui::RecordParams;
+use crate::oci_uploader::PendingUpload;
+
+/// Пауза перед перезапуском: даём порталу и PipeWire восстановиться.
+pub const RESTART_DELAY: Duration = Duration::from_secs(2);
+
+/// Ошибки D-Bus, которыми портал отвечает, пока он упал или перезапускается.
+const PORTAL_GONE_ERRORS: &[&str] = &[
+    "org.freedesktop.DBus.Error.NoReply",
+    "org.freedesktop.DBus.Error.ServiceUnknown",
+    "org.freedesktop.DBus.Error.Disconnected",
+];
+
+/// Поток PipeWire оборвался посреди записи: декодер входа перестал получать кадры.
+#[derive(Debug)]
+pub struct StreamInterrupted(pub ffmpeg::Error);
+
+impl fmt::Display for StreamInterrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PipeWire stream interrupted")
+    }
+}
+
+impl std::error::Error for StreamInterrupted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Захват оборвался, но записанное до обрыва дописано и выгружено, а при
+/// отложенной выгрузке лежит в `pending`.
+pub struct SegmentInterrupted {
+    pub pending: Option<PendingUpload>,
+    pub source: anyhow::Error,
+}
+
+impl fmt::Debug for SegmentInterrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recording segment interrupted: {:?}", self.source)
+    }
+}
+
+impl fmt::Display for SegmentInterrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recording segment interrupted, the part recorded so far is kept")
+    }
+}
+
+impl std::error::Error for SegmentInterrupted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// Итог сегмента: `pending` как обычно, а если захват был прерван
+/// (`interrupted`), — `SegmentInterrupted` с тем же `pending` внутри.
+pub fn finish_segment(
+    pending: Option<PendingUpload>,
+    interrupted: Option<anyhow::Error>,
+) -> anyhow::Result<Option<PendingUpload>> {
+    match interrupted {
+        Some(source) => Err(SegmentInterrupted { pending, source }.into()),
+        None => Ok(pending),
+    }
+}
+
+/// Оборвался ли поток PipeWire (а не, скажем, фильтр или кодер).
+pub fn is_stream_interruption(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<StreamInterrupted>())
+}
+
+/// Можно ли лечить ошибку перезапуском записи: только обрывы транспорта —
+/// потока PipeWire, соединения с D-Bus или самого портала. Прочие ошибки
+/// (конфигурация, кодер, хранилище, отказ пользователя) повторять бессмысленно:
+/// выгрузка сама повторяет свои запросы, а ввод-вывод локальных файлов
+/// от перезапуска не починится.
+pub fn is_recoverable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<StreamInterrupted>()
+            || cause.is::<pipewire::Error>()
+            || cause.downcast_ref::<zbus::Error>().map_or(false, is_portal_transport)
+    })
+}
+
+fn is_portal_transport(error: &zbus::Error) -> bool {
+    match error {
+        zbus::Error::InputOutput(_) => true,
+        zbus::Error::MethodError(name, _, _) => PORTAL_GONE_ERRORS.contains(&name.as_str()),
+        _ => false,
+    }
+}
+
+/// Параметры для перезапуска номер `restart`: новый сегмент пишется в отдельный
+/// объект, чтобы не затереть уже выгруженную часть записи.
+pub fn restart_params(params: &RecordParams, restart: u32) -> RecordParams {
+    let mut params = params.clone();
+    params.filename_template = format!("{}-part{}", params.filename_template, restart + 1);
+    params
+}
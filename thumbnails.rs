@@ -0,0 +1,191 @@
+// src/thumbnails.rs
+
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use ffmpeg::software::scaling;
+use serde::{Deserialize, Serialize};
+
+/// Сколько миниатюр в одной строке спрайта.
+const SPRITE_COLUMNS: u32 = 10;
+
+/// Интервал и ширина миниатюр для перемотки в веб-плеерах.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ThumbnailSettings {
+    pub interval_secs: u32,
+    /// Ширина миниатюры; высота выводится из пропорций кадра.
+    pub width: u32,
+}
+
+/// Готовые к выгрузке файлы: JPEG-спрайт и WebVTT-индекс к нему.
+pub struct ThumbnailTrack {
+    pub sprite: Vec<u8>,
+    pub vtt: String,
+}
+
+/// Собирает миниатюры по ходу записи и в конце склеивает их в спрайт
+/// с WebVTT-индексом (`sprite.jpg#xywh=x,y,w,h` на каждый интервал).
+pub struct ThumbnailSprite {
+    settings: ThumbnailSettings,
+    height: u32,
+    scaler: scaling::Context,
+    /// Миниатюры в RGB24 без выравнивания строк, с позицией (мс) снятия.
+    thumbs: Vec<(i64, Vec<u8>)>,
+    next_ms: i64,
+}
+
+impl ThumbnailSprite {
+    pub fn new(
+        settings: ThumbnailSettings,
+        format: ffmpeg::format::Pixel,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let thumb_width = settings.width.max(16) & !1;
+        let thumb_height = ((height as u64 * thumb_width as u64 / width.max(1) as u64) as u32).max(2) & !1;
+        let scaler = scaling::Context::get(
+            format,
+            width,
+            height,
+            ffmpeg::format::Pixel::RGB24,
+            thumb_width,
+            thumb_height,
+            scaling::Flags::BILINEAR,
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to create thumbnail scaler: {:?}", e))?;
+        Ok(Self {
+            settings: ThumbnailSettings {
+                width: thumb_width,
+                ..settings
+            },
+            height: thumb_height,
+            scaler,
+            thumbs: Vec::new(),
+            next_ms: 0,
+        })
+    }
+
+    /// Снимает миниатюру, если позиция записи дошла до следующего интервала.
+    pub fn offer(&mut self, frame: &ffmpeg::frame::Video, position_ms: i64) -> Result<()> {
+        if position_ms < self.next_ms {
+            return Ok(());
+        }
+        let mut scaled = ffmpeg::frame::Video::empty();
+        self.scaler
+            .run(frame, &mut scaled)
+            .map_err(|e| anyhow::anyhow!("Error scaling thumbnail: {:?}", e))?;
+        let row = self.settings.width as usize * 3;
+        let stride = scaled.stride(0);
+        let mut pixels = Vec::with_capacity(row * self.height as usize);
+        for y in 0..self.height as usize {
+            pixels.extend_from_slice(&scaled.data(0)[y * stride..y * stride + row]);
+        }
+        self.thumbs.push((position_ms, pixels));
+        let interval_ms = self.settings.interval_secs.max(1) as i64 * 1000;
+        self.next_ms = (position_ms / interval_ms + 1) * interval_ms;
+        Ok(())
+    }
+
+    /// Склеивает миниатюры в спрайт. `sprite_name` — имя, под которым спрайт
+    /// будет лежать рядом с VTT (ссылки в VTT относительные). `end_ms` — конец записи.
+    pub fn finish(self, sprite_name: &str, end_ms: i64) -> Result<Option<ThumbnailTrack>> {
+        if self.thumbs.is_empty() {
+            return Ok(None);
+        }
+        let (width, height) = (self.settings.width, self.height);
+        let columns = SPRITE_COLUMNS.min(self.thumbs.len() as u32);
+        let rows = (self.thumbs.len() as u32 + columns - 1) / columns;
+        let mut sheet = ffmpeg::frame::Video::new(
+            ffmpeg::format::Pixel::RGB24,
+            width * columns,
+            height * rows,
+        );
+        let stride = sheet.stride(0);
+        let row_bytes = width as usize * 3;
+        let mut vtt = String::from("WEBVTT\n");
+        for (i, (start_ms, pixels)) in self.thumbs.iter().enumerate() {
+            let (x, y) = ((i as u32 % columns) * width, (i as u32 / columns) * height);
+            for line in 0..height as usize {
+                let offset = (y as usize + line) * stride + x as usize * 3;
+                sheet.data_mut(0)[offset..offset + row_bytes]
+                    .copy_from_slice(&pixels[line * row_bytes..(line + 1) * row_bytes]);
+            }
+            let cue_end = self
+                .thumbs
+                .get(i + 1)
+                .map_or(end_ms, |(next_ms, _)| *next_ms)
+                .max(*start_ms);
+            vtt.push_str(&format!(
+                "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+                vtt_timestamp(*start_ms),
+                vtt_timestamp(cue_end),
+                sprite_name,
+                x,
+                y,
+                width,
+                height
+            ));
+        }
+        Ok(Some(ThumbnailTrack {
+            sprite: encode_jpeg(&sheet)?,
+            vtt,
+        }))
+    }
+}
+
+/// Кодирует один RGB24-кадр в JPEG кодером MJPEG из FFmpeg.
+fn encode_jpeg(image: &ffmpeg::frame::Video) -> Result<Vec<u8>> {
+    let format = ffmpeg::format::Pixel::YUVJ420P;
+    let mut converter = scaling::Context::get(
+        image.format(),
+        image.width(),
+        image.height(),
+        format,
+        image.width(),
+        image.height(),
+        scaling::Flags::BILINEAR,
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to create sprite converter: {:?}", e))?;
+    let mut yuv = ffmpeg::frame::Video::empty();
+    converter
+        .run(image, &mut yuv)
+        .map_err(|e| anyhow::anyhow!("Error converting sprite: {:?}", e))?;
+    yuv.set_pts(Some(0));
+
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::MJPEG)
+        .ok_or_else(|| anyhow::anyhow!("MJPEG encoder not found"))?;
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()
+        .map_err(|e| anyhow::anyhow!("Failed to create JPEG encoder: {:?}", e))?;
+    encoder.set_width(image.width());
+    encoder.set_height(image.height());
+    encoder.set_format(format);
+    encoder.set_time_base(ffmpeg::Rational(1, 1));
+    let mut encoder = encoder
+        .open_as(codec)
+        .map_err(|e| anyhow::anyhow!("Failed to open JPEG encoder: {:?}", e))?;
+    encoder
+        .send_frame(&yuv)
+        .map_err(|e| anyhow::anyhow!("Error encoding sprite: {:?}", e))?;
+    encoder
+        .send_eof()
+        .map_err(|e| anyhow::anyhow!("Error finishing sprite: {:?}", e))?;
+    let mut packet = ffmpeg::Packet::empty();
+    let mut jpeg = Vec::new();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        jpeg.extend_from_slice(packet.data().unwrap_or_default());
+    }
+    Ok(jpeg)
+}
+
+/// Время WebVTT: чч:мм:сс.ммм.
+fn vtt_timestamp(ms: i64) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
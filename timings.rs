@@ -0,0 +1,90 @@
+// src/timings.rs
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::info;
+
+use crate::paths;
+
+/// Этап обработки кадра, время которого измеряется.
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Decode,
+    Filter,
+    Encode,
+}
+
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::Decode, Stage::Filter, Stage::Encode];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Filter => "filter",
+            Stage::Encode => "encode",
+        }
+    }
+}
+
+/// Журнал времени этапов по кадрам для диагностики производительности.
+/// Каждое измерение пишется строкой CSV `stage,index,micros` в каталог состояния,
+/// а в конце записи печатается сводка p50/p95/max по этапам.
+pub struct TimingLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    samples: [Vec<u64>; 3],
+}
+
+impl TimingLog {
+    pub fn create(object_name: &str) -> Result<Self> {
+        let path = paths::state_dir()?.join(format!(
+            "timings-{}-{}.csv",
+            object_name.replace('/', "_"),
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        let mut writer = BufWriter::new(
+            File::create(&path)
+                .with_context(|| format!("Failed to create timing log {}", path.display()))?,
+        );
+        writeln!(writer, "stage,index,micros")?;
+        info!("Writing per-frame timings to {}", path.display());
+        Ok(Self {
+            path,
+            writer,
+            samples: Default::default(),
+        })
+    }
+
+    pub fn record(&mut self, stage: Stage, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        let samples = &mut self.samples[stage as usize];
+        // Ошибка записи диагностики не должна прерывать саму запись экрана.
+        let _ = writeln!(self.writer, "{},{},{}", stage.name(), samples.len(), micros);
+        samples.push(micros);
+    }
+
+    /// Сбрасывает CSV на диск и печатает сводку по этапам.
+    pub fn finish(&mut self) {
+        let _ = self.writer.flush();
+        info!("Frame timings ({}):", self.path.display());
+        for stage in Stage::ALL {
+            let samples = &mut self.samples[stage as usize];
+            if samples.is_empty() {
+                continue;
+            }
+            samples.sort_unstable();
+            let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+            info!(
+                "  {:<6} n={} p50={}us p95={}us max={}us",
+                stage.name(),
+                samples.len(),
+                percentile(50),
+                percentile(95),
+                samples[samples.len() - 1]
+            );
+        }
+    }
+}
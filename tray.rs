@@ -0,0 +1,61 @@
+// src/tray.rs
+
+use gtk::prelude::*;
+use gtk::{Menu, MenuItem, SeparatorMenuItem};
+use libappindicator::{AppIndicator, AppIndicatorStatus};
+
+/// Значки темы для состояний "ничего не пишется" и "идёт запись".
+const IDLE_ICON: &str = "media-playback-stop";
+const RECORDING_ICON: &str = "media-record";
+
+/// Значок в трее (StatusNotifier через libappindicator) с меню Start, Stop и Quit.
+///
+/// Пункты меню только вызывают переданные обработчики; доступность пунктов
+/// и значок задаёт `update` по тому же состоянию, что и кнопки окна.
+pub struct Tray {
+    indicator: AppIndicator,
+    start_item: MenuItem,
+    stop_item: MenuItem,
+}
+
+impl Tray {
+    pub fn new(
+        on_start: impl Fn() + 'static,
+        on_stop: impl Fn() + 'static,
+        on_quit: impl Fn() + 'static,
+    ) -> Self {
+        let mut indicator = AppIndicator::new("rscap", IDLE_ICON);
+        indicator.set_status(AppIndicatorStatus::Active);
+        let mut menu = Menu::new();
+        let start_item = MenuItem::with_label("Start Recording");
+        start_item.connect_activate(move |_| on_start());
+        let stop_item = MenuItem::with_label("Stop Recording");
+        stop_item.connect_activate(move |_| on_stop());
+        stop_item.set_sensitive(false);
+        let quit_item = MenuItem::with_label("Quit");
+        quit_item.connect_activate(move |_| on_quit());
+        menu.append(&start_item);
+        menu.append(&stop_item);
+        menu.append(&SeparatorMenuItem::new());
+        menu.append(&quit_item);
+        menu.show_all();
+        indicator.set_menu(&mut menu);
+        Self {
+            indicator,
+            start_item,
+            stop_item,
+        }
+    }
+
+    /// `can_start` — не достигнут предел одновременных записей,
+    /// `recording` — идёт хотя бы одна запись.
+    pub fn update(&mut self, can_start: bool, recording: bool) {
+        self.start_item.set_sensitive(can_start);
+        self.stop_item.set_sensitive(recording);
+        if recording {
+            self.indicator.set_icon_full(RECORDING_ICON, "Recording");
+        } else {
+            self.indicator.set_icon_full(IDLE_ICON, "Idle");
+        }
+    }
+}